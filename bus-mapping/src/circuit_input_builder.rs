@@ -5,27 +5,41 @@ mod access;
 mod block;
 mod call;
 mod execution;
+mod gas_checker;
 mod input_state_ref;
+mod receipt;
 #[cfg(test)]
 mod tracer_tests;
 mod transaction;
+mod validator;
+mod witness;
 
 use self::access::gen_state_access_trace;
-use crate::error::Error;
+use crate::constants::GWEI;
+use crate::error::{Error, StepErrorContext};
 use crate::evm::opcodes::{gen_associated_ops, gen_begin_tx_ops, gen_end_tx_ops};
+use crate::evm::{ChainConfig, Hardfork};
+use crate::mpt::verify_account_proof;
 use crate::operation::{CallContextField, RW};
 use crate::rpc::GethClient;
 use crate::state_db::{self, CodeDB, StateDB};
 pub use access::{Access, AccessSet, AccessValue, CodeSource};
-pub use block::{Block, BlockContext};
+pub use block::{Block, BlockContext, BlockHead};
 pub use call::{Call, CallContext, CallKind};
 use core::fmt::Debug;
-use eth_types::{self, Address, GethExecStep, GethExecTrace, Word};
+use eth_types::{self, Address, GethExecStep, GethExecTrace, Hash, Withdrawal, Word};
 use ethers_providers::JsonRpcClient;
 pub use execution::{CopyDetails, ExecState, ExecStep, StepAuxiliaryData};
+pub use gas_checker::{check_block_gas, GasMismatch};
 pub use input_state_ref::CircuitInputStateRef;
+pub use receipt::{
+    block_receipts, check_block_receipts, logs_bloom, receipts_root, Log, Receipt, ReceiptMismatch,
+};
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 pub use transaction::{Transaction, TransactionContext};
+pub use validator::{validate_block, MismatchKind, TraceMismatch};
+pub use witness::WITNESS_FORMAT_VERSION;
 
 /// Builder to generate a complete circuit input from data gathered from a geth
 /// instance. This structure is the centre of the crate and is intended to be
@@ -55,6 +69,14 @@ pub struct CircuitInputBuilder {
     pub block: Block,
     /// Block Context
     pub block_ctx: BlockContext,
+    /// Blob versioned hashes (EIP-4844) of the blob transactions, by
+    /// transaction hash.  The transactions of ethers don't carry them, so they
+    /// must be set before handling the transactions.
+    pub blob_hashes: HashMap<Hash, Vec<Hash>>,
+    /// Withdrawals (EIP-4895) of the blocks, by block hash, which are
+    /// credited after the transactions of their block.  The blocks of ethers
+    /// don't carry them, so they must be set before handling the blocks.
+    pub withdrawals: HashMap<Hash, Vec<Withdrawal>>,
 }
 
 impl<'a> CircuitInputBuilder {
@@ -66,6 +88,8 @@ impl<'a> CircuitInputBuilder {
             code_db,
             block,
             block_ctx: BlockContext::new(),
+            blob_hashes: HashMap::new(),
+            withdrawals: HashMap::new(),
         }
     }
 
@@ -95,18 +119,22 @@ impl<'a> CircuitInputBuilder {
     ) -> Result<Transaction, Error> {
         let call_id = self.block_ctx.rwc.0;
 
-        self.block_ctx.call_map.insert(
+        self.block_ctx
+            .call_map
+            .insert(call_id, (self.block.txs.len(), 0));
+
+        Transaction::new(
             call_id,
-            (
-                eth_tx
-                    .transaction_index
-                    .ok_or(Error::EthTypeError(eth_types::Error::IncompleteBlock))?
-                    .as_u64() as usize,
-                0,
-            ),
-        );
-
-        Transaction::new(call_id, &self.sdb, &mut self.code_db, eth_tx, is_success)
+            self.block.number,
+            &self.sdb,
+            &mut self.code_db,
+            eth_tx,
+            self.blob_hashes
+                .get(&eth_tx.hash)
+                .cloned()
+                .unwrap_or_default(),
+            is_success,
+        )
     }
 
     /// Iterate over all generated CallContext RwCounterEndOfReversion
@@ -136,6 +164,47 @@ impl<'a> CircuitInputBuilder {
         &mut self,
         eth_block: &EthBlock,
         geth_traces: &[eth_types::GethExecTrace],
+    ) -> Result<(), Error> {
+        self.handle_block_txs(eth_block, geth_traces, true)?;
+        self.set_value_ops_call_context_rwc_eor();
+        Ok(())
+    }
+
+    /// Handle a range of consecutive blocks, in order, to generate a single
+    /// witness proving all of them.  The state and codes are carried from one
+    /// block to the next, the transaction ids are unique across the range and
+    /// the read-write counter continues from one block to the next.  The
+    /// builder must have been created with the [`Block`] of the first block of
+    /// the range.
+    ///
+    /// The transactions and the `withdrawals` of each block modify the carried
+    /// state, but the block rewards are not applied between the blocks, so a
+    /// range must not touch the coinbase of a block with rewards after it.
+    pub fn handle_blocks(
+        &mut self,
+        blocks: &[(EthBlock, Vec<eth_types::GethExecTrace>)],
+    ) -> Result<(), Error> {
+        let last_block_with_txs = blocks
+            .iter()
+            .rposition(|(eth_block, _)| !eth_block.transactions.is_empty());
+        for (index, (eth_block, geth_traces)) in blocks.iter().enumerate() {
+            if index > 0 {
+                self.block.push_block(eth_block)?;
+            }
+            self.handle_block_txs(eth_block, geth_traces, Some(index) == last_block_with_txs)?;
+        }
+        self.set_value_ops_call_context_rwc_eor();
+        Ok(())
+    }
+
+    /// Handle each transaction of a block and then credit its withdrawals,
+    /// where `is_last_block` tells whether its last transaction is the last one
+    /// of the witness.
+    fn handle_block_txs(
+        &mut self,
+        eth_block: &EthBlock,
+        geth_traces: &[eth_types::GethExecTrace],
+        is_last_block: bool,
     ) -> Result<(), Error> {
         // accumulates gas across all txs in the block
         let mut cumulative_gas_used = HashMap::new();
@@ -143,12 +212,24 @@ impl<'a> CircuitInputBuilder {
             let geth_trace = &geth_traces[tx_index];
             self.handle_tx(
                 tx,
+                tx_index,
                 geth_trace,
-                tx_index + 1 == eth_block.transactions.len(),
+                is_last_block && tx_index + 1 == eth_block.transactions.len(),
                 &mut cumulative_gas_used,
             )?;
         }
-        self.set_value_ops_call_context_rwc_eor();
+
+        // Withdrawals of a zero amount don't touch their account.
+        let withdrawals = eth_block
+            .hash
+            .and_then(|hash| self.withdrawals.get(&hash))
+            .into_iter()
+            .flatten()
+            .filter(|withdrawal| !withdrawal.amount.is_zero());
+        for withdrawal in withdrawals {
+            let (_, account) = self.sdb.get_account_mut(&withdrawal.address);
+            account.balance += Word::from(withdrawal.amount.as_u64()) * Word::from(GWEI);
+        }
         Ok(())
     }
 
@@ -156,32 +237,45 @@ impl<'a> CircuitInputBuilder {
     /// all the associated operations.  Each operation is registered in
     /// `self.block.container`, and each step stores the
     /// [`OperationRef`](crate::exec_trace::OperationRef) to each of the
-    /// generated operations.
+    /// generated operations.  Any failure is wrapped in an
+    /// [`Error::TxError`] with the context in which it happened.
     fn handle_tx(
         &mut self,
         eth_tx: &eth_types::Transaction,
+        tx_index: usize,
         geth_trace: &GethExecTrace,
         is_last_tx: bool,
         cumulative_gas_used: &mut HashMap<usize, u64>,
     ) -> Result<(), Error> {
-        let mut tx = self.new_tx(eth_tx, !geth_trace.failed)?;
-        let mut tx_ctx = TransactionContext::new(eth_tx, geth_trace, is_last_tx)?;
+        let tx_error = |err: Error| err.with_tx_context(tx_index, eth_tx.hash, None);
+
+        let mut tx = self.new_tx(eth_tx, !geth_trace.failed).map_err(tx_error)?;
+        let mut tx_ctx =
+            TransactionContext::new(self.block.txs.len() + 1, eth_tx, geth_trace, is_last_tx)
+                .map_err(tx_error)?;
 
         // TODO: Move into gen_associated_steps with
         // - execution_state: BeginTx
         // - op: None
         // Generate BeginTx step
-        let begin_tx_step = gen_begin_tx_ops(&mut self.state_ref(&mut tx, &mut tx_ctx))?;
+        let begin_tx_step =
+            gen_begin_tx_ops(&mut self.state_ref(&mut tx, &mut tx_ctx)).map_err(tx_error)?;
         tx.steps_mut().push(begin_tx_step);
 
         for (index, geth_step) in geth_trace.struct_logs.iter().enumerate() {
             let mut state_ref = self.state_ref(&mut tx, &mut tx_ctx);
             log::trace!("handle {}th opcode {:?} ", index, geth_step.op);
+            let step_context = StepErrorContext::new(
+                index,
+                geth_step,
+                state_ref.call().map(|call| call.call_id).ok(),
+            );
             let exec_steps = gen_associated_ops(
                 &geth_step.op,
                 &mut state_ref,
                 &geth_trace.struct_logs[index..],
-            )?;
+            )
+            .map_err(|err| err.with_tx_context(tx_index, eth_tx.hash, Some(step_context)))?;
             tx.steps_mut().extend(exec_steps);
         }
 
@@ -192,7 +286,8 @@ impl<'a> CircuitInputBuilder {
         let end_tx_step = gen_end_tx_ops(
             &mut self.state_ref(&mut tx, &mut tx_ctx),
             cumulative_gas_used,
-        )?;
+        )
+        .map_err(tx_error)?;
         tx.steps_mut().push(end_tx_step);
 
         self.sdb.commit_tx();
@@ -202,11 +297,11 @@ impl<'a> CircuitInputBuilder {
     }
 }
 
-/// Retrieve the init_code from memory for {CREATE, CREATE2}
-pub fn get_create_init_code(step: &GethExecStep) -> Result<&[u8], Error> {
-    let offset = step.stack.nth_last(1)?;
-    let length = step.stack.nth_last(2)?;
-    Ok(&step.memory.0[offset.low_u64() as usize..(offset.low_u64() + length.low_u64()) as usize])
+/// Retrieve the init_code from memory for {CREATE, CREATE2}.  The memory of the
+/// step may not be expanded yet, in which case the missing bytes are zero.
+pub fn get_create_init_code(step: &GethExecStep) -> Result<Vec<u8>, Error> {
+    let (offset, length) = get_call_memory_offset_length(step, 1)?;
+    Ok(step.memory.read_chunk(offset.into(), length.into()))
 }
 
 /// Retrieve the memory offset and length of call.
@@ -227,18 +322,19 @@ type EthBlock = eth_types::Block<eth_types::Transaction>;
 /// the necessary information and using the CircuitInputBuilder.
 pub struct BuilderClient<P: JsonRpcClient> {
     cli: GethClient<P>,
-    chain_id: Word,
+    chain_config: ChainConfig,
     history_hashes: Vec<Word>,
 }
 
 impl<P: JsonRpcClient> BuilderClient<P> {
-    /// Create a new BuilderClient
-    pub async fn new(client: GethClient<P>) -> Result<Self, Error> {
+    /// Create a new BuilderClient for blocks of the chain of `client` where the
+    /// rules of `hardfork` apply.
+    pub async fn new(client: GethClient<P>, hardfork: Hardfork) -> Result<Self, Error> {
         let chain_id = client.get_chain_id().await?;
 
         Ok(Self {
             cli: client,
-            chain_id: chain_id.into(),
+            chain_config: ChainConfig::new(chain_id.into(), hardfork),
             // TODO: Get history hashes
             history_hashes: Vec::new(),
         })
@@ -277,7 +373,7 @@ impl<P: JsonRpcClient> BuilderClient<P> {
     }
 
     /// Step 3. Query geth for all accounts, storage keys, and codes from
-    /// Accesses
+    /// Accesses, at the parent of the block `block_num`.
     pub async fn get_state(
         &self,
         block_num: u64,
@@ -289,37 +385,37 @@ impl<P: JsonRpcClient> BuilderClient<P> {
         ),
         Error,
     > {
+        let parent_block_num = block_num.checked_sub(1).ok_or(Error::GenesisBlock)?;
         let mut proofs = Vec::new();
         for (address, key_set) in access_set.state {
             let mut keys: Vec<Word> = key_set.iter().cloned().collect();
             keys.sort();
             let proof = self
                 .cli
-                .get_proof(address, keys, (block_num - 1).into())
-                .await
-                .unwrap();
+                .get_proof(address, keys, parent_block_num.into())
+                .await?;
             proofs.push(proof);
         }
         let mut codes: HashMap<Address, Vec<u8>> = HashMap::new();
         for address in access_set.code {
-            let code = self
-                .cli
-                .get_code(address, (block_num - 1).into())
-                .await
-                .unwrap();
+            let code = self.cli.get_code(address, parent_block_num.into()).await?;
             codes.insert(address, code);
         }
         Ok((proofs, codes))
     }
 
-    /// Step 4. Build a partial StateDB from step 3
+    /// Step 4. Build a partial StateDB from step 3, after verifying the
+    /// account and storage proofs against `prev_state_root`, the state root of
+    /// the parent block.
     pub fn build_state_code_db(
         &self,
+        prev_state_root: Hash,
         proofs: Vec<eth_types::EIP1186ProofResponse>,
         codes: HashMap<Address, Vec<u8>>,
-    ) -> (StateDB, CodeDB) {
+    ) -> Result<(StateDB, CodeDB), Error> {
         let mut sdb = StateDB::new();
         for proof in proofs {
+            verify_account_proof(prev_state_root, &proof)?;
             let mut storage = HashMap::new();
             for storage_proof in proof.storage_proof {
                 storage.insert(storage_proof.key, storage_proof.value);
@@ -339,7 +435,7 @@ impl<P: JsonRpcClient> BuilderClient<P> {
         for (_address, code) in codes {
             code_db.insert(code.clone());
         }
-        (sdb, code_db)
+        Ok((sdb, code_db))
     }
 
     /// Step 5. For each step in TxExecTraces, gen the associated ops and state
@@ -351,18 +447,64 @@ impl<P: JsonRpcClient> BuilderClient<P> {
         eth_block: &EthBlock,
         geth_traces: &[eth_types::GethExecTrace],
     ) -> Result<CircuitInputBuilder, Error> {
-        let block = Block::new(self.chain_id, self.history_hashes.clone(), eth_block)?;
+        let block = Block::new(self.chain_config, self.history_hashes.clone(), eth_block)?;
         let mut builder = CircuitInputBuilder::new(sdb, code_db, block);
         builder.handle_block(eth_block, geth_traces)?;
         Ok(builder)
     }
 
+    /// Step 5 for a range of consecutive blocks: gen the associated ops of all
+    /// the blocks in a single witness, starting from the state before the
+    /// first one.
+    pub fn gen_inputs_from_state_range(
+        &self,
+        sdb: StateDB,
+        code_db: CodeDB,
+        blocks: &[(EthBlock, Vec<eth_types::GethExecTrace>)],
+    ) -> Result<CircuitInputBuilder, Error> {
+        let (first_block, _) = blocks.first().ok_or(Error::EmptyBlockRange)?;
+        let block = Block::new(self.chain_config, self.history_hashes.clone(), first_block)?;
+        let mut builder = CircuitInputBuilder::new(sdb, code_db, block);
+        builder.handle_blocks(blocks)?;
+        Ok(builder)
+    }
+
+    /// Perform all the steps to generate the circuit inputs of the
+    /// consecutive blocks `block_nums`, to be proven together.  The state is
+    /// queried once, at the parent of the first block, for the accesses of
+    /// all the blocks.  See [`CircuitInputBuilder::handle_blocks`] for the
+    /// accounts the range must not touch.
+    pub async fn gen_inputs_range(
+        &self,
+        block_nums: RangeInclusive<u64>,
+    ) -> Result<CircuitInputBuilder, Error> {
+        let first_block_num = *block_nums.start();
+        if first_block_num == 0 {
+            return Err(Error::GenesisBlock);
+        }
+        let mut blocks = Vec::new();
+        let mut access_set = AccessSet::from(Vec::new());
+        for block_num in block_nums {
+            let (eth_block, geth_traces) = self.get_block(block_num).await?;
+            access_set.extend(self.get_state_accesses(&eth_block, &geth_traces)?);
+            blocks.push((eth_block, geth_traces));
+        }
+        if blocks.is_empty() {
+            return Err(Error::EmptyBlockRange);
+        }
+        let (proofs, codes) = self.get_state(first_block_num, access_set).await?;
+        let prev_block = self.cli.get_block_by_hash(blocks[0].0.parent_hash).await?;
+        let (state_db, code_db) = self.build_state_code_db(prev_block.state_root, proofs, codes)?;
+        self.gen_inputs_from_state_range(state_db, code_db, &blocks)
+    }
+
     /// Perform all the steps to generate the circuit inputs
     pub async fn gen_inputs(&self, block_num: u64) -> Result<CircuitInputBuilder, Error> {
         let (eth_block, geth_traces) = self.get_block(block_num).await?;
         let access_set = self.get_state_accesses(&eth_block, &geth_traces)?;
         let (proofs, codes) = self.get_state(block_num, access_set).await?;
-        let (state_db, code_db) = self.build_state_code_db(proofs, codes);
+        let prev_block = self.cli.get_block_by_hash(eth_block.parent_hash).await?;
+        let (state_db, code_db) = self.build_state_code_db(prev_block.state_root, proofs, codes)?;
         let builder = self.gen_inputs_from_state(state_db, code_db, &eth_block, &geth_traces)?;
         Ok(builder)
     }
@@ -1,6 +1,7 @@
-use crate::{operation::RW, Error};
+use crate::{error::TraceError, operation::RW, Error};
 use eth_types::{evm_types::OpcodeId, Address, GethExecStep, GethExecTrace, ToAddress, Word};
 use ethers_core::utils::get_contract_address;
+use serde::{Deserialize, Serialize};
 use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 /// State and Code Access with "keys/index" used in the access operation.
@@ -91,8 +92,21 @@ impl From<Vec<Access>> for AccessSet {
     }
 }
 
+impl AccessSet {
+    /// Add the accesses of `other` to this set.
+    pub fn extend(&mut self, other: AccessSet) {
+        for (address, keys) in other.state {
+            self.state
+                .entry(address)
+                .or_insert_with(HashSet::new)
+                .extend(keys);
+        }
+        self.code.extend(other.code);
+    }
+}
+
 /// Source of the code in the EVM execution.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum CodeSource {
     /// Code comes from a deployed contract at `Address`.
     Address(Address),
@@ -257,13 +271,13 @@ pub fn gen_state_access_trace<TX>(
             _ => {}
         }
         if pop_call_stack {
-            if call_stack.len() == 1 {
+            if call_stack.len() <= 1 {
                 return Err(Error::InvalidGethExecStep(
-                    "gen_state_access_trace: call stack will be empty",
+                    TraceError::CallStackUnderflow,
                     step.clone(),
                 ));
             }
-            call_stack.pop().expect("call stack is empty");
+            call_stack.pop();
         }
     }
     Ok(accs)
@@ -2,11 +2,23 @@
 
 use super::transaction::Transaction;
 use crate::{
+    evm::ChainConfig,
     operation::{OperationContainer, RWCounter},
     Error,
 };
 use eth_types::{Address, Hash, Word};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of most recent block hashes kept in [`Block::history_hashes`].
+const MAX_HISTORY_HASHES: usize = 256;
+
+/// Minimum blob base fee (EIP-4844).
+const MIN_BLOB_BASE_FEE: u64 = 1;
+
+/// Denominator of the exponent of the blob base fee (EIP-4844), which bounds
+/// its change rate.
+const BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 3338477;
 
 /// Context of a [`Block`] which can mutate in a [`Transaction`].
 #[derive(Debug)]
@@ -36,41 +48,31 @@ impl BlockContext {
     }
 }
 
-/// Circuit Input related to a block.
-#[derive(Debug)]
-pub struct Block {
-    /// chain id
-    pub chain_id: Word,
-    /// history hashes contains most recent 256 block hashes in history, where
-    /// the lastest one is at history_hashes[history_hashes.len() - 1].
-    pub history_hashes: Vec<Word>,
+/// Header fields of one of the blocks of a [`Block`] witness.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHead {
     /// coinbase
     pub coinbase: Address,
-    /// time
+    /// gas limit
     pub gas_limit: u64,
     /// number
     pub number: Word,
-    /// difficulty
+    /// time
     pub timestamp: Word,
-    /// gas limit
+    /// difficulty
     pub difficulty: Word,
     /// base fee
     pub base_fee: Word,
-    /// Container of operations done in this block.
-    pub container: OperationContainer,
-    /// Transactions contained in the block
-    pub txs: Vec<Transaction>,
-    code: HashMap<Hash, Vec<u8>>,
 }
 
-impl Block {
-    /// Create a new block.
+impl BlockHead {
+    /// Create a new block head.  The base fee is zero if it is not active in
+    /// `chain_config`.
     pub fn new<TX>(
-        chain_id: Word,
-        history_hashes: Vec<Word>,
+        chain_config: &ChainConfig,
         eth_block: &eth_types::Block<TX>,
     ) -> Result<Self, Error> {
-        if eth_block.base_fee_per_gas.is_none() {
+        if chain_config.is_base_fee_active() && eth_block.base_fee_per_gas.is_none() {
             // FIXME: resolve this once we have proper EIP-1559 support
             log::warn!(
                 "This does not look like a EIP-1559 block - base_fee_per_gas defaults to zero"
@@ -78,8 +80,6 @@ impl Block {
         }
 
         Ok(Self {
-            chain_id,
-            history_hashes,
             coinbase: eth_block.author,
             gas_limit: eth_block.gas_limit.low_u64(),
             number: eth_block
@@ -89,13 +89,128 @@ impl Block {
                 .into(),
             timestamp: eth_block.timestamp,
             difficulty: eth_block.difficulty,
-            base_fee: eth_block.base_fee_per_gas.unwrap_or_default(),
+            base_fee: if chain_config.is_base_fee_active() {
+                eth_block.base_fee_per_gas.unwrap_or_default()
+            } else {
+                Word::zero()
+            },
+        })
+    }
+}
+
+/// Circuit Input related to a block, or to a range of consecutive blocks
+/// proven together.  In the latter case, the header fields are those of the
+/// last block added, and the headers of all the blocks are kept in
+/// [`Block::headers`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Block {
+    /// Configuration of the chain, with the hardfork whose rules apply
+    pub chain_config: ChainConfig,
+    /// history hashes contains most recent 256 block hashes in history, where
+    /// the lastest one is at history_hashes[history_hashes.len() - 1].
+    pub history_hashes: Vec<Word>,
+    /// coinbase
+    pub coinbase: Address,
+    /// time
+    pub gas_limit: u64,
+    /// number
+    pub number: Word,
+    /// difficulty
+    pub timestamp: Word,
+    /// gas limit
+    pub difficulty: Word,
+    /// base fee
+    pub base_fee: Word,
+    /// Excess blob gas (EIP-4844), from which the blob base fee is derived.
+    /// The blocks of ethers don't carry it, so it's zero unless set after
+    /// creating the block, and it's kept for all the blocks of a range.
+    pub excess_blob_gas: u64,
+    /// Headers of the blocks in this witness, in order.
+    pub headers: Vec<BlockHead>,
+    /// Container of operations done in this block.
+    pub container: OperationContainer,
+    /// Transactions contained in the block
+    pub txs: Vec<Transaction>,
+    /// Inputs of all the keccak256 hashes computed in the block: the memory
+    /// hashed by SHA3, the CREATE2 address preimages and the hashed code.
+    pub keccak_inputs: Vec<Vec<u8>>,
+    code: BTreeMap<Hash, Vec<u8>>,
+}
+
+impl Block {
+    /// Create a new block.
+    pub fn new<TX>(
+        chain_config: ChainConfig,
+        history_hashes: Vec<Word>,
+        eth_block: &eth_types::Block<TX>,
+    ) -> Result<Self, Error> {
+        let head = BlockHead::new(&chain_config, eth_block)?;
+        Ok(Self {
+            chain_config,
+            history_hashes,
+            coinbase: head.coinbase,
+            gas_limit: head.gas_limit,
+            number: head.number,
+            timestamp: head.timestamp,
+            difficulty: head.difficulty,
+            base_fee: head.base_fee,
+            excess_blob_gas: 0,
+            headers: vec![head],
             container: OperationContainer::new(),
             txs: Vec::new(),
-            code: HashMap::new(),
+            keccak_inputs: Vec::new(),
+            code: BTreeMap::new(),
         })
     }
 
+    /// Add the next block of a range to this witness.  Its header fields
+    /// replace the current ones, and the hash of its parent is appended to the
+    /// history hashes.  The block must follow the last one added.
+    pub fn push_block<TX>(&mut self, eth_block: &eth_types::Block<TX>) -> Result<(), Error> {
+        let head = BlockHead::new(&self.chain_config, eth_block)?;
+        if head.number != self.number + 1 {
+            return Err(Error::NonConsecutiveBlock {
+                expected: self.number + 1,
+                found: head.number,
+            });
+        }
+
+        self.history_hashes
+            .push(Word::from_big_endian(eth_block.parent_hash.as_bytes()));
+        if self.history_hashes.len() > MAX_HISTORY_HASHES {
+            self.history_hashes.remove(0);
+        }
+        self.coinbase = head.coinbase;
+        self.gas_limit = head.gas_limit;
+        self.number = head.number;
+        self.timestamp = head.timestamp;
+        self.difficulty = head.difficulty;
+        self.base_fee = head.base_fee;
+        self.headers.push(head);
+        Ok(())
+    }
+
+    /// Return the blob base fee (EIP-4844) of the block, which is
+    /// `MIN_BLOB_BASE_FEE * e ** (excess_blob_gas / BLOB_BASE_FEE_UPDATE_FRACTION)`
+    /// approximated with the Taylor expansion of the specification.
+    pub fn blob_base_fee(&self) -> Word {
+        let denominator = Word::from(BLOB_BASE_FEE_UPDATE_FRACTION);
+        let numerator = Word::from(self.excess_blob_gas);
+        let mut output = Word::zero();
+        let mut numerator_accum = Word::from(MIN_BLOB_BASE_FEE) * denominator;
+        let mut i = Word::one();
+        while !numerator_accum.is_zero() {
+            output = output.saturating_add(numerator_accum);
+            numerator_accum = match numerator_accum.checked_mul(numerator) {
+                Some(product) => product / (denominator * i),
+                // The fee doesn't fit in a word anyway.
+                None => return Word::MAX,
+            };
+            i += Word::one();
+        }
+        output / denominator
+    }
+
     /// Return the list of transactions of this block.
     pub fn txs(&self) -> &[Transaction] {
         &self.txs
@@ -106,3 +221,262 @@ impl Block {
         &mut self.txs
     }
 }
+
+#[cfg(test)]
+mod block_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::check_block_gas,
+        constants::GWEI,
+        mock::BlockData,
+        operation::{AccountField, CallContextField, Target, RW},
+    };
+    use eth_types::{bytecode, geth_types::GethData, Bytecode, Withdrawal, H256};
+    use mock::{
+        eth,
+        test_ctx::{helpers::*, TestContext},
+        MOCK_ACCOUNTS,
+    };
+    use pretty_assertions::assert_eq;
+
+    // Increments the storage slot 0.
+    fn code() -> Bytecode {
+        bytecode! {
+            PUSH1(0x00)
+            SLOAD
+            PUSH1(0x01)
+            ADD
+            PUSH1(0x00)
+            SSTORE
+            STOP
+        }
+    }
+
+    #[test]
+    fn handle_blocks_range() {
+        let block_0: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(MOCK_ACCOUNTS[0])
+                    .balance(eth(10))
+                    .code(code());
+                accs[1].address(MOCK_ACCOUNTS[1]).balance(eth(10));
+            },
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64).hash(H256::repeat_byte(0xaa)),
+        )
+        .unwrap()
+        .into();
+
+        // The trace of block 1 is generated from the state after block 0.
+        let mut builder_0 =
+            BlockData::new_from_geth_data(block_0.clone()).new_circuit_input_builder();
+        builder_0
+            .handle_block(&block_0.eth_block, &block_0.geth_traces)
+            .unwrap();
+        let state_0 = |address| builder_0.sdb.get_account(&address).1.clone();
+        let (account_0, account_1) = (state_0(MOCK_ACCOUNTS[0]), state_0(MOCK_ACCOUNTS[1]));
+        let slot_0 = *builder_0
+            .sdb
+            .get_storage(&MOCK_ACCOUNTS[0], &Word::zero())
+            .1;
+        assert_eq!(slot_0, Word::one());
+        let block_1: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(MOCK_ACCOUNTS[0])
+                    .balance(account_0.balance)
+                    .nonce(account_0.nonce)
+                    .code(code())
+                    .storage(vec![(Word::zero(), slot_0)].into_iter());
+                accs[1]
+                    .address(MOCK_ACCOUNTS[1])
+                    .balance(account_1.balance)
+                    .nonce(account_1.nonce);
+            },
+            |mut txs, accs| {
+                txs[0]
+                    .from(accs[1].address)
+                    .to(accs[0].address)
+                    .nonce(Word::one());
+            },
+            |block, _tx| block.number(0xcaffu64).parent_hash(H256::repeat_byte(0xaa)),
+        )
+        .unwrap()
+        .into();
+
+        let mut builder =
+            BlockData::new_from_geth_data(block_0.clone()).new_circuit_input_builder();
+        builder
+            .handle_blocks(&[
+                (block_0.eth_block, block_0.geth_traces.clone()),
+                (block_1.eth_block.clone(), block_1.geth_traces.clone()),
+            ])
+            .unwrap();
+        let block = &builder.block;
+
+        // The gas accounting, including the cumulative gas used of each
+        // block, matches the traces.
+        let geth_traces = [block_0.geth_traces, block_1.geth_traces.clone()].concat();
+        assert_eq!(check_block_gas(block, &geth_traces), vec![]);
+
+        assert_eq!(block.number, Word::from(0xcaffu64));
+        assert_eq!(
+            block
+                .headers
+                .iter()
+                .map(|head| head.number)
+                .collect::<Vec<_>>(),
+            vec![Word::from(0xcafeu64), Word::from(0xcaffu64)]
+        );
+        assert_eq!(
+            block.history_hashes.last(),
+            Some(&Word::from_big_endian(&[0xaa; 32]))
+        );
+        assert_eq!(
+            block
+                .txs()
+                .iter()
+                .map(|tx| tx.block_number)
+                .collect::<Vec<_>>(),
+            vec![Word::from(0xcafeu64), Word::from(0xcaffu64)]
+        );
+
+        // Transaction ids are unique across the blocks
+        for (tx_index, tx) in block.txs().iter().enumerate() {
+            let call_id = tx.calls()[0].call_id;
+            assert!(block.container.call_context.iter().any(|op| {
+                op.op().call_id == call_id
+                    && op.op().field == CallContextField::TxId
+                    && op.op().value == Word::from(tx_index + 1)
+            }));
+        }
+
+        // The read-write counter continues from one block to the next
+        let rwcs: Vec<usize> = block
+            .container
+            .ops_by_rwc_range(..)
+            .into_iter()
+            .map(|op_ref| block.container[op_ref].rwc().0)
+            .collect();
+        assert_eq!(rwcs, (1..=rwcs.len()).collect::<Vec<_>>());
+
+        // Block 1 starts from the state left by block 0: its SLOAD reads the
+        // value written by block 0, which its SSTORE overwrites.
+        let storage_ops: Vec<_> = block
+            .container
+            .storage
+            .iter()
+            .map(|op| (op.op().tx_id, op.rw(), op.op().value_prev, op.op().value))
+            .collect();
+        assert_eq!(
+            storage_ops,
+            vec![
+                (1, RW::READ, Word::zero(), Word::zero()),
+                (1, RW::WRITE, Word::zero(), Word::one()),
+                (2, RW::READ, Word::one(), Word::one()),
+                (2, RW::WRITE, Word::one(), Word::from(2)),
+            ]
+        );
+        assert_eq!(
+            builder.sdb.get_storage(&MOCK_ACCOUNTS[0], &Word::zero()).1,
+            &Word::from(2)
+        );
+
+        // Blocks which don't follow the last one are rejected
+        let mut block_2 = block_1.eth_block;
+        block_2.number = Some(0xcb01u64.into());
+        assert!(matches!(
+            builder.block.push_block(&block_2),
+            Err(Error::NonConsecutiveBlock { .. })
+        ));
+    }
+
+    #[test]
+    fn handle_blocks_withdrawals() {
+        let gen_block = |balance: Word, nonce: Word, number: u64, hash: u8| -> GethData {
+            TestContext::<2, 1>::new(
+                None,
+                |accs| {
+                    accs[0].address(MOCK_ACCOUNTS[0]).code(code());
+                    accs[1]
+                        .address(MOCK_ACCOUNTS[1])
+                        .balance(balance)
+                        .nonce(nonce);
+                },
+                |mut txs, accs| {
+                    txs[0]
+                        .from(accs[1].address)
+                        .to(accs[0].address)
+                        .nonce(nonce);
+                },
+                |block, _tx| block.number(number).hash(H256::repeat_byte(hash)),
+            )
+            .unwrap()
+            .into()
+        };
+        let block_0 = gen_block(eth(10), Word::zero(), 0xcafe, 0xaa);
+        let mut builder_0 =
+            BlockData::new_from_geth_data(block_0.clone()).new_circuit_input_builder();
+        builder_0
+            .handle_block(&block_0.eth_block, &block_0.geth_traces)
+            .unwrap();
+
+        // The sender of block 1 receives a withdrawal at the end of block 0.
+        let amount = 5u64;
+        let balance = builder_0.sdb.get_account(&MOCK_ACCOUNTS[1]).1.balance
+            + Word::from(amount) * Word::from(GWEI);
+        let block_1 = gen_block(balance, Word::one(), 0xcaff, 0xbb);
+
+        let mut builder =
+            BlockData::new_from_geth_data(block_0.clone()).new_circuit_input_builder();
+        builder.withdrawals.insert(
+            H256::repeat_byte(0xaa),
+            vec![Withdrawal {
+                address: MOCK_ACCOUNTS[1],
+                amount: amount.into(),
+                ..Default::default()
+            }],
+        );
+        builder
+            .handle_blocks(&[
+                (block_0.eth_block, block_0.geth_traces),
+                (block_1.eth_block, block_1.geth_traces),
+            ])
+            .unwrap();
+
+        // Block 1 starts from the balance including the withdrawal, which its
+        // BeginTx step reads before charging the fee.
+        let begin_tx_step = &builder.block.txs()[1].steps()[0];
+        let sender_balance_prev = begin_tx_step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == Target::Account)
+            .map(|op_ref| builder.block.container.account[op_ref.as_usize()].op())
+            .find(|op| op.address == MOCK_ACCOUNTS[1] && op.field == AccountField::Balance)
+            .map(|op| op.value_prev);
+        assert_eq!(sender_balance_prev, Some(balance));
+    }
+
+    #[test]
+    fn blob_base_fee() {
+        let eth_block = eth_types::Block::<()> {
+            number: Some(0xcafeu64.into()),
+            ..Default::default()
+        };
+        let mut block = Block::new(ChainConfig::default(), Vec::new(), &eth_block).unwrap();
+        for (excess_blob_gas, blob_base_fee) in [
+            (0, 1u64),
+            (BLOB_BASE_FEE_UPDATE_FRACTION, 2),
+            (10 * BLOB_BASE_FEE_UPDATE_FRACTION, 22026),
+            (39321600, 130392),
+        ] {
+            block.excess_blob_gas = excess_blob_gas;
+            assert_eq!(block.blob_base_fee(), Word::from(blob_base_fee));
+        }
+        block.excess_blob_gas = u64::MAX;
+        assert_eq!(block.blob_base_fee(), Word::MAX);
+    }
+}
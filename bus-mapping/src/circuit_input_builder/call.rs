@@ -1,9 +1,10 @@
 use super::CodeSource;
 use crate::{exec_trace::OperationRef, Error};
 use eth_types::{evm_types::OpcodeId, Address, Hash, Word};
+use serde::{Deserialize, Serialize};
 
 /// Type of a *CALL*/CREATE* Function.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum CallKind {
     /// CALL
     Call,
@@ -48,7 +49,7 @@ impl TryFrom<OpcodeId> for CallKind {
 }
 
 /// Circuit Input related to an Ethereum Call
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Call {
     /// Unique call identifier within the Block.
     pub call_id: usize,
@@ -108,6 +109,18 @@ pub struct CallContext {
     /// Call data (copy of tx input or caller's
     /// memory[call_data_offset..call_data_offset + call_data_length])
     pub call_data: Vec<u8>,
+    /// Id of the last call made by this call (0 if there is none).
+    pub last_callee_id: usize,
+    /// Offset of the return data in the last callee's memory
+    pub last_callee_return_data_offset: u64,
+    /// Length of the return data of the last callee
+    pub last_callee_return_data_length: u64,
+    /// Return data of the last callee (copy of the callee's
+    /// memory[return_data_offset..return_data_offset + return_data_length])
+    pub return_data: Vec<u8>,
+    /// Id of the [`StateDB`](crate::state_db::StateDB) snapshot taken when the
+    /// call started, to which the state is reverted if the call fails.
+    pub(crate) snapshot: usize,
 }
 
 /// A reversion group is the collection of calls and the operations which are
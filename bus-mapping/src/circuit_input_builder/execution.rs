@@ -1,13 +1,19 @@
 //! Execution step related module.
 
-use crate::{error::ExecError, exec_trace::OperationRef, operation::RWCounter};
+use crate::{
+    error::ExecError,
+    evm::{PrecompileCall, PrecompileCalls},
+    exec_trace::OperationRef,
+    operation::RWCounter,
+};
 use eth_types::{
     evm_types::{Gas, GasCost, OpcodeId, ProgramCounter},
     GethExecStep, U256,
 };
+use serde::{Deserialize, Serialize};
 
 /// An execution step of the EVM.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ExecStep {
     /// Execution state
     pub exec_state: ExecState,
@@ -40,6 +46,8 @@ pub struct ExecStep {
     pub error: Option<ExecError>,
     /// Step auxiliary data
     pub aux_data: Option<StepAuxiliaryData>,
+    /// Witness of the call to a precompiled contract done in this step
+    pub precompile_call: Option<PrecompileCall>,
 }
 
 impl ExecStep {
@@ -66,6 +74,7 @@ impl ExecStep {
             bus_mapping_instance: Vec::new(),
             error: None,
             aux_data: None,
+            precompile_call: None,
         }
     }
 }
@@ -87,15 +96,16 @@ impl Default for ExecStep {
             bus_mapping_instance: Vec::new(),
             error: None,
             aux_data: None,
+            precompile_call: None,
         }
     }
 }
 
 /// Execution state
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ExecState {
     /// EVM Opcode ID
-    Op(OpcodeId),
+    Op(#[serde(with = "super::witness::opcode_id")] OpcodeId),
     /// Virtual step Begin Tx
     BeginTx,
     /// Virtual step End Tx
@@ -106,6 +116,8 @@ pub enum ExecState {
     CopyToLog,
     /// Virtal step Copy Code To Memory
     CopyCodeToMemory,
+    /// Virtual step Call to a precompiled contract
+    Precompile(PrecompileCalls),
 }
 
 impl ExecState {
@@ -148,12 +160,18 @@ impl ExecState {
 
 /// Provides specific details about the data copy for which an
 /// [`StepAuxiliaryData`] holds info about.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum CopyDetails {
     /// Origin of the copied bytes is or not the Tx CallData.
     TxCallData(bool),
     /// Origin of the copied bytes is bytecode. For which it's hash is provided.
     Code(U256),
+    /// Origin of the copied bytes is the return data of the last callee. The
+    /// callee's call id is provided.
+    ReturnData(usize),
+    /// Origin of the copied bytes is the memory of the current call, which is
+    /// also the destination (`MCOPY`). The call id is provided.
+    Memory(usize),
     /// The bytes are being copied to a Log.
     /// Call's state change's persistance and tx_id are provided.
     /// the data start index when enter this copy step
@@ -161,7 +179,7 @@ pub enum CopyDetails {
 }
 
 /// Auxiliary data of Execution step
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct StepAuxiliaryData {
     /// Source start address
     pub(crate) src_addr: u64,
@@ -228,6 +246,16 @@ impl StepAuxiliaryData {
         matches!(self.copy_details, CopyDetails::TxCallData(_))
     }
 
+    /// Returns true if the data origin is the return data of a call.
+    pub fn is_return_data_originated(&self) -> bool {
+        matches!(self.copy_details, CopyDetails::ReturnData(_))
+    }
+
+    /// Returns true if the data origin is the memory of the current call.
+    pub fn is_memory_originated(&self) -> bool {
+        matches!(self.copy_details, CopyDetails::Memory(_))
+    }
+
     /// Returns true if the data is copied to Logs.
     pub fn is_log_destinated(&self) -> bool {
         matches!(self.copy_details, CopyDetails::Log(_))
@@ -0,0 +1,692 @@
+//! Independent check of the gas accounting of a [`Block`] against the geth
+//! traces it was built from.
+
+use super::{validator::opcode_step_indices, Block, ExecState, ExecStep, Transaction};
+use crate::{
+    evm::GasSchedule,
+    exec_trace::OperationRef,
+    operation::{AccountField, OpEnum, Target, TxReceiptField},
+};
+use eth_types::{
+    evm_types::{
+        gas_utils::{eip150_gas, memory_copier_gas_cost, memory_expansion_gas_cost},
+        GasCost, OpcodeId, ProgramCounter,
+    },
+    Address, GethExecStep, GethExecTrace, ToAddress, Word,
+};
+use keccak256::EMPTY_HASH;
+use std::fmt;
+
+/// Mismatch found between the gas accounting of a [`Block`] and geth.
+/// `expected` is the value reported by geth, and `found` the one recomputed
+/// from the steps and their operations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GasMismatch {
+    /// Number of opcode steps of a transaction, whose steps can't be checked
+    StepCount {
+        /// Index of the transaction in the block
+        tx_index: usize,
+        /// Number of steps in the geth trace
+        expected: usize,
+        /// Number of opcode steps of the transaction
+        found: usize,
+    },
+    /// Gas cost of a step
+    StepCost {
+        /// Index of the transaction in the block
+        tx_index: usize,
+        /// Index of the [`GethExecStep`] in the trace of the transaction
+        step_index: usize,
+        /// Opcode of the step
+        op: OpcodeId,
+        /// Program counter of the step
+        pc: ProgramCounter,
+        /// Gas cost reported by geth
+        expected: u64,
+        /// Recomputed gas cost
+        found: u64,
+    },
+    /// Change of the gas refund counter in a step
+    StepRefund {
+        /// Index of the transaction in the block
+        tx_index: usize,
+        /// Index of the [`GethExecStep`] in the trace of the transaction
+        step_index: usize,
+        /// Opcode of the step
+        op: OpcodeId,
+        /// Program counter of the step
+        pc: ProgramCounter,
+        /// Refund change reported by geth
+        expected: i64,
+        /// Recomputed refund change
+        found: i64,
+    },
+    /// Gas used by a transaction, after refund
+    TxGasUsed {
+        /// Index of the transaction in the block
+        tx_index: usize,
+        /// Gas used reported by geth
+        expected: u64,
+        /// Gas used derived from the EndTx step
+        found: u64,
+    },
+    /// Cumulative gas used of the receipt of a transaction
+    CumulativeGasUsed {
+        /// Index of the transaction in the block
+        tx_index: usize,
+        /// Sum of the gas used reported by geth up to the transaction
+        expected: u64,
+        /// Value in the receipt operations of the transaction
+        found: Option<u64>,
+    },
+}
+
+impl fmt::Display for GasMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GasMismatch::StepCount {
+                tx_index,
+                expected,
+                found,
+            } => write!(f, "tx {}: step count {} != {}", tx_index, found, expected),
+            GasMismatch::StepCost {
+                tx_index,
+                step_index,
+                op,
+                pc,
+                expected,
+                found,
+            } => write!(
+                f,
+                "tx {} step {} ({:?} at pc {}): gas cost {} != {}",
+                tx_index, step_index, op, pc.0, found, expected
+            ),
+            GasMismatch::StepRefund {
+                tx_index,
+                step_index,
+                op,
+                pc,
+                expected,
+                found,
+            } => write!(
+                f,
+                "tx {} step {} ({:?} at pc {}): gas refund {} != {}",
+                tx_index, step_index, op, pc.0, found, expected
+            ),
+            GasMismatch::TxGasUsed {
+                tx_index,
+                expected,
+                found,
+            } => write!(f, "tx {}: gas used {} != {}", tx_index, found, expected),
+            GasMismatch::CumulativeGasUsed {
+                tx_index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "tx {}: cumulative gas used {:?} != {}",
+                tx_index, found, expected
+            ),
+        }
+    }
+}
+
+/// Recompute the gas cost and refund of each step of every transaction in
+/// `block`, and the gas used by each transaction, and compare them with the
+/// ones reported by geth.  Steps which end in an error, or whose cost depends
+/// on state not found in their operations, are not checked, and neither are
+/// the steps of a transaction which don't match the trace one to one.  Returns
+/// all the mismatches found.
+pub fn check_block_gas(block: &Block, geth_traces: &[GethExecTrace]) -> Vec<GasMismatch> {
+    let mut mismatches = Vec::new();
+    let gas_schedule = block.chain_config.gas_schedule();
+    let mut cumulative_gas_used = 0;
+    let mut block_number = None;
+    for (tx_index, (tx, geth_trace)) in block.txs().iter().zip(geth_traces).enumerate() {
+        // The cumulative gas used starts over in each block of a range.
+        if block_number != Some(tx.block_number) {
+            block_number = Some(tx.block_number);
+            cumulative_gas_used = 0;
+        }
+
+        let geth_steps = &geth_trace.struct_logs;
+        let opcode_steps = opcode_step_indices(tx);
+        if opcode_steps.len() != geth_steps.len() {
+            mismatches.push(GasMismatch::StepCount {
+                tx_index,
+                expected: geth_steps.len(),
+                found: opcode_steps.len(),
+            });
+        } else {
+            for (step_index, (exec_step, geth_step)) in opcode_steps
+                .iter()
+                .map(|idx| &tx.steps()[*idx])
+                .zip(geth_steps)
+                .enumerate()
+            {
+                if exec_step.error.is_some() {
+                    continue;
+                }
+                let ops = step_ops(block, exec_step);
+                if let Some(found) = step_gas_cost(tx, exec_step, geth_step, &ops) {
+                    if found != geth_step.gas_cost.0 {
+                        mismatches.push(GasMismatch::StepCost {
+                            tx_index,
+                            step_index,
+                            op: geth_step.op,
+                            pc: geth_step.pc,
+                            expected: geth_step.gas_cost.0,
+                            found,
+                        });
+                    }
+                }
+                if let (Some(found), Some(next_step)) = (
+                    step_refund(&gas_schedule, geth_step, &ops),
+                    geth_steps.get(step_index + 1),
+                ) {
+                    let expected = next_step.refund.0 as i64 - geth_step.refund.0 as i64;
+                    if found != expected {
+                        mismatches.push(GasMismatch::StepRefund {
+                            tx_index,
+                            step_index,
+                            op: geth_step.op,
+                            pc: geth_step.pc,
+                            expected,
+                            found,
+                        });
+                    }
+                }
+            }
+        }
+
+        let end_tx_step = match tx
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::EndTx)
+        {
+            Some(step) => step,
+            None => continue,
+        };
+        let end_tx_ops = step_ops(block, end_tx_step);
+        let refund = end_tx_ops
+            .iter()
+            .find_map(|op| match op {
+                OpEnum::TxRefund(op) => Some(op.value),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let gas_used = tx.gas - end_tx_step.gas_left.0;
+        let gas_used = gas_used - refund.min(gas_used / gas_schedule.max_refund_quotient);
+        if gas_used != geth_trace.gas.0 {
+            mismatches.push(GasMismatch::TxGasUsed {
+                tx_index,
+                expected: geth_trace.gas.0,
+                found: gas_used,
+            });
+        }
+
+        cumulative_gas_used += geth_trace.gas.0;
+        let tx_id = tx_index + 1;
+        let receipt_cumulative_gas_used = end_tx_ops.iter().find_map(|op| match op {
+            OpEnum::TxReceipt(op)
+                if op.tx_id == tx_id && op.field == TxReceiptField::CumulativeGasUsed =>
+            {
+                Some(op.value)
+            }
+            _ => None,
+        });
+        if receipt_cumulative_gas_used != Some(cumulative_gas_used) {
+            mismatches.push(GasMismatch::CumulativeGasUsed {
+                tx_index,
+                expected: cumulative_gas_used,
+                found: receipt_cumulative_gas_used,
+            });
+        }
+    }
+    mismatches
+}
+
+/// Return the operations of `step` which were done while handling it, in
+/// order.  The reversions of its operations, which are done later, are
+/// skipped.
+fn step_ops(block: &Block, step: &ExecStep) -> Vec<OpEnum> {
+    let container = &block.container;
+    step.bus_mapping_instance
+        .iter()
+        .filter_map(|op_ref| {
+            let OperationRef(target, idx) = *op_ref;
+            Some(match target {
+                Target::Storage if container.storage[idx].reversible() => {
+                    OpEnum::Storage(container.storage[idx].op().clone())
+                }
+                Target::TxAccessListAccount
+                    if container.tx_access_list_account[idx].reversible() =>
+                {
+                    OpEnum::TxAccessListAccount(container.tx_access_list_account[idx].op().clone())
+                }
+                Target::TxAccessListAccountStorage
+                    if container.tx_access_list_account_storage[idx].reversible() =>
+                {
+                    OpEnum::TxAccessListAccountStorage(
+                        container.tx_access_list_account_storage[idx].op().clone(),
+                    )
+                }
+                Target::TxRefund => OpEnum::TxRefund(container.tx_refund[idx].op().clone()),
+                Target::Account => OpEnum::Account(container.account[idx].op().clone()),
+                Target::TxReceipt => OpEnum::TxReceipt(container.tx_receipt[idx].op().clone()),
+                _ => return None,
+            })
+        })
+        .collect()
+}
+
+/// Return whether the account at `address` was warm before `ops`, if found in
+/// them.
+fn is_account_warm(ops: &[OpEnum], address: Address) -> Option<bool> {
+    ops.iter().find_map(|op| match op {
+        OpEnum::TxAccessListAccount(op) if op.address == address => Some(op.is_warm_prev),
+        _ => None,
+    })
+}
+
+/// Return whether the account at `address` was empty before `ops`, if its
+/// nonce, code hash and balance are found in them.
+fn is_account_empty(ops: &[OpEnum], address: Address) -> Option<bool> {
+    let field = |field: AccountField| {
+        ops.iter().find_map(|op| match op {
+            OpEnum::Account(op) if op.address == address && op.field == field => {
+                Some(op.value_prev)
+            }
+            _ => None,
+        })
+    };
+    let code_hash = field(AccountField::CodeHash)?;
+    Some(
+        field(AccountField::Nonce)?.is_zero()
+            && field(AccountField::Balance)?.is_zero()
+            && (code_hash.is_zero() || code_hash == Word::from_big_endian(&*EMPTY_HASH)),
+    )
+}
+
+/// Cost of accessing an account, on top of the constant gas cost.
+fn account_access_cost(is_warm: bool) -> u64 {
+    if is_warm {
+        0
+    } else {
+        GasCost::COLD_ACCOUNT_ACCESS.as_u64() - GasCost::WARM_ACCESS.as_u64()
+    }
+}
+
+/// Number of memory words needed to access `length` bytes at `offset`.
+fn memory_word_size(offset: Word, length: Word) -> u64 {
+    if length.is_zero() {
+        0
+    } else {
+        (offset.low_u64() + length.low_u64() + 31) / 32
+    }
+}
+
+/// Recompute the gas cost of `geth_step`, or `None` if it depends on state
+/// not found in `ops`.
+fn step_gas_cost(
+    tx: &Transaction,
+    exec_step: &ExecStep,
+    geth_step: &GethExecStep,
+    ops: &[OpEnum],
+) -> Option<u64> {
+    let stack = |nth: usize| geth_step.stack.nth_last(nth).ok();
+    let curr_memory_word_size = geth_step.memory.word_size() as u64;
+    let memory_expansion = |next_memory_word_sizes: &[u64]| {
+        let next_memory_word_size = next_memory_word_sizes
+            .iter()
+            .fold(curr_memory_word_size, |size, next| size.max(*next));
+        memory_expansion_gas_cost(curr_memory_word_size, next_memory_word_size)
+    };
+    let memory_copy = |offset: Word, length: Word| {
+        let next_memory_word_size = curr_memory_word_size.max(memory_word_size(offset, length));
+        memory_copier_gas_cost(
+            curr_memory_word_size,
+            next_memory_word_size,
+            length.low_u64(),
+        )
+    };
+    let words = |length: Word| (length.low_u64() + 31) / 32;
+
+    let op = geth_step.op;
+    let dynamic_cost = match op {
+        OpcodeId::MLOAD | OpcodeId::MSTORE => {
+            memory_expansion(&[memory_word_size(stack(0)?, 32.into())])
+        }
+        OpcodeId::MSTORE8 => memory_expansion(&[memory_word_size(stack(0)?, 1.into())]),
+        OpcodeId::SHA3 => {
+            6 * words(stack(1)?) + memory_expansion(&[memory_word_size(stack(0)?, stack(1)?)])
+        }
+        OpcodeId::CALLDATACOPY | OpcodeId::CODECOPY | OpcodeId::RETURNDATACOPY => {
+            memory_copy(stack(0)?, stack(2)?)
+        }
+        OpcodeId::MCOPY => {
+            let next_memory_word_size = curr_memory_word_size
+                .max(memory_word_size(stack(0)?, stack(2)?))
+                .max(memory_word_size(stack(1)?, stack(2)?));
+            memory_copier_gas_cost(
+                curr_memory_word_size,
+                next_memory_word_size,
+                stack(2)?.low_u64(),
+            )
+        }
+        OpcodeId::EXTCODECOPY => {
+            account_access_cost(is_account_warm(ops, stack(0)?.to_address())?)
+                + memory_copy(stack(1)?, stack(3)?)
+        }
+        OpcodeId::BALANCE | OpcodeId::EXTCODESIZE | OpcodeId::EXTCODEHASH => {
+            account_access_cost(is_account_warm(ops, stack(0)?.to_address())?)
+        }
+        OpcodeId::EXP => {
+            let exponent_bytes = (stack(1)?.bits() as u64 + 7) / 8;
+            50 * exponent_bytes
+        }
+        OpcodeId::LOG0 | OpcodeId::LOG1 | OpcodeId::LOG2 | OpcodeId::LOG3 | OpcodeId::LOG4 => {
+            let topics = op.as_u64() - OpcodeId::LOG0.as_u64();
+            GasCost::LOG.as_u64() * (1 + topics)
+                + 8 * stack(1)?.low_u64()
+                + memory_expansion(&[memory_word_size(stack(0)?, stack(1)?)])
+        }
+        OpcodeId::RETURN | OpcodeId::REVERT => {
+            memory_expansion(&[memory_word_size(stack(0)?, stack(1)?)])
+        }
+        OpcodeId::CREATE => memory_expansion(&[memory_word_size(stack(1)?, stack(2)?)]),
+        OpcodeId::CREATE2 => {
+            6 * words(stack(2)?) + memory_expansion(&[memory_word_size(stack(1)?, stack(2)?)])
+        }
+        OpcodeId::SLOAD => {
+            let is_warm = ops.iter().find_map(|op| match op {
+                OpEnum::TxAccessListAccountStorage(op) => Some(op.is_warm_prev),
+                _ => None,
+            })?;
+            if is_warm {
+                GasCost::WARM_ACCESS.as_u64()
+            } else {
+                GasCost::COLD_SLOAD.as_u64()
+            }
+        }
+        OpcodeId::SSTORE => {
+            let is_warm = ops.iter().find_map(|op| match op {
+                OpEnum::TxAccessListAccountStorage(op) => Some(op.is_warm_prev),
+                _ => None,
+            })?;
+            let op = ops.iter().find_map(|op| match op {
+                OpEnum::Storage(op) => Some(op),
+                _ => None,
+            })?;
+            let (original, current, value) = (op.committed_value, op.value_prev, op.value);
+            let cold_cost = if is_warm {
+                0
+            } else {
+                GasCost::COLD_SLOAD.as_u64()
+            };
+            cold_cost
+                + if value == current || original != current {
+                    GasCost::WARM_ACCESS.as_u64()
+                } else if original.is_zero() {
+                    GasCost::SSTORE_SET.as_u64()
+                } else {
+                    GasCost::SSTORE_RESET.as_u64()
+                }
+        }
+        OpcodeId::CALL | OpcodeId::CALLCODE | OpcodeId::DELEGATECALL | OpcodeId::STATICCALL => {
+            let address = stack(1)?.to_address();
+            let has_value_arg = matches!(op, OpcodeId::CALL | OpcodeId::CALLCODE);
+            let value = if has_value_arg {
+                stack(2)?
+            } else {
+                Word::zero()
+            };
+            let args_offset = if has_value_arg { 3 } else { 2 };
+            let new_account_cost = if op == OpcodeId::CALL && !value.is_zero() {
+                if is_account_empty(ops, address)? {
+                    GasCost::NEW_ACCOUNT.as_u64()
+                } else {
+                    0
+                }
+            } else {
+                0
+            };
+            let cost = op.constant_gas_cost().as_u64()
+                + account_access_cost(is_account_warm(ops, address)?)
+                + if value.is_zero() {
+                    0
+                } else {
+                    GasCost::CALL_WITH_VALUE.as_u64()
+                }
+                + new_account_cost
+                + memory_expansion(&[
+                    memory_word_size(stack(args_offset)?, stack(args_offset + 1)?),
+                    memory_word_size(stack(args_offset + 2)?, stack(args_offset + 3)?),
+                ]);
+            // The gas given to the callee is part of the cost.
+            let callee_gas = eip150_gas(geth_step.gas.0.checked_sub(cost)?, stack(0)?);
+            return Some(cost + callee_gas);
+        }
+        OpcodeId::SELFDESTRUCT => {
+            let beneficiary = stack(0)?.to_address();
+            let address = tx.calls()[exec_step.call_index].address;
+            let balance = |address: Address| {
+                ops.iter().find_map(|op| match op {
+                    OpEnum::Account(op)
+                        if op.address == address && op.field == AccountField::Balance =>
+                    {
+                        Some(op.value_prev)
+                    }
+                    _ => None,
+                })
+            };
+            // The emptiness of the beneficiary is only known from its balance.
+            let new_account_cost = if beneficiary != address
+                && !balance(address)?.is_zero()
+                && balance(beneficiary)?.is_zero()
+            {
+                return None;
+            } else {
+                0
+            };
+            new_account_cost
+                + if is_account_warm(ops, beneficiary)? {
+                    0
+                } else {
+                    GasCost::COLD_ACCOUNT_ACCESS.as_u64()
+                }
+        }
+        _ => 0,
+    };
+
+    Some(op.constant_gas_cost().as_u64() + dynamic_cost)
+}
+
+/// Recompute the change of the gas refund counter done by `geth_step`, or
+/// `None` if it doesn't change it.
+fn step_refund(
+    gas_schedule: &GasSchedule,
+    geth_step: &GethExecStep,
+    ops: &[OpEnum],
+) -> Option<i64> {
+    if geth_step.op != OpcodeId::SSTORE {
+        return None;
+    }
+    let op = ops.iter().find_map(|op| match op {
+        OpEnum::Storage(op) => Some(op),
+        _ => None,
+    })?;
+    let (original, current, value) = (op.committed_value, op.value_prev, op.value);
+    let clears_schedule = gas_schedule.sstore_clears_schedule as i64;
+
+    // EIP-2200 with the costs of EIP-2929, and the refunds of EIP-3529 from
+    // London
+    let mut refund = 0;
+    if current == value {
+        return Some(refund);
+    }
+    if original == current {
+        if !original.is_zero() && value.is_zero() {
+            refund += clears_schedule;
+        }
+        return Some(refund);
+    }
+    if !original.is_zero() {
+        if current.is_zero() {
+            refund -= clears_schedule;
+        } else if value.is_zero() {
+            refund += clears_schedule;
+        }
+    }
+    if original == value {
+        refund += if original.is_zero() {
+            GasCost::SSTORE_SET.as_u64() - GasCost::WARM_ACCESS.as_u64()
+        } else {
+            GasCost::SSTORE_RESET.as_u64() - GasCost::WARM_ACCESS.as_u64()
+        } as i64;
+    }
+    Some(refund)
+}
+
+#[cfg(test)]
+mod gas_checker_tests {
+    use super::*;
+    use crate::mock::BlockData;
+    use eth_types::{bytecode, geth_types::GethData, Bytecode, ToWord};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    fn check(code: Bytecode) -> Vec<GasMismatch> {
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        check_block_gas(&builder.block, &block.geth_traces)
+    }
+
+    #[test]
+    fn check_block_gas_memory() {
+        let code = bytecode! {
+            PUSH1(0x20)
+            PUSH1(0x40)
+            MSTORE
+            PUSH1(0x40) // size
+            PUSH1(0x10) // offset
+            SHA3
+            PUSH1(0x20) // size
+            PUSH1(0x00) // offset
+            PUSH1(0x80) // dest_offset
+            CODECOPY
+            PUSH1(0x03)
+            PUSH1(0x1ff)
+            EXP
+            STOP
+        };
+        assert_eq!(check(code), vec![]);
+    }
+
+    #[test]
+    fn check_block_gas_storage() {
+        // Set, reset, clear and restore a slot, which exercises the refund
+        // rules.
+        let code = bytecode! {
+            PUSH1(0x01)
+            PUSH1(0x00)
+            SSTORE
+            PUSH1(0x02)
+            PUSH1(0x00)
+            SSTORE
+            PUSH1(0x00)
+            PUSH1(0x00)
+            SSTORE
+            PUSH1(0x00)
+            SLOAD
+            PUSH1(0x01)
+            SLOAD
+            STOP
+        };
+        assert_eq!(check(code), vec![]);
+    }
+
+    #[test]
+    fn check_block_gas_call() {
+        // Call the sender of the tx, which has no code, with some value.
+        let code = bytecode! {
+            PUSH1(0x20) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x20) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x01) // value
+            PUSH32(mock::MOCK_ACCOUNTS[1].to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            ADDRESS
+            BALANCE
+            PUSH32(mock::MOCK_ACCOUNTS[1].to_word())
+            EXTCODESIZE
+            STOP
+        };
+        assert_eq!(check(code), vec![]);
+    }
+
+    #[test]
+    fn check_block_gas_mismatch() {
+        let code = bytecode! {
+            PUSH1(0x01)
+            PUSH1(0x00)
+            MSTORE
+            STOP
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        // Geth reports a cost without the memory expansion.
+        let mut geth_traces = block.geth_traces.clone();
+        let step = &mut geth_traces[0].struct_logs[2];
+        assert_eq!(step.op, OpcodeId::MSTORE);
+        step.gas_cost = GasCost::FASTEST;
+
+        assert_eq!(
+            check_block_gas(&builder.block, &geth_traces),
+            vec![GasMismatch::StepCost {
+                tx_index: 0,
+                step_index: 2,
+                op: OpcodeId::MSTORE,
+                pc: ProgramCounter(4),
+                expected: GasCost::FASTEST.as_u64(),
+                // Expansion to one word of memory
+                found: GasCost::FASTEST.as_u64() + 3,
+            }]
+        );
+
+        // A trace with a missing step can't be checked step by step.
+        let mut geth_traces = block.geth_traces.clone();
+        geth_traces[0].struct_logs.pop();
+        assert_eq!(
+            check_block_gas(&builder.block, &geth_traces),
+            vec![GasMismatch::StepCount {
+                tx_index: 0,
+                expected: 3,
+                found: 4,
+            }]
+        );
+    }
+}
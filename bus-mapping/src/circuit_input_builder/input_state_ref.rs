@@ -5,7 +5,7 @@ use super::{
     CallKind, CodeSource, ExecState, ExecStep, Transaction, TransactionContext,
 };
 use crate::{
-    error::{get_step_reported_error, ExecError},
+    error::{get_step_reported_error, ExecError, TraceError, UnexpectedStepError},
     exec_trace::OperationRef,
     operation::{
         AccountField, AccountOp, CallContextField, CallContextOp, MemoryOp, Op, OpEnum, Operation,
@@ -16,7 +16,7 @@ use crate::{
 };
 use eth_types::{
     evm_types::{Gas, MemoryAddress, OpcodeId, StackAddress},
-    Address, GethExecStep, ToAddress, ToBigEndian, Word, H256,
+    Address, GethExecStep, ToAddress, ToBigEndian, ToWord, Word, H256,
 };
 use ethers_core::utils::{get_contract_address, get_create2_address};
 
@@ -45,7 +45,7 @@ impl<'a> CircuitInputStateRef<'a> {
         let pre_log_id = if self.tx.is_steps_empty() {
             0
         } else {
-            self.tx.last_step().log_id
+            self.tx.last_step()?.log_id
         };
 
         Ok(ExecStep::new(
@@ -68,15 +68,16 @@ impl<'a> CircuitInputStateRef<'a> {
     }
 
     /// Create a new EndTx step
-    pub fn new_end_tx_step(&self) -> ExecStep {
-        let prev_step = self
-            .tx
-            .steps()
-            .last()
-            .expect("steps should have at least one BeginTx step");
-        ExecStep {
+    pub fn new_end_tx_step(&self) -> Result<ExecStep, Error> {
+        let prev_step = self.tx.last_step()?;
+        let gas_left = prev_step
+            .gas_left
+            .0
+            .checked_sub(prev_step.gas_cost.0)
+            .ok_or(Error::InvalidGethExecTrace(TraceError::GasUnderflow))?;
+        Ok(ExecStep {
             exec_state: ExecState::EndTx,
-            gas_left: Gas(prev_step.gas_left.0 - prev_step.gas_cost.0),
+            gas_left: Gas(gas_left),
             rwc: self.block_ctx.rwc,
             // For tx without code execution
             reversible_write_counter: if let Some(call_ctx) = self.tx_ctx.calls().last() {
@@ -86,7 +87,7 @@ impl<'a> CircuitInputStateRef<'a> {
             },
             log_id: prev_step.log_id,
             ..Default::default()
-        }
+        })
     }
 
     /// Push an [`Operation`](crate::operation::Operation) into the
@@ -182,7 +183,9 @@ impl<'a> CircuitInputStateRef<'a> {
             self.tx_ctx
                 .reversion_groups
                 .last_mut()
-                .expect("reversion_groups should not be empty for non-persistent call")
+                .ok_or(Error::InvalidGethExecTrace(
+                    TraceError::MissingReversionGroup,
+                ))?
                 .op_refs
                 .push((self.tx.steps().len(), op_ref));
         }
@@ -396,7 +399,7 @@ impl<'a> CircuitInputStateRef<'a> {
 
     /// Push a new [`Call`] into the [`Transaction`], and add its index and
     /// [`CallContext`] in the `call_stack` of the [`TransactionContext`]
-    pub fn push_call(&mut self, call: Call, step: &GethExecStep) {
+    pub fn push_call(&mut self, call: Call, step: &GethExecStep) -> Result<(), Error> {
         let call_data = match call.kind {
             CallKind::Call | CallKind::CallCode | CallKind::DelegateCall | CallKind::StaticCall => {
                 step.memory
@@ -408,12 +411,16 @@ impl<'a> CircuitInputStateRef<'a> {
         let call_id = call.call_id;
         let call_idx = self.tx.calls().len();
 
-        self.tx_ctx.push_call_ctx(call_idx, call_data);
+        self.tx_ctx.push_call_ctx(call_idx, call_data)?;
         self.tx.push_call(call);
+        if let Ok(call_ctx) = self.tx_ctx.call_ctx_mut() {
+            call_ctx.snapshot = self.sdb.snapshot();
+        }
 
         self.block_ctx
             .call_map
             .insert(call_id, (self.block.txs.len(), call_idx));
+        Ok(())
     }
 
     /// Return the contract address of a CREATE step.  This is calculated by
@@ -435,23 +442,30 @@ impl<'a> CircuitInputStateRef<'a> {
         Ok(get_create2_address(
             self.call()?.address,
             salt.to_be_bytes().to_vec(),
-            init_code.to_vec(),
+            init_code,
         ))
     }
 
-    /// Check if address is a precompiled or not.
+    /// Check if address is a precompiled contract deployed in the hardfork of
+    /// the block or not.
     pub fn is_precompiled(&self, address: &Address) -> bool {
-        address.0[0..19] == [0u8; 19] && (1..=9).contains(&address.0[19])
+        self.block
+            .chain_config
+            .precompile_addresses()
+            .contains(address)
     }
 
-    // TODO: Remove unwrap() and add err handling.
     /// Parse [`Call`] from a *CALL*/CREATE* step.
     pub fn parse_call(&mut self, step: &GethExecStep) -> Result<Call, Error> {
-        let is_success = *self
-            .tx_ctx
-            .call_is_success
-            .get(self.tx.calls().len())
-            .unwrap();
+        let call_index = self.tx.calls().len();
+        let is_success =
+            *self
+                .tx_ctx
+                .call_is_success
+                .get(call_index)
+                .ok_or(Error::InvalidGethExecTrace(TraceError::MissingCallResult(
+                    call_index,
+                )))?;
         let kind = CallKind::try_from(step.op)?;
         let caller = self.call()?;
 
@@ -462,7 +476,7 @@ impl<'a> CircuitInputStateRef<'a> {
                 step.stack.nth_last(2)?,
             ),
             CallKind::CallCode => (caller.address, caller.address, step.stack.nth_last(2)?),
-            CallKind::DelegateCall => (caller.caller_address, caller.address, Word::zero()),
+            CallKind::DelegateCall => (caller.caller_address, caller.address, caller.value),
             CallKind::StaticCall => (
                 caller.address,
                 step.stack.nth_last(1)?.to_address(),
@@ -479,7 +493,19 @@ impl<'a> CircuitInputStateRef<'a> {
         let (code_source, code_hash) = match kind {
             CallKind::Create | CallKind::Create2 => {
                 let init_code = get_create_init_code(step)?;
-                let code_hash = self.code_db.insert(init_code.to_vec());
+                let code_hash = self.code_db.insert(init_code.clone());
+                self.block.keccak_inputs.push(init_code);
+                if kind == CallKind::Create2 {
+                    // 0xff ++ caller_address ++ salt ++ keccak256(init_code)
+                    let salt = step.stack.nth_last(3)?;
+                    self.block.keccak_inputs.push(
+                        std::iter::once(0xff)
+                            .chain(caller_address.to_fixed_bytes())
+                            .chain(salt.to_be_bytes())
+                            .chain(code_hash.to_fixed_bytes())
+                            .collect(),
+                    );
+                }
                 (CodeSource::Memory, code_hash)
             }
             _ => {
@@ -489,8 +515,13 @@ impl<'a> CircuitInputStateRef<'a> {
                     }
                     _ => address,
                 };
+                // CALL and STATICCALL may target an account which doesn't
+                // exist, which is empty, and so do precompiled contracts.
                 let (found, account) = self.sdb.get_account(&code_address);
-                if !found {
+                if !found
+                    && !matches!(kind, CallKind::Call | CallKind::StaticCall)
+                    && !self.is_precompiled(&code_address)
+                {
                     return Err(Error::AccountNotFound(code_address));
                 }
                 (CodeSource::Address(code_address), account.code_hash)
@@ -589,6 +620,14 @@ impl<'a> CircuitInputStateRef<'a> {
                     None
                 }
             }
+            OperationRef(Target::TransientStorage, idx) => {
+                let operation = &self.block.container.transient_storage[*idx];
+                if operation.rw().is_write() && operation.reversible() {
+                    Some(OpEnum::TransientStorage(operation.op().reverse()))
+                } else {
+                    None
+                }
+            }
             _ => None,
         }
     }
@@ -599,6 +638,10 @@ impl<'a> CircuitInputStateRef<'a> {
             OpEnum::Storage(op) => {
                 self.sdb.set_storage(&op.address, &op.key, &op.value);
             }
+            OpEnum::TransientStorage(op) => {
+                self.sdb
+                    .set_transient_storage(&op.address, &op.key, &op.value);
+            }
             OpEnum::TxAccessListAccount(op) => {
                 if !op.is_warm_prev && op.is_warm {
                     self.sdb.add_account_to_access_list(op.address);
@@ -630,23 +673,32 @@ impl<'a> CircuitInputStateRef<'a> {
             OpEnum::TxRefund(op) => {
                 self.sdb.set_refund(op.value);
             }
-            OpEnum::AccountDestructed(_) => unimplemented!(),
+            OpEnum::AccountDestructed(op) => {
+                if !op.is_destructed_prev && op.is_destructed {
+                    self.sdb.destruct_account(op.address);
+                }
+                if op.is_destructed_prev && !op.is_destructed {
+                    self.sdb.undestruct_account(&op.address);
+                }
+            }
             _ => unreachable!(),
         };
     }
 
     /// Handle a reversion group
-    fn handle_reversion(&mut self) {
-        let reversion_group = self
-            .tx_ctx
-            .reversion_groups
-            .pop()
-            .expect("reversion_groups should not be empty for non-persistent call");
-
-        // Apply reversions
+    fn handle_reversion(&mut self) -> Result<(), Error> {
+        let reversion_group =
+            self.tx_ctx
+                .reversion_groups
+                .pop()
+                .ok_or(Error::InvalidGethExecTrace(
+                    TraceError::MissingReversionGroup,
+                ))?;
+
+        // Record the reversions, whose effect on the state has already been
+        // undone by reverting the StateDB to the snapshot of the call.
         for (step_index, op_ref) in reversion_group.op_refs.iter().rev().copied() {
             if let Some(op) = self.get_rev_op_by_ref(&op_ref) {
-                self.apply_op(&op);
                 let rev_op_ref = self.block.container.insert_op_enum(
                     self.block_ctx.rwc.inc_pre(),
                     RW::WRITE,
@@ -665,6 +717,8 @@ impl<'a> CircuitInputStateRef<'a> {
             self.tx.calls_mut()[call_idx].rw_counter_end_of_reversion =
                 rwc - reversible_write_counter_offset;
         }
+
+        Ok(())
     }
 
     /// Handle a return step caused by any opcode that causes a return to the
@@ -672,27 +726,106 @@ impl<'a> CircuitInputStateRef<'a> {
     pub fn handle_return(&mut self, step: &GethExecStep) -> Result<(), Error> {
         let call = self.call()?.clone();
 
-        // Store deployed code if it's a successful create
-        if call.is_create() && call.is_success {
-            let offset = step.stack.nth_last(0)?;
-            let length = step.stack.nth_last(1)?;
-            let code = step
-                .memory
-                .read_chunk(offset.low_u64().into(), length.low_u64().into());
-            let code_hash = self.code_db.insert(code);
-            let (found, callee_account) = self.sdb.get_account_mut(&call.address);
-            if !found {
-                return Err(Error::AccountNotFound(call.address));
-            }
-            callee_account.code_hash = code_hash;
+        // Handle reversion if this call doens't end successfully: the state is
+        // restored to the snapshot taken when the call started, which also
+        // undoes the changes that aren't backed by a reversible operation.
+        if !self.call()?.is_success {
+            let snapshot = self.call_ctx()?.snapshot;
+            self.sdb.revert_to(snapshot);
+            self.handle_reversion()?;
         }
 
-        // Handle reversion if this call doens't end successfully
-        if !self.call()?.is_success {
-            self.handle_reversion();
+        // Only RETURN and REVERT leave return data to the caller, except a
+        // successful create, whose returned bytes are the deployed code.
+        let has_return_data = step.error.is_none()
+            && match step.op {
+                OpcodeId::RETURN => !call.is_create(),
+                OpcodeId::REVERT => true,
+                _ => false,
+            };
+        let (return_data_offset, return_data_length) = if has_return_data {
+            get_call_memory_offset_length(step, 0)?
+        } else {
+            (0, 0)
+        };
+        let return_data = step
+            .memory
+            .read_chunk(return_data_offset.into(), return_data_length.into());
+        // A call to an account without code ends at the step that made it, in
+        // which case there is no callee to refer to.
+        let callee_id = if call.depth > step.depth as usize {
+            0
+        } else {
+            call.call_id
+        };
+
+        self.tx_ctx.pop_call_ctx()?;
+
+        // Record the return data of the call in its caller, if any.
+        if let Ok(caller_ctx) = self.call_ctx_mut() {
+            caller_ctx.last_callee_id = callee_id;
+            caller_ctx.last_callee_return_data_offset = return_data_offset;
+            caller_ctx.last_callee_return_data_length = return_data_length;
+            caller_ctx.return_data = return_data;
         }
 
-        self.tx_ctx.pop_call_ctx();
+        Ok(())
+    }
+
+    /// Generate the operations that restore the context of the caller of
+    /// `callee` after it halts with an error at the first of the
+    /// `geth_steps`: the caller resumes in the state of the step following it,
+    /// without any return data from `callee`.  It must be invoked after
+    /// [`Self::handle_return`].
+    pub(crate) fn gen_restore_context_ops(
+        &mut self,
+        exec_step: &mut ExecStep,
+        callee: &Call,
+        geth_steps: &[GethExecStep],
+    ) -> Result<(), Error> {
+        let next_step = geth_steps
+            .get(1)
+            .ok_or(Error::InvalidGethExecTrace(TraceError::MissingCallerStep))?;
+        let caller = self.call()?.clone();
+        let reversible_write_counter = self.call_ctx()?.reversible_write_counter;
+
+        self.call_context_read(
+            exec_step,
+            callee.call_id,
+            CallContextField::CallerId,
+            caller.call_id.into(),
+        );
+        for (field, value) in [
+            (CallContextField::IsRoot, (caller.is_root as u64).into()),
+            (
+                CallContextField::IsCreate,
+                (caller.is_create() as u64).into(),
+            ),
+            (CallContextField::CodeSource, caller.code_hash.to_word()),
+            (CallContextField::ProgramCounter, next_step.pc.0.into()),
+            (
+                CallContextField::StackPointer,
+                next_step.stack.stack_pointer().0.into(),
+            ),
+            (CallContextField::GasLeft, next_step.gas.0.into()),
+            (
+                CallContextField::MemorySize,
+                next_step.memory.word_size().into(),
+            ),
+            (
+                CallContextField::StateWriteCounter,
+                reversible_write_counter.into(),
+            ),
+        ] {
+            self.call_context_read(exec_step, caller.call_id, field, value);
+        }
+        for (field, value) in [
+            (CallContextField::LastCalleeId, callee.call_id.into()),
+            (CallContextField::LastCalleeReturnDataOffset, 0.into()),
+            (CallContextField::LastCalleeReturnDataLength, 0.into()),
+        ] {
+            self.call_context_write(exec_step, caller.call_id, field, value);
+        }
 
         Ok(())
     }
@@ -702,12 +835,12 @@ impl<'a> CircuitInputStateRef<'a> {
         step: &GethExecStep,
         next_step: Option<&GethExecStep>,
     ) -> Result<Option<ExecError>, Error> {
-        if let Some(error) = &step.error {
-            return Ok(Some(get_step_reported_error(&step.op, error)));
+        if !self.block.chain_config.is_opcode_valid(step.op) {
+            return Ok(Some(ExecError::InvalidOpcode));
         }
 
-        if matches!(step.op, OpcodeId::INVALID(_)) {
-            return Ok(Some(ExecError::InvalidOpcode));
+        if let Some(error) = &step.error {
+            return get_step_reported_error(&step.op, error).map(Some);
         }
 
         // When last step has opcodes that halt, there's no error.
@@ -736,6 +869,7 @@ impl<'a> CircuitInputStateRef<'a> {
                     OpcodeId::RETURNDATACOPY => Some(ExecError::ReturnDataOutOfBounds),
                     // Break write protection (CALL with value will be handled below)
                     OpcodeId::SSTORE
+                    | OpcodeId::TSTORE
                     | OpcodeId::CREATE
                     | OpcodeId::CREATE2
                     | OpcodeId::SELFDESTRUCT
@@ -751,7 +885,7 @@ impl<'a> CircuitInputStateRef<'a> {
                     OpcodeId::REVERT => None,
                     _ => {
                         return Err(Error::UnexpectedExecStepError(
-                            "call failure without return",
+                            UnexpectedStepError::CallFailureWithoutReturn,
                             step.clone(),
                         ));
                     }
@@ -772,13 +906,13 @@ impl<'a> CircuitInputStateRef<'a> {
                         return Ok(Some(ExecError::CodeStoreOutOfGas));
                     } else {
                         return Err(Error::UnexpectedExecStepError(
-                            "failure in RETURN from {CREATE, CREATE2}",
+                            UnexpectedStepError::CreateReturnFailure,
                             step.clone(),
                         ));
                     }
                 } else {
                     return Err(Error::UnexpectedExecStepError(
-                        "failure in RETURN",
+                        UnexpectedStepError::ReturnFailure,
                         step.clone(),
                     ));
                 }
@@ -788,14 +922,17 @@ impl<'a> CircuitInputStateRef<'a> {
         // Return from a call via RETURN or STOP and having a success result is
         // OK.
 
-        // Return from a call without calling RETURN or STOP and having success
-        // is unexpected.
+        // Return from a call without calling RETURN, STOP or SELFDESTRUCT and
+        // having success is unexpected.
         if step.depth != next_depth
             && next_result != Word::zero()
-            && !matches!(step.op, OpcodeId::RETURN | OpcodeId::STOP)
+            && !matches!(
+                step.op,
+                OpcodeId::RETURN | OpcodeId::STOP | OpcodeId::SELFDESTRUCT
+            )
         {
             return Err(Error::UnexpectedExecStepError(
-                "success result without {RETURN, STOP}",
+                UnexpectedStepError::SuccessWithoutReturn,
                 step.clone(),
             ));
         }
@@ -838,6 +975,18 @@ impl<'a> CircuitInputStateRef<'a> {
                 return Ok(Some(ExecError::InsufficientBalance));
             }
 
+            // A failing precompiled contract has no code to execute.
+            let code_address = match step.op {
+                OpcodeId::CALL
+                | OpcodeId::CALLCODE
+                | OpcodeId::DELEGATECALL
+                | OpcodeId::STATICCALL => Some(step.stack.nth_last(1)?.to_address()),
+                _ => None,
+            };
+            if code_address.map_or(false, |address| self.is_precompiled(&address)) {
+                return Ok(None);
+            }
+
             // Address collision
             if matches!(step.op, OpcodeId::CREATE | OpcodeId::CREATE2) {
                 let address = match step.op {
@@ -852,7 +1001,7 @@ impl<'a> CircuitInputStateRef<'a> {
             }
 
             return Err(Error::UnexpectedExecStepError(
-                "*CALL*/CREATE* code not executed",
+                UnexpectedStepError::CallCodeNotExecuted,
                 step.clone(),
             ));
         }
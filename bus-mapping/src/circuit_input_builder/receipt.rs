@@ -0,0 +1,426 @@
+//! Receipts of the transactions of a [`Block`], rebuilt from their receipt and
+//! log operations, and check of their logs bloom and trie root against the
+//! block header.
+
+use super::{Block, EthBlock};
+use crate::{
+    mpt::{keccak, rlp_bytes, rlp_list, rlp_word, PartialTrie, EMPTY_TRIE_ROOT},
+    operation::{TxLogField, TxReceiptField},
+};
+use eth_types::{Address, ToAddress, ToBigEndian, Word, H256};
+use ethers_core::types::Bloom;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Log emitted by a transaction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Log {
+    /// Address of the contract which emitted the log
+    pub address: Address,
+    /// Topics of the log
+    pub topics: Vec<H256>,
+    /// Data of the log
+    pub data: Vec<u8>,
+}
+
+impl Log {
+    /// RLP encode the log.
+    fn encode(&self) -> Vec<u8> {
+        let topics: Vec<Vec<u8>> = self
+            .topics
+            .iter()
+            .map(|topic| rlp_bytes(topic.as_bytes()))
+            .collect();
+        rlp_list(&[
+            rlp_bytes(self.address.as_bytes()),
+            rlp_list(&topics),
+            rlp_bytes(&self.data),
+        ])
+    }
+}
+
+/// Receipt of a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    /// Type of the transaction, 0 for legacy transactions
+    pub tx_type: u64,
+    /// Status: 1 if the transaction succeeded, 0 otherwise
+    pub status: u64,
+    /// Gas used in the block up to and including the transaction
+    pub cumulative_gas_used: u64,
+    /// Bloom filter of the addresses and topics of the logs
+    pub logs_bloom: Bloom,
+    /// Logs emitted by the transaction
+    pub logs: Vec<Log>,
+}
+
+impl Receipt {
+    /// Encode the receipt as stored in the receipts trie: the RLP encoding,
+    /// prefixed by the transaction type for typed transactions (EIP-2718).
+    pub fn encode(&self) -> Vec<u8> {
+        let logs: Vec<Vec<u8>> = self.logs.iter().map(Log::encode).collect();
+        let receipt = rlp_list(&[
+            rlp_word(Word::from(self.status)),
+            rlp_word(Word::from(self.cumulative_gas_used)),
+            rlp_bytes(&self.logs_bloom.0),
+            rlp_list(&logs),
+        ]);
+        if self.tx_type == 0 {
+            receipt
+        } else {
+            [vec![self.tx_type as u8], receipt].concat()
+        }
+    }
+}
+
+/// Add `input` to `bloom`, setting the 3 bits selected by its hash.
+fn accrue_bloom(bloom: &mut Bloom, input: &[u8]) {
+    let hash = keccak(input);
+    let hash = hash.as_bytes();
+    for i in 0..3 {
+        let bit = (((hash[2 * i] as usize) << 8) | hash[2 * i + 1] as usize) & 2047;
+        bloom.0[255 - bit / 8] |= 1 << (bit % 8);
+    }
+}
+
+/// Return the bloom filter of the addresses and topics of `logs`.
+fn logs_bloom_of(logs: &[Log]) -> Bloom {
+    let mut bloom = Bloom::zero();
+    for log in logs {
+        accrue_bloom(&mut bloom, log.address.as_bytes());
+        for topic in &log.topics {
+            accrue_bloom(&mut bloom, topic.as_bytes());
+        }
+    }
+    bloom
+}
+
+/// Rebuild the logs of every transaction from the log operations of `block`,
+/// indexed by transaction id and in emission order.
+fn tx_logs(block: &Block) -> HashMap<usize, Vec<Log>> {
+    let mut logs: HashMap<usize, BTreeMap<usize, Log>> = HashMap::new();
+    for operation in &block.container.tx_log {
+        let op = operation.op();
+        let log = logs
+            .entry(op.tx_id)
+            .or_default()
+            .entry(op.log_id)
+            .or_default();
+        match op.field {
+            TxLogField::Address => log.address = op.value.to_address(),
+            TxLogField::Topic => {
+                if log.topics.len() <= op.index {
+                    log.topics.resize(op.index + 1, H256::zero());
+                }
+                log.topics[op.index] = H256::from(op.value.to_be_bytes());
+            }
+            TxLogField::Data => {
+                if log.data.len() <= op.index {
+                    log.data.resize(op.index + 1, 0);
+                }
+                log.data[op.index] = op.value.low_u32() as u8;
+            }
+        }
+    }
+    logs.into_iter()
+        .map(|(tx_id, logs)| (tx_id, logs.into_values().collect()))
+        .collect()
+}
+
+/// Rebuild the receipts of the transactions of `block` which belong to
+/// `eth_block`, from their receipt and log operations.  The type of each
+/// transaction is taken from `eth_block`.
+pub fn block_receipts(block: &Block, eth_block: &EthBlock) -> Vec<Receipt> {
+    let mut fields: BTreeMap<(usize, TxReceiptField), u64> = BTreeMap::new();
+    for operation in &block.container.tx_receipt {
+        let op = operation.op();
+        fields.insert((op.tx_id, op.field.clone()), op.value);
+    }
+    let mut logs = tx_logs(block);
+    let block_number = Word::from(eth_block.number.unwrap_or_default().as_u64());
+
+    block
+        .txs()
+        .iter()
+        .enumerate()
+        .filter(|(_, tx)| tx.block_number == block_number)
+        .zip(&eth_block.transactions)
+        .map(|((index, _), eth_tx)| {
+            let tx_id = index + 1;
+            let field = |field| fields.get(&(tx_id, field)).copied().unwrap_or_default();
+            let logs = logs.remove(&tx_id).unwrap_or_default();
+            Receipt {
+                tx_type: eth_tx.transaction_type.unwrap_or_default().as_u64(),
+                status: field(TxReceiptField::PostStateOrStatus),
+                cumulative_gas_used: field(TxReceiptField::CumulativeGasUsed),
+                logs_bloom: logs_bloom_of(&logs),
+                logs,
+            }
+        })
+        .collect()
+}
+
+/// Return the bloom filter of a block, the union of the ones of `receipts`.
+pub fn logs_bloom(receipts: &[Receipt]) -> Bloom {
+    let mut bloom = Bloom::zero();
+    for receipt in receipts {
+        for (byte, receipt_byte) in bloom.0.iter_mut().zip(receipt.logs_bloom.0) {
+            *byte |= receipt_byte;
+        }
+    }
+    bloom
+}
+
+/// Return the root of the trie of `receipts`, keyed by the RLP encoding of
+/// their index in the block.
+pub fn receipts_root(receipts: &[Receipt]) -> H256 {
+    let mut trie = PartialTrie::new_unsecured(*EMPTY_TRIE_ROOT, []);
+    for (index, receipt) in receipts.iter().enumerate() {
+        trie.insert(&rlp_word(Word::from(index)), receipt.encode())
+            .expect("all the nodes of a trie built from scratch are known");
+    }
+    trie.root()
+}
+
+/// Mismatch found between the receipts rebuilt from a [`Block`] and the header
+/// of the block they belong to.  `expected` is the value in the header, and
+/// `found` the one computed from the receipts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReceiptMismatch {
+    /// Bloom filter of the logs of the block
+    LogsBloom {
+        /// Logs bloom in the header
+        expected: Bloom,
+        /// Logs bloom of the receipts
+        found: Bloom,
+    },
+    /// Root of the receipts trie
+    ReceiptsRoot {
+        /// Receipts root in the header
+        expected: H256,
+        /// Root of the trie of the receipts
+        found: H256,
+    },
+}
+
+impl fmt::Display for ReceiptMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiptMismatch::LogsBloom { expected, found } => {
+                write!(f, "logs bloom {:?} != {:?}", found, expected)
+            }
+            ReceiptMismatch::ReceiptsRoot { expected, found } => {
+                write!(f, "receipts root {:?} != {:?}", found, expected)
+            }
+        }
+    }
+}
+
+/// Rebuild the receipts of the transactions of `block` which belong to
+/// `eth_block`, and compare their logs bloom and trie root with the ones in
+/// the header of `eth_block`.  Returns all the mismatches found.
+pub fn check_block_receipts(block: &Block, eth_block: &EthBlock) -> Vec<ReceiptMismatch> {
+    let receipts = block_receipts(block, eth_block);
+    let mut mismatches = Vec::new();
+
+    let expected = eth_block.logs_bloom.unwrap_or_default();
+    let found = logs_bloom(&receipts);
+    if found != expected {
+        mismatches.push(ReceiptMismatch::LogsBloom { expected, found });
+    }
+
+    let expected = eth_block.receipts_root;
+    let found = receipts_root(&receipts);
+    if found != expected {
+        mismatches.push(ReceiptMismatch::ReceiptsRoot { expected, found });
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod receipt_tests {
+    use super::*;
+    use crate::mock::BlockData;
+    use crate::mpt::{hex_prefix_encode, to_nibbles};
+    use eth_types::{bytecode, geth_types::GethData, Bytecode};
+    use mock::{
+        test_ctx::{helpers::*, TestContext},
+        MOCK_ACCOUNTS,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn build(code: Bytecode) -> (Block, GethData) {
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        (builder.block, block)
+    }
+
+    #[test]
+    fn receipts_logs() {
+        let (block, geth_data) = build(bytecode! {
+            PUSH32(Word::from(0x1234)) // value
+            PUSH1(0x00) // offset
+            MSTORE
+            PUSH1(0xef) // topic 1
+            PUSH1(0xa0) // topic 0
+            PUSH1(0x02) // size
+            PUSH1(0x1e) // offset
+            LOG2
+            STOP
+        });
+        let receipts = block_receipts(&block, &geth_data.eth_block);
+        assert_eq!(receipts.len(), 1);
+        let receipt = &receipts[0];
+        assert_eq!(receipt.status, 1);
+        assert_eq!(receipt.cumulative_gas_used, geth_data.geth_traces[0].gas.0);
+
+        let log = Log {
+            address: MOCK_ACCOUNTS[0],
+            topics: vec![H256::from_low_u64_be(0xa0), H256::from_low_u64_be(0xef)],
+            data: vec![0x12, 0x34],
+        };
+        assert_eq!(receipt.logs, vec![log.clone()]);
+
+        // Every address and topic sets 3 bits of the bloom.
+        let bits = |bloom: &Bloom| bloom.0.iter().map(|byte| byte.count_ones()).sum::<u32>();
+        assert!(bits(&receipt.logs_bloom) > 3 && bits(&receipt.logs_bloom) <= 9);
+        let mut bloom = Bloom::zero();
+        accrue_bloom(&mut bloom, log.address.as_bytes());
+        assert!(bloom
+            .0
+            .iter()
+            .zip(receipt.logs_bloom.0)
+            .all(|(a, b)| a & b == *a));
+        assert_eq!(logs_bloom(&receipts), receipt.logs_bloom);
+
+        // A single receipt is a leaf at the path of the index 0.
+        let leaf = rlp_list(&[
+            rlp_bytes(&hex_prefix_encode(
+                &to_nibbles(&rlp_word(Word::zero())),
+                true,
+            )),
+            rlp_bytes(&receipt.encode()),
+        ]);
+        assert_eq!(receipts_root(&receipts), keccak(&leaf));
+    }
+
+    #[test]
+    fn check_block_receipts_header() {
+        let (block, geth_data) = build(bytecode! {
+            PUSH1(0xa0) // topic
+            PUSH1(0x00) // size
+            PUSH1(0x00) // offset
+            LOG1
+            STOP
+        });
+        let mut eth_block = geth_data.eth_block;
+        let receipts = block_receipts(&block, &eth_block);
+        let root = receipts_root(&receipts);
+        let bloom = logs_bloom(&receipts);
+        // The mock header has a zero receipts root and no bloom.
+        assert_eq!(
+            check_block_receipts(&block, &eth_block),
+            vec![
+                ReceiptMismatch::LogsBloom {
+                    expected: Bloom::zero(),
+                    found: bloom,
+                },
+                ReceiptMismatch::ReceiptsRoot {
+                    expected: H256::zero(),
+                    found: root,
+                },
+            ]
+        );
+
+        eth_block.logs_bloom = Some(bloom);
+        eth_block.receipts_root = root;
+        assert_eq!(check_block_receipts(&block, &eth_block), vec![]);
+    }
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex::decode(hex).unwrap())
+    }
+
+    #[test]
+    fn receipts_root_single_transfer() {
+        // The receipts root of the mainnet blocks whose only transaction is a
+        // successful call of 21000 gas without logs, such as a plain transfer.
+        let (block, geth_data) = build(bytecode! { STOP });
+        let mut eth_block = geth_data.eth_block;
+        eth_block.logs_bloom = Some(Bloom::zero());
+        eth_block.receipts_root =
+            h256("056b23fbba480696b65fe5a59b8f2148a1299103c4f57df839233af2cf4ca2d2");
+        assert_eq!(check_block_receipts(&block, &eth_block), vec![]);
+    }
+
+    #[test]
+    fn receipts_known_answer() {
+        // An ERC-20 Transfer of 1000000 from 0x..cafe111 to 0x..cafe222 emitted
+        // by 0xdac17f958d2ee523a2206206994597c13d831ec7 in a dynamic fee
+        // transaction, between two legacy ones, the last of which fails.  The
+        // expected values were derived from the specifications, independently
+        // of this module.
+        let log = Log {
+            address: Address::from_slice(
+                &hex::decode("dac17f958d2ee523a2206206994597c13d831ec7").unwrap(),
+            ),
+            topics: vec![
+                // keccak256("Transfer(address,address,uint256)")
+                h256("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"),
+                H256::from_low_u64_be(0xcafe111),
+                H256::from_low_u64_be(0xcafe222),
+            ],
+            data: Word::from(1000000).to_be_bytes().to_vec(),
+        };
+        let receipt = |tx_type, status, cumulative_gas_used, logs: Vec<Log>| Receipt {
+            tx_type,
+            status,
+            cumulative_gas_used,
+            logs_bloom: logs_bloom_of(&logs),
+            logs,
+        };
+        let receipts = vec![
+            receipt(0, 1, 21000, vec![]),
+            receipt(2, 1, 72234, vec![log]),
+            receipt(0, 0, 102234, vec![]),
+        ];
+
+        let mut bloom = Bloom::zero();
+        for (index, byte) in [
+            (17, 0x10),
+            (46, 0x01),
+            (58, 0x20),
+            (75, 0x08),
+            (100, 0x08),
+            (123, 0x10),
+            (125, 0x20),
+            (157, 0x10),
+            (171, 0x80),
+            (180, 0x04),
+            (195, 0x02),
+            (218, 0x08),
+        ] {
+            bloom.0[index] = byte;
+        }
+        assert_eq!(receipts[1].logs_bloom, bloom);
+        assert_eq!(logs_bloom(&receipts), bloom);
+
+        // Typed receipts are prefixed by the transaction type (EIP-2718).
+        assert_eq!(receipts[1].encode()[..4], [0x02, 0xf9, 0x01, 0xa7]);
+        assert_eq!(
+            receipts_root(&receipts),
+            h256("d11557178340ca1a7e60065081581c815f51e230b35e3d9d01a5c1fb2f508526")
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use super::*;
 use crate::circuit_input_builder::access::gen_state_access_trace;
-use crate::error::{ExecError, OogError};
+use crate::error::{ExecError, OogError, TraceError};
 use crate::geth_errors::{
     GETH_ERR_GAS_UINT_OVERFLOW, GETH_ERR_OUT_OF_GAS, GETH_ERR_STACK_OVERFLOW,
     GETH_ERR_STACK_UNDERFLOW,
@@ -37,6 +37,7 @@ impl CircuitInputBuilderTx {
             .new_tx(&block.eth_block.transactions[0], true)
             .unwrap();
         let tx_ctx = TransactionContext::new(
+            1,
             &block.eth_block.transactions[0],
             &GethExecTrace {
                 gas: Gas(0),
@@ -51,7 +52,7 @@ impl CircuitInputBuilderTx {
         let prev_log_id = if tx.is_steps_empty() {
             0
         } else {
-            tx.last_step().log_id
+            tx.last_step().unwrap().log_id
         };
 
         Self {
@@ -265,6 +266,70 @@ fn tracer_err_insufficient_balance() {
     );
 }
 
+#[test]
+fn tracer_err_insufficient_balance_precompile() {
+    // code_a calls the identity precompile with more value than it has.
+    let code_a = bytecode! {
+        PUSH1(0x0) // retLength
+        PUSH1(0x0) // retOffset
+        PUSH1(0x0) // argsLength
+        PUSH1(0x0) // argsOffset
+        PUSH32(Word::from(0x1000)) // value
+        PUSH1(0x04) // addr
+        PUSH32(0x1_0000) // gas
+        CALL
+
+        PUSH2(0xaa)
+    };
+
+    // Get the execution steps from the external tracer
+    let block: GethData = TestContext::<2, 1>::new(
+        None,
+        |accs| {
+            accs[0].address(*ADDR_B).code(code_a);
+            accs[1]
+                .address(address!("0x000000000000000000000000000000000cafe002"))
+                .balance(Word::from(1u64 << 30));
+        },
+        |mut txs, accs| {
+            txs[0].to(accs[0].address).from(accs[1].address);
+        },
+        |block, _tx| block.number(0xcafeu64),
+    )
+    .unwrap()
+    .into();
+
+    let (index, step) = block.geth_traces[0]
+        .struct_logs
+        .iter()
+        .enumerate()
+        .find(|(_, s)| s.op == OpcodeId::CALL)
+        .unwrap();
+    let next_step = block.geth_traces[0].struct_logs.get(index + 1);
+    assert_eq!(step.error, None);
+    assert_eq!(next_step.unwrap().stack, Stack(vec![Word::zero()])); // success = 0
+
+    let mut builder = CircuitInputBuilderTx::new(&block, step);
+    assert_eq!(
+        builder.state_ref().get_step_err(step, next_step).unwrap(),
+        Some(ExecError::InsufficientBalance)
+    );
+
+    // The failed call doesn't transfer any value.
+    let mut builder =
+        crate::mock::BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+    builder
+        .handle_block(&block.eth_block, &block.geth_traces)
+        .unwrap();
+    let step = builder.block.txs()[0]
+        .steps()
+        .iter()
+        .find(|step| step.exec_state == ExecState::Op(OpcodeId::CALL))
+        .unwrap();
+    assert_eq!(step.error, Some(ExecError::InsufficientBalance));
+    assert_eq!(builder.sdb.get_account(&*ADDR_B).1.balance, Word::zero());
+}
+
 #[test]
 fn tracer_err_address_collision() {
     // We do CREATE2 twice with the same parameters, with a code_creater
@@ -373,7 +438,10 @@ fn tracer_err_address_collision() {
     let mut builder = CircuitInputBuilderTx::new(&block, step);
     // Set up call context at CREATE2
     builder.tx_ctx.call_is_success.push(false);
-    builder.state_ref().push_call(mock_internal_create(), step);
+    builder
+        .state_ref()
+        .push_call(mock_internal_create(), step)
+        .unwrap();
     // Set up account and contract that exist during the second CREATE2
     builder.builder.sdb.set_account(
         &ADDR_B,
@@ -497,7 +565,10 @@ fn tracer_err_code_store_out_of_gas() {
     let mut builder = CircuitInputBuilderTx::new(&block, step);
     // Set up call context at CREATE
     builder.tx_ctx.call_is_success.push(false);
-    builder.state_ref().push_call(mock_internal_create(), step);
+    builder
+        .state_ref()
+        .push_call(mock_internal_create(), step)
+        .unwrap();
     assert_eq!(
         builder.state_ref().get_step_err(step, next_step).unwrap(),
         Some(ExecError::CodeStoreOutOfGas)
@@ -603,7 +674,10 @@ fn tracer_err_invalid_code() {
     let mut builder = CircuitInputBuilderTx::new(&block, step);
     // Set up call context at RETURN
     builder.tx_ctx.call_is_success.push(false);
-    builder.state_ref().push_call(mock_internal_create(), step);
+    builder
+        .state_ref()
+        .push_call(mock_internal_create(), step)
+        .unwrap();
     assert_eq!(
         builder.state_ref().get_step_err(step, next_step).unwrap(),
         Some(ExecError::InvalidCreationCode)
@@ -707,7 +781,10 @@ fn tracer_err_max_code_size_exceeded() {
     let mut builder = CircuitInputBuilderTx::new(&block, step);
     // Set up call context at RETURN
     builder.tx_ctx.call_is_success.push(false);
-    builder.state_ref().push_call(mock_internal_create(), step);
+    builder
+        .state_ref()
+        .push_call(mock_internal_create(), step)
+        .unwrap();
     assert_eq!(
         builder.state_ref().get_step_err(step, next_step).unwrap(),
         Some(ExecError::MaxCodeSizeExceeded)
@@ -801,7 +878,10 @@ fn tracer_create_stop() {
     let mut builder = CircuitInputBuilderTx::new(&block, step);
     // Set up call context at STOP
     builder.tx_ctx.call_is_success.push(false);
-    builder.state_ref().push_call(mock_internal_create(), step);
+    builder
+        .state_ref()
+        .push_call(mock_internal_create(), step)
+        .unwrap();
     assert_eq!(
         builder.state_ref().get_step_err(step, next_step).unwrap(),
         None
@@ -1248,6 +1328,38 @@ fn tracer_err_invalid_opcode() {
     );
 }
 
+#[test]
+fn tracer_err_invalid_opcode_before_cancun() {
+    // TLOAD is only valid since Cancun, and the builder defaults to London.
+    let code = bytecode! {
+        PUSH1(0x0)
+        TLOAD
+        STOP
+    };
+    let mut block: GethData = TestContext::<2, 1>::new(
+        None,
+        account_0_code_account_1_no_code(code.clone()),
+        tx_from_1_to_0,
+        |block, _tx| block.number(0xcafeu64),
+    )
+    .unwrap()
+    .into();
+    // The geth of geth-utils doesn't know TLOAD, so the trace is made by hand.
+    block.geth_traces[0] =
+        crate::mock::trace_code(&block.eth_block.transactions[0], &code, &[], Word::one());
+
+    let index = 1; // TLOAD
+    let step = &block.geth_traces[0].struct_logs[index];
+    let next_step = block.geth_traces[0].struct_logs.get(index + 1);
+    assert_eq!(step.op, OpcodeId::TLOAD);
+
+    let mut builder = CircuitInputBuilderTx::new(&block, step);
+    assert_eq!(
+        builder.state_ref().get_step_err(step, next_step).unwrap(),
+        Some(ExecError::InvalidOpcode)
+    );
+}
+
 #[test]
 fn tracer_err_write_protection() {
     // code_a calls code_b via static call, which tries to SSTORE and fails.
@@ -1300,29 +1412,135 @@ fn tracer_err_write_protection() {
 
     let mut builder = CircuitInputBuilderTx::new(&block, step);
     builder.tx_ctx.call_is_success.push(false);
-    builder.state_ref().push_call(
-        Call {
-            call_id: 0,
-            caller_id: 0,
-            kind: CallKind::StaticCall,
-            is_static: true,
-            is_root: false,
-            is_persistent: false,
-            is_success: false,
-            rw_counter_end_of_reversion: 0,
-            caller_address: *ADDR_A,
-            address: *ADDR_B,
-            code_source: CodeSource::Address(*ADDR_B),
+    builder
+        .state_ref()
+        .push_call(
+            Call {
+                call_id: 0,
+                caller_id: 0,
+                kind: CallKind::StaticCall,
+                is_static: true,
+                is_root: false,
+                is_persistent: false,
+                is_success: false,
+                rw_counter_end_of_reversion: 0,
+                caller_address: *ADDR_A,
+                address: *ADDR_B,
+                code_source: CodeSource::Address(*ADDR_B),
+                code_hash: Hash::zero(),
+                depth: 2,
+                value: Word::zero(),
+                call_data_offset: 0,
+                call_data_length: 0,
+                return_data_offset: 0,
+                return_data_length: 0,
+            },
+            step,
+        )
+        .unwrap();
+
+    assert_eq!(
+        builder.state_ref().get_step_err(step, next_step).unwrap(),
+        Some(ExecError::WriteProtection)
+    );
+}
+
+#[test]
+fn tracer_err_write_protection_precompile() {
+    // code_a calls code_b via static call, which tries to send value to the
+    // identity precompile and fails.
+    let code_a = bytecode! {
+        PUSH1(0x0) // retLength
+        PUSH1(0x0) // retOffset
+        PUSH1(0x0) // argsLength
+        PUSH1(0x0) // argsOffset
+        PUSH32(*WORD_ADDR_B) // addr
+        PUSH32(0x1_0000) // gas
+        STATICCALL
+
+        PUSH2(0xaa)
+    };
+    let code_b = bytecode! {
+        PUSH1(0x0) // retLength
+        PUSH1(0x0) // retOffset
+        PUSH1(0x0) // argsLength
+        PUSH1(0x0) // argsOffset
+        PUSH1(0x01) // value
+        PUSH1(0x04) // addr
+        PUSH32(0x1_0000) // gas
+        CALL
+
+        PUSH3(0xbb)
+    };
+    // Get the execution steps from the external tracer
+    let block: GethData = TestContext::<3, 1>::new(
+        None,
+        |accs| {
+            accs[0]
+                .address(address!("0x0000000000000000000000000000000000000000"))
+                .code(code_a);
+            accs[1]
+                .address(*ADDR_B)
+                .balance(Word::from(1u64 << 20))
+                .code(code_b);
+            accs[2]
+                .address(address!("0x000000000000000000000000000000000cafe002"))
+                .balance(Word::from(1u64 << 30));
+        },
+        |mut txs, accs| {
+            txs[0].to(accs[0].address).from(accs[2].address);
+        },
+        |block, _tx| block.number(0xcafeu64),
+    )
+    .unwrap()
+    .into();
+
+    let (index, step) = block.geth_traces[0]
+        .struct_logs
+        .iter()
+        .enumerate()
+        .find(|(_, s)| s.op == OpcodeId::CALL)
+        .unwrap();
+    let next_step = block.geth_traces[0].struct_logs.get(index + 1);
+    assert_eq!(step.depth, 2);
+
+    let mut builder = CircuitInputBuilderTx::new(&block, step);
+    builder.builder.sdb.set_account(
+        &ADDR_B,
+        Account {
+            nonce: Word::zero(),
+            balance: Word::from(1u64 << 20),
+            storage: HashMap::new(),
             code_hash: Hash::zero(),
-            depth: 2,
-            value: Word::zero(),
-            call_data_offset: 0,
-            call_data_length: 0,
-            return_data_offset: 0,
-            return_data_length: 0,
         },
-        step,
     );
+    builder.tx_ctx.call_is_success.push(false);
+    builder
+        .state_ref()
+        .push_call(
+            Call {
+                call_id: 0,
+                caller_id: 0,
+                kind: CallKind::StaticCall,
+                is_static: true,
+                is_root: false,
+                is_persistent: false,
+                is_success: false,
+                rw_counter_end_of_reversion: 0,
+                caller_address: *ADDR_A,
+                address: *ADDR_B,
+                code_source: CodeSource::Address(*ADDR_B),
+                code_hash: Hash::zero(),
+                depth: 2,
+                value: Word::zero(),
+                call_data_offset: 0,
+                call_data_length: 0,
+                return_data_offset: 0,
+                return_data_length: 0,
+            },
+            step,
+        )
+        .unwrap();
 
     assert_eq!(
         builder.state_ref().get_step_err(step, next_step).unwrap(),
@@ -1517,7 +1735,8 @@ fn create2_address() {
     builder.tx_ctx.call_is_success.push(false);
     builder
         .state_ref()
-        .push_call(mock_internal_create(), step_create2);
+        .push_call(mock_internal_create(), step_create2)
+        .unwrap();
     let addr = builder.state_ref().create2_address(step_create2).unwrap();
 
     assert_eq!(addr.to_word(), addr_expect);
@@ -1625,7 +1844,8 @@ fn create_address() {
     builder.tx_ctx.call_is_success.push(false);
     builder
         .state_ref()
-        .push_call(mock_internal_create(), step_create);
+        .push_call(mock_internal_create(), step_create)
+        .unwrap();
     builder.builder.sdb.set_account(
         &ADDR_B,
         Account {
@@ -1975,3 +2195,146 @@ fn test_gen_access_trace_create_push_call_stack() {
         }
     )
 }
+
+fn handle_block_err(block: &GethData) -> Error {
+    let mut builder =
+        crate::mock::BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+    builder
+        .handle_block(&block.eth_block, &block.geth_traces)
+        .unwrap_err()
+}
+
+#[test]
+fn tracer_err_context() {
+    let code = bytecode! {
+        PUSH1(0x01)
+        PUSH1(0x02)
+        ADD
+        STOP
+    };
+    let mut block: GethData = TestContext::<2, 1>::new(
+        None,
+        account_0_code_account_1_no_code(code),
+        tx_from_1_to_0,
+        |block, _tx| block.number(0xcafeu64),
+    )
+    .unwrap()
+    .into();
+
+    // Corrupt the stack of ADD, so that its operands can't be read.
+    let index = block.geth_traces[0]
+        .struct_logs
+        .iter()
+        .position(|step| step.op == OpcodeId::ADD)
+        .unwrap();
+    block.geth_traces[0].struct_logs[index].stack = Stack::new();
+
+    let err = handle_block_err(&block);
+    let context = err.tx_context().unwrap();
+    assert_eq!(context.tx_index, 0);
+    assert_eq!(context.tx_hash, block.eth_block.transactions[0].hash);
+    let step = context.step.as_ref().unwrap();
+    assert_eq!(step.index, index);
+    assert_eq!(step.op, OpcodeId::ADD);
+    assert_eq!(step.pc.0, 4);
+    assert_eq!(step.depth, 1);
+    assert!(step.call_id.is_some());
+
+    assert!(matches!(
+        err.root_cause(),
+        Error::EthTypeError(eth_types::Error::InvalidStackPointer)
+    ));
+    let source = std::error::Error::source(&err).unwrap();
+    assert_eq!(source.to_string(), err.root_cause().to_string());
+    assert!(err.to_string().contains("step 2 (ADD at pc 4, depth 1"));
+}
+
+#[test]
+fn tracer_err_unknown_step_error() {
+    let code = bytecode! {
+        PUSH1(0x01)
+        STOP
+    };
+    let mut block: GethData = TestContext::<2, 1>::new(
+        None,
+        account_0_code_account_1_no_code(code),
+        tx_from_1_to_0,
+        |block, _tx| block.number(0xcafeu64),
+    )
+    .unwrap()
+    .into();
+    block.geth_traces[0].struct_logs[0].error = Some("unknown error".to_string());
+
+    let err = handle_block_err(&block);
+    assert_eq!(err.tx_context().unwrap().step.as_ref().unwrap().index, 0);
+    assert!(matches!(
+        err.root_cause(),
+        Error::InvalidGethExecTrace(TraceError::UnknownStepError(error)) if error == "unknown error"
+    ));
+}
+
+#[test]
+fn tracer_err_call_stack_misuse() {
+    let code = bytecode! {
+        PUSH1(0x01)
+        STOP
+    };
+    let block: GethData = TestContext::<2, 1>::new(
+        None,
+        account_0_code_account_1_no_code(code),
+        tx_from_1_to_0,
+        |block, _tx| block.number(0xcafeu64),
+    )
+    .unwrap()
+    .into();
+    let step = &block.geth_traces[0].struct_logs[0];
+    let mut builder = CircuitInputBuilderTx::new(&block, step);
+
+    // A call whose result isn't in the trace can't be entered.
+    assert!(matches!(
+        builder.tx_ctx.push_call_ctx(5, Vec::new()),
+        Err(Error::InvalidGethExecTrace(TraceError::MissingCallResult(
+            5
+        )))
+    ));
+
+    // Returning from the root call leaves nothing else to return from.
+    builder.tx_ctx.pop_call_ctx().unwrap();
+    assert!(matches!(
+        builder.tx_ctx.pop_call_ctx(),
+        Err(Error::InvalidGethExecTrace(TraceError::EmptyCallStack))
+    ));
+
+    assert!(matches!(
+        builder.tx.last_step(),
+        Err(Error::InvalidGethExecTrace(
+            TraceError::EmptyTransactionSteps
+        ))
+    ));
+}
+
+#[test]
+fn precompiled_addresses_by_hardfork() {
+    let block: GethData = TestContext::<2, 1>::new(
+        None,
+        account_0_code_account_1_no_code(bytecode! { STOP }),
+        tx_from_1_to_0,
+        |block, _tx| block.number(0xcafeu64),
+    )
+    .unwrap()
+    .into();
+    let step = &block.geth_traces[0].struct_logs[0];
+    let mut builder = CircuitInputBuilderTx::new(&block, step);
+    let point_evaluation = Address::from_low_u64_be(0x0a);
+
+    // The point evaluation precompile is deployed from Cancun on.
+    assert!(builder
+        .state_ref()
+        .is_precompiled(&Address::from_low_u64_be(0x09)));
+    assert!(!builder.state_ref().is_precompiled(&point_evaluation));
+    builder.builder.block.chain_config.hardfork = Hardfork::Cancun;
+    assert!(builder.state_ref().is_precompiled(&point_evaluation));
+    assert!(!builder
+        .state_ref()
+        .is_precompiled(&Address::from_low_u64_be(0x0b)));
+}
@@ -2,10 +2,12 @@
 
 use std::collections::BTreeMap;
 
-use eth_types::{Address, GethExecTrace, Word};
+use eth_types::{AccessList, Address, GethExecTrace, Hash, Word};
 use ethers_core::utils::get_contract_address;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    error::TraceError,
     state_db::{CodeDB, StateDB},
     Error,
 };
@@ -15,7 +17,9 @@ use super::{call::ReversionGroup, Call, CallContext, CallKind, CodeSource, ExecS
 #[derive(Debug)]
 /// Context of a [`Transaction`] which can mutate in an [`ExecStep`].
 pub struct TransactionContext {
-    /// Unique identifier of transaction of the block. The value is `index + 1`.
+    /// Unique identifier of transaction of the block. The value is `index + 1`,
+    /// where `index` is the position of the transaction among all the
+    /// transactions of the witness, which may span several blocks.
     id: usize,
     /// Identifier if this transaction is last one of the block or not.
     is_last_tx: bool,
@@ -34,6 +38,7 @@ pub struct TransactionContext {
 impl TransactionContext {
     /// Create a new Self.
     pub fn new(
+        id: usize,
         eth_tx: &eth_types::Transaction,
         geth_trace: &GethExecTrace,
         is_last_tx: bool,
@@ -51,7 +56,11 @@ impl TransactionContext {
                     // Emerge from call
                     } else if geth_step.depth - 1 == geth_next_step.depth {
                         let is_success = !geth_next_step.stack.last()?.is_zero();
-                        call_is_success_map.insert(call_indices.pop().unwrap(), is_success);
+                        let call_index = call_indices.pop().ok_or(Error::InvalidGethExecStep(
+                            TraceError::CallStackUnderflow,
+                            geth_step.clone(),
+                        ))?;
+                        call_is_success_map.insert(call_index, is_success);
                     // Callee with empty code
                     } else if CallKind::try_from(geth_step.op).is_ok() {
                         let is_success = !geth_next_step.stack.last()?.is_zero();
@@ -66,17 +75,13 @@ impl TransactionContext {
         };
 
         let mut tx_ctx = Self {
-            id: eth_tx
-                .transaction_index
-                .ok_or(Error::EthTypeError(eth_types::Error::IncompleteBlock))?
-                .as_u64() as usize
-                + 1,
+            id,
             is_last_tx,
             call_is_success,
             calls: Vec::new(),
             reversion_groups: Vec::new(),
         };
-        tx_ctx.push_call_ctx(0, eth_tx.input.to_vec());
+        tx_ctx.push_call_ctx(0, eth_tx.input.to_vec())?;
 
         Ok(tx_ctx)
     }
@@ -100,45 +105,57 @@ impl TransactionContext {
     pub(crate) fn call_index(&self) -> Result<usize, Error> {
         self.calls
             .last()
-            .ok_or(Error::InvalidGethExecTrace(
-                "Call stack is empty but call is used",
-            ))
+            .ok_or(Error::InvalidGethExecTrace(TraceError::EmptyCallStack))
             .map(|call| call.index)
     }
 
     pub(crate) fn call_ctx(&self) -> Result<&CallContext, Error> {
-        self.calls.last().ok_or(Error::InvalidGethExecTrace(
-            "Call stack is empty but call is used",
-        ))
+        self.calls
+            .last()
+            .ok_or(Error::InvalidGethExecTrace(TraceError::EmptyCallStack))
     }
 
     pub(crate) fn call_ctx_mut(&mut self) -> Result<&mut CallContext, Error> {
-        self.calls.last_mut().ok_or(Error::InvalidGethExecTrace(
-            "Call stack is empty but call is used",
-        ))
+        self.calls
+            .last_mut()
+            .ok_or(Error::InvalidGethExecTrace(TraceError::EmptyCallStack))
+    }
+
+    /// Return whether the call with the given index ends successfully.
+    fn call_is_success(&self, call_idx: usize) -> Result<bool, Error> {
+        self.call_is_success
+            .get(call_idx)
+            .copied()
+            .ok_or(Error::InvalidGethExecTrace(TraceError::MissingCallResult(
+                call_idx,
+            )))
     }
 
     /// Push a new call context and its index into the call stack.
-    pub(crate) fn push_call_ctx(&mut self, call_idx: usize, call_data: Vec<u8>) {
-        if !self.call_is_success[call_idx] {
+    pub(crate) fn push_call_ctx(
+        &mut self,
+        call_idx: usize,
+        call_data: Vec<u8>,
+    ) -> Result<(), Error> {
+        if !self.call_is_success(call_idx)? {
             self.reversion_groups
                 .push(ReversionGroup::new(vec![(call_idx, 0)], Vec::new()))
         } else if let Some(reversion_group) = self.reversion_groups.last_mut() {
-            let caller_ctx = self.calls.last().expect("calls should not be empty");
-            let caller_reversible_write_counter = self
+            let caller_ctx = self
                 .calls
                 .last()
-                .expect("calls should not be empty")
-                .reversible_write_counter;
+                .ok_or(Error::InvalidGethExecTrace(TraceError::EmptyCallStack))?;
             let caller_reversible_write_counter_offset = reversion_group
                 .calls
                 .iter()
                 .find(|(call_idx, _)| *call_idx == caller_ctx.index)
-                .expect("calls should not be empty")
+                .ok_or(Error::InvalidGethExecTrace(
+                    TraceError::MissingReversionGroupCall(caller_ctx.index),
+                ))?
                 .1;
             reversion_group.calls.push((
                 call_idx,
-                caller_reversible_write_counter + caller_reversible_write_counter_offset,
+                caller_ctx.reversible_write_counter + caller_reversible_write_counter_offset,
             ));
         }
 
@@ -146,24 +163,32 @@ impl TransactionContext {
             index: call_idx,
             reversible_write_counter: 0,
             call_data,
+            ..Default::default()
         });
+        Ok(())
     }
 
     /// Pop the last entry in the call stack.
-    pub(crate) fn pop_call_ctx(&mut self) {
-        let call = self.calls.pop().expect("calls should not be empty");
+    pub(crate) fn pop_call_ctx(&mut self) -> Result<(), Error> {
+        let call = self
+            .calls
+            .pop()
+            .ok_or(Error::InvalidGethExecTrace(TraceError::EmptyCallStack))?;
         // Accumulate reversible_write_counter if call is success
-        if self.call_is_success[call.index] {
+        if self.call_is_success(call.index)? {
             if let Some(caller) = self.calls.last_mut() {
                 caller.reversible_write_counter += call.reversible_write_counter;
             }
         }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Result of the parsing of an Ethereum Transaction.
 pub struct Transaction {
+    /// Number of the block containing this transaction
+    pub block_number: Word,
     /// Nonce
     pub nonce: u64,
     /// Gas
@@ -178,6 +203,10 @@ pub struct Transaction {
     pub value: Word,
     /// Input / Call Data
     pub input: Vec<u8>,
+    /// Access list (EIP-2930), empty for legacy transactions
+    pub access_list: AccessList,
+    /// Blob versioned hashes (EIP-4844), empty for non-blob transactions
+    pub blob_hashes: Vec<Hash>,
     /// Calls made in the transaction
     calls: Vec<Call>,
     /// Execution steps
@@ -185,12 +214,15 @@ pub struct Transaction {
 }
 
 impl Transaction {
-    /// Create a new Self.
+    /// Create a new Self.  The transactions of ethers don't carry the blob
+    /// versioned hashes, so they're given apart.
     pub fn new(
         call_id: usize,
+        block_number: Word,
         sdb: &StateDB,
         code_db: &mut CodeDB,
         eth_tx: &eth_types::Transaction,
+        blob_hashes: Vec<Hash>,
         is_success: bool,
     ) -> Result<Self, Error> {
         let (found, _) = sdb.get_account(&eth_tx.from);
@@ -240,6 +272,7 @@ impl Transaction {
         };
 
         Ok(Self {
+            block_number,
             nonce: eth_tx.nonce.as_u64(),
             gas: eth_tx.gas.as_u64(),
             gas_price: eth_tx.gas_price.unwrap_or_default(),
@@ -247,6 +280,8 @@ impl Transaction {
             to: eth_tx.to.unwrap_or_default(),
             value: eth_tx.value,
             input: eth_tx.input.to_vec(),
+            access_list: eth_tx.access_list.clone().unwrap_or_default(),
+            blob_hashes,
             calls: vec![call],
             steps: Vec::new(),
         })
@@ -284,12 +319,10 @@ impl Transaction {
     }
 
     /// Return last step in this transaction.
-    pub fn last_step(&self) -> &ExecStep {
-        if self.steps().is_empty() {
-            panic!("there is no steps in tx");
-        }
-
-        &self.steps[self.steps.len() - 1]
+    pub fn last_step(&self) -> Result<&ExecStep, Error> {
+        self.steps.last().ok_or(Error::InvalidGethExecTrace(
+            TraceError::EmptyTransactionSteps,
+        ))
     }
 
     /// Return whether the steps in this transaction is empty
@@ -0,0 +1,581 @@
+//! Validation of the operations of a [`Block`] against the geth traces it was
+//! built from.
+
+use super::{Block, ExecState, Transaction};
+use crate::{
+    exec_trace::OperationRef,
+    operation::{Target, RW},
+};
+use eth_types::{
+    evm_types::{MemoryAddress, OpcodeId, ProgramCounter, StackAddress},
+    Address, GethExecStep, GethExecTrace, Word,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+/// Mismatch found between the operations of a step and the geth trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceMismatch {
+    /// Index of the transaction in the block
+    pub tx_index: usize,
+    /// Index of the [`GethExecStep`] in the trace of the transaction
+    pub step_index: usize,
+    /// Opcode of the step
+    pub op: OpcodeId,
+    /// Program counter of the step
+    pub pc: ProgramCounter,
+    /// What doesn't match
+    pub kind: MismatchKind,
+}
+
+impl fmt::Display for TraceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tx {} step {} ({:?} at pc {}): {:?}",
+            self.tx_index, self.step_index, self.op, self.pc.0, self.kind
+        )
+    }
+}
+
+/// Kind of [`TraceMismatch`].  `expected` is the value found in the trace, and
+/// `found` the one derived from the operations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// The number of opcode steps differs from the number of geth steps
+    StepCount {
+        /// Number of geth steps
+        expected: usize,
+        /// Number of opcode steps
+        found: usize,
+    },
+    /// Stack value of a call
+    Stack {
+        /// Call ID
+        call_id: usize,
+        /// Stack Address
+        address: StackAddress,
+        /// Value in the trace
+        expected: Option<Word>,
+        /// Value derived from the operations
+        found: Option<Word>,
+    },
+    /// Memory byte of a call
+    Memory {
+        /// Call ID
+        call_id: usize,
+        /// Memory Address
+        address: MemoryAddress,
+        /// Value in the trace
+        expected: u8,
+        /// Value derived from the operations
+        found: u8,
+    },
+    /// Storage value of an account
+    Storage {
+        /// Account Address
+        address: Address,
+        /// Storage Key
+        key: Word,
+        /// Value in the trace, or known from previous operations
+        expected: Word,
+        /// Value derived from the operations
+        found: Option<Word>,
+    },
+    /// Operation on a call which is not in the call stack
+    UnknownCall {
+        /// Call ID
+        call_id: usize,
+    },
+}
+
+/// Stack and memory of a call, as seen by the operations.
+struct Frame {
+    call_id: usize,
+    stack: BTreeMap<usize, Word>,
+    memory: Vec<u8>,
+}
+
+impl Frame {
+    fn new(call_id: usize, step: &GethExecStep) -> Self {
+        Self {
+            call_id,
+            stack: step
+                .stack
+                .0
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| (1023 - idx, *value))
+                .collect(),
+            memory: step.memory.0.clone(),
+        }
+    }
+
+    fn memory_byte(&self, address: usize) -> u8 {
+        self.memory.get(address).cloned().unwrap_or(0)
+    }
+}
+
+/// Replay the operations of every transaction of `block` over the geth trace
+/// it was built from.  Starting from the stack, memory and storage of each
+/// step, the [`StackOp`](crate::operation::StackOp)s,
+/// [`MemoryOp`](crate::operation::MemoryOp)s and
+/// [`StorageOp`](crate::operation::StorageOp)s of the step are checked and
+/// applied, and the result is compared to the next step of the same call.
+/// Returns all the mismatches found, which is empty when the operations
+/// explain the trace.
+pub fn validate_block(block: &Block, geth_traces: &[GethExecTrace]) -> Vec<TraceMismatch> {
+    block
+        .txs()
+        .iter()
+        .zip(geth_traces)
+        .enumerate()
+        .flat_map(|(tx_index, (tx, geth_trace))| {
+            validate_tx(block, tx_index, tx, &geth_trace.struct_logs)
+        })
+        .collect()
+}
+
+fn validate_tx(
+    block: &Block,
+    tx_index: usize,
+    tx: &Transaction,
+    geth_steps: &[GethExecStep],
+) -> Vec<TraceMismatch> {
+    let mut mismatches = Vec::new();
+    let mismatch = |step_index: usize, kind: MismatchKind| {
+        let step = &geth_steps[step_index.min(geth_steps.len() - 1)];
+        TraceMismatch {
+            tx_index,
+            step_index,
+            op: step.op,
+            pc: step.pc,
+            kind,
+        }
+    };
+
+    let group_starts = opcode_step_indices(tx);
+    if group_starts.len() != geth_steps.len() {
+        if !geth_steps.is_empty() {
+            mismatches.push(mismatch(
+                group_starts.len(),
+                MismatchKind::StepCount {
+                    expected: geth_steps.len(),
+                    found: group_starts.len(),
+                },
+            ));
+        }
+        return mismatches;
+    }
+
+    // Operations are applied in the order they were done, which is not the
+    // order of the steps for the reverted ones.  Each one is assigned to the
+    // group being handled when it was done.
+    let mut op_refs: Vec<_> = tx
+        .steps()
+        .iter()
+        .flat_map(|step| step.bus_mapping_instance.iter())
+        .filter_map(|op_ref| op_rwc(block, op_ref).map(|rwc| (rwc, *op_ref)))
+        .collect();
+    op_refs.sort_by_key(|(rwc, _)| *rwc);
+    let group_end_rwcs: Vec<_> = group_starts
+        .iter()
+        .skip(1)
+        .map(|idx| tx.steps()[*idx].rwc.0)
+        .chain(std::iter::once(
+            tx.steps()
+                .iter()
+                .find(|step| step.exec_state == ExecState::EndTx)
+                .map_or(usize::MAX, |step| step.rwc.0),
+        ))
+        .collect();
+    let first_rwc = group_starts
+        .first()
+        .map_or(usize::MAX, |idx| tx.steps()[*idx].rwc.0);
+    let mut op_refs = op_refs
+        .into_iter()
+        .skip_while(|(rwc, _)| *rwc < first_rwc)
+        .peekable();
+
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut storage: HashMap<(Address, Word), Word> = HashMap::new();
+
+    for (step_index, geth_step) in geth_steps.iter().enumerate() {
+        let exec_step = &tx.steps()[group_starts[step_index]];
+        let call = &tx.calls()[exec_step.call_index];
+        frames.truncate(geth_step.depth as usize - 1);
+        frames.push(Frame::new(call.call_id, geth_step));
+
+        while let Some((_, op_ref)) = op_refs.next_if(|(rwc, _)| *rwc < group_end_rwcs[step_index])
+        {
+            apply_op(block, &op_ref, &mut frames, &mut storage, |kind| {
+                mismatches.push(mismatch(step_index, kind))
+            });
+        }
+
+        // The accessed storage slot is known by geth.
+        if matches!(geth_step.op, OpcodeId::SLOAD | OpcodeId::SSTORE) && exec_step.error.is_none() {
+            if let Some((key, expected)) = geth_step
+                .stack
+                .last()
+                .ok()
+                .and_then(|key| geth_step.storage.get(&key).map(|value| (key, *value)))
+            {
+                let found = storage.get(&(call.address, key)).cloned();
+                if found != Some(expected) {
+                    mismatches.push(mismatch(
+                        step_index,
+                        MismatchKind::Storage {
+                            address: call.address,
+                            key,
+                            expected,
+                            found,
+                        },
+                    ));
+                }
+            }
+        }
+
+        // A new call starts with an empty stack and memory.  Otherwise, the
+        // next step continues in the current call or in its caller.
+        let next_step = match geth_steps.get(step_index + 1) {
+            Some(next_step) if next_step.depth <= geth_step.depth => next_step,
+            _ => continue,
+        };
+        if let Some(frame) = frames.get(next_step.depth as usize - 1) {
+            for kind in compare_frame(frame, next_step) {
+                mismatches.push(mismatch(step_index, kind));
+            }
+        }
+    }
+
+    mismatches
+}
+
+/// Return the index of the opcode step of each geth step in `tx`.  Each geth
+/// step is handled into an opcode step, followed by its virtual steps.
+pub(super) fn opcode_step_indices(tx: &Transaction) -> Vec<usize> {
+    tx.steps()
+        .iter()
+        .enumerate()
+        .filter(|(_, step)| matches!(step.exec_state, ExecState::Op(_)))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Return the rwc of a stack, memory or storage operation.
+fn op_rwc(block: &Block, op_ref: &OperationRef) -> Option<usize> {
+    let container = &block.container;
+    let idx = op_ref.as_usize();
+    match op_ref.target() {
+        Target::Stack => Some(container.stack[idx].rwc().0),
+        Target::Memory => Some(container.memory[idx].rwc().0),
+        Target::Storage => Some(container.storage[idx].rwc().0),
+        _ => None,
+    }
+}
+
+fn apply_op(
+    block: &Block,
+    op_ref: &OperationRef,
+    frames: &mut [Frame],
+    storage: &mut HashMap<(Address, Word), Word>,
+    mut report: impl FnMut(MismatchKind),
+) {
+    let container = &block.container;
+    let idx = op_ref.as_usize();
+    match op_ref.target() {
+        Target::Stack => {
+            let operation = &container.stack[idx];
+            let op = operation.op();
+            let frame = match frames.iter_mut().rev().find(|f| f.call_id == op.call_id) {
+                Some(frame) => frame,
+                None => {
+                    return report(MismatchKind::UnknownCall {
+                        call_id: op.call_id,
+                    })
+                }
+            };
+            let address = usize::from(op.address);
+            match operation.rw() {
+                RW::READ => {
+                    let expected = frame.stack.get(&address).cloned();
+                    if expected != Some(op.value) {
+                        report(MismatchKind::Stack {
+                            call_id: op.call_id,
+                            address: op.address,
+                            expected,
+                            found: Some(op.value),
+                        });
+                    }
+                }
+                RW::WRITE => {
+                    frame.stack.insert(address, op.value);
+                }
+            }
+        }
+        Target::Memory => {
+            let operation = &container.memory[idx];
+            let op = operation.op();
+            let frame = match frames.iter_mut().rev().find(|f| f.call_id == op.call_id) {
+                Some(frame) => frame,
+                None => {
+                    return report(MismatchKind::UnknownCall {
+                        call_id: op.call_id,
+                    })
+                }
+            };
+            let address = op.address.0;
+            match operation.rw() {
+                RW::READ => {
+                    let expected = frame.memory_byte(address);
+                    if expected != op.value {
+                        report(MismatchKind::Memory {
+                            call_id: op.call_id,
+                            address: op.address,
+                            expected,
+                            found: op.value,
+                        });
+                    }
+                }
+                RW::WRITE => {
+                    if frame.memory.len() <= address {
+                        frame.memory.resize(address + 1, 0);
+                    }
+                    frame.memory[address] = op.value;
+                }
+            }
+        }
+        Target::Storage => {
+            let operation = &container.storage[idx];
+            let op = operation.op();
+            let slot = (op.address, op.key);
+            let (expected, found) = match operation.rw() {
+                RW::READ => (storage.get(&slot).cloned(), op.value),
+                RW::WRITE => (storage.get(&slot).cloned(), op.value_prev),
+            };
+            if let Some(expected) = expected.filter(|expected| *expected != found) {
+                report(MismatchKind::Storage {
+                    address: op.address,
+                    key: op.key,
+                    expected,
+                    found: Some(found),
+                });
+            }
+            storage.insert(slot, op.value);
+        }
+        _ => {}
+    }
+}
+
+/// Compare the stack and memory of `frame` with the ones of `step`.
+fn compare_frame(frame: &Frame, step: &GethExecStep) -> Vec<MismatchKind> {
+    let stack = step.stack.0.iter().enumerate().filter_map(|(idx, value)| {
+        let address = 1023 - idx;
+        let found = frame.stack.get(&address).cloned();
+        (found != Some(*value)).then(|| MismatchKind::Stack {
+            call_id: frame.call_id,
+            address: address.into(),
+            expected: Some(*value),
+            found,
+        })
+    });
+    let memory = (0..frame.memory.len().max(step.memory.0.len())).filter_map(|address| {
+        let expected = step.memory.0.get(address).cloned().unwrap_or(0);
+        let found = frame.memory_byte(address);
+        (expected != found).then(|| MismatchKind::Memory {
+            call_id: frame.call_id,
+            address: address.into(),
+            expected,
+            found,
+        })
+    });
+    stack.chain(memory).collect()
+}
+
+#[cfg(test)]
+mod validator_tests {
+    use super::*;
+    use crate::{circuit_input_builder::CircuitInputBuilder, mock::BlockData};
+    use eth_types::{bytecode, geth_types::GethData, Bytecode, ToWord};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    fn build(code: Bytecode) -> (GethData, CircuitInputBuilder) {
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        (block, builder)
+    }
+
+    fn add_code() -> Bytecode {
+        bytecode! {
+            PUSH1(0x02)
+            PUSH1(0x03)
+            ADD
+            PUSH1(0x00)
+            MSTORE
+            PUSH1(0x20)
+            PUSH1(0x00)
+            SHA3
+            PUSH1(0x00)
+            SSTORE
+            PUSH1(0x00)
+            SLOAD
+            STOP
+        }
+    }
+
+    #[test]
+    fn validate_block_ok() {
+        let (block, builder) = build(add_code());
+        assert_eq!(validate_block(&builder.block, &block.geth_traces), vec![]);
+    }
+
+    #[test]
+    fn validate_block_call_ok() {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+        let code_b = bytecode! {
+            PUSH1(0x01) // value
+            PUSH1(0x00) // key
+            SSTORE
+            PUSH32(Word::MAX)
+            PUSH1(0x00)
+            MSTORE
+            PUSH1(0x10) // length
+            PUSH1(0x08) // offset
+            RETURN
+        };
+        let code_a = bytecode! {
+            PUSH1(0x20) // retLength
+            PUSH1(0x10) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x00) // value
+            PUSH32(addr_b.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            PUSH1(0x00)
+            MLOAD
+            STOP
+        };
+        let block: GethData = TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_a).code(code_a);
+                accs[1].address(addr_b).code(code_b);
+                accs[2]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[2].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        assert_eq!(validate_block(&builder.block, &block.geth_traces), vec![]);
+    }
+
+    #[test]
+    fn validate_block_missing_op() {
+        let (block, mut builder) = build(add_code());
+
+        // Drop the stack write of ADD, as a handler missing it would do.
+        let step = builder.block.txs_mut()[0]
+            .steps_mut()
+            .iter_mut()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::ADD))
+            .unwrap();
+        let write_ref = step.bus_mapping_instance.pop().unwrap();
+        assert_eq!(
+            builder.block.container.stack[write_ref.as_usize()].rw(),
+            RW::WRITE
+        );
+
+        let mismatches = validate_block(&builder.block, &block.geth_traces);
+        assert_eq!(mismatches.len(), 1);
+        let mismatch = &mismatches[0];
+        assert_eq!(
+            (
+                mismatch.tx_index,
+                mismatch.step_index,
+                mismatch.op,
+                mismatch.pc
+            ),
+            (0, 2, OpcodeId::ADD, ProgramCounter(4))
+        );
+        assert_eq!(
+            mismatch.kind,
+            MismatchKind::Stack {
+                call_id: builder.block.txs()[0].calls()[0].call_id,
+                address: StackAddress(1023),
+                expected: Some(Word::from(5)),
+                found: Some(Word::from(2)),
+            }
+        );
+    }
+
+    #[test]
+    fn validate_block_unexpected_trace() {
+        let (mut block, builder) = build(add_code());
+
+        // Geth stores a different word in memory than the operations.  Both the
+        // MSTORE and the step following it, which starts from the corrupted
+        // memory, don't match.
+        let index = block.geth_traces[0]
+            .struct_logs
+            .iter()
+            .position(|step| step.op == OpcodeId::MSTORE)
+            .unwrap();
+        block.geth_traces[0].struct_logs[index + 1].memory.0[31] = 0x06;
+
+        let call_id = builder.block.txs()[0].calls()[0].call_id;
+        let mismatches = validate_block(&builder.block, &block.geth_traces);
+        assert_eq!(
+            mismatches
+                .iter()
+                .map(|mismatch| (mismatch.step_index, mismatch.op, mismatch.kind.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    index,
+                    OpcodeId::MSTORE,
+                    MismatchKind::Memory {
+                        call_id,
+                        address: MemoryAddress(31),
+                        expected: 0x06,
+                        found: 0x05,
+                    }
+                ),
+                (
+                    index + 1,
+                    OpcodeId::PUSH1,
+                    MismatchKind::Memory {
+                        call_id,
+                        address: MemoryAddress(31),
+                        expected: 0x05,
+                        found: 0x06,
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Versioned encoding of the [`Block`] built by the
+//! [`CircuitInputBuilder`](super::CircuitInputBuilder), so that witnesses can
+//! be cached on disk, diffed between versions and moved between machines.
+
+use super::Block;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+
+/// Version of the witness encoding.  It must be bumped whenever the layout of
+/// any type reachable from [`Block`] changes.
+pub const WITNESS_FORMAT_VERSION: u32 = 1;
+
+/// Magic bytes at the start of a binary encoded witness.
+const WITNESS_MAGIC: [u8; 4] = *b"BMWT";
+
+/// Length of the binary header: magic bytes followed by the little endian
+/// format version.
+const WITNESS_HEADER_LEN: usize = WITNESS_MAGIC.len() + 4;
+
+/// JSON envelope of a witness.
+#[derive(Serialize, Deserialize)]
+struct VersionedWitness<T> {
+    version: u32,
+    block: T,
+}
+
+impl Block {
+    /// Encode the block in the compact binary witness format: a header with
+    /// the format version followed by the bincode encoding of the block.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(WITNESS_HEADER_LEN);
+        bytes.extend_from_slice(&WITNESS_MAGIC);
+        bytes.extend_from_slice(&WITNESS_FORMAT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self).map_err(Error::WitnessEncodingError)?;
+        Ok(bytes)
+    }
+
+    /// Decode a block encoded with [`Block::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < WITNESS_HEADER_LEN || bytes[..WITNESS_MAGIC.len()] != WITNESS_MAGIC {
+            return Err(Error::InvalidWitnessHeader);
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[WITNESS_MAGIC.len()..WITNESS_HEADER_LEN]);
+        check_version(u32::from_le_bytes(version))?;
+        bincode::deserialize(&bytes[WITNESS_HEADER_LEN..]).map_err(Error::WitnessEncodingError)
+    }
+
+    /// Encode the block as JSON, wrapped in an object together with the format
+    /// version.
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(&VersionedWitness {
+            version: WITNESS_FORMAT_VERSION,
+            block: self,
+        })
+        .map_err(Error::SerdeError)
+    }
+
+    /// Decode a block encoded with [`Block::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let witness: VersionedWitness<serde_json::Value> =
+            serde_json::from_str(json).map_err(Error::SerdeError)?;
+        check_version(witness.version)?;
+        serde_json::from_value(witness.block).map_err(Error::SerdeError)
+    }
+}
+
+fn check_version(version: u32) -> Result<(), Error> {
+    if version != WITNESS_FORMAT_VERSION {
+        return Err(Error::UnsupportedWitnessVersion {
+            found: version,
+            expected: WITNESS_FORMAT_VERSION,
+        });
+    }
+    Ok(())
+}
+
+/// Lossless encoding of an [`OpcodeId`](eth_types::evm_types::OpcodeId) as a
+/// `u16`: the opcode byte for defined opcodes and `0x100 | byte` for
+/// `OpcodeId::INVALID(byte)`, which would otherwise collide with the defined
+/// opcode of the same byte.
+pub(crate) mod opcode_id {
+    use eth_types::evm_types::OpcodeId;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    const INVALID_FLAG: u16 = 0x100;
+
+    pub(crate) fn serialize<S: Serializer>(
+        op: &OpcodeId,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let code = match op {
+            OpcodeId::INVALID(byte) => INVALID_FLAG | u16::from(*byte),
+            op => u16::from(op.as_u8()),
+        };
+        serializer.serialize_u16(code)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OpcodeId, D::Error> {
+        let code = u16::deserialize(deserializer)?;
+        match code >> 8 {
+            0 => OpcodeId::try_from(code as u8).map_err(de::Error::custom),
+            1 => Ok(OpcodeId::INVALID(code as u8)),
+            _ => Err(de::Error::custom(format!(
+                "invalid opcode encoding {:#x}",
+                code
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod witness_tests {
+    use super::*;
+    use crate::{circuit_input_builder::ExecState, mock::BlockData};
+    use eth_types::{bytecode, evm_types::OpcodeId, geth_types::GethData, word};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    fn build_block() -> Block {
+        let code = bytecode! {
+            PUSH1(0x20)
+            PUSH1(0x40)
+            MSTORE
+            PUSH1(0x01)
+            PUSH1(0x00)
+            SSTORE
+            PUSH1(0x20) // size
+            PUSH1(0x00) // offset
+            PUSH1(0x80) // dest_offset
+            CODECOPY
+            PUSH32(word!("0xff")) // topic
+            PUSH1(0x20) // size
+            PUSH1(0x00) // offset
+            LOG1
+            STOP
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        builder.block
+    }
+
+    #[test]
+    fn witness_bytes_roundtrip() {
+        let mut block = build_block();
+        // Make sure invalid opcodes don't collide with defined ones.
+        block.txs_mut()[0].steps_mut()[1].exec_state = ExecState::Op(OpcodeId::INVALID(0x01));
+
+        let bytes = block.to_bytes().unwrap();
+        let decoded = Block::from_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", block));
+        assert_eq!(decoded.container, block.container);
+        assert_eq!(decoded.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn witness_json_roundtrip() {
+        let block = build_block();
+
+        let json = block.to_json().unwrap();
+        let decoded = Block::from_json(&json).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", block));
+        assert_eq!(decoded.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn witness_unsupported_version() {
+        let block = build_block();
+
+        let mut bytes = block.to_bytes().unwrap();
+        bytes[WITNESS_MAGIC.len()..WITNESS_HEADER_LEN]
+            .copy_from_slice(&(WITNESS_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Block::from_bytes(&bytes),
+            Err(Error::UnsupportedWitnessVersion { found, expected })
+                if found == WITNESS_FORMAT_VERSION + 1 && expected == WITNESS_FORMAT_VERSION
+        ));
+        assert!(matches!(
+            Block::from_bytes(&bytes[1..]),
+            Err(Error::InvalidWitnessHeader)
+        ));
+
+        let json = block.to_json().unwrap().replacen(
+            &format!("\"version\":{}", WITNESS_FORMAT_VERSION),
+            "\"version\":0",
+            1,
+        );
+        assert!(matches!(
+            Block::from_json(&json),
+            Err(Error::UnsupportedWitnessVersion { found: 0, .. })
+        ));
+    }
+}
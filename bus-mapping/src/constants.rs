@@ -3,3 +3,6 @@
 /// Maximum number of bytes copied in a single iteration of internal steps like
 /// CopyToMemory and CopyCodeToMemory.
 pub const MAX_COPY_BYTES: usize = 32usize;
+
+/// Wei in a Gwei, the unit of the withdrawn amounts.
+pub const GWEI: u64 = 1_000_000_000;
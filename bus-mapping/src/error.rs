@@ -1,13 +1,18 @@
 //! Error module for the bus-mapping crate
 
 use core::fmt::{Display, Formatter, Result as FmtResult};
-use eth_types::{evm_types::OpcodeId, Address, GethExecStep, Word, H256};
+use eth_types::{
+    evm_types::{OpcodeId, ProgramCounter},
+    Address, GethExecStep, Word, H256,
+};
 use ethers_providers::ProviderError;
+use serde::{Deserialize, Serialize};
 use std::error::Error as StdError;
 
+use crate::evm::PrecompileCalls;
 use crate::geth_errors::{
     GETH_ERR_GAS_UINT_OVERFLOW, GETH_ERR_OUT_OF_GAS, GETH_ERR_STACK_OVERFLOW,
-    GETH_ERR_STACK_UNDERFLOW,
+    GETH_ERR_STACK_UNDERFLOW, GETH_ERR_WRITE_PROTECTION,
 };
 
 /// Error type for any BusMapping related failure.
@@ -26,16 +31,88 @@ pub enum Error {
     /// Code not found in the CodeDB
     CodeNotFound(H256),
     /// Unable to figure out error at a [`GethExecStep`]
-    UnexpectedExecStepError(&'static str, GethExecStep),
+    UnexpectedExecStepError(UnexpectedStepError, GethExecStep),
     /// Invalid [`eth_types::GethExecTrace`] due to an invalid/unexpected value
     /// in it.
-    InvalidGethExecTrace(&'static str),
+    InvalidGethExecTrace(TraceError),
     /// Invalid [`GethExecStep`] due to an invalid/unexpected value in it.
-    InvalidGethExecStep(&'static str, GethExecStep),
+    InvalidGethExecStep(TraceError, GethExecStep),
     /// Eth type related error.
     EthTypeError(eth_types::Error),
     /// EVM Execution error
     ExecutionError(ExecError),
+    /// Failure while handling a transaction of a block, with the context in
+    /// which it happened.
+    TxError(Box<TxErrorContext>, Box<Error>),
+    /// Binary de/serialization error of a witness.
+    WitnessEncodingError(bincode::Error),
+    /// The encoded witness doesn't start with the expected header.
+    InvalidWitnessHeader,
+    /// The encoded witness was produced with an unsupported format version.
+    UnsupportedWitnessVersion {
+        /// Version found in the encoded witness.
+        found: u32,
+        /// Version supported by this crate.
+        expected: u32,
+    },
+    /// Invalid proof of an account against the state root.
+    InvalidAccountProof(Address, ProofError),
+    /// Invalid proof of a storage key of an account against its storage root.
+    InvalidStorageProof(Address, Word, ProofError),
+    /// The range of blocks to prove together is empty.
+    EmptyBlockRange,
+    /// A block added to a range doesn't follow the last block of the range.
+    NonConsecutiveBlock {
+        /// Number of the block expected next.
+        expected: Word,
+        /// Number of the block found.
+        found: Word,
+    },
+    /// The inputs of the genesis block can't be generated, since there is no
+    /// parent block to take the state from.
+    GenesisBlock,
+    /// No precompiled contract is known at the address.
+    UnknownPrecompile(Address),
+    /// The output of a precompiled contract is longer than the return data
+    /// region of the call, which is the only part of it recorded in the trace.
+    UnknownPrecompileOutput(PrecompileCalls),
+}
+
+impl Error {
+    /// Wrap this error with the context of the transaction, and optionally of
+    /// the step, which was being handled when it happened.
+    pub(crate) fn with_tx_context(
+        self,
+        tx_index: usize,
+        tx_hash: H256,
+        step: Option<StepErrorContext>,
+    ) -> Self {
+        Error::TxError(
+            Box::new(TxErrorContext {
+                tx_index,
+                tx_hash,
+                step,
+            }),
+            Box::new(self),
+        )
+    }
+
+    /// Return the context of the transaction in which this error happened, if
+    /// known.
+    pub fn tx_context(&self) -> Option<&TxErrorContext> {
+        match self {
+            Error::TxError(context, _) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Return the innermost error, stripped of any context.
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::TxError(_, err) => err.root_cause(),
+            _ => self,
+        }
+    }
 }
 
 impl From<eth_types::Error> for Error {
@@ -52,14 +129,256 @@ impl From<ProviderError> for Error {
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{:?}", self)
+        match self {
+            Error::SerdeError(_) => write!(f, "serde de/serialization error"),
+            Error::JSONRpcError(_) => write!(f, "JSON-RPC error"),
+            Error::OpcodeIdNotCallType => write!(f, "opcode is not a call type"),
+            Error::AccountNotFound(address) => {
+                write!(f, "account {:?} not found in the state", address)
+            }
+            Error::StorageKeyNotFound(address, key) => write!(
+                f,
+                "storage key {:?} of account {:?} not found in the state",
+                key, address
+            ),
+            Error::CodeNotFound(code_hash) => write!(f, "code {:?} not found", code_hash),
+            Error::UnexpectedExecStepError(err, step) => write!(
+                f,
+                "unexpected execution of {:?} at pc {}: {}",
+                step.op, step.pc.0, err
+            ),
+            Error::InvalidGethExecTrace(err) => write!(f, "invalid geth trace: {}", err),
+            Error::InvalidGethExecStep(err, step) => write!(
+                f,
+                "invalid geth step {:?} at pc {}: {}",
+                step.op, step.pc.0, err
+            ),
+            Error::EthTypeError(_) => write!(f, "eth types error"),
+            Error::ExecutionError(err) => write!(f, "execution error: {:?}", err),
+            Error::TxError(context, _) => write!(f, "failed to handle {}", context),
+            Error::WitnessEncodingError(_) => write!(f, "witness binary de/serialization error"),
+            Error::InvalidWitnessHeader => write!(f, "invalid witness header"),
+            Error::UnsupportedWitnessVersion { found, expected } => write!(
+                f,
+                "unsupported witness format version {} (expected {})",
+                found, expected
+            ),
+            Error::InvalidAccountProof(address, err) => {
+                write!(f, "invalid proof of account {:?}: {}", address, err)
+            }
+            Error::InvalidStorageProof(address, key, err) => write!(
+                f,
+                "invalid proof of storage key {:?} of account {:?}: {}",
+                key, address, err
+            ),
+            Error::EmptyBlockRange => write!(f, "empty range of blocks"),
+            Error::NonConsecutiveBlock { expected, found } => {
+                write!(f, "expected block {} but found block {}", expected, found)
+            }
+            Error::GenesisBlock => write!(f, "the genesis block has no parent state"),
+            Error::UnknownPrecompile(address) => {
+                write!(f, "no precompiled contract at {:?}", address)
+            }
+            Error::UnknownPrecompileOutput(kind) => write!(
+                f,
+                "output of precompiled {:?} is longer than the traced return data",
+                kind
+            ),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::SerdeError(err) => Some(err),
+            Error::JSONRpcError(err) => Some(err),
+            Error::EthTypeError(err) => Some(err),
+            Error::TxError(_, err) => Some(err.as_ref()),
+            Error::WitnessEncodingError(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Inconsistency found in a [`eth_types::GethExecTrace`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    /// The call stack is empty but a call is used
+    EmptyCallStack,
+    /// Returning from a call which was never entered
+    CallStackUnderflow,
+    /// The step of the caller following a failed call is missing
+    MissingCallerStep,
+    /// The result of the call with the given index is missing
+    MissingCallResult(usize),
+    /// The caller with the given index is missing from its reversion group
+    MissingReversionGroupCall(usize),
+    /// The last step of a transaction is used before any step was added
+    EmptyTransactionSteps,
+    /// The error reported by geth at a step is unknown
+    UnknownStepError(String),
+    /// The step following a call, needed to know its result, is missing
+    MissingNextStep,
+    /// A non-persistent call has no reversion group
+    MissingReversionGroup,
+    /// A step has more gas left than the gas available to it
+    GasUnderflow,
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            TraceError::EmptyCallStack => write!(f, "call stack is empty but call is used"),
+            TraceError::CallStackUnderflow => {
+                write!(f, "return from a call which was never entered")
+            }
+            TraceError::MissingCallerStep => {
+                write!(f, "missing step of the caller after a failed call")
+            }
+            TraceError::MissingCallResult(call_index) => {
+                write!(f, "missing result of call {}", call_index)
+            }
+            TraceError::MissingReversionGroupCall(call_index) => {
+                write!(f, "call {} is missing from its reversion group", call_index)
+            }
+            TraceError::EmptyTransactionSteps => write!(f, "transaction has no steps"),
+            TraceError::UnknownStepError(error) => write!(f, "unknown step error {:?}", error),
+            TraceError::MissingNextStep => write!(f, "missing step after the call"),
+            TraceError::MissingReversionGroup => {
+                write!(f, "non-persistent call without reversion group")
+            }
+            TraceError::GasUnderflow => write!(f, "more gas left than available"),
+        }
     }
 }
 
-impl StdError for Error {}
+/// Execution result at a [`GethExecStep`] which can't be explained by any
+/// [`ExecError`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnexpectedStepError {
+    /// A call fails without any reason
+    CallFailureWithoutReturn,
+    /// A {CREATE, CREATE2} fails at RETURN without any reason
+    CreateReturnFailure,
+    /// A call fails at RETURN
+    ReturnFailure,
+    /// A call succeeds without {RETURN, STOP, SELFDESTRUCT}
+    SuccessWithoutReturn,
+    /// The code of a *CALL*/CREATE* isn't executed without any reason
+    CallCodeNotExecuted,
+}
+
+impl Display for UnexpectedStepError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let message = match self {
+            UnexpectedStepError::CallFailureWithoutReturn => "call failure without return",
+            UnexpectedStepError::CreateReturnFailure => "failure in RETURN from {CREATE, CREATE2}",
+            UnexpectedStepError::ReturnFailure => "failure in RETURN",
+            UnexpectedStepError::SuccessWithoutReturn => {
+                "success result without {RETURN, STOP, SELFDESTRUCT}"
+            }
+            UnexpectedStepError::CallCodeNotExecuted => "*CALL*/CREATE* code not executed",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// Error found when verifying a Merkle-Patricia trie proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofError {
+    /// A node of the proof is not valid RLP.
+    InvalidRlp,
+    /// A node of the proof is not a valid branch, extension or leaf node.
+    InvalidNode,
+    /// The proof ends before reaching the key.
+    MissingNode,
+    /// A node of the proof doesn't have the hash referenced by its parent
+    /// (or the root), which is provided.
+    NodeHashMismatch(H256),
+    /// The proven value doesn't match the claimed one.
+    ValueMismatch,
+}
+
+impl Display for ProofError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ProofError::InvalidRlp => write!(f, "node is not valid RLP"),
+            ProofError::InvalidNode => write!(f, "node is not a valid trie node"),
+            ProofError::MissingNode => write!(f, "proof is missing nodes"),
+            ProofError::NodeHashMismatch(hash) => {
+                write!(f, "node doesn't match the expected hash {:?}", hash)
+            }
+            ProofError::ValueMismatch => write!(f, "proven value doesn't match the claimed one"),
+        }
+    }
+}
+
+/// Transaction being handled when an [`Error`] happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxErrorContext {
+    /// Index of the transaction in the block
+    pub tx_index: usize,
+    /// Hash of the transaction
+    pub tx_hash: H256,
+    /// Step being handled, if the error happened while handling the steps of
+    /// the transaction.
+    pub step: Option<StepErrorContext>,
+}
+
+impl Display for TxErrorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "tx {} ({:?})", self.tx_index, self.tx_hash)?;
+        if let Some(step) = &self.step {
+            write!(f, " at {}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// [`GethExecStep`] being handled when an [`Error`] happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepErrorContext {
+    /// Index of the step in the trace of the transaction
+    pub index: usize,
+    /// Program counter of the step
+    pub pc: ProgramCounter,
+    /// Opcode of the step
+    pub op: OpcodeId,
+    /// Call depth of the step
+    pub depth: u16,
+    /// Id of the call the step belongs to, if any
+    pub call_id: Option<usize>,
+}
+
+impl StepErrorContext {
+    pub(crate) fn new(index: usize, step: &GethExecStep, call_id: Option<usize>) -> Self {
+        Self {
+            index,
+            pc: step.pc,
+            op: step.op,
+            depth: step.depth,
+            call_id,
+        }
+    }
+}
+
+impl Display for StepErrorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "step {} ({:?} at pc {}, depth {}",
+            self.index, self.op, self.pc.0, self.depth
+        )?;
+        if let Some(call_id) = self.call_id {
+            write!(f, ", call {}", call_id)?;
+        }
+        write!(f, ")")
+    }
+}
 
 /// Out of Gas errors by opcode
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum OogError {
     /// Out of Gas for opcodes which have non-zero constant gas cost
     Constant,
@@ -105,7 +424,7 @@ pub enum OogError {
 }
 
 /// EVM Execution Error
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ExecError {
     /// Invalid Opcode
     InvalidOpcode,
@@ -138,7 +457,7 @@ pub enum ExecError {
 }
 
 // TODO: Move to impl block.
-pub(crate) fn get_step_reported_error(op: &OpcodeId, error: &str) -> ExecError {
+pub(crate) fn get_step_reported_error(op: &OpcodeId, error: &str) -> Result<ExecError, Error> {
     if error == GETH_ERR_OUT_OF_GAS || error == GETH_ERR_GAS_UINT_OVERFLOW {
         // NOTE: We report a GasUintOverflow error as an OutOfGas error
         let oog_err = match op {
@@ -170,12 +489,16 @@ pub(crate) fn get_step_reported_error(op: &OpcodeId, error: &str) -> ExecError {
             OpcodeId::SELFDESTRUCT => OogError::SelfDestruct,
             _ => OogError::Constant,
         };
-        ExecError::OutOfGas(oog_err)
+        Ok(ExecError::OutOfGas(oog_err))
     } else if error.starts_with(GETH_ERR_STACK_OVERFLOW) {
-        ExecError::StackOverflow
+        Ok(ExecError::StackOverflow)
     } else if error.starts_with(GETH_ERR_STACK_UNDERFLOW) {
-        ExecError::StackUnderflow
+        Ok(ExecError::StackUnderflow)
+    } else if error == GETH_ERR_WRITE_PROTECTION {
+        Ok(ExecError::WriteProtection)
     } else {
-        panic!("Unknown GethExecStep.error: {}", error);
+        Err(Error::InvalidGethExecTrace(TraceError::UnknownStepError(
+            error.to_string(),
+        )))
     }
 }
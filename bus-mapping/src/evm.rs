@@ -1,6 +1,10 @@
 //! Evm types needed for parsing instruction sets as well
 
+pub mod hardfork;
 pub(crate) mod opcodes;
+pub mod precompiles;
 
 pub use eth_types::evm_types::opcode_ids::OpcodeId;
+pub use hardfork::{ChainConfig, GasSchedule, Hardfork};
 pub use opcodes::Opcode;
+pub use precompiles::{PrecompileCall, PrecompileCalls};
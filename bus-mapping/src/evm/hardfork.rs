@@ -0,0 +1,173 @@
+//! Hardforks of the EVM and the rules which depend on them.
+
+use eth_types::{evm_types::OpcodeId, Address, Word};
+use serde::{Deserialize, Serialize};
+
+/// Hardforks supported by the builder, in activation order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum Hardfork {
+    /// Berlin: access lists (EIP-2929, EIP-2930).
+    Berlin,
+    /// London: base fee (EIP-1559, EIP-3198) and reduced refunds (EIP-3529).
+    London,
+    /// Merge: proof of stake, DIFFICULTY returns PREVRANDAO (EIP-4399).
+    Merge,
+    /// Shanghai: PUSH0 (EIP-3855) and warm coinbase (EIP-3651).
+    Shanghai,
+    /// Cancun: transient storage (EIP-1153), MCOPY (EIP-5656) and blobs
+    /// (EIP-4844, EIP-7516).
+    Cancun,
+}
+
+impl Default for Hardfork {
+    fn default() -> Self {
+        Self::London
+    }
+}
+
+/// Gas parameters which changed between hardforks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GasSchedule {
+    /// Refund of an SSTORE clearing a slot.
+    pub sstore_clears_schedule: u64,
+    /// Refund of a SELFDESTRUCT.
+    pub selfdestruct_refund: u64,
+    /// The refund is capped to the gas used divided by this quotient.
+    pub max_refund_quotient: u64,
+}
+
+/// Configuration of the chain the blocks belong to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChainConfig {
+    /// chain id
+    pub chain_id: Word,
+    /// Hardfork active in the blocks
+    pub hardfork: Hardfork,
+}
+
+impl ChainConfig {
+    /// Create a new chain config.
+    pub fn new(chain_id: Word, hardfork: Hardfork) -> Self {
+        Self { chain_id, hardfork }
+    }
+
+    /// Return whether `hardfork` is active, that is, whether the rules it
+    /// introduced apply.
+    pub fn is_active(&self, hardfork: Hardfork) -> bool {
+        self.hardfork >= hardfork
+    }
+
+    /// Return whether `op` is a valid opcode.
+    pub fn is_opcode_valid(&self, op: OpcodeId) -> bool {
+        match op {
+            OpcodeId::INVALID(_) => false,
+            OpcodeId::BASEFEE => self.is_active(Hardfork::London),
+            OpcodeId::PUSH0 => self.is_active(Hardfork::Shanghai),
+            OpcodeId::TLOAD
+            | OpcodeId::TSTORE
+            | OpcodeId::MCOPY
+            | OpcodeId::BLOBHASH
+            | OpcodeId::BLOBBASEFEE => self.is_active(Hardfork::Cancun),
+            _ => true,
+        }
+    }
+
+    /// Return whether accounts and storage slots have a warm/cold access cost
+    /// tracked in an access list (EIP-2929).
+    pub fn is_access_list_active(&self) -> bool {
+        self.is_active(Hardfork::Berlin)
+    }
+
+    /// Return whether the coinbase is warm at the start of every transaction
+    /// (EIP-3651).
+    pub fn is_warm_coinbase_active(&self) -> bool {
+        self.is_active(Hardfork::Shanghai)
+    }
+
+    /// Return the addresses of the precompiled contracts, which are warm at
+    /// the start of every transaction.
+    pub fn precompile_addresses(&self) -> Vec<Address> {
+        // The point evaluation precompile (EIP-4844) is deployed at 0x0a.
+        let last = if self.is_active(Hardfork::Cancun) {
+            0x0a
+        } else {
+            0x09
+        };
+        (0x01..=last).map(Address::from_low_u64_be).collect()
+    }
+
+    /// Return whether the blocks have a base fee which is burnt (EIP-1559).
+    pub fn is_base_fee_active(&self) -> bool {
+        self.is_active(Hardfork::London)
+    }
+
+    /// Return the gas parameters of the hardfork.
+    pub fn gas_schedule(&self) -> GasSchedule {
+        if self.is_active(Hardfork::London) {
+            GasSchedule {
+                sstore_clears_schedule: 4800,
+                selfdestruct_refund: 0,
+                max_refund_quotient: 5,
+            }
+        } else {
+            GasSchedule {
+                sstore_clears_schedule: 15000,
+                selfdestruct_refund: 24000,
+                max_refund_quotient: 2,
+            }
+        }
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::new(Word::one(), Hardfork::default())
+    }
+}
+
+#[cfg(test)]
+mod hardfork_tests {
+    use super::*;
+    use eth_types::evm_types::MAX_REFUND_QUOTIENT_OF_GAS_USED;
+
+    #[test]
+    fn hardfork_rules() {
+        let berlin = ChainConfig::new(Word::one(), Hardfork::Berlin);
+        let cancun = ChainConfig::new(Word::one(), Hardfork::Cancun);
+
+        assert!(!berlin.is_opcode_valid(OpcodeId::BASEFEE));
+        assert!(cancun.is_opcode_valid(OpcodeId::BASEFEE));
+        assert!(!cancun.is_opcode_valid(OpcodeId::INVALID(0xfe)));
+
+        let shanghai = ChainConfig::new(Word::one(), Hardfork::Shanghai);
+        assert!(!berlin.is_opcode_valid(OpcodeId::PUSH0));
+        assert!(shanghai.is_opcode_valid(OpcodeId::PUSH0));
+        for op in [
+            OpcodeId::TLOAD,
+            OpcodeId::TSTORE,
+            OpcodeId::MCOPY,
+            OpcodeId::BLOBHASH,
+            OpcodeId::BLOBBASEFEE,
+        ] {
+            assert!(!shanghai.is_opcode_valid(op));
+            assert!(cancun.is_opcode_valid(op));
+        }
+
+        assert!(!berlin.is_base_fee_active());
+        assert!(cancun.is_base_fee_active());
+        assert!(berlin.is_access_list_active());
+        assert!(!berlin.is_warm_coinbase_active());
+        assert!(shanghai.is_warm_coinbase_active());
+        assert_eq!(berlin.precompile_addresses().len(), 9);
+        assert_eq!(
+            cancun.precompile_addresses().last(),
+            Some(&Address::from_low_u64_be(0x0a))
+        );
+
+        assert_eq!(berlin.gas_schedule().max_refund_quotient, 2);
+        assert_eq!(
+            ChainConfig::default().gas_schedule().max_refund_quotient,
+            MAX_REFUND_QUOTIENT_OF_GAS_USED as u64
+        );
+    }
+}
@@ -1,22 +1,24 @@
 //! Definition of each opcode of the EVM.
 use crate::{
     circuit_input_builder::{CircuitInputStateRef, ExecStep},
+    error::ExecError,
     evm::OpcodeId,
     operation::{
         AccountField, AccountOp, CallContextField, CallContextOp, TxAccessListAccountOp,
-        TxReceiptField, TxReceiptOp, TxRefundOp, RW,
+        TxAccessListAccountStorageOp, TxReceiptField, TxReceiptOp, TxRefundOp, RW,
     },
     Error,
 };
 use core::fmt::Debug;
-use eth_types::{
-    evm_types::{GasCost, MAX_REFUND_QUOTIENT_OF_GAS_USED},
-    GethExecStep, ToAddress, ToWord, Word,
-};
+use eth_types::{evm_types::GasCost, Address, GethExecStep, ToWord, Word};
 use keccak256::EMPTY_HASH;
 use log::warn;
 use std::collections::HashMap;
 
+mod balance;
+mod blobbasefee;
+mod blobhash;
+mod blockhash;
 mod call;
 mod calldatacopy;
 mod calldataload;
@@ -26,21 +28,38 @@ mod callvalue;
 mod chainid;
 mod codecopy;
 mod codesize;
+mod copy_code;
+mod create;
 mod dup;
+mod error_state;
+mod extcodecopy;
 mod extcodehash;
+mod extcodesize;
 mod gasprice;
 mod logs;
+mod mcopy;
 mod mload;
 mod mstore;
 mod number;
 mod origin;
+mod return_revert;
+mod returndatacopy;
+mod returndatasize;
 mod selfbalance;
+mod selfdestruct;
+mod sha3;
 mod sload;
 mod sstore;
 mod stackonlyop;
 mod stop;
 mod swap;
+mod tload;
+mod tstore;
 
+use balance::Balance;
+use blobbasefee::Blobbasefee;
+use blobhash::Blobhash;
+use blockhash::Blockhash;
 use call::Call;
 use calldatacopy::Calldatacopy;
 use calldataload::Calldataload;
@@ -49,19 +68,31 @@ use caller::Caller;
 use callvalue::Callvalue;
 use codecopy::Codecopy;
 use codesize::Codesize;
+use create::Create;
 use dup::Dup;
+use error_state::gen_error_state_ops;
+use extcodecopy::Extcodecopy;
 use extcodehash::Extcodehash;
+use extcodesize::Extcodesize;
 use gasprice::GasPrice;
 use logs::Log;
+use mcopy::Mcopy;
 use mload::Mload;
 use mstore::Mstore;
 use origin::Origin;
+use return_revert::ReturnRevert;
+use returndatacopy::Returndatacopy;
+use returndatasize::Returndatasize;
 use selfbalance::Selfbalance;
+use selfdestruct::Selfdestruct;
+use sha3::Sha3;
 use sload::Sload;
 use sstore::Sstore;
 use stackonlyop::StackOnlyOpcode;
 use stop::Stop;
 use swap::Swap;
+use tload::Tload;
+use tstore::Tstore;
 
 /// Generic opcode trait which defines the logic of the
 /// [`Operation`](crate::operation::Operation) that should be generated for one
@@ -122,9 +153,9 @@ fn fn_gen_associated_ops(opcode_id: &OpcodeId) -> FnGenAssociatedOps {
         OpcodeId::SHL => StackOnlyOpcode::<2, 1>::gen_associated_ops,
         OpcodeId::SHR => StackOnlyOpcode::<2, 1>::gen_associated_ops,
         OpcodeId::SAR => StackOnlyOpcode::<2, 1>::gen_associated_ops,
-        OpcodeId::SHA3 => StackOnlyOpcode::<2, 1>::gen_associated_ops,
+        OpcodeId::SHA3 => Sha3::gen_associated_ops,
         // OpcodeId::ADDRESS => {},
-        // OpcodeId::BALANCE => {},
+        OpcodeId::BALANCE => Balance::gen_associated_ops,
         OpcodeId::ORIGIN => Origin::gen_associated_ops,
         OpcodeId::CALLER => Caller::gen_associated_ops,
         OpcodeId::CALLVALUE => Callvalue::gen_associated_ops,
@@ -134,12 +165,12 @@ fn fn_gen_associated_ops(opcode_id: &OpcodeId) -> FnGenAssociatedOps {
         OpcodeId::GASPRICE => GasPrice::gen_associated_ops,
         OpcodeId::CODECOPY => Codecopy::gen_associated_ops,
         OpcodeId::CODESIZE => Codesize::gen_associated_ops,
-        // OpcodeId::EXTCODESIZE => {},
-        // OpcodeId::EXTCODECOPY => {},
-        // OpcodeId::RETURNDATASIZE => {},
-        // OpcodeId::RETURNDATACOPY => {},
+        OpcodeId::EXTCODESIZE => Extcodesize::gen_associated_ops,
+        OpcodeId::EXTCODECOPY => Extcodecopy::gen_associated_ops,
+        OpcodeId::RETURNDATASIZE => Returndatasize::gen_associated_ops,
+        OpcodeId::RETURNDATACOPY => Returndatacopy::gen_associated_ops,
         OpcodeId::EXTCODEHASH => Extcodehash::gen_associated_ops,
-        // OpcodeId::BLOCKHASH => {},
+        OpcodeId::BLOCKHASH => Blockhash::gen_associated_ops,
         OpcodeId::COINBASE => StackOnlyOpcode::<0, 1>::gen_associated_ops,
         OpcodeId::TIMESTAMP => StackOnlyOpcode::<0, 1>::gen_associated_ops,
         OpcodeId::NUMBER => StackOnlyOpcode::<0, 1>::gen_associated_ops,
@@ -148,18 +179,24 @@ fn fn_gen_associated_ops(opcode_id: &OpcodeId) -> FnGenAssociatedOps {
         OpcodeId::CHAINID => StackOnlyOpcode::<0, 1>::gen_associated_ops,
         OpcodeId::SELFBALANCE => Selfbalance::gen_associated_ops,
         OpcodeId::BASEFEE => StackOnlyOpcode::<0, 1>::gen_associated_ops,
+        OpcodeId::BLOBHASH => Blobhash::gen_associated_ops,
+        OpcodeId::BLOBBASEFEE => Blobbasefee::gen_associated_ops,
         OpcodeId::POP => StackOnlyOpcode::<1, 0>::gen_associated_ops,
         OpcodeId::MLOAD => Mload::gen_associated_ops,
         OpcodeId::MSTORE => Mstore::<false>::gen_associated_ops,
         OpcodeId::MSTORE8 => Mstore::<true>::gen_associated_ops,
         OpcodeId::SLOAD => Sload::gen_associated_ops,
         OpcodeId::SSTORE => Sstore::gen_associated_ops,
+        OpcodeId::TLOAD => Tload::gen_associated_ops,
+        OpcodeId::TSTORE => Tstore::gen_associated_ops,
+        OpcodeId::MCOPY => Mcopy::gen_associated_ops,
         OpcodeId::JUMP => StackOnlyOpcode::<1, 0>::gen_associated_ops,
         OpcodeId::JUMPI => StackOnlyOpcode::<2, 0>::gen_associated_ops,
         OpcodeId::PC => StackOnlyOpcode::<0, 1>::gen_associated_ops,
         OpcodeId::MSIZE => StackOnlyOpcode::<0, 1>::gen_associated_ops,
         OpcodeId::GAS => StackOnlyOpcode::<0, 1>::gen_associated_ops,
         OpcodeId::JUMPDEST => dummy_gen_associated_ops,
+        OpcodeId::PUSH0 => StackOnlyOpcode::<0, 1>::gen_associated_ops,
         OpcodeId::DUP1 => Dup::<1>::gen_associated_ops,
         OpcodeId::DUP2 => Dup::<2>::gen_associated_ops,
         OpcodeId::DUP3 => Dup::<3>::gen_associated_ops,
@@ -197,28 +234,15 @@ fn fn_gen_associated_ops(opcode_id: &OpcodeId) -> FnGenAssociatedOps {
         OpcodeId::LOG2 => Log::gen_associated_ops,
         OpcodeId::LOG3 => Log::gen_associated_ops,
         OpcodeId::LOG4 => Log::gen_associated_ops,
-        // OpcodeId::CREATE => {},
+        OpcodeId::CREATE => Create::<false>::gen_associated_ops,
         OpcodeId::CALL => Call::gen_associated_ops,
-        // OpcodeId::CALLCODE => {},
-        // TODO: Handle RETURN by its own gen_associated_ops.
-        OpcodeId::RETURN => Stop::gen_associated_ops,
-        // OpcodeId::DELEGATECALL => {},
-        // OpcodeId::CREATE2 => {},
-        // OpcodeId::STATICCALL => {},
-        // TODO: Handle REVERT by its own gen_associated_ops.
-        OpcodeId::REVERT => Stop::gen_associated_ops,
-        OpcodeId::SELFDESTRUCT => {
-            warn!("Using dummy gen_selfdestruct_ops for opcode SELFDESTRUCT");
-            dummy_gen_selfdestruct_ops
-        }
-        OpcodeId::CALLCODE | OpcodeId::DELEGATECALL | OpcodeId::STATICCALL => {
-            warn!("Using dummy gen_call_ops for opcode {:?}", opcode_id);
-            dummy_gen_call_ops
-        }
-        OpcodeId::CREATE | OpcodeId::CREATE2 => {
-            warn!("Using dummy gen_create_ops for opcode {:?}", opcode_id);
-            dummy_gen_create_ops
-        }
+        OpcodeId::CALLCODE => Call::gen_associated_ops,
+        OpcodeId::RETURN => ReturnRevert::gen_associated_ops,
+        OpcodeId::DELEGATECALL => Call::gen_associated_ops,
+        OpcodeId::CREATE2 => Create::<true>::gen_associated_ops,
+        OpcodeId::STATICCALL => Call::gen_associated_ops,
+        OpcodeId::REVERT => ReturnRevert::gen_associated_ops,
+        OpcodeId::SELFDESTRUCT => Selfdestruct::gen_associated_ops,
         _ => {
             warn!("Using dummy gen_associated_ops for opcode {:?}", opcode_id);
             dummy_gen_associated_ops
@@ -233,10 +257,68 @@ pub fn gen_associated_ops(
     state: &mut CircuitInputStateRef,
     geth_steps: &[GethExecStep],
 ) -> Result<Vec<ExecStep>, Error> {
+    let geth_step = &geth_steps[0];
+    let next_step = geth_steps.get(1);
+
+    // A step which enters a new call doesn't fail.  Failures of *CALL*/CREATE*
+    // which don't halt the current call are handled by their own
+    // gen_associated_ops.
+    let is_entering_call = next_step.map_or(false, |s| s.depth == geth_step.depth + 1);
+    if !is_entering_call {
+        if let Some(error) = state.get_step_err(geth_step, next_step)? {
+            if !matches!(
+                error,
+                ExecError::Depth
+                    | ExecError::InsufficientBalance
+                    | ExecError::ContractAddressCollision
+            ) {
+                return gen_error_state_ops(state, geth_steps, error);
+            }
+        }
+    }
+
     let fn_gen_associated_ops = fn_gen_associated_ops(opcode_id);
     fn_gen_associated_ops(state, geth_steps)
 }
 
+/// Add `addresses` and `storage_keys` into the access list of the current
+/// transaction.  An entry may be warm already, for example when the access list
+/// repeats an address, in which case the op records that it was warm.
+fn gen_access_list_ops(
+    state: &mut CircuitInputStateRef,
+    exec_step: &mut ExecStep,
+    addresses: Vec<Address>,
+    storage_keys: Vec<(Address, Word)>,
+) {
+    for address in addresses {
+        let is_warm_prev = !state.sdb.add_account_to_access_list(address);
+        state.push_op(
+            exec_step,
+            RW::WRITE,
+            TxAccessListAccountOp {
+                tx_id: state.tx_ctx.id(),
+                address,
+                is_warm: true,
+                is_warm_prev,
+            },
+        );
+    }
+    for (address, key) in storage_keys {
+        let is_warm_prev = !state.sdb.add_account_storage_to_access_list((address, key));
+        state.push_op(
+            exec_step,
+            RW::WRITE,
+            TxAccessListAccountStorageOp {
+                tx_id: state.tx_ctx.id(),
+                address,
+                key,
+                is_warm: true,
+                is_warm_prev,
+            },
+        );
+    }
+}
+
 pub fn gen_begin_tx_ops(state: &mut CircuitInputStateRef) -> Result<ExecStep, Error> {
     let mut exec_step = state.new_begin_tx_step();
     let call = state.call()?.clone();
@@ -292,19 +374,47 @@ pub fn gen_begin_tx_ops(state: &mut CircuitInputStateRef) -> Result<ExecStep, Er
         );
     }
 
+    // Add precompiles, the entries of the tx access list (EIP-2930) and the
+    // coinbase (EIP-3651) into access list, in the same order as geth.
+    if state.block.chain_config.is_access_list_active() {
+        let mut addresses = state.block.chain_config.precompile_addresses();
+        let mut storage_keys = Vec::new();
+        for item in state.tx.access_list.0.iter() {
+            addresses.push(item.address);
+            storage_keys.extend(
+                item.storage_keys
+                    .iter()
+                    .map(|key| (item.address, key.to_word())),
+            );
+        }
+        if state.block.chain_config.is_warm_coinbase_active() {
+            addresses.push(state.block.coinbase);
+        }
+        gen_access_list_ops(state, &mut exec_step, addresses, storage_keys);
+    }
+
     // Calculate intrinsic gas cost
     let call_data_gas_cost = state
         .tx
         .input
         .iter()
         .fold(0, |acc, byte| acc + if *byte == 0 { 4 } else { 16 });
+    let access_list_gas_cost = state.tx.access_list.0.iter().fold(0, |acc, item| {
+        acc + GasCost::ACCESS_LIST_ADDRESS.as_u64()
+            + GasCost::ACCESS_LIST_STORAGE_KEY.as_u64() * item.storage_keys.len() as u64
+    });
     let intrinsic_gas_cost = if state.tx.is_create() {
         GasCost::CREATION_TX.as_u64()
     } else {
         GasCost::TX.as_u64()
-    } + call_data_gas_cost;
+    } + call_data_gas_cost
+        + access_list_gas_cost;
     exec_step.gas_cost = GasCost(intrinsic_gas_cost);
 
+    // The root call reverts to the state after the nonce increase and the
+    // access list warm up.
+    state.call_ctx_mut()?.snapshot = state.sdb.snapshot();
+
     // Transfer with fee
     state.transfer_with_fee(
         &mut exec_step,
@@ -326,6 +436,7 @@ pub fn gen_begin_tx_ops(state: &mut CircuitInputStateRef) -> Result<ExecStep, Er
     ) {
         // 1. Creation transaction.
         (true, _, _) => {
+            state.sdb.create_contract(call.address);
             warn!("Creation transaction is left unimplemented");
             Ok(exec_step)
         }
@@ -397,7 +508,7 @@ pub fn gen_end_tx_ops(
     state: &mut CircuitInputStateRef,
     cumulative_gas_used: &mut HashMap<usize, u64>,
 ) -> Result<ExecStep, Error> {
-    let mut exec_step = state.new_end_tx_step();
+    let mut exec_step = state.new_end_tx_step()?;
     let call = state.tx.calls()[0].clone();
 
     state.push_op(
@@ -430,8 +541,10 @@ pub fn gen_end_tx_ops(
         },
     );
 
-    let effective_refund =
-        refund.min((state.tx.gas - exec_step.gas_left.0) / MAX_REFUND_QUOTIENT_OF_GAS_USED as u64);
+    let max_refund_quotient = state.block.chain_config.gas_schedule().max_refund_quotient;
+    let effective_refund = refund.min((state.tx.gas - exec_step.gas_left.0) / max_refund_quotient);
+    // The gas used by the tx is net of the refund.
+    let gas_used = state.tx.gas - exec_step.gas_left.0 - effective_refund;
     let (found, caller_account) = state.sdb.get_account_mut(&call.caller_address);
     if !found {
         return Err(Error::AccountNotFound(call.caller_address));
@@ -456,8 +569,7 @@ pub fn gen_end_tx_ops(
         return Err(Error::AccountNotFound(state.block.coinbase));
     }
     let coinbase_balance_prev = coinbase_account.balance;
-    let coinbase_balance =
-        coinbase_account.balance + effective_tip * (state.tx.gas - exec_step.gas_left.0);
+    let coinbase_balance = coinbase_account.balance + effective_tip * gas_used;
     state.push_op(
         &mut exec_step,
         RW::WRITE,
@@ -491,10 +603,11 @@ pub fn gen_end_tx_ops(
         },
     );
 
-    let gas_used = state.tx.gas - exec_step.gas_left.0;
+    // The cumulative gas used restarts at the first transaction of each block,
+    // for which there is no previous transaction in `cumulative_gas_used`.
     let mut current_cumulative_gas_used: u64 = 0;
-    if state.tx_ctx.id() > 1 {
-        current_cumulative_gas_used = *cumulative_gas_used.get(&(state.tx_ctx.id() - 1)).unwrap();
+    if let Some(prev_cumulative_gas_used) = cumulative_gas_used.get(&(state.tx_ctx.id() - 1)) {
+        current_cumulative_gas_used = *prev_cumulative_gas_used;
         // query pre tx cumulative gas
         state.push_op(
             &mut exec_step,
@@ -533,150 +646,3 @@ pub fn gen_end_tx_ops(
 
     Ok(exec_step)
 }
-
-fn dummy_gen_call_ops(
-    state: &mut CircuitInputStateRef,
-    geth_steps: &[GethExecStep],
-) -> Result<Vec<ExecStep>, Error> {
-    let geth_step = &geth_steps[0];
-    let mut exec_step = state.new_step(geth_step)?;
-
-    let tx_id = state.tx_ctx.id();
-    let call = state.parse_call(geth_step)?;
-
-    let (_, account) = state.sdb.get_account(&call.address);
-    let callee_code_hash = account.code_hash;
-
-    let is_warm = state.sdb.check_account_in_access_list(&call.address);
-    state.push_op_reversible(
-        &mut exec_step,
-        RW::WRITE,
-        TxAccessListAccountOp {
-            tx_id,
-            address: call.address,
-            is_warm: true,
-            is_warm_prev: is_warm,
-        },
-    )?;
-
-    state.push_call(call.clone(), geth_step);
-
-    match (
-        state.is_precompiled(&call.address),
-        callee_code_hash.to_fixed_bytes() == *EMPTY_HASH,
-    ) {
-        // 1. Call to precompiled.
-        (true, _) => Ok(vec![exec_step]),
-        // 2. Call to account with empty code.
-        (_, true) => {
-            state.handle_return(geth_step)?;
-            Ok(vec![exec_step])
-        }
-        // 3. Call to account with non-empty code.
-        (_, false) => Ok(vec![exec_step]),
-    }
-}
-
-fn dummy_gen_create_ops(
-    state: &mut CircuitInputStateRef,
-    geth_steps: &[GethExecStep],
-) -> Result<Vec<ExecStep>, Error> {
-    let geth_step = &geth_steps[0];
-    let mut exec_step = state.new_step(geth_step)?;
-
-    let tx_id = state.tx_ctx.id();
-    let call = state.parse_call(geth_step)?;
-
-    // Increase caller's nonce
-    let nonce_prev = state.sdb.get_nonce(&call.caller_address);
-    state.push_op_reversible(
-        &mut exec_step,
-        RW::WRITE,
-        AccountOp {
-            address: call.caller_address,
-            field: AccountField::Nonce,
-            value: (nonce_prev + 1).into(),
-            value_prev: nonce_prev.into(),
-        },
-    )?;
-
-    // Add callee into access list
-    let is_warm = state.sdb.check_account_in_access_list(&call.address);
-    state.push_op_reversible(
-        &mut exec_step,
-        RW::WRITE,
-        TxAccessListAccountOp {
-            tx_id,
-            address: call.address,
-            is_warm: true,
-            is_warm_prev: is_warm,
-        },
-    )?;
-
-    state.push_call(call.clone(), geth_step);
-
-    // Increase callee's nonce
-    let nonce_prev = state.sdb.get_nonce(&call.address);
-    debug_assert!(nonce_prev == 0);
-    state.push_op_reversible(
-        &mut exec_step,
-        RW::WRITE,
-        AccountOp {
-            address: call.address,
-            field: AccountField::Nonce,
-            value: 1.into(),
-            value_prev: 0.into(),
-        },
-    )?;
-
-    state.transfer(
-        &mut exec_step,
-        call.caller_address,
-        call.address,
-        call.value,
-    )?;
-
-    if call.code_hash.to_fixed_bytes() == *EMPTY_HASH {
-        // 1. Create with empty initcode.
-        state.handle_return(geth_step)?;
-        Ok(vec![exec_step])
-    } else {
-        // 2. Create with non-empty initcode.
-        Ok(vec![exec_step])
-    }
-}
-
-fn dummy_gen_selfdestruct_ops(
-    state: &mut CircuitInputStateRef,
-    geth_steps: &[GethExecStep],
-) -> Result<Vec<ExecStep>, Error> {
-    let geth_step = &geth_steps[0];
-    let mut exec_step = state.new_step(geth_step)?;
-    let sender = state.call()?.address;
-    let receiver = geth_step.stack.last()?.to_address();
-
-    let is_warm = state.sdb.check_account_in_access_list(&receiver);
-    state.push_op_reversible(
-        &mut exec_step,
-        RW::WRITE,
-        TxAccessListAccountOp {
-            tx_id: state.tx_ctx.id(),
-            address: receiver,
-            is_warm: true,
-            is_warm_prev: is_warm,
-        },
-    )?;
-
-    let (found, receiver_account) = state.sdb.get_account(&receiver);
-    if !found {
-        return Err(Error::AccountNotFound(receiver));
-    }
-    let value = receiver_account.balance;
-    state.transfer(&mut exec_step, sender, receiver, value)?;
-
-    if state.call()?.is_persistent {
-        state.sdb.destruct_account(sender);
-    }
-
-    Ok(vec![exec_step])
-}
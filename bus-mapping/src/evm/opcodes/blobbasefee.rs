@@ -0,0 +1,88 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{CircuitInputStateRef, ExecStep},
+    Error,
+};
+use eth_types::GethExecStep;
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the
+/// [`OpcodeId::BLOBBASEFEE`](crate::evm::OpcodeId::BLOBBASEFEE) `OpcodeId`.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Blobbasefee;
+
+impl Opcode for Blobbasefee {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+
+        // Stack write of the blob base fee of the block
+        state.stack_write(
+            &mut exec_step,
+            geth_step.stack.last_filled().map(|a| a - 1),
+            state.block.blob_base_fee(),
+        )?;
+
+        Ok(vec![exec_step])
+    }
+}
+
+#[cfg(test)]
+mod blobbasefee_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::ExecState,
+        evm::{Hardfork, OpcodeId},
+        mock::{trace_code, BlockData},
+        operation::{StackOp, RW},
+    };
+    use eth_types::{bytecode, evm_types::StackAddress, geth_types::GethData, Word};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn blobbasefee_opcode_impl() {
+        // e ** 10 wei
+        let (excess_blob_gas, blob_base_fee) = (33384770, Word::from(22026));
+        let code = bytecode! {
+            BLOBBASEFEE
+            STOP
+        };
+        let mut block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code.clone()),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        // The geth of geth-utils can't trace BLOBBASEFEE.
+        block.geth_traces[0] =
+            trace_code(&block.eth_block.transactions[0], &code, &[], blob_base_fee);
+
+        let mut block_data = BlockData::new_from_geth_data(block.clone());
+        block_data.hardfork = Hardfork::Cancun;
+        let mut builder = block_data.new_circuit_input_builder();
+        builder.block.excess_blob_gas = excess_blob_gas;
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::BLOBBASEFEE))
+            .unwrap();
+        let operation = &builder.block.container.stack[step.bus_mapping_instance[0].as_usize()];
+        assert_eq!(
+            (operation.rw(), operation.op()),
+            (
+                RW::WRITE,
+                &StackOp::new(1, StackAddress::from(1023), blob_base_fee)
+            )
+        );
+    }
+}
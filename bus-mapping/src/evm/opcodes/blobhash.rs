@@ -0,0 +1,133 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{CircuitInputStateRef, ExecStep},
+    operation::CallContextField,
+    Error,
+};
+use eth_types::{GethExecStep, ToWord, Word};
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the
+/// [`OpcodeId::BLOBHASH`](crate::evm::OpcodeId::BLOBHASH) `OpcodeId`.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Blobhash;
+
+impl Opcode for Blobhash {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+        let stack_address = geth_step.stack.last_filled();
+
+        let index = geth_step.stack.last()?;
+        state.stack_read(&mut exec_step, stack_address, index)?;
+
+        // CallContext read of the TxId, whose blob hashes are indexed
+        let tx_id = state.tx_ctx.id();
+        state.call_context_read(
+            &mut exec_step,
+            state.call()?.call_id,
+            CallContextField::TxId,
+            tx_id.into(),
+        );
+
+        // Indexes past the blob hashes of the transaction read a zero hash.
+        let blob_hash = if index < Word::from(state.tx.blob_hashes.len()) {
+            state.tx.blob_hashes[index.as_usize()].to_word()
+        } else {
+            Word::zero()
+        };
+        state.stack_write(&mut exec_step, stack_address, blob_hash)?;
+
+        Ok(vec![exec_step])
+    }
+}
+
+#[cfg(test)]
+mod blobhash_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::ExecState,
+        evm::{Hardfork, OpcodeId},
+        mock::{trace_code, BlockData},
+        operation::{CallContextOp, StackOp, RW},
+    };
+    use eth_types::{bytecode, evm_types::StackAddress, geth_types::GethData, H256};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    fn test_ok(index: u64, expected: Word) {
+        let blob_hashes = vec![H256::repeat_byte(0x01), H256::repeat_byte(0x02)];
+        let code = bytecode! {
+            PUSH32(index)
+            BLOBHASH
+            STOP
+        };
+        let mut block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code.clone()),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        // The geth of geth-utils can't trace BLOBHASH.
+        let tx = &block.eth_block.transactions[0];
+        block.geth_traces[0] = trace_code(tx, &code, &blob_hashes, Word::one());
+
+        let mut block_data = BlockData::new_from_geth_data(block.clone());
+        block_data.hardfork = Hardfork::Cancun;
+        let mut builder = block_data.new_circuit_input_builder();
+        builder.blob_hashes.insert(tx.hash, blob_hashes);
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::BLOBHASH))
+            .unwrap();
+        let container = &builder.block.container;
+
+        assert_eq!(
+            [0, 2]
+                .map(|idx| &container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1023), Word::from(index))
+                ),
+                (
+                    RW::WRITE,
+                    &StackOp::new(1, StackAddress::from(1023), expected)
+                ),
+            ]
+        );
+        let call_context_op = &container.call_context[step.bus_mapping_instance[1].as_usize()];
+        assert_eq!(
+            (call_context_op.rw(), call_context_op.op()),
+            (
+                RW::READ,
+                &CallContextOp {
+                    call_id: builder.block.txs()[0].calls()[0].call_id,
+                    field: CallContextField::TxId,
+                    value: Word::one(),
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn blobhash_opcode_impl() {
+        test_ok(1, H256::repeat_byte(0x02).to_word());
+    }
+
+    #[test]
+    fn blobhash_opcode_impl_out_of_range() {
+        test_ok(2, Word::zero());
+    }
+}
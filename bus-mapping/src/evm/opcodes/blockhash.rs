@@ -0,0 +1,167 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{CircuitInputStateRef, ExecStep},
+    Error,
+};
+use eth_types::{GethExecStep, Word};
+
+/// Number of most recent blocks whose hashes are accessible through
+/// BLOCKHASH.
+const NUM_PREV_BLOCK_ALLOWED: u64 = 256;
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the
+/// [`OpcodeId::BLOCKHASH`](crate::evm::OpcodeId::BLOCKHASH) `OpcodeId`.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Blockhash;
+
+impl Opcode for Blockhash {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+        let stack_address = geth_step.stack.last_filled();
+
+        let block_number = geth_step.stack.last()?;
+        state.stack_read(&mut exec_step, stack_address, block_number)?;
+
+        let block_hash = block_hash(
+            state.block.number,
+            &state.block.history_hashes,
+            block_number,
+        );
+        state.stack_write(&mut exec_step, stack_address, block_hash)?;
+
+        Ok(vec![exec_step])
+    }
+}
+
+/// Returns the hash of the block `block_number` from the history of the block
+/// `current_block_number`, where `history_hashes` holds the hashes of the most
+/// recent blocks with the latest one at the end.  Blocks outside of the 256
+/// most recent ones (including the current block and future ones) have a zero
+/// hash.
+fn block_hash(current_block_number: Word, history_hashes: &[Word], block_number: Word) -> Word {
+    if block_number >= current_block_number {
+        return Word::zero();
+    }
+    let distance = current_block_number - block_number;
+    if distance > Word::from(NUM_PREV_BLOCK_ALLOWED) || distance > Word::from(history_hashes.len())
+    {
+        return Word::zero();
+    }
+    history_hashes[history_hashes.len() - distance.as_usize()]
+}
+
+#[cfg(test)]
+mod blockhash_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::ExecState,
+        evm::OpcodeId,
+        mock::BlockData,
+        operation::{StackOp, RW},
+    };
+    use eth_types::{bytecode, evm_types::StackAddress, geth_types::GethData};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    fn test_ok(block_number: u64, history_hashes: Vec<Word>, queried_number: u64) {
+        let code = bytecode! {
+            PUSH32(queried_number)
+            BLOCKHASH
+            STOP
+        };
+
+        // Get the execution steps from the external tracer
+        let block: GethData = TestContext::<2, 1>::new(
+            Some(history_hashes.clone()),
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(block_number),
+        )
+        .unwrap()
+        .into();
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::BLOCKHASH))
+            .unwrap();
+
+        // Cross-check the block hash against the one returned by geth.
+        let geth_step_index = block.geth_traces[0]
+            .struct_logs
+            .iter()
+            .position(|step| step.op == OpcodeId::BLOCKHASH)
+            .unwrap();
+        let block_hash = block.geth_traces[0].struct_logs[geth_step_index + 1]
+            .stack
+            .last()
+            .unwrap();
+        let expected = match block_number.checked_sub(queried_number) {
+            Some(distance) if distance > 0 && distance <= history_hashes.len() as u64 => {
+                history_hashes[history_hashes.len() - distance as usize]
+            }
+            _ => Word::zero(),
+        };
+        assert_eq!(block_hash, expected);
+
+        assert_eq!(
+            [0, 1]
+                .map(|idx| &builder.block.container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1023), Word::from(queried_number))
+                ),
+                (
+                    RW::WRITE,
+                    &StackOp::new(1, StackAddress::from(1023), block_hash)
+                ),
+            ]
+        );
+    }
+
+    fn history_hashes(len: u64) -> Vec<Word> {
+        (0..len).map(|i| Word::from(0xbeef0000u64 + i)).collect()
+    }
+
+    #[test]
+    fn blockhash_opcode_latest_block() {
+        test_ok(0xcafe, history_hashes(256), 0xcafe - 1);
+    }
+
+    #[test]
+    fn blockhash_opcode_oldest_block() {
+        test_ok(0xcafe, history_hashes(256), 0xcafe - 256);
+    }
+
+    #[test]
+    fn blockhash_opcode_within_history() {
+        test_ok(0xcafe, history_hashes(256), 0xcafe - 100);
+    }
+
+    #[test]
+    fn blockhash_opcode_out_of_window() {
+        test_ok(0xcafe, history_hashes(256), 0xcafe - 257);
+    }
+
+    #[test]
+    fn blockhash_opcode_current_block() {
+        test_ok(0xcafe, history_hashes(256), 0xcafe);
+    }
+
+    #[test]
+    fn blockhash_opcode_future_block() {
+        test_ok(0xcafe, history_hashes(256), 0xcafe + 1);
+    }
+}
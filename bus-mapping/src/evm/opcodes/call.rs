@@ -1,21 +1,25 @@
 use super::Opcode;
 use crate::{
-    circuit_input_builder::{CircuitInputStateRef, ExecStep},
-    operation::{AccountField, CallContextField, TxAccessListAccountOp, RW},
+    circuit_input_builder::{CallKind, CircuitInputStateRef, CodeSource, ExecState, ExecStep},
+    error::{ExecError, TraceError},
+    evm::{PrecompileCall, PrecompileCalls},
+    operation::{AccountField, CallContextField, MemoryOp, TxAccessListAccountOp, RW},
     Error,
 };
 use eth_types::{
     evm_types::{
         gas_utils::{eip150_gas, memory_expansion_gas_cost},
-        GasCost,
+        Gas, GasCost, GAS_STIPEND_CALL_WITH_VALUE,
     },
-    GethExecStep, ToWord,
+    Address, GethExecStep, ToWord,
 };
 use keccak256::EMPTY_HASH;
-use log::warn;
+use ripemd160::{Digest, Ripemd160};
+use sha2::Sha256;
 
 /// Placeholder structure used to implement [`Opcode`] trait over it
-/// corresponding to the `OpcodeId::CALL` `OpcodeId`.
+/// corresponding to the `OpcodeId::CALL`, `OpcodeId::CALLCODE`,
+/// `OpcodeId::DELEGATECALL` and `OpcodeId::STATICCALL` `OpcodeId`s.
 #[derive(Debug, Copy, Clone)]
 pub(crate) struct Call;
 
@@ -31,6 +35,29 @@ impl Opcode for Call {
         let current_call = state.call()?.clone();
         let call = state.parse_call(geth_step)?;
 
+        // The callee code is executed in a new call when the next step goes
+        // one level deeper.  Otherwise, the call may have failed before
+        // running, in which case the reason is found here.
+        let next_step = geth_steps.get(1);
+        let error = if next_step.map_or(false, |s| s.depth == geth_step.depth + 1) {
+            None
+        } else {
+            state.get_step_err(geth_step, next_step)?
+        };
+
+        // CALL and CALLCODE take a value argument, DELEGATECALL and STATICCALL
+        // don't.
+        let n_pop = match call.kind {
+            CallKind::Call | CallKind::CallCode => 7,
+            _ => 6,
+        };
+        // Address whose code is executed. CALLCODE and DELEGATECALL execute it
+        // in the storage context of the caller (`call.address`).
+        let code_address = match call.code_source {
+            CodeSource::Address(address) => address,
+            _ => call.address,
+        };
+
         // NOTE: For `RwCounterEndOfReversion` we use the `0` value as a placeholder,
         // and later set the proper value in
         // `CircuitInputBuilder::set_value_ops_call_context_rwc_eor`
@@ -54,7 +81,20 @@ impl Opcode for Call {
             state.call_context_read(&mut exec_step, current_call.call_id, field, value);
         }
 
-        for i in 0..7 {
+        // DELEGATECALL inherits the caller address and value of the current call
+        if call.kind == CallKind::DelegateCall {
+            for (field, value) in [
+                (
+                    CallContextField::CallerAddress,
+                    current_call.caller_address.to_word(),
+                ),
+                (CallContextField::Value, current_call.value),
+            ] {
+                state.call_context_read(&mut exec_step, current_call.call_id, field, value);
+            }
+        }
+
+        for i in 0..n_pop {
             state.stack_read(
                 &mut exec_step,
                 geth_step.stack.nth_last_filled(i),
@@ -64,24 +104,24 @@ impl Opcode for Call {
 
         state.stack_write(
             &mut exec_step,
-            geth_step.stack.nth_last_filled(6),
+            geth_step.stack.nth_last_filled(n_pop - 1),
             (call.is_success as u64).into(),
         )?;
 
-        let is_warm = state.sdb.check_account_in_access_list(&call.address);
+        let is_warm = state.sdb.check_account_in_access_list(&code_address);
         state.push_op_reversible(
             &mut exec_step,
             RW::WRITE,
             TxAccessListAccountOp {
                 tx_id,
-                address: call.address,
+                address: code_address,
                 is_warm: true,
                 is_warm_prev: is_warm,
             },
         )?;
 
         // Switch to callee's call context
-        state.push_call(call.clone(), geth_step);
+        state.push_call(call.clone(), geth_step)?;
 
         for (field, value) in [
             (CallContextField::RwCounterEndOfReversion, 0.into()),
@@ -93,22 +133,46 @@ impl Opcode for Call {
             state.call_context_read(&mut exec_step, call.call_id, field, value);
         }
 
-        state.transfer(
-            &mut exec_step,
-            call.caller_address,
-            call.address,
-            call.value,
-        )?;
+        // The call fails before touching the state when the depth limit is
+        // reached or the caller can't afford the value.
+        if let Some(error @ (ExecError::Depth | ExecError::InsufficientBalance)) = error {
+            exec_step.error = Some(error);
+            for (field, value) in [
+                (CallContextField::LastCalleeId, 0.into()),
+                (CallContextField::LastCalleeReturnDataOffset, 0.into()),
+                (CallContextField::LastCalleeReturnDataLength, 0.into()),
+            ] {
+                state.call_context_write(&mut exec_step, current_call.call_id, field, value);
+            }
+            state.handle_return(geth_step)?;
+            return Ok(vec![exec_step]);
+        }
+
+        // The emptiness of the callee decides the cost of a call with value, so
+        // it's taken before the value is transferred to it.
+        let is_account_empty = state.sdb.get_account(&code_address).1.is_empty();
+
+        // Only CALL moves value to another account. CALLCODE sends it to the
+        // caller itself, and DELEGATECALL and STATICCALL don't transfer any.
+        if call.kind == CallKind::Call {
+            // Precompiled contracts may not exist in the state yet.
+            state.sdb.get_account_mut(&call.address);
+            state.transfer(
+                &mut exec_step,
+                call.caller_address,
+                call.address,
+                call.value,
+            )?;
+        }
 
-        let (_, callee_account) = state.sdb.get_account(&call.address);
-        let is_account_empty = callee_account.is_empty();
+        let (_, callee_account) = state.sdb.get_account(&code_address);
         let callee_nonce = callee_account.nonce;
         let callee_code_hash = callee_account.code_hash;
         for (field, value) in [
             (AccountField::Nonce, callee_nonce),
             (AccountField::CodeHash, callee_code_hash.to_word()),
         ] {
-            state.account_read(&mut exec_step, call.address, field, value, value)?;
+            state.account_read(&mut exec_step, code_address, field, value, value)?;
         }
 
         // Calculate next_memory_word_size and callee_gas_left manually in case
@@ -121,14 +185,15 @@ impl Opcode for Call {
         .into_iter()
         .max()
         .unwrap();
-        let has_value = !call.value.is_zero();
+        let has_value =
+            !call.value.is_zero() && matches!(call.kind, CallKind::Call | CallKind::CallCode);
         let gas_cost = if is_warm {
             GasCost::WARM_ACCESS.as_u64()
         } else {
             GasCost::COLD_ACCOUNT_ACCESS.as_u64()
         } + if has_value {
             GasCost::CALL_WITH_VALUE.as_u64()
-                + if is_account_empty {
+                + if is_account_empty && call.kind == CallKind::Call {
                     GasCost::NEW_ACCOUNT.as_u64()
                 } else {
                     0
@@ -143,13 +208,29 @@ impl Opcode for Call {
 
         // There are 3 branches from here.
         match (
-            state.is_precompiled(&call.address),
+            state.is_precompiled(&code_address),
             callee_code_hash.to_fixed_bytes() == *EMPTY_HASH,
         ) {
             // 1. Call to precompiled.
             (true, _) => {
-                warn!("Call to precompiled is left unimplemented");
-                Ok(vec![exec_step])
+                let caller_gas_left = geth_step.gas.0 - gas_cost - callee_gas_left;
+                // The stipend of a call with value is given to the callee for free.
+                let callee_gas_left = callee_gas_left
+                    + if has_value {
+                        GAS_STIPEND_CALL_WITH_VALUE
+                    } else {
+                        0
+                    };
+                let precompile_step = gen_precompile_step(
+                    state,
+                    &mut exec_step,
+                    geth_steps,
+                    &call,
+                    code_address,
+                    caller_gas_left,
+                    callee_gas_left,
+                )?;
+                Ok(vec![exec_step, precompile_step])
             }
             // 2. Call to account with empty code.
             (_, true) => {
@@ -172,7 +253,7 @@ impl Opcode for Call {
                     ),
                     (
                         CallContextField::StackPointer,
-                        (geth_step.stack.stack_pointer().0 + 6).into(),
+                        (geth_step.stack.stack_pointer().0 + n_pop - 1).into(),
                     ),
                     (
                         CallContextField::GasLeft,
@@ -230,3 +311,618 @@ impl Opcode for Call {
         }
     }
 }
+
+/// Generate the virtual step of a call to the precompiled contract at
+/// `code_address`, which reads its input from the caller memory and copies its
+/// output into the return data region of the caller memory.
+fn gen_precompile_step(
+    state: &mut CircuitInputStateRef,
+    exec_step: &mut ExecStep,
+    geth_steps: &[GethExecStep],
+    call: &crate::circuit_input_builder::Call,
+    code_address: Address,
+    caller_gas_left: u64,
+    callee_gas_left: u64,
+) -> Result<ExecStep, Error> {
+    let geth_step = &geth_steps[0];
+    let kind = PrecompileCalls::from_address(&code_address)
+        .ok_or(Error::UnknownPrecompile(code_address))?;
+
+    let input = geth_step
+        .memory
+        .read_chunk(call.call_data_offset.into(), call.call_data_length.into());
+    // A failing precompiled contract consumes all the gas it was given and
+    // returns nothing.
+    let (output, gas_used) = if call.is_success {
+        let next_step = geth_steps.get(1).ok_or_else(|| {
+            Error::InvalidGethExecStep(TraceError::MissingNextStep, geth_step.clone())
+        })?;
+        let output = get_precompile_output(kind, &input, call, next_step)?;
+        let gas_used = next_step
+            .gas
+            .0
+            .checked_sub(caller_gas_left)
+            .and_then(|returned_gas| callee_gas_left.checked_sub(returned_gas))
+            .ok_or_else(|| {
+                Error::InvalidGethExecStep(TraceError::GasUnderflow, next_step.clone())
+            })?;
+        (output, gas_used)
+    } else {
+        (Vec::new(), callee_gas_left)
+    };
+
+    for (field, value) in [
+        (CallContextField::LastCalleeId, call.call_id.into()),
+        (CallContextField::LastCalleeReturnDataOffset, 0.into()),
+        (
+            CallContextField::LastCalleeReturnDataLength,
+            output.len().into(),
+        ),
+    ] {
+        state.call_context_write(exec_step, call.caller_id, field, value);
+    }
+
+    let mut precompile_step = state.new_step(geth_step)?;
+    precompile_step.exec_state = ExecState::Precompile(kind);
+    precompile_step.gas_left = Gas(callee_gas_left);
+    precompile_step.gas_cost = GasCost(gas_used);
+
+    for (idx, byte) in input.iter().enumerate() {
+        state.push_op(
+            &mut precompile_step,
+            RW::READ,
+            MemoryOp::new(
+                call.caller_id,
+                (call.call_data_offset as usize + idx).into(),
+                *byte,
+            ),
+        );
+    }
+    let copy_length = std::cmp::min(output.len(), call.return_data_length as usize);
+    for (idx, byte) in output.iter().take(copy_length).enumerate() {
+        state.push_op(
+            &mut precompile_step,
+            RW::WRITE,
+            MemoryOp::new(
+                call.caller_id,
+                (call.return_data_offset as usize + idx).into(),
+                *byte,
+            ),
+        );
+    }
+
+    precompile_step.precompile_call = Some(PrecompileCall {
+        kind,
+        input,
+        output: output.clone(),
+        gas_used,
+        is_success: call.is_success,
+    });
+
+    state.handle_return(geth_step)?;
+
+    // The output of the precompiled contract is the return data of the call.
+    let caller_ctx = state.call_ctx_mut()?;
+    caller_ctx.last_callee_id = call.call_id;
+    caller_ctx.last_callee_return_data_offset = 0;
+    caller_ctx.last_callee_return_data_length = output.len() as u64;
+    caller_ctx.return_data = output;
+
+    Ok(precompile_step)
+}
+
+/// Return the output of a successful call to a precompiled contract.  The
+/// hash functions and the identity are computed from the input.  For the
+/// others the output is taken from the return data region of the caller memory
+/// in the step following the call, which is the only part of the callee return
+/// data recorded in the trace, so an output longer than that region is an
+/// error.
+fn get_precompile_output(
+    kind: PrecompileCalls,
+    input: &[u8],
+    call: &crate::circuit_input_builder::Call,
+    next_step: &GethExecStep,
+) -> Result<Vec<u8>, Error> {
+    match kind {
+        PrecompileCalls::Sha256 => return Ok(Sha256::digest(input).to_vec()),
+        // The 20 bytes hash is left padded to a word.
+        PrecompileCalls::Ripemd160 => {
+            let mut output = vec![0u8; 12];
+            output.extend_from_slice(&Ripemd160::digest(input));
+            return Ok(output);
+        }
+        PrecompileCalls::Identity => return Ok(input.to_vec()),
+        _ => (),
+    }
+
+    let length = kind.output_length(input);
+    if length > call.return_data_length.into() {
+        return Err(Error::UnknownPrecompileOutput(kind));
+    }
+    Ok(next_step
+        .memory
+        .read_chunk(call.return_data_offset.into(), length.as_usize().into()))
+}
+
+#[cfg(test)]
+mod call_tests {
+    use super::*;
+    use crate::{error::ExecError, mock::BlockData};
+    use eth_types::{bytecode, evm_types::OpcodeId, geth_types::GethData, Bytecode, Word};
+    use mock::test_ctx::TestContext;
+    use pretty_assertions::assert_eq;
+
+    const CALL_VALUE: u64 = 0x10;
+    const TX_VALUE: u64 = 0x20;
+
+    // code_a calls code_b with the given call opcode, and then stops.
+    fn gen_block(op: OpcodeId, code_b: Bytecode) -> GethData {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+
+        let mut code_a = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+        };
+        if matches!(op, OpcodeId::CALL | OpcodeId::CALLCODE) {
+            code_a.append(&bytecode! {
+                PUSH1(CALL_VALUE) // value
+            });
+        }
+        code_a.append(&bytecode! {
+            PUSH32(addr_b.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+        });
+        code_a.write_op(op);
+        code_a.append(&bytecode! { STOP });
+
+        TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(addr_a)
+                    .balance(Word::from(1u64 << 20))
+                    .code(code_a);
+                accs[1].address(addr_b).code(code_b);
+                accs[2]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0]
+                    .to(accs[0].address)
+                    .from(accs[2].address)
+                    .value(Word::from(TX_VALUE));
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn handle_block(block: &GethData) -> crate::circuit_input_builder::CircuitInputBuilder {
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        builder
+    }
+
+    // Callee that writes into its storage context.
+    fn code_sstore() -> Bytecode {
+        bytecode! {
+            PUSH1(0x01) // value
+            PUSH1(0x00) // key
+            SSTORE
+            STOP
+        }
+    }
+
+    fn test_call_kind(op: OpcodeId, kind: CallKind) {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+        let block = gen_block(op, code_sstore());
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let caller = &transaction.calls()[0];
+        let callee = &transaction.calls()[1];
+        assert_eq!(callee.kind, kind);
+        assert_eq!(callee.caller_id, caller.call_id);
+        assert!(matches!(callee.code_source, CodeSource::Address(address) if address == addr_b));
+        assert!(callee.is_success);
+        assert!(!callee.is_static);
+
+        // CALLCODE and DELEGATECALL keep the storage context of the caller, and
+        // DELEGATECALL also keeps its caller address and value.
+        let (caller_address, address, value) = match kind {
+            CallKind::Call => (addr_a, addr_b, Word::from(CALL_VALUE)),
+            CallKind::CallCode => (addr_a, addr_a, Word::from(CALL_VALUE)),
+            CallKind::DelegateCall => (caller.caller_address, addr_a, Word::from(TX_VALUE)),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            (callee.caller_address, callee.address, callee.value),
+            (caller_address, address, value)
+        );
+
+        let sstore_step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::SSTORE))
+            .unwrap();
+        assert_eq!(sstore_step.error, None);
+        let storage_op = sstore_step
+            .bus_mapping_instance
+            .iter()
+            .find(|op_ref| op_ref.target() == crate::operation::Target::Storage)
+            .map(|op_ref| &builder.block.container.storage[op_ref.as_usize()])
+            .unwrap();
+        assert_eq!(storage_op.op().address, address);
+    }
+
+    #[test]
+    fn call_opcode_impl() {
+        test_call_kind(OpcodeId::CALL, CallKind::Call);
+    }
+
+    #[test]
+    fn callcode_opcode_impl() {
+        test_call_kind(OpcodeId::CALLCODE, CallKind::CallCode);
+    }
+
+    #[test]
+    fn delegatecall_opcode_impl() {
+        test_call_kind(OpcodeId::DELEGATECALL, CallKind::DelegateCall);
+    }
+
+    #[test]
+    fn staticcall_opcode_impl() {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+        let block = gen_block(
+            OpcodeId::STATICCALL,
+            bytecode! {
+                PUSH1(0x00) // key
+                SLOAD
+                STOP
+            },
+        );
+        let builder = handle_block(&block);
+
+        let callee = &builder.block.txs()[0].calls()[1];
+        assert_eq!(callee.kind, CallKind::StaticCall);
+        assert_eq!(
+            (callee.caller_address, callee.address, callee.value),
+            (addr_a, addr_b, Word::zero())
+        );
+        assert!(callee.is_static);
+        assert!(callee.is_success);
+    }
+
+    fn test_write_protection(code_b: Bytecode, op: OpcodeId) {
+        let block = gen_block(OpcodeId::STATICCALL, code_b);
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let callee = &transaction.calls()[1];
+        assert!(callee.is_static);
+        assert!(!callee.is_success);
+
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(op))
+            .unwrap();
+        assert_eq!(step.error, Some(ExecError::WriteProtection));
+        assert_eq!(step.call_index, 1);
+        // The caller continues after the failed static call.
+        let last_stop = transaction
+            .steps()
+            .iter()
+            .rev()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::STOP))
+            .unwrap();
+        assert_eq!(last_stop.call_index, 0);
+    }
+
+    #[test]
+    fn staticcall_sstore_write_protection() {
+        test_write_protection(code_sstore(), OpcodeId::SSTORE);
+    }
+
+    #[test]
+    fn staticcall_log_write_protection() {
+        test_write_protection(
+            bytecode! {
+                PUSH1(0x00) // length
+                PUSH1(0x00) // offset
+                LOG0
+                STOP
+            },
+            OpcodeId::LOG0,
+        );
+    }
+
+    #[test]
+    fn staticcall_call_with_value_write_protection() {
+        test_write_protection(
+            bytecode! {
+                PUSH1(0x00) // retLength
+                PUSH1(0x00) // retOffset
+                PUSH1(0x00) // argsLength
+                PUSH1(0x00) // argsOffset
+                PUSH1(0x01) // value
+                PUSH32(mock::MOCK_ACCOUNTS[2].to_word()) // addr
+                PUSH32(0x1_0000) // gas
+                CALL
+                STOP
+            },
+            OpcodeId::CALL,
+        );
+    }
+
+    const PRECOMPILE_RET_OFFSET: usize = 0x20;
+
+    // Stores `input` at the start of memory and calls the precompiled contract
+    // `kind`, reserving `ret_length` bytes at PRECOMPILE_RET_OFFSET for its
+    // output.
+    fn test_precompile(
+        kind: PrecompileCalls,
+        input: &[u8],
+        ret_length: usize,
+        expected_output: &[u8],
+        expected_gas_used: u64,
+    ) {
+        let mut input_word = [0u8; 32];
+        input_word[..input.len()].copy_from_slice(input);
+        let code = bytecode! {
+            PUSH32(Word::from_big_endian(&input_word))
+            PUSH1(0x00)
+            MSTORE
+            PUSH1(ret_length) // retLength
+            PUSH1(PRECOMPILE_RET_OFFSET) // retOffset
+            PUSH1(input.len()) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x00) // value
+            PUSH1(kind as u64) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            STOP
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(mock::MOCK_ACCOUNTS[0]).code(code);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[1])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[1].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let caller = &transaction.calls()[0];
+        let callee = &transaction.calls()[1];
+        assert!(callee.is_success);
+        assert_eq!(callee.depth, 2);
+
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Precompile(kind))
+            .unwrap();
+        assert_eq!(
+            step.precompile_call,
+            Some(PrecompileCall {
+                kind,
+                input: input.to_vec(),
+                output: expected_output.to_vec(),
+                gas_used: expected_gas_used,
+                is_success: true,
+            })
+        );
+        assert_eq!(step.gas_cost.0, expected_gas_used);
+
+        // The return data of the call is the whole output.
+        let call_step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::CALL))
+            .unwrap();
+        let return_data_length = call_step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == crate::operation::Target::CallContext)
+            .map(|op_ref| builder.block.container.call_context[op_ref.as_usize()].op())
+            .find(|op| op.field == CallContextField::LastCalleeReturnDataLength)
+            .unwrap()
+            .value;
+        assert_eq!(return_data_length, expected_output.len().into());
+
+        // The input is read from the caller memory and the output is copied
+        // into its return data region.
+        let copy_length = std::cmp::min(expected_output.len(), ret_length);
+        let memory_ops = step
+            .bus_mapping_instance
+            .iter()
+            .map(|op_ref| &builder.block.container.memory[op_ref.as_usize()])
+            .map(|operation| (operation.rw(), operation.op().clone()))
+            .collect::<Vec<_>>();
+        let expected_memory_ops = input
+            .iter()
+            .enumerate()
+            .map(|(idx, byte)| (RW::READ, MemoryOp::new(caller.call_id, idx.into(), *byte)))
+            .chain(
+                expected_output
+                    .iter()
+                    .take(copy_length)
+                    .enumerate()
+                    .map(|(idx, byte)| {
+                        (
+                            RW::WRITE,
+                            MemoryOp::new(
+                                caller.call_id,
+                                (PRECOMPILE_RET_OFFSET + idx).into(),
+                                *byte,
+                            ),
+                        )
+                    }),
+            )
+            .collect::<Vec<_>>();
+        assert_eq!(memory_ops, expected_memory_ops);
+
+        // Cross-check the output against the caller memory after the call.
+        let geth_step_index = block.geth_traces[0]
+            .struct_logs
+            .iter()
+            .position(|step| step.op == OpcodeId::CALL)
+            .unwrap();
+        assert_eq!(
+            block.geth_traces[0].struct_logs[geth_step_index + 1]
+                .memory
+                .read_chunk(PRECOMPILE_RET_OFFSET.into(), copy_length.into()),
+            expected_output[..copy_length].to_vec()
+        );
+    }
+
+    #[test]
+    fn call_precompile_identity() {
+        let input = [0x01, 0x02, 0x03, 0x04, 0x05];
+        // 15 + 3 per input word
+        test_precompile(PrecompileCalls::Identity, &input, 0x20, &input, 18);
+    }
+
+    #[test]
+    fn call_precompile_identity_truncated_output() {
+        let input = [0x01, 0x02, 0x03, 0x04, 0x05];
+        test_precompile(PrecompileCalls::Identity, &input, 0x02, &input, 18);
+    }
+
+    #[test]
+    fn call_precompile_sha256() {
+        let output =
+            hex::decode("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                .unwrap();
+        // 60 + 12 per input word
+        test_precompile(PrecompileCalls::Sha256, b"abc", 0x20, &output, 72);
+    }
+
+    #[test]
+    fn call_precompile_sha256_truncated_output() {
+        // The return data is the whole output, even if only its first bytes
+        // are copied into memory.
+        let output =
+            hex::decode("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                .unwrap();
+        test_precompile(PrecompileCalls::Sha256, b"abc", 0x02, &output, 72);
+    }
+
+    #[test]
+    fn call_precompile_ripemd160() {
+        let output =
+            hex::decode("0000000000000000000000008eb208f7e05d987a9b044a8e98c6b087f15a0bfc")
+                .unwrap();
+        // 600 + 120 per input word
+        test_precompile(PrecompileCalls::Ripemd160, b"abc", 0x20, &output, 720);
+    }
+
+    #[test]
+    fn call_precompile_ecrecover_invalid_signature() {
+        // A zero `v` is invalid, so there's no output.
+        test_precompile(PrecompileCalls::ECRecover, &[0x01], 0x20, &[], 3000);
+    }
+
+    #[test]
+    fn staticcall_missing_account() {
+        let (addr_a, addr_c) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[3]);
+        let code_a = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH32(addr_c.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            STATICCALL
+            STOP
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_a).code(code_a);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[1].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block(&block);
+
+        // The account which doesn't exist is called as an empty one, and
+        // isn't created by the call.
+        let callee = &builder.block.txs()[0].calls()[1];
+        assert!(callee.is_success);
+        assert_eq!(callee.code_hash.to_fixed_bytes(), *EMPTY_HASH);
+        assert!(!builder.sdb.get_account(&addr_c).0);
+    }
+
+    #[test]
+    fn call_reverted_state() {
+        let (addr_a, addr_c) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[3]);
+        // code_a sends value to an account which doesn't exist, and then
+        // reverts.
+        let code_a = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(CALL_VALUE) // value
+            PUSH32(addr_c.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            PUSH1(0x00)
+            PUSH1(0x00)
+            REVERT
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(addr_a)
+                    .balance(Word::from(1u64 << 20))
+                    .code(code_a);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0]
+                    .to(accs[0].address)
+                    .from(accs[1].address)
+                    .value(Word::from(TX_VALUE));
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block(&block);
+
+        // The account created by the value transfer is gone along with the
+        // rest of the changes of the reverted call.
+        assert!(!builder.sdb.get_account(&addr_c).0);
+        assert_eq!(
+            builder.sdb.get_account(&addr_a).1.balance,
+            Word::from(1u64 << 20)
+        );
+    }
+}
@@ -1,13 +1,10 @@
 use crate::{
-    circuit_input_builder::{
-        CircuitInputStateRef, CopyDetails, ExecState, ExecStep, StepAuxiliaryData,
-    },
-    constants::MAX_COPY_BYTES,
+    circuit_input_builder::{CircuitInputStateRef, ExecStep},
     Error,
 };
-use eth_types::{GethExecStep, ToWord};
+use eth_types::GethExecStep;
 
-use super::Opcode;
+use super::{copy_code::gen_copy_code_to_memory_steps, Opcode};
 
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct Codecopy;
@@ -51,27 +48,6 @@ fn gen_codecopy_step(
     Ok(exec_step)
 }
 
-fn gen_memory_copy_step(
-    state: &mut CircuitInputStateRef,
-    exec_step: &mut ExecStep,
-    aux_data: StepAuxiliaryData,
-    code: &[u8],
-) -> Result<(), Error> {
-    for idx in 0..std::cmp::min(aux_data.bytes_left as usize, MAX_COPY_BYTES) {
-        let addr = (aux_data.src_addr as usize) + idx;
-        let byte = if addr < (aux_data.src_addr_end as usize) {
-            code[addr]
-        } else {
-            0
-        };
-        state.memory_write(exec_step, ((aux_data.dst_addr as usize) + idx).into(), byte)?;
-    }
-
-    exec_step.aux_data = Some(aux_data);
-
-    Ok(())
-}
-
 fn gen_memory_copy_steps(
     state: &mut CircuitInputStateRef,
     geth_steps: &[GethExecStep],
@@ -82,31 +58,15 @@ fn gen_memory_copy_steps(
 
     let code_hash = state.call()?.code_hash;
     let code = state.code(code_hash)?;
-    let src_addr_end = code.len() as u64;
-
-    let code_hash = code_hash.to_word();
-    let mut copied = 0;
-    let mut steps = vec![];
-    while copied < length {
-        let mut exec_step = state.new_step(&geth_steps[1])?;
-        exec_step.exec_state = ExecState::CopyCodeToMemory;
-        gen_memory_copy_step(
-            state,
-            &mut exec_step,
-            StepAuxiliaryData::new(
-                code_offset + copied,
-                dest_offset + copied,
-                length - copied,
-                src_addr_end,
-                CopyDetails::Code(code_hash),
-            ),
-            &code,
-        )?;
-        steps.push(exec_step);
-        copied += MAX_COPY_BYTES as u64;
-    }
-
-    Ok(steps)
+    gen_copy_code_to_memory_steps(
+        state,
+        geth_steps,
+        code_hash,
+        &code,
+        dest_offset,
+        code_offset,
+        length,
+    )
 }
 
 #[cfg(test)]
@@ -123,6 +83,7 @@ mod codecopy_tests {
     };
 
     use crate::{
+        circuit_input_builder::ExecState,
         mock::BlockData,
         operation::{MemoryOp, StackOp, RW},
     };
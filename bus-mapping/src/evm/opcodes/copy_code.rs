@@ -0,0 +1,68 @@
+use crate::{
+    circuit_input_builder::{
+        CircuitInputStateRef, CopyDetails, ExecState, ExecStep, StepAuxiliaryData,
+    },
+    constants::MAX_COPY_BYTES,
+    Error,
+};
+use eth_types::{GethExecStep, ToWord, H256};
+
+/// Generate the [`ExecState::CopyCodeToMemory`] steps of CODECOPY and
+/// EXTCODECOPY, which copy `length` bytes of `code` from `code_offset` into the
+/// memory at `dest_offset`, in chunks of [`MAX_COPY_BYTES`].  The bytes past the
+/// end of the code are zero.
+pub(super) fn gen_copy_code_to_memory_steps(
+    state: &mut CircuitInputStateRef,
+    geth_steps: &[GethExecStep],
+    code_hash: H256,
+    code: &[u8],
+    dest_offset: u64,
+    code_offset: u64,
+    length: u64,
+) -> Result<Vec<ExecStep>, Error> {
+    let src_addr_end = code.len() as u64;
+    let code_hash = code_hash.to_word();
+    let mut copied = 0;
+    let mut steps = vec![];
+    while copied < length {
+        let mut exec_step = state.new_step(&geth_steps[1])?;
+        exec_step.exec_state = ExecState::CopyCodeToMemory;
+        gen_copy_code_to_memory_step(
+            state,
+            &mut exec_step,
+            StepAuxiliaryData::new(
+                code_offset + copied,
+                dest_offset + copied,
+                length - copied,
+                src_addr_end,
+                CopyDetails::Code(code_hash),
+            ),
+            code,
+        )?;
+        steps.push(exec_step);
+        copied += MAX_COPY_BYTES as u64;
+    }
+
+    Ok(steps)
+}
+
+fn gen_copy_code_to_memory_step(
+    state: &mut CircuitInputStateRef,
+    exec_step: &mut ExecStep,
+    aux_data: StepAuxiliaryData,
+    code: &[u8],
+) -> Result<(), Error> {
+    for idx in 0..std::cmp::min(aux_data.bytes_left as usize, MAX_COPY_BYTES) {
+        let addr = (aux_data.src_addr as usize) + idx;
+        let byte = if addr < (aux_data.src_addr_end as usize) {
+            code[addr]
+        } else {
+            0
+        };
+        state.memory_write(exec_step, ((aux_data.dst_addr as usize) + idx).into(), byte)?;
+    }
+
+    exec_step.aux_data = Some(aux_data);
+
+    Ok(())
+}
@@ -0,0 +1,492 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{get_create_init_code, CircuitInputStateRef, ExecStep},
+    error::ExecError,
+    operation::{AccountField, AccountOp, CallContextField, TxAccessListAccountOp, RW},
+    Error,
+};
+use eth_types::{GethExecStep, ToWord, Word};
+use keccak256::EMPTY_HASH;
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the [`OpcodeId::CREATE`](crate::evm::OpcodeId::CREATE)
+/// and [`OpcodeId::CREATE2`](crate::evm::OpcodeId::CREATE2) `OpcodeId`s.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Create<const IS_CREATE2: bool>;
+
+impl<const IS_CREATE2: bool> Opcode for Create<IS_CREATE2> {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+
+        // The init code is executed in a new call when the next step goes one
+        // level deeper.  Otherwise, either the init code is empty or the
+        // create didn't happen, in which case the reason is found here.  Any
+        // error halting the current call has been handled before.
+        let next_step = geth_steps.get(1);
+        let is_init_code_executed = next_step.map_or(false, |s| s.depth == geth_step.depth + 1);
+        let error = if is_init_code_executed {
+            None
+        } else {
+            state.get_step_err(geth_step, next_step)?
+        };
+
+        let n_pop = if IS_CREATE2 { 4 } else { 3 };
+        for i in 0..n_pop {
+            state.stack_read(
+                &mut exec_step,
+                geth_step.stack.nth_last_filled(i),
+                geth_step.stack.nth_last(i)?,
+            )?;
+        }
+
+        let tx_id = state.tx_ctx.id();
+        let current_call = state.call()?.clone();
+        for (field, value) in [
+            (CallContextField::TxId, tx_id.into()),
+            (
+                CallContextField::RwCounterEndOfReversion,
+                current_call.rw_counter_end_of_reversion.into(),
+            ),
+            (
+                CallContextField::IsPersistent,
+                (current_call.is_persistent as u64).into(),
+            ),
+            (
+                CallContextField::IsStatic,
+                (current_call.is_static as u64).into(),
+            ),
+            (CallContextField::Depth, current_call.depth.into()),
+            (
+                CallContextField::CalleeAddress,
+                current_call.address.to_word(),
+            ),
+        ] {
+            state.call_context_read(&mut exec_step, current_call.call_id, field, value);
+        }
+
+        // Read the init code from the caller's memory
+        let offset = geth_step.stack.nth_last(1)?.low_u64() as usize;
+        for (idx, byte) in get_create_init_code(geth_step)?.into_iter().enumerate() {
+            state.memory_read(&mut exec_step, (offset + idx).into(), byte)?;
+        }
+
+        // The created address is derived from the caller's nonce (CREATE) or
+        // from the salt and the init code (CREATE2), so it must be computed
+        // before the nonce is increased.
+        let call = state.parse_call(geth_step)?;
+
+        state.stack_write(
+            &mut exec_step,
+            geth_step.stack.nth_last_filled(n_pop - 1),
+            if call.is_success {
+                call.address.to_word()
+            } else {
+                Word::zero()
+            },
+        )?;
+
+        match error {
+            // 1. The create fails before touching the state.
+            Some(ExecError::Depth | ExecError::InsufficientBalance) => {
+                exec_step.error = error;
+                state.push_call(call, geth_step)?;
+                state.handle_return(geth_step)?;
+                return Ok(vec![exec_step]);
+            }
+            Some(ExecError::ContractAddressCollision) => exec_step.error = error,
+            _ => {}
+        }
+
+        // Increase caller's nonce
+        let nonce_prev = state.sdb.get_nonce(&call.caller_address);
+        state.push_op_reversible(
+            &mut exec_step,
+            RW::WRITE,
+            AccountOp {
+                address: call.caller_address,
+                field: AccountField::Nonce,
+                value: (nonce_prev + 1).into(),
+                value_prev: nonce_prev.into(),
+            },
+        )?;
+
+        // Add callee into access list
+        let is_warm = state.sdb.check_account_in_access_list(&call.address);
+        state.push_op_reversible(
+            &mut exec_step,
+            RW::WRITE,
+            TxAccessListAccountOp {
+                tx_id,
+                address: call.address,
+                is_warm: true,
+                is_warm_prev: is_warm,
+            },
+        )?;
+
+        // 2. The created address is already in use.
+        if exec_step.error.is_some() {
+            state.push_call(call, geth_step)?;
+            state.handle_return(geth_step)?;
+            return Ok(vec![exec_step]);
+        }
+
+        // Switch to callee's call context
+        state.push_call(call.clone(), geth_step)?;
+        state.sdb.create_contract(call.address);
+
+        // Increase callee's nonce
+        let nonce_prev = state.sdb.get_nonce(&call.address);
+        debug_assert!(nonce_prev == 0);
+        state.push_op_reversible(
+            &mut exec_step,
+            RW::WRITE,
+            AccountOp {
+                address: call.address,
+                field: AccountField::Nonce,
+                value: 1.into(),
+                value_prev: 0.into(),
+            },
+        )?;
+
+        state.transfer(
+            &mut exec_step,
+            call.caller_address,
+            call.address,
+            call.value,
+        )?;
+
+        if call.code_hash.to_fixed_bytes() == *EMPTY_HASH {
+            // 3. Create with empty init code.
+            state.handle_return(geth_step)?;
+        }
+        // 4. Create with non-empty init code, which is executed next.
+
+        Ok(vec![exec_step])
+    }
+}
+
+#[cfg(test)]
+mod create_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::{CallKind, ExecState},
+        mock::BlockData,
+        operation::{MemoryOp, StackOp, Target},
+    };
+    use eth_types::{
+        bytecode,
+        evm_types::{OpcodeId, StackAddress},
+        geth_types::GethData,
+        Bytecode, ToBigEndian,
+    };
+    use ethers_core::utils::{get_contract_address, get_create2_address, keccak256};
+    use mock::test_ctx::TestContext;
+    use pretty_assertions::assert_eq;
+
+    const SALT: u64 = 0x123456;
+
+    // Init code that deploys a single 0x00 byte.
+    fn init_code() -> Vec<u8> {
+        bytecode! {
+            PUSH1(0x01) // length
+            PUSH1(0x00) // offset
+            RETURN
+        }
+        .to_vec()
+    }
+
+    // Creator code that stores the init code at the end of the first memory
+    // word and then runs CREATE or CREATE2 `times` times with `value`.
+    fn creator_code(is_create2: bool, value: u64, times: usize) -> Bytecode {
+        let init_code = init_code();
+        let mut code = bytecode! {
+            PUSH32(Word::from_big_endian(&init_code))
+            PUSH1(0x00)
+            MSTORE
+        };
+        for _ in 0..times {
+            if is_create2 {
+                code.append(&bytecode! {
+                    PUSH3(SALT) // salt
+                });
+            }
+            code.append(&bytecode! {
+                PUSH1(init_code.len()) // length
+                PUSH1(32 - init_code.len()) // offset
+                PUSH2(value) // value
+            });
+            code.write_op(if is_create2 {
+                OpcodeId::CREATE2
+            } else {
+                OpcodeId::CREATE
+            });
+        }
+        code.append(&bytecode! { STOP });
+        code
+    }
+
+    fn gen_block(code: Bytecode, balance: u64) -> GethData {
+        TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(mock::MOCK_ACCOUNTS[0])
+                    .balance(Word::from(balance))
+                    .code(code);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[1])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[1].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn test_ok(is_create2: bool) {
+        let creator = mock::MOCK_ACCOUNTS[0];
+        let value = 0x100u64;
+        let block = gen_block(creator_code(is_create2, value, 1), 1 << 20);
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        let creator_nonce = builder.sdb.get_nonce(&creator);
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let init_code = init_code();
+        let expected_address = if is_create2 {
+            get_create2_address(
+                creator,
+                Word::from(SALT).to_be_bytes().to_vec(),
+                init_code.clone(),
+            )
+        } else {
+            get_contract_address(creator, creator_nonce)
+        };
+
+        let opcode = if is_create2 {
+            OpcodeId::CREATE2
+        } else {
+            OpcodeId::CREATE
+        };
+        let transaction = &builder.block.txs()[0];
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(opcode))
+            .unwrap();
+        assert_eq!(step.error, None);
+
+        // The new call
+        let call = &transaction.calls()[1];
+        assert_eq!(
+            call.kind,
+            if is_create2 {
+                CallKind::Create2
+            } else {
+                CallKind::Create
+            }
+        );
+        assert_eq!(call.address, expected_address);
+        assert_eq!(call.value, Word::from(value));
+        assert!(call.is_success);
+
+        let container = &builder.block.container;
+        let call_id = transaction.calls()[0].call_id;
+        let n_pop = if is_create2 { 4 } else { 3 };
+        let stack_ops = step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == Target::Stack)
+            .map(|op_ref| {
+                let op = &container.stack[op_ref.as_usize()];
+                (op.rw(), op.op().clone())
+            })
+            .collect::<Vec<_>>();
+        let mut stack_values = vec![Word::from(value), Word::from(32 - init_code.len())];
+        stack_values.push(Word::from(init_code.len()));
+        if is_create2 {
+            stack_values.push(Word::from(SALT));
+        }
+        let mut expected_stack_ops = stack_values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                (
+                    RW::READ,
+                    StackOp::new(call_id, StackAddress::from(1024 - n_pop + i), value),
+                )
+            })
+            .collect::<Vec<_>>();
+        expected_stack_ops.push((
+            RW::WRITE,
+            StackOp::new(
+                call_id,
+                StackAddress::from(1023),
+                expected_address.to_word(),
+            ),
+        ));
+        assert_eq!(stack_ops, expected_stack_ops);
+
+        // Init code memory reads
+        assert_eq!(
+            step.bus_mapping_instance
+                .iter()
+                .filter(|op_ref| op_ref.target() == Target::Memory)
+                .map(|op_ref| {
+                    let op = &container.memory[op_ref.as_usize()];
+                    (op.rw(), op.op().clone())
+                })
+                .collect::<Vec<_>>(),
+            init_code
+                .iter()
+                .enumerate()
+                .map(|(idx, byte)| {
+                    (
+                        RW::READ,
+                        MemoryOp::new(call_id, (32 - init_code.len() + idx).into(), *byte),
+                    )
+                })
+                .collect::<Vec<_>>()
+        );
+
+        // Nonce of the creator is increased and the new account starts with
+        // nonce 1 and the transferred value.
+        assert_eq!(
+            step.bus_mapping_instance
+                .iter()
+                .filter(|op_ref| op_ref.target() == Target::Account)
+                .map(|op_ref| container.account[op_ref.as_usize()].op().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                AccountOp {
+                    address: creator,
+                    field: AccountField::Nonce,
+                    value: (creator_nonce + 1).into(),
+                    value_prev: creator_nonce.into(),
+                },
+                AccountOp {
+                    address: expected_address,
+                    field: AccountField::Nonce,
+                    value: Word::one(),
+                    value_prev: Word::zero(),
+                },
+                AccountOp {
+                    address: creator,
+                    field: AccountField::Balance,
+                    value: Word::from((1u64 << 20) - value),
+                    value_prev: Word::from(1u64 << 20),
+                },
+                AccountOp {
+                    address: expected_address,
+                    field: AccountField::Balance,
+                    value: Word::from(value),
+                    value_prev: Word::zero(),
+                },
+            ]
+        );
+
+        // The deployed code is stored for the new account
+        let (found, account) = builder.sdb.get_account(&expected_address);
+        assert!(found);
+        assert_eq!(builder.code_db.0[&account.code_hash], vec![0x00]);
+
+        // The init code, the CREATE2 address preimage and the deployed code
+        // are hashed.
+        let mut keccak_inputs = vec![init_code.clone()];
+        if is_create2 {
+            keccak_inputs.push(
+                std::iter::once(0xff)
+                    .chain(creator.to_fixed_bytes())
+                    .chain(Word::from(SALT).to_be_bytes())
+                    .chain(keccak256(&init_code))
+                    .collect(),
+            );
+        }
+        keccak_inputs.push(vec![0x00]);
+        assert_eq!(builder.block.keccak_inputs, keccak_inputs);
+    }
+
+    #[test]
+    fn create_opcode_impl() {
+        test_ok(false);
+    }
+
+    #[test]
+    fn create2_opcode_impl() {
+        test_ok(true);
+    }
+
+    #[test]
+    fn create_insufficient_balance() {
+        let creator = mock::MOCK_ACCOUNTS[0];
+        let block = gen_block(creator_code(false, 0x100, 1), 0xff);
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        let creator_nonce = builder.sdb.get_nonce(&creator);
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let transaction = &builder.block.txs()[0];
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::CREATE))
+            .unwrap();
+        assert_eq!(step.error, Some(ExecError::InsufficientBalance));
+        assert!(!transaction.calls()[1].is_success);
+
+        // The result is zero and the nonce is untouched.
+        let container = &builder.block.container;
+        let result = step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == Target::Stack)
+            .map(|op_ref| &container.stack[op_ref.as_usize()])
+            .last()
+            .unwrap();
+        assert_eq!(
+            (result.rw(), result.op().value()),
+            (RW::WRITE, &Word::zero())
+        );
+        assert!(!step
+            .bus_mapping_instance
+            .iter()
+            .any(|op_ref| op_ref.target() == Target::Account));
+        assert_eq!(builder.sdb.get_nonce(&creator), creator_nonce);
+    }
+
+    #[test]
+    fn create2_address_collision() {
+        let creator = mock::MOCK_ACCOUNTS[0];
+        let block = gen_block(creator_code(true, 0, 2), 1 << 20);
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        let creator_nonce = builder.sdb.get_nonce(&creator);
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let transaction = &builder.block.txs()[0];
+        let steps = transaction
+            .steps()
+            .iter()
+            .filter(|step| step.exec_state == ExecState::Op(OpcodeId::CREATE2))
+            .collect::<Vec<_>>();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].error, None);
+        assert_eq!(steps[1].error, Some(ExecError::ContractAddressCollision));
+
+        // The nonce is increased on both creations.
+        assert_eq!(builder.sdb.get_nonce(&creator), creator_nonce + 2);
+    }
+}
@@ -0,0 +1,918 @@
+use crate::{
+    circuit_input_builder::{CircuitInputStateRef, ExecStep},
+    error::ExecError,
+    operation::CallContextField,
+    Error,
+};
+use eth_types::{evm_types::GasCost, GethExecStep, Word};
+
+/// Generate the step of an [`ExecError`] which halts the current call, for any
+/// opcode.  The call ends with failure, so all of its reversible operations
+/// are reverted, and the execution continues in the caller context, if any.
+pub(crate) fn gen_error_state_ops(
+    state: &mut CircuitInputStateRef,
+    geth_steps: &[GethExecStep],
+    error: ExecError,
+) -> Result<Vec<ExecStep>, Error> {
+    let geth_step = &geth_steps[0];
+    let mut exec_step = state.new_step(geth_step)?;
+    exec_step.error = Some(error);
+    // A halting error consumes all the gas left in the call, whatever the cost
+    // of the opcode reported by geth.
+    exec_step.gas_cost = GasCost(geth_step.gas.0);
+
+    let call = state.call()?.clone();
+    debug_assert!(!call.is_success, "call halting with an error must fail");
+
+    // NOTE: For `RwCounterEndOfReversion` we use the current value as a
+    // placeholder, and later set the proper value in
+    // `CircuitInputBuilder::set_value_ops_call_context_rwc_eor`
+    for (field, value) in [
+        (
+            CallContextField::IsSuccess,
+            Word::from(call.is_success as u8),
+        ),
+        (
+            CallContextField::RwCounterEndOfReversion,
+            Word::from(call.rw_counter_end_of_reversion),
+        ),
+    ] {
+        state.call_context_read(&mut exec_step, call.call_id, field, value);
+    }
+
+    state.handle_return(geth_step)?;
+
+    if !call.is_root {
+        state.gen_restore_context_ops(&mut exec_step, &call, geth_steps)?;
+    }
+
+    Ok(vec![exec_step])
+}
+
+#[cfg(test)]
+mod error_state_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::{CircuitInputBuilder, ExecState},
+        error::OogError,
+        mock::BlockData,
+        operation::{CallContextOp, RW},
+    };
+    use eth_types::{bytecode, evm_types::OpcodeId, geth_types::GethData, Bytecode, ToWord};
+    use mock::test_ctx::TestContext;
+    use pretty_assertions::assert_eq;
+
+    fn handle_block(block: &GethData) -> CircuitInputBuilder {
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        builder
+    }
+
+    // code_a calls code_b with `gas`, and then stores the result of the call.
+    fn gen_block(code_b: Bytecode, gas: u64) -> GethData {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+        let code_a = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x00) // value
+            PUSH32(addr_b.to_word()) // addr
+            PUSH32(gas) // gas
+            CALL
+            PUSH1(0x00) // key
+            SSTORE
+            STOP
+        };
+        TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_a).code(code_a);
+                accs[1].address(addr_b).code(code_b);
+                accs[2]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[2].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into()
+    }
+
+    // Runs `code_b` in a call which must halt at `op` with `error`, and checks
+    // the operations of the error step and that the callee state changes are
+    // reverted.
+    fn test_error(code_b: Bytecode, gas: u64, op: OpcodeId, error: ExecError) {
+        // Every callee writes into its storage before failing.
+        let mut code = bytecode! {
+            PUSH1(0x01) // value
+            PUSH1(0x00) // key
+            SSTORE
+        };
+        code.append(&code_b);
+        let block = gen_block(code, gas);
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let caller = &transaction.calls()[0];
+        let callee = &transaction.calls()[1];
+        assert!(!callee.is_success);
+        assert!(!callee.is_persistent);
+
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.error.is_some())
+            .unwrap();
+        assert_eq!(step.exec_state, ExecState::Op(op));
+        assert_eq!(step.error, Some(error));
+        assert_eq!(step.call_index, 1);
+        assert_eq!(step.gas_cost, GasCost(step.gas_left.0));
+
+        // The caller resumes at the step following the last one of the callee.
+        let struct_logs = &block.geth_traces[0].struct_logs;
+        let index = struct_logs
+            .windows(2)
+            .position(|steps| steps[0].depth == 2 && steps[1].depth == 1)
+            .unwrap();
+        assert_eq!(struct_logs[index].op, op);
+        let next_step = &struct_logs[index + 1];
+
+        // The caller resumes with the write counter it had after the CALL,
+        // which wrote the callee address into the access list and the balances
+        // of the value transfer.
+        let call_step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::CALL))
+            .unwrap();
+        let state_write_counter = call_step.reversible_write_counter + 3;
+        let resumed_step = transaction
+            .steps()
+            .iter()
+            .skip_while(|s| !std::ptr::eq(*s, step))
+            .nth(1)
+            .unwrap();
+        assert_eq!(resumed_step.call_index, 0);
+        assert_eq!(resumed_step.reversible_write_counter, state_write_counter);
+
+        let container = &builder.block.container;
+        let call_context_ops = step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == crate::operation::Target::CallContext)
+            .map(|op_ref| &container.call_context[op_ref.as_usize()])
+            .map(|operation| (operation.rw(), operation.op().clone()))
+            .collect::<Vec<_>>();
+        let expected = [
+            (callee.call_id, CallContextField::IsSuccess, Word::zero()),
+            (
+                callee.call_id,
+                CallContextField::RwCounterEndOfReversion,
+                Word::from(callee.rw_counter_end_of_reversion),
+            ),
+            (
+                callee.call_id,
+                CallContextField::CallerId,
+                Word::from(caller.call_id),
+            ),
+            (caller.call_id, CallContextField::IsRoot, Word::one()),
+            (caller.call_id, CallContextField::IsCreate, Word::zero()),
+            (
+                caller.call_id,
+                CallContextField::CodeSource,
+                caller.code_hash.to_word(),
+            ),
+            (
+                caller.call_id,
+                CallContextField::ProgramCounter,
+                Word::from(next_step.pc.0),
+            ),
+            (
+                caller.call_id,
+                CallContextField::StackPointer,
+                Word::from(next_step.stack.stack_pointer().0),
+            ),
+            (
+                caller.call_id,
+                CallContextField::GasLeft,
+                Word::from(next_step.gas.0),
+            ),
+            (
+                caller.call_id,
+                CallContextField::MemorySize,
+                Word::from(next_step.memory.word_size()),
+            ),
+            (
+                caller.call_id,
+                CallContextField::StateWriteCounter,
+                Word::from(state_write_counter),
+            ),
+        ]
+        .into_iter()
+        .map(|(call_id, field, value)| {
+            (
+                RW::READ,
+                CallContextOp {
+                    call_id,
+                    field,
+                    value,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(call_context_ops[..expected.len()], expected);
+        assert_eq!(
+            call_context_ops[expected.len()..],
+            [
+                (CallContextField::LastCalleeId, Word::from(callee.call_id)),
+                (CallContextField::LastCalleeReturnDataOffset, Word::zero()),
+                (CallContextField::LastCalleeReturnDataLength, Word::zero()),
+            ]
+            .map(|(field, value)| {
+                (
+                    RW::WRITE,
+                    CallContextOp {
+                        call_id: caller.call_id,
+                        field,
+                        value,
+                    },
+                )
+            })
+        );
+
+        // The storage write of the callee is reverted, and the caller stores
+        // the failure of the call.
+        let (_, value) = builder
+            .sdb
+            .get_storage(&mock::MOCK_ACCOUNTS[1], &Word::zero());
+        assert_eq!(*value, Word::zero());
+        let (_, value) = builder
+            .sdb
+            .get_storage(&mock::MOCK_ACCOUNTS[0], &Word::zero());
+        assert_eq!(*value, Word::zero());
+        let last_step = transaction
+            .steps()
+            .iter()
+            .rev()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::STOP))
+            .unwrap();
+        assert_eq!(last_step.call_index, 0);
+    }
+
+    const ROOT_TX_GAS: u64 = 21_100;
+
+    // Runs `code` in the root call, which must halt at `op` with `error`, and
+    // checks that the whole gas of the tx is paid by the sender.
+    fn test_root_error(code: Bytecode, op: OpcodeId, error: ExecError) {
+        let sender_balance = Word::from(1u64 << 30);
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(mock::MOCK_ACCOUNTS[0]).code(code);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[1])
+                    .balance(sender_balance);
+            },
+            |mut txs, accs| {
+                txs[0]
+                    .to(accs[0].address)
+                    .from(accs[1].address)
+                    .gas(Word::from(ROOT_TX_GAS));
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        assert!(!transaction.calls()[0].is_success);
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.error.is_some())
+            .unwrap();
+        assert_eq!(step.exec_state, ExecState::Op(op));
+        assert_eq!(step.error, Some(error));
+        assert_eq!(step.gas_cost, GasCost(step.gas_left.0));
+
+        // Nothing is refunded to the sender.
+        let end_tx_step = transaction.steps().last().unwrap();
+        assert_eq!(end_tx_step.exec_state, ExecState::EndTx);
+        assert_eq!(end_tx_step.gas_left.0, 0);
+        let sender_balance_op = end_tx_step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == crate::operation::Target::Account)
+            .map(|op_ref| builder.block.container.account[op_ref.as_usize()].op())
+            .find(|op| op.address == mock::MOCK_ACCOUNTS[1])
+            .unwrap();
+        assert_eq!(sender_balance_op.value, sender_balance_op.value_prev);
+        let gas_price = block.eth_block.transactions[0].gas_price.unwrap();
+        assert_eq!(
+            builder.sdb.get_account(&mock::MOCK_ACCOUNTS[1]).1.balance,
+            sender_balance - gas_price * ROOT_TX_GAS
+        );
+    }
+
+    #[test]
+    fn error_state_root_out_of_gas() {
+        // The tx gas left after the intrinsic gas doesn't cover the SSTORE.
+        test_root_error(
+            bytecode! {
+                PUSH1(0x01) // value
+                PUSH1(0x00) // key
+                SSTORE
+                STOP
+            },
+            OpcodeId::SSTORE,
+            ExecError::OutOfGas(OogError::Sstore),
+        );
+    }
+
+    #[test]
+    fn error_state_root_invalid_jump() {
+        test_root_error(
+            bytecode! {
+                PUSH1(0x05)
+                JUMP
+                STOP
+            },
+            OpcodeId::JUMP,
+            ExecError::InvalidJump,
+        );
+    }
+
+    #[test]
+    fn error_state_invalid_opcode() {
+        let mut code = Bytecode::default();
+        code.write_op(OpcodeId::INVALID(0xfe));
+        test_error(
+            code,
+            0x1_0000,
+            OpcodeId::INVALID(0xfe),
+            ExecError::InvalidOpcode,
+        );
+    }
+
+    #[test]
+    fn error_state_stack_underflow() {
+        test_error(
+            bytecode! { ADD },
+            0x1_0000,
+            OpcodeId::ADD,
+            ExecError::StackUnderflow,
+        );
+    }
+
+    #[test]
+    fn error_state_stack_overflow() {
+        let mut code = Bytecode::default();
+        for _ in 0..1025 {
+            code.append(&bytecode! { PUSH1(0x00) });
+        }
+        test_error(code, 0x1_0000, OpcodeId::PUSH1, ExecError::StackOverflow);
+    }
+
+    #[test]
+    fn error_state_out_of_gas() {
+        // The SSTORE before it costs 22100 gas, leaving too little for a cold
+        // SLOAD.
+        test_error(
+            bytecode! {
+                PUSH1(0x01) // key
+                SLOAD
+            },
+            22100 + 3 + 2000,
+            OpcodeId::SLOAD,
+            ExecError::OutOfGas(OogError::Sload),
+        );
+    }
+
+    #[test]
+    fn error_state_invalid_jump() {
+        test_error(
+            bytecode! {
+                PUSH1(0x00) // dest
+                JUMP
+            },
+            0x1_0000,
+            OpcodeId::JUMP,
+            ExecError::InvalidJump,
+        );
+    }
+
+    #[test]
+    fn error_state_return_data_out_of_bounds() {
+        test_error(
+            bytecode! {
+                PUSH1(0x01) // length
+                PUSH1(0x00) // offset
+                PUSH1(0x00) // dstOffset
+                RETURNDATACOPY
+            },
+            0x1_0000,
+            OpcodeId::RETURNDATACOPY,
+            ExecError::ReturnDataOutOfBounds,
+        );
+    }
+
+    #[test]
+    fn error_state_write_protection() {
+        // The callee calls itself with STATICCALL and some calldata, which
+        // makes it try to write into its storage.
+        let call_self = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x01) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH32(mock::MOCK_ACCOUNTS[1].to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            STATICCALL
+            STOP
+        };
+        let mut code = bytecode! {
+            CALLDATASIZE
+            PUSH1(4 + call_self.code().len()) // dest
+            JUMPI
+        };
+        code.append(&call_self);
+        code.append(&bytecode! {
+            JUMPDEST
+            PUSH1(0x01) // value
+            PUSH1(0x00) // key
+            SSTORE
+        });
+        let block = gen_block(code, 0x2_0000);
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.error.is_some())
+            .unwrap();
+        assert_eq!(step.exec_state, ExecState::Op(OpcodeId::SSTORE));
+        assert_eq!(step.error, Some(ExecError::WriteProtection));
+        let callee = &transaction.calls()[2];
+        assert!(callee.is_static);
+        assert!(!callee.is_success);
+        assert_eq!(step.call_index, 2);
+    }
+
+    // The prefix of test_error, which pushes two words and writes into a cold
+    // storage slot, costs 22106 gas.
+    const PREFIX_GAS: u64 = 3 + 3 + 22100;
+
+    // An offset or length whose memory expansion can't be paid for.
+    const HUGE_LENGTH: u64 = 0xffff_ffff;
+
+    #[test]
+    fn error_state_out_of_gas_constant() {
+        test_error(
+            bytecode! {
+                PUSH1(0x01)
+                PUSH1(0x02)
+                ADD
+            },
+            PREFIX_GAS + 3 + 3 + 2,
+            OpcodeId::ADD,
+            ExecError::OutOfGas(OogError::Constant),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_static_memory_expansion() {
+        test_error(
+            bytecode! {
+                PUSH32(HUGE_LENGTH) // offset
+                MLOAD
+            },
+            0x1_0000,
+            OpcodeId::MLOAD,
+            ExecError::OutOfGas(OogError::StaticMemoryExpansion),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_dynamic_memory_expansion() {
+        test_error(
+            bytecode! {
+                PUSH32(HUGE_LENGTH) // length
+                PUSH1(0x00) // offset
+                RETURN
+            },
+            0x1_0000,
+            OpcodeId::RETURN,
+            ExecError::OutOfGas(OogError::DynamicMemoryExpansion),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_memory_copy() {
+        test_error(
+            bytecode! {
+                PUSH32(HUGE_LENGTH) // length
+                PUSH1(0x00) // offset
+                PUSH1(0x00) // dstOffset
+                CALLDATACOPY
+            },
+            0x1_0000,
+            OpcodeId::CALLDATACOPY,
+            ExecError::OutOfGas(OogError::MemoryCopy),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_account_access() {
+        // A cold account access costs 2600 gas.
+        test_error(
+            bytecode! {
+                PUSH1(0x42) // address
+                BALANCE
+            },
+            PREFIX_GAS + 3 + 2000,
+            OpcodeId::BALANCE,
+            ExecError::OutOfGas(OogError::AccountAccess),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_log() {
+        test_error(
+            bytecode! {
+                PUSH32(HUGE_LENGTH) // length
+                PUSH1(0x00) // offset
+                LOG0
+            },
+            0x1_0000,
+            OpcodeId::LOG0,
+            ExecError::OutOfGas(OogError::Log),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_exp() {
+        // A 32 bytes exponent costs 10 + 50 * 32 gas.
+        test_error(
+            bytecode! {
+                PUSH32(Word::MAX) // exponent
+                PUSH1(0x02) // base
+                EXP
+            },
+            PREFIX_GAS + 3 + 3 + 1000,
+            OpcodeId::EXP,
+            ExecError::OutOfGas(OogError::Exp),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_sha3() {
+        test_error(
+            bytecode! {
+                PUSH32(HUGE_LENGTH) // length
+                PUSH1(0x00) // offset
+                SHA3
+            },
+            0x1_0000,
+            OpcodeId::SHA3,
+            ExecError::OutOfGas(OogError::Sha3),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_ext_code_copy() {
+        test_error(
+            bytecode! {
+                PUSH32(HUGE_LENGTH) // length
+                PUSH1(0x00) // offset
+                PUSH1(0x00) // dstOffset
+                PUSH1(0x42) // address
+                EXTCODECOPY
+            },
+            0x1_0000,
+            OpcodeId::EXTCODECOPY,
+            ExecError::OutOfGas(OogError::ExtCodeCopy),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_sstore() {
+        // Writing into another cold slot costs 22100 gas again.
+        test_error(
+            bytecode! {
+                PUSH1(0x01) // value
+                PUSH1(0x01) // key
+                SSTORE
+            },
+            PREFIX_GAS + 3 + 3 + 5000,
+            OpcodeId::SSTORE,
+            ExecError::OutOfGas(OogError::Sstore),
+        );
+    }
+
+    // Calls with `op` reserving a return data region whose memory expansion
+    // can't be paid for.
+    fn test_call_out_of_gas(op: OpcodeId, oog_error: OogError) {
+        let mut code = bytecode! {
+            PUSH32(HUGE_LENGTH) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+        };
+        if matches!(op, OpcodeId::CALL | OpcodeId::CALLCODE) {
+            code.push(1, Word::zero()); // value
+        }
+        code.append(&bytecode! {
+            PUSH1(0x42) // addr
+            PUSH32(0x1_0000) // gas
+        });
+        code.write_op(op);
+        test_error(code, 0x2_0000, op, ExecError::OutOfGas(oog_error));
+    }
+
+    #[test]
+    fn error_state_out_of_gas_call() {
+        test_call_out_of_gas(OpcodeId::CALL, OogError::Call);
+    }
+
+    #[test]
+    fn error_state_out_of_gas_callcode() {
+        test_call_out_of_gas(OpcodeId::CALLCODE, OogError::CallCode);
+    }
+
+    #[test]
+    fn error_state_out_of_gas_delegatecall() {
+        test_call_out_of_gas(OpcodeId::DELEGATECALL, OogError::DelegateCall);
+    }
+
+    #[test]
+    fn error_state_out_of_gas_staticcall() {
+        test_call_out_of_gas(OpcodeId::STATICCALL, OogError::StaticCall);
+    }
+
+    #[test]
+    fn error_state_out_of_gas_create2() {
+        test_error(
+            bytecode! {
+                PUSH1(0x00) // salt
+                PUSH32(HUGE_LENGTH) // length
+                PUSH1(0x00) // offset
+                PUSH1(0x00) // value
+                CREATE2
+            },
+            0x1_0000,
+            OpcodeId::CREATE2,
+            ExecError::OutOfGas(OogError::Create2),
+        );
+    }
+
+    #[test]
+    fn error_state_out_of_gas_selfdestruct() {
+        // A SELFDESTRUCT to a cold beneficiary costs 5000 + 2600 gas.
+        test_error(
+            bytecode! {
+                PUSH1(0x42) // beneficiary
+                SELFDESTRUCT
+            },
+            PREFIX_GAS + 3 + 5000,
+            OpcodeId::SELFDESTRUCT,
+            ExecError::OutOfGas(OogError::SelfDestruct),
+        );
+    }
+
+    // Returns the code which writes `code` at the start of memory.
+    fn store_code(code: &Bytecode) -> Bytecode {
+        let mut code = code.code().to_vec();
+        code.resize((code.len() + 31) / 32 * 32, 0);
+        let mut store = Bytecode::default();
+        for (index, word) in code.chunks(32).enumerate() {
+            store.push(32, Word::from_big_endian(word));
+            store.push(32, Word::from(index * 32));
+            store.write_op(OpcodeId::MSTORE);
+        }
+        store
+    }
+
+    // Runs `code_creator` with CREATE in the callee of gen_block, which must
+    // halt at its RETURN with `error`, and checks that the callee resumes.
+    fn test_create_error(code_creator: Bytecode, gas: u64, error: ExecError) {
+        let mut code_b = store_code(&code_creator);
+        code_b.append(&bytecode! {
+            PUSH1(code_creator.code().len()) // length
+            PUSH1(0x00) // offset
+            PUSH1(0x00) // value
+            CREATE
+            STOP
+        });
+        let block = gen_block(code_b, gas);
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let (index, step) = transaction
+            .steps()
+            .iter()
+            .enumerate()
+            .find(|(_, step)| step.error.is_some())
+            .unwrap();
+        assert_eq!(step.exec_state, ExecState::Op(OpcodeId::RETURN));
+        assert_eq!(step.error, Some(error));
+        let creator = &transaction.calls()[step.call_index];
+        assert!(creator.is_create());
+        assert!(!creator.is_success);
+        assert_eq!(creator.depth, 3);
+
+        // The callee resumes after the CREATE.
+        let resumed_step = &transaction.steps()[index + 1];
+        assert_eq!(resumed_step.call_index, 1);
+        assert_eq!(resumed_step.exec_state, ExecState::Op(OpcodeId::STOP));
+    }
+
+    #[test]
+    fn error_state_code_store_out_of_gas() {
+        // Storing 0x100 bytes of code costs 200 gas per byte, which is more
+        // than what's left after the CREATE.
+        test_create_error(
+            bytecode! {
+                PUSH2(0x100) // length
+                PUSH1(0x00) // offset
+                RETURN
+            },
+            0x1_0000,
+            ExecError::CodeStoreOutOfGas,
+        );
+    }
+
+    #[test]
+    fn error_state_max_code_size_exceeded() {
+        test_create_error(
+            bytecode! {
+                PUSH2(0x6001) // length
+                PUSH1(0x00) // offset
+                RETURN
+            },
+            0x10_0000,
+            ExecError::MaxCodeSizeExceeded,
+        );
+    }
+
+    #[test]
+    fn error_state_invalid_creation_code() {
+        test_create_error(
+            bytecode! {
+                PUSH1(0xef) // value
+                PUSH1(0x00) // offset
+                MSTORE8
+                PUSH1(0x01) // length
+                PUSH1(0x00) // offset
+                RETURN
+            },
+            0x1_0000,
+            ExecError::InvalidCreationCode,
+        );
+    }
+
+    // Checks that the step of `op` fails with `error` without halting the
+    // current call, so that no operation is reverted and the next step runs in
+    // the same call.
+    fn assert_failed_without_halting(
+        builder: &CircuitInputBuilder,
+        op: OpcodeId,
+        error: ExecError,
+    ) {
+        let transaction = &builder.block.txs()[0];
+        let (index, step) = transaction
+            .steps()
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, step)| step.exec_state == ExecState::Op(op))
+            .unwrap();
+        assert_eq!(step.error, Some(error));
+        assert_eq!(
+            transaction
+                .steps()
+                .iter()
+                .filter(|step| step.error.is_some())
+                .count(),
+            1
+        );
+        let next_step = &transaction.steps()[index + 1];
+        assert_eq!(next_step.call_index, step.call_index);
+        assert!(transaction.calls()[step.call_index].is_success);
+    }
+
+    #[test]
+    fn error_state_depth() {
+        // The callee calls itself until the depth limit is reached.
+        let addr_b = mock::MOCK_ACCOUNTS[1];
+        let code_b = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x00) // value
+            PUSH32(addr_b.to_word()) // addr
+            GAS // gas
+            CALL
+            STOP
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_b).code(code_b);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(10u64.pow(19)));
+            },
+            |mut txs, accs| {
+                txs[0]
+                    .to(accs[0].address)
+                    .from(accs[1].address)
+                    .gas(Word::from(10u64.pow(15)));
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        // The root call and the 1024 nested calls.
+        assert_eq!(transaction.calls().len(), 1025);
+        assert_failed_without_halting(&builder, OpcodeId::CALL, ExecError::Depth);
+    }
+
+    #[test]
+    fn error_state_insufficient_balance() {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+        let code_a = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x01) // value
+            PUSH32(addr_b.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            STOP
+        };
+        let block: GethData = TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_a).code(code_a);
+                accs[1].address(addr_b).code(bytecode! { STOP });
+                accs[2]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[2].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block(&block);
+
+        assert_eq!(builder.block.txs()[0].calls().len(), 1);
+        assert_failed_without_halting(&builder, OpcodeId::CALL, ExecError::InsufficientBalance);
+    }
+
+    #[test]
+    fn error_state_contract_address_collision() {
+        // The second CREATE2 with the same salt and init code collides with
+        // the contract created by the first one.
+        let code_creator = bytecode! {
+            PUSH1(0x00) // length
+            PUSH1(0x00) // offset
+            RETURN
+        };
+        let mut code_b = store_code(&code_creator);
+        for _ in 0..2 {
+            code_b.append(&bytecode! {
+                PUSH3(0x123456) // salt
+                PUSH1(code_creator.code().len()) // length
+                PUSH1(0x00) // offset
+                PUSH1(0x00) // value
+                CREATE2
+            });
+        }
+        code_b.append(&bytecode! { STOP });
+        let block = gen_block(code_b, 0x2_0000);
+        let builder = handle_block(&block);
+
+        assert_eq!(builder.block.txs()[0].calls().len(), 3);
+        assert_failed_without_halting(
+            &builder,
+            OpcodeId::CREATE2,
+            ExecError::ContractAddressCollision,
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use super::{copy_code::gen_copy_code_to_memory_steps, Opcode};
+use crate::{
+    circuit_input_builder::{get_call_memory_offset_length, CircuitInputStateRef, ExecStep},
+    operation::{AccountField, CallContextField, TxAccessListAccountOp, RW},
+    Error,
+};
+use eth_types::{GethExecStep, ToAddress, ToWord, H256, U256};
+use keccak256::EMPTY_HASH;
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the
+/// [`OpcodeId::EXTCODECOPY`](crate::evm::OpcodeId::EXTCODECOPY) `OpcodeId`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Extcodecopy;
+
+impl Opcode for Extcodecopy {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let (exec_step, code_hash) = gen_extcodecopy_step(state, geth_step)?;
+        let mut exec_steps = vec![exec_step];
+        let memory_copy_steps = gen_memory_copy_steps(state, geth_steps, code_hash)?;
+        exec_steps.extend(memory_copy_steps);
+        Ok(exec_steps)
+    }
+}
+
+fn gen_extcodecopy_step(
+    state: &mut CircuitInputStateRef,
+    geth_step: &GethExecStep,
+) -> Result<(ExecStep, H256), Error> {
+    let mut exec_step = state.new_step(geth_step)?;
+
+    let external_address = geth_step.stack.nth_last(0)?.to_address();
+    let dest_offset = geth_step.stack.nth_last(1)?;
+    let code_offset = geth_step.stack.nth_last(2)?;
+    let length = geth_step.stack.nth_last(3)?;
+
+    // stack reads
+    state.stack_read(
+        &mut exec_step,
+        geth_step.stack.nth_last_filled(0),
+        external_address.to_word(),
+    )?;
+    state.stack_read(
+        &mut exec_step,
+        geth_step.stack.nth_last_filled(1),
+        dest_offset,
+    )?;
+    state.stack_read(
+        &mut exec_step,
+        geth_step.stack.nth_last_filled(2),
+        code_offset,
+    )?;
+    state.stack_read(&mut exec_step, geth_step.stack.nth_last_filled(3), length)?;
+
+    for (field, value) in [
+        (CallContextField::TxId, U256::from(state.tx_ctx.id())),
+        (
+            CallContextField::RwCounterEndOfReversion,
+            U256::from(state.call()?.rw_counter_end_of_reversion as u64),
+        ),
+        (
+            CallContextField::IsPersistent,
+            U256::from(state.call()?.is_persistent as u64),
+        ),
+    ] {
+        state.call_context_read(&mut exec_step, state.call()?.call_id, field, value);
+    }
+
+    let is_warm = state.sdb.check_account_in_access_list(&external_address);
+    state.push_op_reversible(
+        &mut exec_step,
+        RW::WRITE,
+        TxAccessListAccountOp {
+            tx_id: state.tx_ctx.id(),
+            address: external_address,
+            is_warm: true,
+            is_warm_prev: is_warm,
+        },
+    )?;
+
+    let code_hash = state.sdb.get_account(&external_address).1.code_hash;
+    state.account_read(
+        &mut exec_step,
+        external_address,
+        AccountField::CodeHash,
+        code_hash.to_word(),
+        code_hash.to_word(),
+    )?;
+
+    Ok((exec_step, code_hash))
+}
+
+fn gen_memory_copy_steps(
+    state: &mut CircuitInputStateRef,
+    geth_steps: &[GethExecStep],
+    code_hash: H256,
+) -> Result<Vec<ExecStep>, Error> {
+    let (dest_offset, length) = get_call_memory_offset_length(&geth_steps[0], 1)?;
+    let code_offset = geth_steps[0].stack.nth_last(2)?;
+
+    // Accounts without code may be missing from the CodeDB.
+    let code = if code_hash.to_fixed_bytes() == *EMPTY_HASH {
+        vec![]
+    } else {
+        state.code(code_hash)?
+    };
+    // The bytes past the end of the code are zero, so any offset past it can
+    // be clamped to it.
+    let code_offset = std::cmp::min(code_offset, U256::from(code.len())).as_u64();
+    gen_copy_code_to_memory_steps(
+        state,
+        geth_steps,
+        code_hash,
+        &code,
+        dest_offset,
+        code_offset,
+        length,
+    )
+}
+
+#[cfg(test)]
+mod extcodecopy_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::ExecState,
+        constants::MAX_COPY_BYTES,
+        mock::BlockData,
+        operation::{AccountOp, CallContextOp, MemoryOp, StackOp},
+    };
+    use eth_types::{
+        address, bytecode,
+        evm_types::{MemoryAddress, OpcodeId, StackAddress},
+        geth_types::GethData,
+        Bytecode, Bytes, Word,
+    };
+    use ethers_core::utils::keccak256;
+    use mock::TestContext;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn cold_empty_account() {
+        test_ok(false, false, 0x00, 0x00, 0x20);
+    }
+
+    #[test]
+    fn warm_empty_account() {
+        test_ok(false, true, 0x00, 0x00, 0x20);
+    }
+
+    #[test]
+    fn cold_existing_account() {
+        test_ok(true, false, 0x00, 0x00, 0x40);
+        test_ok(true, false, 0x20, 0x01, 0x22);
+    }
+
+    #[test]
+    fn warm_existing_account() {
+        test_ok(true, true, 0x00, 0x00, 0x40);
+    }
+
+    #[test]
+    fn huge_offsets() {
+        let external_address = address!("0xaabbccddee000000000000000000000000000000");
+        let code = bytecode! {
+            // Code offset past the end of the code
+            PUSH1(0x20) // size
+            PUSH32(Word::MAX) // code_offset
+            PUSH1(0x00) // dest_offset
+            PUSH20(external_address.to_word())
+            EXTCODECOPY
+            // Empty copy to a memory offset past u64
+            PUSH1(0x00) // size
+            PUSH1(0x00) // code_offset
+            PUSH32(Word::MAX) // dest_offset
+            PUSH20(external_address.to_word())
+            EXTCODECOPY
+            STOP
+        };
+        let block: GethData = TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(address!("0x0000000000000000000000000000000000000010"))
+                    .balance(Word::from(1u64 << 20))
+                    .code(code);
+                accs[1]
+                    .address(external_address)
+                    .code(Bytes::from([0x60, 0x01, 0x60, 0x02, 0x01, 0x00]));
+                accs[2]
+                    .address(address!("0x0000000000000000000000000000000000cafe01"))
+                    .balance(Word::from(1u64 << 20));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[2].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        // Only the first copy has steps, which write zeros.
+        let transaction = &builder.block.txs()[0];
+        let copy_steps = transaction
+            .steps()
+            .iter()
+            .filter(|step| step.exec_state == ExecState::CopyCodeToMemory)
+            .collect::<Vec<_>>();
+        assert_eq!(copy_steps.len(), 1);
+        assert_eq!(
+            copy_steps[0]
+                .bus_mapping_instance
+                .iter()
+                .map(|op_ref| builder.block.container.memory[op_ref.as_usize()]
+                    .op()
+                    .clone())
+                .collect::<Vec<MemoryOp>>(),
+            (0..0x20)
+                .map(|idx| MemoryOp::new(1, MemoryAddress::from(idx), 0))
+                .collect::<Vec<MemoryOp>>(),
+        );
+    }
+
+    fn test_ok(exists: bool, is_warm: bool, dest_offset: usize, code_offset: usize, size: usize) {
+        let external_address = address!("0xaabbccddee000000000000000000000000000000");
+
+        // Make the external account warm, if needed, by first getting its code hash.
+        let mut code = Bytecode::default();
+        if is_warm {
+            code.append(&bytecode! {
+                PUSH20(external_address.to_word())
+                EXTCODEHASH
+                POP
+            });
+        }
+        code.append(&bytecode! {
+            PUSH32(size)
+            PUSH32(code_offset)
+            PUSH32(dest_offset)
+            PUSH20(external_address.to_word())
+            EXTCODECOPY
+            STOP
+        });
+        let code_ext = if exists {
+            Bytes::from([0x60, 0x01, 0x60, 0x02, 0x01, 0x00])
+        } else {
+            Bytes::default()
+        };
+
+        let block: GethData = TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(address!("0x0000000000000000000000000000000000000010"))
+                    .balance(Word::from(1u64 << 20))
+                    .code(code.clone());
+
+                accs[1].address(external_address).code(code_ext.clone());
+
+                accs[2]
+                    .address(address!("0x0000000000000000000000000000000000cafe01"))
+                    .balance(Word::from(1u64 << 20));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[2].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+
+        let code_hash = Word::from(keccak256(&code_ext));
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let tx_id = 1;
+        let transaction = &builder.block.txs()[tx_id - 1];
+        let call_id = transaction.calls()[0].call_id;
+
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::EXTCODECOPY))
+            .unwrap();
+        let container = &builder.block.container;
+
+        assert_eq!(
+            [0, 1, 2, 3]
+                .map(|idx| &container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|op| (op.rw(), op.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(
+                        call_id,
+                        StackAddress::from(1020),
+                        external_address.to_word()
+                    ),
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(call_id, StackAddress::from(1021), Word::from(dest_offset)),
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(call_id, StackAddress::from(1022), Word::from(code_offset)),
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(call_id, StackAddress::from(1023), Word::from(size)),
+                ),
+            ]
+        );
+        assert_eq!(
+            [4, 5, 6]
+                .map(|idx| &container.call_context[step.bus_mapping_instance[idx].as_usize()])
+                .map(|op| (op.rw(), op.op())),
+            [
+                (
+                    RW::READ,
+                    &CallContextOp {
+                        call_id,
+                        field: CallContextField::TxId,
+                        value: tx_id.into()
+                    }
+                ),
+                (
+                    RW::READ,
+                    &CallContextOp {
+                        call_id,
+                        field: CallContextField::RwCounterEndOfReversion,
+                        value: U256::zero()
+                    }
+                ),
+                (
+                    RW::READ,
+                    &CallContextOp {
+                        call_id,
+                        field: CallContextField::IsPersistent,
+                        value: U256::one()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            {
+                let operation =
+                    &container.tx_access_list_account[step.bus_mapping_instance[7].as_usize()];
+                (operation.rw(), operation.op())
+            },
+            (
+                RW::WRITE,
+                &TxAccessListAccountOp {
+                    tx_id,
+                    address: external_address,
+                    is_warm: true,
+                    is_warm_prev: is_warm
+                }
+            )
+        );
+        assert_eq!(
+            {
+                let operation = &container.account[step.bus_mapping_instance[8].as_usize()];
+                (operation.rw(), operation.op())
+            },
+            (
+                RW::READ,
+                &AccountOp {
+                    address: external_address,
+                    field: AccountField::CodeHash,
+                    value: code_hash,
+                    value_prev: code_hash,
+                }
+            )
+        );
+
+        // Memory writes of the CopyCodeToMemory steps.
+        let copy_steps = transaction
+            .steps()
+            .iter()
+            .filter(|step| step.exec_state == ExecState::CopyCodeToMemory)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            copy_steps.len(),
+            (size + MAX_COPY_BYTES - 1) / MAX_COPY_BYTES
+        );
+        assert_eq!(
+            copy_steps
+                .iter()
+                .flat_map(|step| step.bus_mapping_instance.iter())
+                .map(|op_ref| {
+                    let op = &container.memory[op_ref.as_usize()];
+                    (op.rw(), op.op().clone())
+                })
+                .collect::<Vec<(RW, MemoryOp)>>(),
+            (0..size)
+                .map(|idx| {
+                    (
+                        RW::WRITE,
+                        MemoryOp::new(
+                            call_id,
+                            MemoryAddress::from(dest_offset + idx),
+                            code_ext.get(code_offset + idx).copied().unwrap_or(0),
+                        ),
+                    )
+                })
+                .collect::<Vec<(RW, MemoryOp)>>(),
+        );
+    }
+}
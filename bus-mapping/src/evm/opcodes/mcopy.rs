@@ -0,0 +1,304 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{
+        CircuitInputStateRef, CopyDetails, ExecState, ExecStep, StepAuxiliaryData,
+    },
+    constants::MAX_COPY_BYTES,
+    Error,
+};
+use eth_types::{evm_types::MemoryAddress, GethExecStep};
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the [`OpcodeId::MCOPY`](crate::evm::OpcodeId::MCOPY)
+/// `OpcodeId`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Mcopy;
+
+impl Opcode for Mcopy {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let exec_step = gen_mcopy_step(state, geth_step)?;
+        let mut exec_steps = vec![exec_step];
+        let memory_copy_steps = gen_memory_copy_steps(state, geth_steps)?;
+        exec_steps.extend(memory_copy_steps);
+        Ok(exec_steps)
+    }
+}
+
+fn gen_mcopy_step(
+    state: &mut CircuitInputStateRef,
+    geth_step: &GethExecStep,
+) -> Result<ExecStep, Error> {
+    let mut exec_step = state.new_step(geth_step)?;
+    let dst_offset = geth_step.stack.nth_last(0)?;
+    let src_offset = geth_step.stack.nth_last(1)?;
+    let length = geth_step.stack.nth_last(2)?;
+
+    state.stack_read(
+        &mut exec_step,
+        geth_step.stack.nth_last_filled(0),
+        dst_offset,
+    )?;
+    state.stack_read(
+        &mut exec_step,
+        geth_step.stack.nth_last_filled(1),
+        src_offset,
+    )?;
+    state.stack_read(&mut exec_step, geth_step.stack.nth_last_filled(2), length)?;
+
+    Ok(exec_step)
+}
+
+/// Generate the memory reads and writes of a copy step.  The source and
+/// destination regions may overlap, so when the destination is after the
+/// source the bytes are copied from last to first, which guarantees that every
+/// byte is read before it's overwritten.
+fn gen_memory_copy_step(
+    state: &mut CircuitInputStateRef,
+    exec_step: &mut ExecStep,
+    aux_data: StepAuxiliaryData,
+    src_bytes: &[u8],
+    src_offset: u64,
+) -> Result<(), Error> {
+    let chunk_len = std::cmp::min(aux_data.bytes_left as usize, MAX_COPY_BYTES);
+    let is_backwards = aux_data.dst_addr > aux_data.src_addr;
+    for i in 0..chunk_len {
+        let idx = if is_backwards { chunk_len - 1 - i } else { i };
+        let byte = src_bytes[(aux_data.src_addr - src_offset) as usize + idx];
+        state.memory_read(exec_step, ((aux_data.src_addr as usize) + idx).into(), byte)?;
+        state.memory_write(exec_step, ((aux_data.dst_addr as usize) + idx).into(), byte)?;
+    }
+
+    exec_step.aux_data = Some(aux_data);
+
+    Ok(())
+}
+
+fn gen_memory_copy_steps(
+    state: &mut CircuitInputStateRef,
+    geth_steps: &[GethExecStep],
+) -> Result<Vec<ExecStep>, Error> {
+    // The offsets of an empty copy may be huge, as they aren't used.
+    let length = geth_steps[0].stack.nth_last(2)?;
+    if length.is_zero() {
+        return Ok(vec![]);
+    }
+    let dst_offset = geth_steps[0].stack.nth_last(0)?.as_u64();
+    let src_offset = geth_steps[0].stack.nth_last(1)?.as_u64();
+    let length = length.as_u64();
+
+    // The memory of the step before the copy, padded with zeros past its end.
+    let src_bytes = geth_steps[0].memory.read_chunk(
+        MemoryAddress::from(src_offset as usize),
+        MemoryAddress::from(length as usize),
+    );
+    let call_id = state.call()?.call_id;
+
+    let mut chunk_offsets = (0..length).step_by(MAX_COPY_BYTES).collect::<Vec<u64>>();
+    if dst_offset > src_offset {
+        chunk_offsets.reverse();
+    }
+
+    let mut steps = vec![];
+    for copied in chunk_offsets {
+        let mut exec_step = state.new_step(&geth_steps[1])?;
+        exec_step.exec_state = ExecState::CopyToMemory;
+        gen_memory_copy_step(
+            state,
+            &mut exec_step,
+            StepAuxiliaryData::new(
+                src_offset + copied,
+                dst_offset + copied,
+                length - copied,
+                src_offset + length,
+                CopyDetails::Memory(call_id),
+            ),
+            &src_bytes,
+            src_offset,
+        )?;
+        steps.push(exec_step);
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod mcopy_tests {
+    use super::*;
+    use crate::{
+        evm::Hardfork,
+        mock::{trace_code, BlockData},
+        operation::{MemoryOp, StackOp, RW},
+    };
+    use eth_types::{
+        bytecode,
+        evm_types::{OpcodeId, StackAddress},
+        geth_types::GethData,
+        Word,
+    };
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    fn test_ok(dst_offset: usize, src_offset: usize, size: usize) {
+        let memory = (0..0x40).collect::<Vec<u8>>();
+        let code = bytecode! {
+            PUSH32(Word::from_big_endian(&memory[..0x20]))
+            PUSH1(0x00)
+            MSTORE
+            PUSH32(Word::from_big_endian(&memory[0x20..]))
+            PUSH1(0x20)
+            MSTORE
+            PUSH32(size) // length
+            PUSH32(src_offset) // srcOffset
+            PUSH32(dst_offset) // dstOffset
+            MCOPY
+            STOP
+        };
+
+        let mut block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code.clone()),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        // The geth of geth-utils can't trace MCOPY.
+        block.geth_traces[0] =
+            trace_code(&block.eth_block.transactions[0], &code, &[], Word::one());
+
+        let mut block_data = BlockData::new_from_geth_data(block.clone());
+        block_data.hardfork = Hardfork::Cancun;
+        let mut builder = block_data.new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let transaction = &builder.block.txs()[0];
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::MCOPY))
+            .unwrap();
+        let container = &builder.block.container;
+
+        assert_eq!(
+            [0, 1, 2]
+                .map(|idx| &container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1021), Word::from(dst_offset))
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1022), Word::from(src_offset))
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1023), Word::from(size))
+                ),
+            ]
+        );
+
+        let copy_steps = transaction
+            .steps()
+            .iter()
+            .filter(|step| step.exec_state == ExecState::CopyToMemory)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            copy_steps.len(),
+            (size + MAX_COPY_BYTES - 1) / MAX_COPY_BYTES
+        );
+        assert!(copy_steps
+            .iter()
+            .all(|step| step.aux_data.unwrap().is_memory_originated()));
+
+        // Tuples of memory reads and writes, which must read every byte
+        // before overwriting it.
+        let ops = copy_steps
+            .iter()
+            .flat_map(|step| step.bus_mapping_instance.iter())
+            .map(|op_ref| {
+                let op = &container.memory[op_ref.as_usize()];
+                (op.rw(), op.op().clone())
+            })
+            .collect::<Vec<(RW, MemoryOp)>>();
+        let mut expected = (0..size)
+            .map(|idx| {
+                [
+                    (
+                        RW::READ,
+                        MemoryOp::new(1, (src_offset + idx).into(), memory[src_offset + idx]),
+                    ),
+                    (
+                        RW::WRITE,
+                        MemoryOp::new(1, (dst_offset + idx).into(), memory[src_offset + idx]),
+                    ),
+                ]
+            })
+            .collect::<Vec<_>>();
+        if dst_offset > src_offset {
+            expected.reverse();
+        }
+        assert_eq!(ops, expected.concat());
+    }
+
+    #[test]
+    fn mcopy_opcode_impl() {
+        test_ok(0x00, 0x20, 0x20)
+    }
+
+    #[test]
+    fn mcopy_opcode_impl_overlapping_forwards() {
+        test_ok(0x00, 0x08, 0x30)
+    }
+
+    #[test]
+    fn mcopy_opcode_impl_overlapping_backwards() {
+        test_ok(0x08, 0x00, 0x30)
+    }
+
+    #[test]
+    fn mcopy_opcode_impl_zero_length() {
+        // The offsets of an empty copy don't fit in 64 bits.
+        let code = bytecode! {
+            PUSH1(0x00) // length
+            PUSH32(Word::MAX) // srcOffset
+            PUSH32(Word::MAX) // dstOffset
+            MCOPY
+            STOP
+        };
+        let mut block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code.clone()),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        // The geth of geth-utils can't trace MCOPY.
+        block.geth_traces[0] =
+            trace_code(&block.eth_block.transactions[0], &code, &[], Word::one());
+
+        let mut block_data = BlockData::new_from_geth_data(block.clone());
+        block_data.hardfork = Hardfork::Cancun;
+        let mut builder = block_data.new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let steps = builder.block.txs()[0].steps();
+        assert!(steps
+            .iter()
+            .any(|step| step.exec_state == ExecState::Op(OpcodeId::MCOPY)));
+        assert!(steps
+            .iter()
+            .all(|step| step.exec_state != ExecState::CopyToMemory));
+        assert!(builder.block.container.memory.is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{get_call_memory_offset_length, CircuitInputStateRef, ExecStep},
+    evm::OpcodeId,
+    operation::{AccountField, AccountOp, CallContextField, MemoryOp, RW},
+    Error,
+};
+use eth_types::{GethExecStep, ToWord, Word};
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the [`OpcodeId::RETURN`](crate::evm::OpcodeId::RETURN) and
+/// [`OpcodeId::REVERT`](crate::evm::OpcodeId::REVERT) `OpcodeId`s.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ReturnRevert;
+
+impl Opcode for ReturnRevert {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+
+        let offset = geth_step.stack.nth_last(0)?;
+        let length = geth_step.stack.nth_last(1)?;
+        state.stack_read(&mut exec_step, geth_step.stack.nth_last_filled(0), offset)?;
+        state.stack_read(&mut exec_step, geth_step.stack.nth_last_filled(1), length)?;
+
+        let call = state.call()?.clone();
+        state.call_context_read(
+            &mut exec_step,
+            call.call_id,
+            CallContextField::IsSuccess,
+            Word::from(call.is_success as u8),
+        );
+
+        // An empty range doesn't access the memory, so its offset may be past
+        // u64.
+        let (offset, length) = get_call_memory_offset_length(geth_step, 0)?;
+        let (offset, length) = (offset as usize, length as usize);
+        let data = geth_step.memory.read_chunk(offset.into(), length.into());
+
+        if call.is_create() && call.is_success && geth_step.op == OpcodeId::RETURN {
+            // The returned bytes of a successful create are the deployed code.
+            for (idx, byte) in data.iter().enumerate() {
+                state.memory_read(&mut exec_step, (offset + idx).into(), *byte)?;
+            }
+            let code_hash = state.code_db.insert(data.clone());
+            state.block.keccak_inputs.push(data);
+            let (found, callee_account) = state.sdb.get_account(&call.address);
+            if !found {
+                return Err(Error::AccountNotFound(call.address));
+            }
+            let code_hash_prev = callee_account.code_hash;
+            state.push_op_reversible(
+                &mut exec_step,
+                RW::WRITE,
+                AccountOp {
+                    address: call.address,
+                    field: AccountField::CodeHash,
+                    value: code_hash.to_word(),
+                    value_prev: code_hash_prev.to_word(),
+                },
+            )?;
+        } else if !call.is_root && !call.is_create() {
+            // The returned bytes are copied into the region of the caller's
+            // memory reserved for them, up to the size of that region.
+            for (field, value) in [
+                (
+                    CallContextField::ReturnDataOffset,
+                    call.return_data_offset.into(),
+                ),
+                (
+                    CallContextField::ReturnDataLength,
+                    call.return_data_length.into(),
+                ),
+            ] {
+                state.call_context_read(&mut exec_step, call.call_id, field, value);
+            }
+
+            let copy_length = std::cmp::min(length, call.return_data_length as usize);
+            for (idx, byte) in data.iter().take(copy_length).enumerate() {
+                state.memory_read(&mut exec_step, (offset + idx).into(), *byte)?;
+                state.push_op(
+                    &mut exec_step,
+                    RW::WRITE,
+                    MemoryOp::new(
+                        call.caller_id,
+                        (call.return_data_offset as usize + idx).into(),
+                        *byte,
+                    ),
+                );
+            }
+        }
+
+        // A REVERT always ends the call with failure, so that all of its
+        // reversible operations are reverted when returning to the caller.
+        debug_assert!(geth_step.op != OpcodeId::REVERT || !call.is_success);
+        state.handle_return(geth_step)?;
+
+        Ok(vec![exec_step])
+    }
+}
+
+#[cfg(test)]
+mod return_revert_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::{CircuitInputBuilder, ExecState},
+        mock::BlockData,
+        operation::{CallContextOp, StackOp, Target},
+    };
+    use eth_types::{
+        bytecode, evm_types::StackAddress, geth_types::GethData, Bytecode, ToWord, Word,
+    };
+    use keccak256::EMPTY_HASH;
+    use mock::test_ctx::TestContext;
+    use pretty_assertions::assert_eq;
+
+    const RET_OFFSET: usize = 0x10;
+
+    fn handle_block(block: &GethData) -> CircuitInputBuilder {
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        builder
+    }
+
+    // code_a calls code_b reserving `ret_length` bytes at RET_OFFSET for its
+    // return data, and then stops.
+    fn gen_block(code_b: Bytecode, ret_length: usize) -> GethData {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+        let code_a = bytecode! {
+            PUSH1(ret_length) // retLength
+            PUSH1(RET_OFFSET) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x00) // value
+            PUSH32(addr_b.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            STOP
+        };
+        TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_a).code(code_a);
+                accs[1].address(addr_b).code(code_b);
+                accs[2]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[2].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into()
+    }
+
+    // Callee code that stores some bytes in its first memory word, writes into
+    // its storage and ends with `op` returning `length` bytes from offset 0.
+    fn callee_code(op: OpcodeId, length: usize) -> Bytecode {
+        let mut code = bytecode! {
+            PUSH32(Word::from_big_endian(&return_data()))
+            PUSH1(0x00) // offset
+            MSTORE
+            PUSH1(0x01) // value
+            PUSH1(0x00) // key
+            SSTORE
+            PUSH1(length) // length
+            PUSH1(0x00) // offset
+        };
+        code.write_op(op);
+        code
+    }
+
+    fn return_data() -> Vec<u8> {
+        (1..=32).collect()
+    }
+
+    fn test_ok(op: OpcodeId, length: usize, ret_length: usize) {
+        let block = gen_block(callee_code(op, length), ret_length);
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let callee = &transaction.calls()[1];
+        assert_eq!(callee.is_success, op == OpcodeId::RETURN);
+
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(op))
+            .unwrap();
+        let container = &builder.block.container;
+        assert_eq!(
+            [0, 1]
+                .map(|idx| &container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(callee.call_id, StackAddress::from(1022), Word::zero())
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(callee.call_id, StackAddress::from(1023), Word::from(length))
+                ),
+            ]
+        );
+        assert_eq!(
+            [2, 3, 4]
+                .map(|idx| &container.call_context[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op().clone())),
+            [
+                (
+                    CallContextField::IsSuccess,
+                    Word::from(callee.is_success as u8)
+                ),
+                (CallContextField::ReturnDataOffset, Word::from(RET_OFFSET)),
+                (CallContextField::ReturnDataLength, Word::from(ret_length)),
+            ]
+            .map(|(field, value)| {
+                (
+                    RW::READ,
+                    CallContextOp {
+                        call_id: callee.call_id,
+                        field,
+                        value,
+                    },
+                )
+            })
+        );
+
+        // Each copied byte is read from the callee memory and written into the
+        // caller memory.
+        let copy_length = std::cmp::min(length, ret_length);
+        let memory_ops = step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == Target::Memory)
+            .map(|op_ref| &container.memory[op_ref.as_usize()])
+            .map(|operation| (operation.rw(), operation.op().clone()))
+            .collect::<Vec<_>>();
+        let expected = return_data()
+            .into_iter()
+            .take(copy_length)
+            .enumerate()
+            .flat_map(|(idx, byte)| {
+                [
+                    (RW::READ, MemoryOp::new(callee.call_id, idx.into(), byte)),
+                    (
+                        RW::WRITE,
+                        MemoryOp::new(callee.caller_id, (RET_OFFSET + idx).into(), byte),
+                    ),
+                ]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(memory_ops, expected);
+
+        // The storage write of the callee is only kept if it returns.
+        let (_, value) = builder
+            .sdb
+            .get_storage(&mock::MOCK_ACCOUNTS[1], &Word::zero());
+        assert_eq!(*value, Word::from((op == OpcodeId::RETURN) as u64));
+    }
+
+    #[test]
+    fn return_opcode_impl() {
+        test_ok(OpcodeId::RETURN, 0x20, 0x08);
+    }
+
+    #[test]
+    fn return_opcode_shorter_than_reserved() {
+        test_ok(OpcodeId::RETURN, 0x04, 0x08);
+    }
+
+    #[test]
+    fn revert_opcode_impl() {
+        test_ok(OpcodeId::REVERT, 0x20, 0x08);
+    }
+
+    #[test]
+    fn return_opcode_empty_huge_offset() {
+        let code_b = bytecode! {
+            PUSH1(0x00) // length
+            PUSH32(Word::one() << 255) // offset
+            RETURN
+        };
+        let block = gen_block(code_b, 0x08);
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        assert!(transaction.calls()[1].is_success);
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::RETURN))
+            .unwrap();
+        assert_eq!(step.error, None);
+        assert!(!step
+            .bus_mapping_instance
+            .iter()
+            .any(|op_ref| op_ref.target() == Target::Memory));
+    }
+
+    #[test]
+    fn return_opcode_create() {
+        // Init code that deploys the 0x00 byte at the end of its first memory
+        // word.
+        let init_code = bytecode! {
+            PUSH1(0x01) // length
+            PUSH1(0x1f) // offset
+            RETURN
+        }
+        .to_vec();
+        let code = bytecode! {
+            PUSH32(Word::from_big_endian(&init_code))
+            PUSH1(0x00)
+            MSTORE
+            PUSH1(init_code.len()) // length
+            PUSH1(32 - init_code.len()) // offset
+            PUSH1(0x00) // value
+            CREATE
+            STOP
+        };
+        let block = gen_block(code, 0);
+        let builder = handle_block(&block);
+
+        let transaction = &builder.block.txs()[0];
+        let call = &transaction.calls()[2];
+        assert!(call.is_create());
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::RETURN))
+            .unwrap();
+        let container = &builder.block.container;
+        let memory_op = step
+            .bus_mapping_instance
+            .iter()
+            .find(|op_ref| op_ref.target() == Target::Memory)
+            .map(|op_ref| &container.memory[op_ref.as_usize()])
+            .unwrap();
+        assert_eq!(
+            (memory_op.rw(), memory_op.op()),
+            (RW::READ, &MemoryOp::new(call.call_id, 0x1f.into(), 0x00))
+        );
+
+        let (_, account) = builder.sdb.get_account(&call.address);
+        assert_eq!(builder.code_db.0[&account.code_hash], vec![0x00]);
+        let account_op = step
+            .bus_mapping_instance
+            .iter()
+            .find(|op_ref| op_ref.target() == Target::Account)
+            .map(|op_ref| &container.account[op_ref.as_usize()])
+            .unwrap();
+        assert_eq!(
+            (account_op.rw(), account_op.op()),
+            (
+                RW::WRITE,
+                &AccountOp {
+                    address: call.address,
+                    field: AccountField::CodeHash,
+                    value: account.code_hash.to_word(),
+                    value_prev: Word::from_big_endian(&*EMPTY_HASH),
+                }
+            )
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{
+        CircuitInputStateRef, CopyDetails, ExecState, ExecStep, StepAuxiliaryData,
+    },
+    constants::MAX_COPY_BYTES,
+    operation::{CallContextField, MemoryOp, RW},
+    Error,
+};
+use eth_types::GethExecStep;
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the
+/// [`OpcodeId::RETURNDATACOPY`](crate::evm::OpcodeId::RETURNDATACOPY)
+/// `OpcodeId`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Returndatacopy;
+
+impl Opcode for Returndatacopy {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let exec_step = gen_returndatacopy_step(state, geth_step)?;
+        let mut exec_steps = vec![exec_step];
+        let memory_copy_steps = gen_memory_copy_steps(state, geth_steps)?;
+        exec_steps.extend(memory_copy_steps);
+        Ok(exec_steps)
+    }
+}
+
+fn gen_returndatacopy_step(
+    state: &mut CircuitInputStateRef,
+    geth_step: &GethExecStep,
+) -> Result<ExecStep, Error> {
+    let mut exec_step = state.new_step(geth_step)?;
+    let memory_offset = geth_step.stack.nth_last(0)?;
+    let data_offset = geth_step.stack.nth_last(1)?;
+    let length = geth_step.stack.nth_last(2)?;
+
+    state.stack_read(
+        &mut exec_step,
+        geth_step.stack.nth_last_filled(0),
+        memory_offset,
+    )?;
+    state.stack_read(
+        &mut exec_step,
+        geth_step.stack.nth_last_filled(1),
+        data_offset,
+    )?;
+    state.stack_read(&mut exec_step, geth_step.stack.nth_last_filled(2), length)?;
+
+    let call_ctx = state.call_ctx()?;
+    let return_data_length = call_ctx.last_callee_return_data_length;
+    for (field, value) in [
+        (
+            CallContextField::LastCalleeId,
+            call_ctx.last_callee_id.into(),
+        ),
+        (
+            CallContextField::LastCalleeReturnDataOffset,
+            call_ctx.last_callee_return_data_offset.into(),
+        ),
+        (
+            CallContextField::LastCalleeReturnDataLength,
+            return_data_length.into(),
+        ),
+    ] {
+        state.call_context_read(&mut exec_step, state.call()?.call_id, field, value);
+    }
+
+    Ok(exec_step)
+}
+
+fn gen_memory_copy_step(
+    state: &mut CircuitInputStateRef,
+    exec_step: &mut ExecStep,
+    aux_data: StepAuxiliaryData,
+    return_data_offset: u64,
+    callee_id: usize,
+) -> Result<(), Error> {
+    for idx in 0..std::cmp::min(aux_data.bytes_left as usize, MAX_COPY_BYTES) {
+        let addr = aux_data.src_addr + idx as u64;
+        let byte = state.call_ctx()?.return_data[(addr - return_data_offset) as usize];
+        state.push_op(
+            exec_step,
+            RW::READ,
+            MemoryOp::new(callee_id, (addr as usize).into(), byte),
+        );
+        state.memory_write(exec_step, ((aux_data.dst_addr as usize) + idx).into(), byte)?;
+    }
+
+    exec_step.aux_data = Some(aux_data);
+
+    Ok(())
+}
+
+fn gen_memory_copy_steps(
+    state: &mut CircuitInputStateRef,
+    geth_steps: &[GethExecStep],
+) -> Result<Vec<ExecStep>, Error> {
+    let length = geth_steps[0].stack.nth_last(2)?;
+    // An empty copy doesn't access the memory, so its offset may be past u64.
+    if length.is_zero() {
+        return Ok(vec![]);
+    }
+    let memory_offset = geth_steps[0].stack.nth_last(0)?.as_u64();
+    let data_offset = geth_steps[0].stack.nth_last(1)?.as_u64();
+    let length = length.as_u64();
+
+    let call_ctx = state.call_ctx()?;
+    let callee_id = call_ctx.last_callee_id;
+    let return_data_offset = call_ctx.last_callee_return_data_offset;
+    let (src_addr, src_addr_end) = (
+        return_data_offset + data_offset,
+        return_data_offset + call_ctx.last_callee_return_data_length,
+    );
+
+    let mut copied = 0;
+    let mut steps = vec![];
+    while copied < length {
+        let mut exec_step = state.new_step(&geth_steps[1])?;
+        exec_step.exec_state = ExecState::CopyToMemory;
+        gen_memory_copy_step(
+            state,
+            &mut exec_step,
+            StepAuxiliaryData::new(
+                src_addr + copied,
+                memory_offset + copied,
+                length - copied,
+                src_addr_end,
+                CopyDetails::ReturnData(callee_id),
+            ),
+            return_data_offset,
+            callee_id,
+        )?;
+        steps.push(exec_step);
+        copied += MAX_COPY_BYTES as u64;
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod returndatacopy_tests {
+    use super::*;
+    use crate::{
+        error::ExecError,
+        mock::BlockData,
+        operation::{CallContextOp, StackOp},
+    };
+    use eth_types::{
+        bytecode,
+        evm_types::{OpcodeId, StackAddress},
+        geth_types::GethData,
+        ToWord, Word,
+    };
+    use mock::test_ctx::TestContext;
+    use pretty_assertions::assert_eq;
+
+    fn gen_block(return_data: &[u8], dst_offset: Word, offset: usize, size: usize) -> GethData {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+
+        // code B returns `return_data` from its memory.
+        let code_b = bytecode! {
+            PUSH32(Word::from_big_endian(return_data)) // value
+            PUSH1(0x00) // offset
+            MSTORE
+            PUSH1(0x20) // length
+            PUSH1(0x00) // offset
+            RETURN
+        };
+
+        // code A calls code B and copies its return data to memory.
+        let code_a = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x00) // value
+            PUSH32(addr_b.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            PUSH32(size) // length
+            PUSH32(offset) // offset
+            PUSH32(dst_offset) // dstOffset
+            RETURNDATACOPY
+            STOP
+        };
+
+        TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_b).code(code_b);
+                accs[1].address(addr_a).code(code_a);
+                accs[2]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[1].address).from(accs[2].address);
+            },
+            |block, _tx| block,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn returndatacopy_opcode_impl() {
+        let return_data = (0..0x20).collect::<Vec<u8>>();
+        let (dst_offset, offset, size) = (0x00usize, 0x08usize, 0x18usize);
+        let block = gen_block(&return_data, Word::from(dst_offset), offset, size);
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let transaction = &builder.block.txs()[0];
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::RETURNDATACOPY))
+            .unwrap();
+        let call_id = transaction.calls()[0].call_id;
+        let callee_id = transaction.calls()[1].call_id;
+        let container = &builder.block.container;
+
+        // 3 stack reads + 3 call context reads.
+        assert_eq!(step.bus_mapping_instance.len(), 6);
+        assert_eq!(
+            [0, 1, 2]
+                .map(|idx| &container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(call_id, StackAddress::from(1020), Word::from(dst_offset))
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(call_id, StackAddress::from(1021), Word::from(offset))
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(call_id, StackAddress::from(1022), Word::from(size))
+                ),
+            ]
+        );
+        assert_eq!(
+            [3, 4, 5]
+                .map(|idx| &container.call_context[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &CallContextOp {
+                        call_id,
+                        field: CallContextField::LastCalleeId,
+                        value: Word::from(callee_id),
+                    }
+                ),
+                (
+                    RW::READ,
+                    &CallContextOp {
+                        call_id,
+                        field: CallContextField::LastCalleeReturnDataOffset,
+                        value: Word::zero(),
+                    }
+                ),
+                (
+                    RW::READ,
+                    &CallContextOp {
+                        call_id,
+                        field: CallContextField::LastCalleeReturnDataLength,
+                        value: Word::from(return_data.len()),
+                    }
+                ),
+            ]
+        );
+
+        // Tuples of callee memory reads and caller memory writes.
+        let copy_steps = transaction
+            .steps()
+            .iter()
+            .filter(|step| step.exec_state == ExecState::CopyToMemory)
+            .collect::<Vec<_>>();
+        assert_eq!(copy_steps.len(), 1);
+        assert!(copy_steps[0].aux_data.unwrap().is_return_data_originated());
+        assert_eq!(
+            copy_steps[0]
+                .bus_mapping_instance
+                .iter()
+                .map(|op_ref| {
+                    let op = &container.memory[op_ref.as_usize()];
+                    (op.rw(), op.op().clone())
+                })
+                .collect::<Vec<(RW, MemoryOp)>>(),
+            (0..size)
+                .flat_map(|idx| {
+                    [
+                        (
+                            RW::READ,
+                            MemoryOp::new(
+                                callee_id,
+                                (offset + idx).into(),
+                                return_data[offset + idx],
+                            ),
+                        ),
+                        (
+                            RW::WRITE,
+                            MemoryOp::new(
+                                call_id,
+                                (dst_offset + idx).into(),
+                                return_data[offset + idx],
+                            ),
+                        ),
+                    ]
+                })
+                .collect::<Vec<(RW, MemoryOp)>>(),
+        );
+    }
+
+    #[test]
+    fn returndatacopy_empty_huge_offset() {
+        let return_data = (0..0x20).collect::<Vec<u8>>();
+        let block = gen_block(&return_data, Word::MAX, 0x10, 0x00);
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let transaction = &builder.block.txs()[0];
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::RETURNDATACOPY))
+            .unwrap();
+        assert_eq!(step.error, None);
+        assert!(!transaction
+            .steps()
+            .iter()
+            .any(|step| step.exec_state == ExecState::CopyToMemory));
+    }
+
+    #[test]
+    fn returndatacopy_out_of_bounds() {
+        let return_data = (0..0x20).collect::<Vec<u8>>();
+        let block = gen_block(&return_data, Word::zero(), 0x10, 0x11);
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let transaction = &builder.block.txs()[0];
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::RETURNDATACOPY))
+            .unwrap();
+        assert_eq!(step.error, Some(ExecError::ReturnDataOutOfBounds));
+        assert!(!transaction
+            .steps()
+            .iter()
+            .any(|step| step.exec_state == ExecState::CopyToMemory));
+    }
+}
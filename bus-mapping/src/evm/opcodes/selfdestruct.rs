@@ -0,0 +1,515 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{CircuitInputStateRef, ExecStep},
+    evm::Hardfork,
+    operation::{
+        AccountDestructedOp, AccountField, AccountOp, CallContextField, TxAccessListAccountOp,
+        TxRefundOp, RW,
+    },
+    Error,
+};
+use eth_types::{GethExecStep, ToAddress, ToWord, Word};
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the
+/// [`OpcodeId::SELFDESTRUCT`](crate::evm::OpcodeId::SELFDESTRUCT) `OpcodeId`.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Selfdestruct;
+
+impl Opcode for Selfdestruct {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+
+        let beneficiary = geth_step.stack.last()?;
+        state.stack_read(&mut exec_step, geth_step.stack.last_filled(), beneficiary)?;
+        let beneficiary = beneficiary.to_address();
+
+        let call_id = state.call()?.call_id;
+        let sender = state.call()?.address;
+        for (field, value) in [
+            (CallContextField::TxId, Word::from(state.tx_ctx.id())),
+            (
+                CallContextField::RwCounterEndOfReversion,
+                Word::from(state.call()?.rw_counter_end_of_reversion),
+            ),
+            (
+                CallContextField::IsPersistent,
+                Word::from(state.call()?.is_persistent as u8),
+            ),
+            (CallContextField::CalleeAddress, sender.to_word()),
+        ] {
+            state.call_context_read(&mut exec_step, call_id, field, value);
+        }
+
+        let is_warm = state.sdb.check_account_in_access_list(&beneficiary);
+        state.push_op_reversible(
+            &mut exec_step,
+            RW::WRITE,
+            TxAccessListAccountOp {
+                tx_id: state.tx_ctx.id(),
+                address: beneficiary,
+                is_warm: true,
+                is_warm_prev: is_warm,
+            },
+        )?;
+
+        let (found, sender_account) = state.sdb.get_account(&sender);
+        if !found {
+            return Err(Error::AccountNotFound(sender));
+        }
+        let value = sender_account.balance;
+        // From Cancun on, only a contract created in the same transaction is
+        // destroyed (EIP-6780).  Any other just sends its balance away.
+        let is_destructed = !state.block.chain_config.is_active(Hardfork::Cancun)
+            || state.sdb.check_contract_created(&sender);
+        if beneficiary == sender {
+            if is_destructed {
+                // The balance of a contract which is its own beneficiary is
+                // burnt.
+                state.push_op_reversible(
+                    &mut exec_step,
+                    RW::WRITE,
+                    AccountOp {
+                        address: sender,
+                        field: AccountField::Balance,
+                        value: Word::zero(),
+                        value_prev: value,
+                    },
+                )?;
+            }
+        } else {
+            // The beneficiary is created if it doesn't exist yet.
+            state.sdb.get_account_mut(&beneficiary);
+            state.transfer(&mut exec_step, sender, beneficiary, value)?;
+        }
+
+        if !is_destructed {
+            state.handle_return(geth_step)?;
+            return Ok(vec![exec_step]);
+        }
+
+        let is_destructed_prev = state.sdb.check_account_destructed(&sender);
+        state.push_op_reversible(
+            &mut exec_step,
+            RW::WRITE,
+            AccountDestructedOp {
+                tx_id: state.tx_ctx.id(),
+                address: sender,
+                is_destructed: true,
+                is_destructed_prev,
+            },
+        )?;
+
+        // The refund is only given the first time an account is destructed.
+        let refund_prev = state.sdb.refund();
+        let refund = if is_destructed_prev {
+            0
+        } else {
+            state.block.chain_config.gas_schedule().selfdestruct_refund
+        };
+        state.push_op_reversible(
+            &mut exec_step,
+            RW::WRITE,
+            TxRefundOp {
+                tx_id: state.tx_ctx.id(),
+                value_prev: refund_prev,
+                value: refund_prev + refund,
+            },
+        )?;
+
+        state.handle_return(geth_step)?;
+
+        Ok(vec![exec_step])
+    }
+}
+
+#[cfg(test)]
+mod selfdestruct_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::{CircuitInputBuilder, ExecState},
+        evm::{Hardfork, OpcodeId},
+        mock::BlockData,
+        operation::{CallContextOp, StackOp, Target},
+    };
+    use eth_types::{
+        address, bytecode, evm_types::StackAddress, geth_types::GethData, Address, Bytecode,
+    };
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    const CONTRACT_BALANCE: u64 = 0x1234;
+
+    fn handle_block(block: &GethData) -> CircuitInputBuilder {
+        handle_block_with_hardfork(block, Hardfork::default())
+    }
+
+    fn handle_block_with_hardfork(block: &GethData, hardfork: Hardfork) -> CircuitInputBuilder {
+        let mut block_data = BlockData::new_from_geth_data(block.clone());
+        block_data.hardfork = hardfork;
+        let mut builder = block_data.new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        builder
+    }
+
+    fn selfdestruct_block(beneficiary: Address) -> GethData {
+        let code = bytecode! {
+            PUSH20(beneficiary.to_word())
+            SELFDESTRUCT
+        };
+        TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(mock::MOCK_ACCOUNTS[0])
+                    .balance(Word::from(CONTRACT_BALANCE))
+                    .code(code);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[1])
+                    .balance(Word::from(1u64 << 30));
+            },
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn assert_common_ops(builder: &CircuitInputBuilder, beneficiary: Address) -> &ExecStep {
+        let contract = mock::MOCK_ACCOUNTS[0];
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::SELFDESTRUCT))
+            .unwrap();
+        let container = &builder.block.container;
+
+        let operation = &container.stack[step.bus_mapping_instance[0].as_usize()];
+        assert_eq!(
+            (operation.rw(), operation.op()),
+            (
+                RW::READ,
+                &StackOp::new(1, StackAddress::from(1023), beneficiary.to_word())
+            )
+        );
+        assert_eq!(
+            [1, 2, 3, 4]
+                .map(|idx| &container.call_context[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op().clone())),
+            [
+                (CallContextField::TxId, Word::from(1)),
+                (CallContextField::RwCounterEndOfReversion, Word::zero()),
+                (CallContextField::IsPersistent, Word::from(1)),
+                (CallContextField::CalleeAddress, contract.to_word()),
+            ]
+            .map(|(field, value)| {
+                (
+                    RW::READ,
+                    CallContextOp {
+                        call_id: 1,
+                        field,
+                        value,
+                    },
+                )
+            })
+        );
+        let operation = &container.tx_access_list_account[step.bus_mapping_instance[5].as_usize()];
+        assert_eq!(
+            (operation.rw(), operation.op()),
+            (
+                RW::WRITE,
+                &TxAccessListAccountOp {
+                    tx_id: 1,
+                    address: beneficiary,
+                    is_warm: true,
+                    is_warm_prev: beneficiary == contract,
+                }
+            )
+        );
+        step
+    }
+
+    fn assert_destruct_ops(builder: &CircuitInputBuilder, step: &ExecStep, index: usize) {
+        let container = &builder.block.container;
+        let operation = &container.account_destructed[step.bus_mapping_instance[index].as_usize()];
+        assert_eq!(
+            (operation.rw(), operation.op()),
+            (
+                RW::WRITE,
+                &AccountDestructedOp {
+                    tx_id: 1,
+                    address: mock::MOCK_ACCOUNTS[0],
+                    is_destructed: true,
+                    is_destructed_prev: false,
+                }
+            )
+        );
+        let operation = &container.tx_refund[step.bus_mapping_instance[index + 1].as_usize()];
+        assert_eq!(
+            (operation.rw(), operation.op()),
+            (
+                RW::WRITE,
+                &TxRefundOp {
+                    tx_id: 1,
+                    value: 0,
+                    value_prev: 0,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn selfdestruct_opcode_impl() {
+        let contract = mock::MOCK_ACCOUNTS[0];
+        let beneficiary = address!("0x00000000000000000000000000000000000000fe");
+        let block = selfdestruct_block(beneficiary);
+        let builder = handle_block(&block);
+
+        let step = assert_common_ops(&builder, beneficiary);
+        let container = &builder.block.container;
+        assert_eq!(
+            [6, 7]
+                .map(|idx| &container.account[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::WRITE,
+                    &AccountOp {
+                        address: contract,
+                        field: AccountField::Balance,
+                        value: Word::zero(),
+                        value_prev: Word::from(CONTRACT_BALANCE),
+                    }
+                ),
+                (
+                    RW::WRITE,
+                    &AccountOp {
+                        address: beneficiary,
+                        field: AccountField::Balance,
+                        value: Word::from(CONTRACT_BALANCE),
+                        value_prev: Word::zero(),
+                    }
+                ),
+            ]
+        );
+        assert_destruct_ops(&builder, step, 8);
+
+        // The destruction is applied at the end of the transaction.
+        let (_, account) = builder.sdb.get_account(&contract);
+        assert!(account.is_empty());
+        let (found, account) = builder.sdb.get_account(&beneficiary);
+        assert!(found);
+        assert_eq!(account.balance, Word::from(CONTRACT_BALANCE));
+        assert!(!builder.sdb.check_account_destructed(&contract));
+    }
+
+    #[test]
+    fn selfdestruct_opcode_refund_before_london() {
+        let beneficiary = address!("0x00000000000000000000000000000000000000fe");
+        let block = selfdestruct_block(beneficiary);
+        let builder = handle_block_with_hardfork(&block, Hardfork::Berlin);
+
+        let step = assert_common_ops(&builder, beneficiary);
+        let operation = &builder.block.container.tx_refund[step.bus_mapping_instance[9].as_usize()];
+        assert_eq!(
+            (operation.rw(), operation.op()),
+            (
+                RW::WRITE,
+                &TxRefundOp {
+                    tx_id: 1,
+                    value: 24000,
+                    value_prev: 0,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn selfdestruct_opcode_self_beneficiary() {
+        let contract = mock::MOCK_ACCOUNTS[0];
+        let block = selfdestruct_block(contract);
+        let builder = handle_block(&block);
+
+        let step = assert_common_ops(&builder, contract);
+        let container = &builder.block.container;
+        let operation = &container.account[step.bus_mapping_instance[6].as_usize()];
+        assert_eq!(
+            (operation.rw(), operation.op()),
+            (
+                RW::WRITE,
+                &AccountOp {
+                    address: contract,
+                    field: AccountField::Balance,
+                    value: Word::zero(),
+                    value_prev: Word::from(CONTRACT_BALANCE),
+                }
+            )
+        );
+        assert_destruct_ops(&builder, step, 7);
+
+        // The balance is burnt together with the contract.
+        let (_, account) = builder.sdb.get_account(&contract);
+        assert!(account.is_empty());
+    }
+
+    #[test]
+    fn selfdestruct_opcode_reverted() {
+        let (addr_a, addr_b) = (mock::MOCK_ACCOUNTS[0], mock::MOCK_ACCOUNTS[1]);
+        // code_a calls code_b which self destructs, and then reverts.
+        let code_a = bytecode! {
+            PUSH1(0x00) // retLength
+            PUSH1(0x00) // retOffset
+            PUSH1(0x00) // argsLength
+            PUSH1(0x00) // argsOffset
+            PUSH1(0x00) // value
+            PUSH32(addr_b.to_word()) // addr
+            PUSH32(0x1_0000) // gas
+            CALL
+            PUSH1(0x00)
+            PUSH1(0x00)
+            REVERT
+        };
+        let code_b: Bytecode = bytecode! {
+            PUSH20(addr_a.to_word())
+            SELFDESTRUCT
+        };
+        let block: GethData = TestContext::<3, 1>::new(
+            None,
+            |accs| {
+                accs[0].address(addr_a).code(code_a);
+                accs[1]
+                    .address(addr_b)
+                    .balance(Word::from(CONTRACT_BALANCE))
+                    .code(code_b.clone());
+                accs[2]
+                    .address(mock::MOCK_ACCOUNTS[2])
+                    .balance(Word::from(1u64 << 30));
+            },
+            |mut txs, accs| {
+                txs[0].to(accs[0].address).from(accs[2].address);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block(&block);
+
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::SELFDESTRUCT))
+            .unwrap();
+        assert!(step
+            .bus_mapping_instance
+            .iter()
+            .any(|op_ref| op_ref.target() == Target::AccountDestructed));
+
+        // Both the destruction and the transfer are reverted.
+        let (_, account) = builder.sdb.get_account(&addr_b);
+        assert_eq!(account.balance, Word::from(CONTRACT_BALANCE));
+        assert!(!account.is_empty());
+        let (_, account) = builder.sdb.get_account(&addr_a);
+        assert_eq!(account.balance, Word::zero());
+    }
+
+    fn destructed_op_count(step: &ExecStep) -> usize {
+        step.bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == Target::AccountDestructed)
+            .count()
+    }
+
+    #[test]
+    fn selfdestruct_opcode_cancun() {
+        let contract = mock::MOCK_ACCOUNTS[0];
+        let beneficiary = address!("0x00000000000000000000000000000000000000fe");
+        let block = selfdestruct_block(beneficiary);
+        let builder = handle_block_with_hardfork(&block, Hardfork::Cancun);
+
+        // The contract wasn't created in the transaction, so it only sends its
+        // balance to the beneficiary.
+        let step = assert_common_ops(&builder, beneficiary);
+        assert_eq!(destructed_op_count(step), 0);
+        let (_, account) = builder.sdb.get_account(&contract);
+        assert_eq!(account.balance, Word::zero());
+        assert!(!account.is_empty());
+        let (_, account) = builder.sdb.get_account(&beneficiary);
+        assert_eq!(account.balance, Word::from(CONTRACT_BALANCE));
+    }
+
+    #[test]
+    fn selfdestruct_opcode_cancun_self_beneficiary() {
+        let contract = mock::MOCK_ACCOUNTS[0];
+        let block = selfdestruct_block(contract);
+        let builder = handle_block_with_hardfork(&block, Hardfork::Cancun);
+
+        // Neither the contract nor its balance are gone.
+        let step = assert_common_ops(&builder, contract);
+        assert_eq!(step.bus_mapping_instance.len(), 6);
+        let (_, account) = builder.sdb.get_account(&contract);
+        assert_eq!(account.balance, Word::from(CONTRACT_BALANCE));
+        assert!(!account.is_empty());
+    }
+
+    #[test]
+    fn selfdestruct_opcode_cancun_created_in_tx() {
+        let beneficiary = address!("0x00000000000000000000000000000000000000fe");
+        let value = 0x100u64;
+        // The creator deploys a contract whose init code self destructs.
+        let init_code = bytecode! {
+            PUSH20(beneficiary.to_word())
+            SELFDESTRUCT
+        }
+        .code()
+        .to_vec();
+        let mut init_code_word = [0u8; 32];
+        init_code_word[32 - init_code.len()..].copy_from_slice(&init_code);
+        let code = bytecode! {
+            PUSH32(Word::from_big_endian(&init_code_word))
+            PUSH1(0x00)
+            MSTORE
+            PUSH1(init_code.len()) // length
+            PUSH1(32 - init_code.len()) // offset
+            PUSH2(value) // value
+            CREATE
+            STOP
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(mock::MOCK_ACCOUNTS[0])
+                    .balance(Word::from(CONTRACT_BALANCE))
+                    .code(code);
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[1])
+                    .balance(Word::from(1u64 << 30));
+            },
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let builder = handle_block_with_hardfork(&block, Hardfork::Cancun);
+
+        // The contract created in the transaction is destroyed.
+        let transaction = &builder.block.txs()[0];
+        let created = transaction.calls()[1].address;
+        let step = transaction
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::SELFDESTRUCT))
+            .unwrap();
+        assert_eq!(destructed_op_count(step), 1);
+        let (_, account) = builder.sdb.get_account(&created);
+        assert!(account.is_empty());
+        let (_, account) = builder.sdb.get_account(&beneficiary);
+        assert_eq!(account.balance, Word::from(value));
+    }
+}
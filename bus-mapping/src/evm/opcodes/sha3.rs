@@ -0,0 +1,222 @@
+use super::Opcode;
+use crate::{
+    circuit_input_builder::{get_call_memory_offset_length, CircuitInputStateRef, ExecStep},
+    Error,
+};
+use eth_types::{GethExecStep, Word};
+use ethers_core::utils::keccak256;
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the [`OpcodeId::SHA3`](crate::evm::OpcodeId::SHA3)
+/// `OpcodeId`.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Sha3;
+
+impl Opcode for Sha3 {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+
+        let offset = geth_step.stack.nth_last(0)?;
+        let size = geth_step.stack.nth_last(1)?;
+        state.stack_read(&mut exec_step, geth_step.stack.nth_last_filled(0), offset)?;
+        state.stack_read(&mut exec_step, geth_step.stack.nth_last_filled(1), size)?;
+
+        // An empty preimage doesn't access the memory, so its offset may be
+        // past u64.
+        let (offset, size) = get_call_memory_offset_length(geth_step, 0)?;
+        let (offset, size) = (offset as usize, size as usize);
+        let preimage = geth_step.memory.read_chunk(offset.into(), size.into());
+        for (idx, byte) in preimage.iter().enumerate() {
+            state.memory_read(&mut exec_step, (offset + idx).into(), *byte)?;
+        }
+
+        let digest = Word::from_big_endian(&keccak256(&preimage));
+        state.block.keccak_inputs.push(preimage);
+        state.stack_write(&mut exec_step, geth_steps[1].stack.last_filled(), digest)?;
+
+        Ok(vec![exec_step])
+    }
+}
+
+#[cfg(test)]
+mod sha3_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::ExecState,
+        evm::OpcodeId,
+        mock::BlockData,
+        operation::{MemoryOp, StackOp, Target, RW},
+    };
+    use eth_types::{bytecode, evm_types::StackAddress, geth_types::GethData};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    fn test_ok(offset: usize, size: usize) {
+        let data: Vec<u8> = (0xa0..0xc0).collect();
+        let code = bytecode! {
+            PUSH32(Word::from_big_endian(&data))
+            PUSH1(0x00)
+            MSTORE
+            PUSH32(size)
+            PUSH32(offset)
+            SHA3
+            STOP
+        };
+
+        // Get the execution steps from the external tracer
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::SHA3))
+            .unwrap();
+
+        // Memory beyond the stored word is zero.
+        let preimage: Vec<u8> = (offset..offset + size)
+            .map(|addr| data.get(addr).cloned().unwrap_or(0))
+            .collect();
+        let digest = Word::from_big_endian(&keccak256(&preimage));
+
+        // Cross-check the digest against the one returned by geth.
+        let geth_step_index = block.geth_traces[0]
+            .struct_logs
+            .iter()
+            .position(|step| step.op == OpcodeId::SHA3)
+            .unwrap();
+        assert_eq!(
+            block.geth_traces[0].struct_logs[geth_step_index + 1]
+                .stack
+                .last()
+                .unwrap(),
+            digest
+        );
+
+        let container = &builder.block.container;
+        assert_eq!(
+            [0, 1, size + 2]
+                .map(|idx| &container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1022), Word::from(offset))
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1023), Word::from(size))
+                ),
+                (
+                    RW::WRITE,
+                    &StackOp::new(1, StackAddress::from(1023), digest)
+                ),
+            ]
+        );
+        assert_eq!(
+            (2..size + 2)
+                .map(|idx| &container.memory[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op().clone()))
+                .collect::<Vec<_>>(),
+            preimage
+                .iter()
+                .enumerate()
+                .map(|(idx, byte)| (RW::READ, MemoryOp::new(1, (offset + idx).into(), *byte)))
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(builder.block.keccak_inputs, vec![preimage]);
+    }
+
+    #[test]
+    fn sha3_opcode_impl() {
+        test_ok(0x00, 0x20);
+    }
+
+    #[test]
+    fn sha3_opcode_unaligned() {
+        test_ok(0x08, 0x10);
+    }
+
+    #[test]
+    fn sha3_opcode_beyond_memory() {
+        test_ok(0x10, 0x30);
+    }
+
+    #[test]
+    fn sha3_opcode_empty() {
+        test_ok(0x00, 0x00);
+    }
+
+    #[test]
+    fn sha3_opcode_empty_huge_offset() {
+        let offset = Word::one() << 255;
+        let code = bytecode! {
+            PUSH1(0x00) // size
+            PUSH32(offset) // offset
+            SHA3
+            STOP
+        };
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::SHA3))
+            .unwrap();
+        let container = &builder.block.container;
+        assert_eq!(
+            step.bus_mapping_instance
+                .iter()
+                .map(|op_ref| {
+                    assert_eq!(op_ref.target(), Target::Stack);
+                    let operation = &container.stack[op_ref.as_usize()];
+                    (operation.rw(), operation.op().clone())
+                })
+                .collect::<Vec<_>>(),
+            vec![
+                (RW::READ, StackOp::new(1, StackAddress::from(1022), offset)),
+                (
+                    RW::READ,
+                    StackOp::new(1, StackAddress::from(1023), Word::zero())
+                ),
+                (
+                    RW::WRITE,
+                    StackOp::new(
+                        1,
+                        StackAddress::from(1023),
+                        Word::from_big_endian(&keccak256(&[] as &[u8]))
+                    )
+                ),
+            ]
+        );
+        assert_eq!(builder.block.keccak_inputs.last(), Some(&Vec::new()));
+    }
+}
@@ -102,13 +102,18 @@ impl Opcode for Sload {
 #[cfg(test)]
 mod sload_tests {
     use super::*;
-    use crate::{circuit_input_builder::ExecState, mock::BlockData, operation::StackOp};
+    use crate::{
+        circuit_input_builder::ExecState,
+        mock::BlockData,
+        operation::{StackOp, TxAccessListAccountOp},
+    };
     use eth_types::{
         bytecode,
-        evm_types::{OpcodeId, StackAddress},
+        evm_types::{GasCost, OpcodeId, StackAddress},
         geth_types::GethData,
-        Word,
+        AccessList, Word, H256,
     };
+    use ethers_core::types::transaction::eip2930::AccessListItem;
     use mock::{
         test_ctx::{helpers::*, TestContext},
         MOCK_ACCOUNTS,
@@ -216,4 +221,87 @@ mod sload_tests {
     fn sload_opcode_impl_cold() {
         test_ok(false)
     }
+
+    #[test]
+    fn sload_opcode_impl_access_list() {
+        let code = bytecode! {
+            // Load storage slot 0
+            PUSH1(0x00u64)
+            SLOAD
+            STOP
+        };
+
+        // The tx access list declares the slot 0 of the callee, and repeats
+        // the callee address.
+        let access_list = AccessList(vec![AccessListItem {
+            address: MOCK_ACCOUNTS[0],
+            storage_keys: vec![H256::zero()],
+        }]);
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            |mut txs, accs| {
+                txs[0]
+                    .from(accs[1].address)
+                    .to(accs[0].address)
+                    .access_list(access_list);
+            },
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let steps = builder.block.txs()[0].steps();
+        let begin_tx_step = &steps[0];
+        assert_eq!(begin_tx_step.exec_state, ExecState::BeginTx);
+        assert_eq!(
+            begin_tx_step.gas_cost,
+            GasCost(
+                GasCost::TX.as_u64()
+                    + GasCost::ACCESS_LIST_ADDRESS.as_u64()
+                    + GasCost::ACCESS_LIST_STORAGE_KEY.as_u64()
+            )
+        );
+
+        // Caller and callee, the 9 precompiles and the access list entries.
+        let container = &builder.block.container;
+        let account_ops = container.sorted_tx_access_list_account();
+        assert_eq!(account_ops.len(), 12);
+        assert!(account_ops.iter().any(|op| op.op()
+            == &TxAccessListAccountOp {
+                tx_id: 1,
+                address: MOCK_ACCOUNTS[0],
+                is_warm: true,
+                is_warm_prev: true,
+            }));
+        assert_eq!(
+            container
+                .tx_access_list_account_storage
+                .iter()
+                .map(|op| op.op().clone())
+                .collect::<Vec<_>>()[0],
+            TxAccessListAccountStorageOp {
+                tx_id: 1,
+                address: MOCK_ACCOUNTS[0],
+                key: Word::zero(),
+                is_warm: true,
+                is_warm_prev: false,
+            }
+        );
+
+        // The SLOAD finds the slot warm.
+        let step = steps
+            .iter()
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::SLOAD))
+            .unwrap();
+        assert_eq!(step.gas_cost, GasCost::WARM_ACCESS);
+        let access_list_op =
+            &container.tx_access_list_account_storage[step.bus_mapping_instance[7].as_usize()];
+        assert!(access_list_op.op().is_warm_prev);
+    }
 }
@@ -119,7 +119,7 @@ mod sstore_tests {
     use super::*;
     use crate::circuit_input_builder::ExecState;
     use crate::mock::BlockData;
-    use crate::operation::{CallContextOp, StackOp};
+    use crate::operation::{AccountField, CallContextOp, StackOp, Target, TxReceiptField};
     use eth_types::bytecode;
     use eth_types::evm_types::{OpcodeId, StackAddress};
     use eth_types::geth_types::GethData;
@@ -274,4 +274,70 @@ mod sstore_tests {
     fn sstore_opcode_impl_cold() {
         test_ok(false)
     }
+
+    #[test]
+    fn sstore_refund_end_tx() {
+        // Clear storage slot 0, which is refunded
+        let code = bytecode! {
+            PUSH1(0x00u64)
+            PUSH1(0x00u64)
+            SSTORE
+            STOP
+        };
+
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(MOCK_ACCOUNTS[0])
+                    .balance(Word::from(10u64.pow(19)))
+                    .code(code)
+                    .storage(vec![(0x00u64.into(), 0x6fu64.into())].into_iter());
+                accs[1]
+                    .address(MOCK_ACCOUNTS[1])
+                    .balance(Word::from(10u64.pow(19)));
+            },
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let tx = &builder.block.txs()[0];
+        let end_tx_step = tx.steps().last().unwrap();
+        assert_eq!(end_tx_step.exec_state, ExecState::EndTx);
+        let container = &builder.block.container;
+
+        // The gas used reported by geth is net of the refund.
+        let gas_used = block.geth_traces[0].gas.0;
+        assert!(gas_used < tx.gas - end_tx_step.gas_left.0);
+
+        // The coinbase is only paid for the gas used.
+        let coinbase_op = end_tx_step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == Target::Account)
+            .map(|op_ref| container.account[op_ref.as_usize()].op())
+            .find(|op| op.address == builder.block.coinbase)
+            .unwrap();
+        assert_eq!(coinbase_op.field, AccountField::Balance);
+        assert_eq!(
+            coinbase_op.value - coinbase_op.value_prev,
+            (tx.gas_price - builder.block.base_fee) * gas_used
+        );
+
+        let cumulative_gas_used_op = end_tx_step
+            .bus_mapping_instance
+            .iter()
+            .filter(|op_ref| op_ref.target() == Target::TxReceipt)
+            .map(|op_ref| container.tx_receipt[op_ref.as_usize()].op())
+            .find(|op| op.field == TxReceiptField::CumulativeGasUsed)
+            .unwrap();
+        assert_eq!(cumulative_gas_used_op.value, gas_used);
+    }
 }
@@ -44,7 +44,12 @@ impl<const N_POP: usize, const N_PUSH: usize> Opcode for StackOnlyOpcode<N_POP,
 #[cfg(test)]
 mod stackonlyop_tests {
     use crate::operation::RW;
-    use crate::{circuit_input_builder::ExecState, mock::BlockData, operation::StackOp};
+    use crate::{
+        circuit_input_builder::ExecState,
+        evm::Hardfork,
+        mock::{trace_code, BlockData},
+        operation::StackOp,
+    };
     use eth_types::{
         bytecode,
         evm_types::{OpcodeId, StackAddress},
@@ -56,22 +61,30 @@ mod stackonlyop_tests {
     use pretty_assertions::assert_eq;
 
     fn stack_only_opcode_impl<const N_POP: usize, const N_PUSH: usize>(
+        hardfork: Hardfork,
         opcode: OpcodeId,
         code: Bytecode,
         pops: Vec<StackOp>,
         pushes: Vec<StackOp>,
     ) {
         // Get the execution steps from the external tracer
-        let block: GethData = TestContext::<2, 1>::new(
+        let mut block: GethData = TestContext::<2, 1>::new(
             None,
-            account_0_code_account_1_no_code(code),
+            account_0_code_account_1_no_code(code.clone()),
             tx_from_1_to_0,
             |block, _tx| block.number(0xcafeu64),
         )
         .unwrap()
         .into();
+        // The geth of geth-utils only traces the London opcodes.
+        if hardfork > Hardfork::London {
+            block.geth_traces[0] =
+                trace_code(&block.eth_block.transactions[0], &code, &[], Word::one());
+        }
 
-        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        let mut block_data = BlockData::new_from_geth_data(block.clone());
+        block_data.hardfork = hardfork;
+        let mut builder = block_data.new_circuit_input_builder();
         builder
             .handle_block(&block.eth_block, &block.geth_traces)
             .unwrap();
@@ -109,6 +122,7 @@ mod stackonlyop_tests {
     #[test]
     fn not_opcode_impl() {
         stack_only_opcode_impl::<1, 1>(
+            Hardfork::default(),
             OpcodeId::NOT,
             bytecode! {
                 PUSH32(word!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"))
@@ -131,6 +145,7 @@ mod stackonlyop_tests {
     #[test]
     fn add_opcode_impl() {
         stack_only_opcode_impl::<2, 1>(
+            Hardfork::default(),
             OpcodeId::ADD,
             bytecode! {
                 PUSH1(0x80u64)
@@ -153,6 +168,7 @@ mod stackonlyop_tests {
     #[test]
     fn addmod_opcode_impl() {
         stack_only_opcode_impl::<3, 1>(
+            Hardfork::default(),
             OpcodeId::ADDMOD,
             bytecode! {
                 PUSH3(0xbcdef)
@@ -169,4 +185,18 @@ mod stackonlyop_tests {
             vec![StackOp::new(1, StackAddress(1023), Word::from(0x79bdf))],
         );
     }
+
+    #[test]
+    fn push0_opcode_impl() {
+        stack_only_opcode_impl::<0, 1>(
+            Hardfork::Shanghai,
+            OpcodeId::PUSH0,
+            bytecode! {
+                PUSH0
+                STOP
+            },
+            vec![],
+            vec![StackOp::new(1, StackAddress(1023), Word::zero())],
+        );
+    }
 }
@@ -0,0 +1,223 @@
+use super::Opcode;
+use crate::circuit_input_builder::{CircuitInputStateRef, ExecStep};
+use crate::operation::CallContextField;
+use crate::{
+    operation::{TransientStorageOp, RW},
+    Error,
+};
+
+use eth_types::{GethExecStep, ToWord, Word};
+
+/// Placeholder structure used to implement [`Opcode`] trait over it
+/// corresponding to the [`OpcodeId::TSTORE`](crate::evm::OpcodeId::TSTORE)
+/// `OpcodeId`.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Tstore;
+
+impl Opcode for Tstore {
+    fn gen_associated_ops(
+        state: &mut CircuitInputStateRef,
+        geth_steps: &[GethExecStep],
+    ) -> Result<Vec<ExecStep>, Error> {
+        let geth_step = &geth_steps[0];
+        let mut exec_step = state.new_step(geth_step)?;
+
+        let contract_addr = state.call()?.address;
+
+        state.call_context_read(
+            &mut exec_step,
+            state.call()?.call_id,
+            CallContextField::TxId,
+            Word::from(state.tx_ctx.id()),
+        );
+        state.call_context_read(
+            &mut exec_step,
+            state.call()?.call_id,
+            CallContextField::IsStatic,
+            Word::from(state.call()?.is_static as u8),
+        );
+
+        state.call_context_read(
+            &mut exec_step,
+            state.call()?.call_id,
+            CallContextField::RwCounterEndOfReversion,
+            Word::from(state.call()?.rw_counter_end_of_reversion),
+        );
+
+        state.call_context_read(
+            &mut exec_step,
+            state.call()?.call_id,
+            CallContextField::IsPersistent,
+            Word::from(state.call()?.is_persistent as u8),
+        );
+
+        state.call_context_read(
+            &mut exec_step,
+            state.call()?.call_id,
+            CallContextField::CalleeAddress,
+            contract_addr.to_word(),
+        );
+
+        let key = geth_step.stack.nth_last(0)?;
+        let key_stack_position = geth_step.stack.nth_last_filled(0);
+        let value = geth_step.stack.nth_last(1)?;
+        let value_stack_position = geth_step.stack.nth_last_filled(1);
+
+        state.stack_read(&mut exec_step, key_stack_position, key)?;
+        state.stack_read(&mut exec_step, value_stack_position, value)?;
+
+        let value_prev = *state.sdb.get_transient_storage(&contract_addr, &key);
+        state.push_op_reversible(
+            &mut exec_step,
+            RW::WRITE,
+            TransientStorageOp::new(state.tx_ctx.id(), contract_addr, key, value, value_prev),
+        )?;
+
+        Ok(vec![exec_step])
+    }
+}
+
+#[cfg(test)]
+mod tstore_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::ExecState,
+        evm::Hardfork,
+        mock::{trace_code, BlockData},
+        operation::{CallContextOp, StackOp},
+    };
+    use eth_types::{
+        bytecode,
+        evm_types::{OpcodeId, StackAddress},
+        geth_types::GethData,
+        Word,
+    };
+    use mock::{
+        test_ctx::{helpers::*, TestContext},
+        MOCK_ACCOUNTS,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn test_ok(is_reverted: bool) {
+        let mut code = bytecode! {
+            // Write 0x6f and then 0x70 to transient storage slot 0
+            PUSH1(0x6fu64)
+            PUSH1(0x00u64)
+            TSTORE
+            PUSH1(0x70u64)
+            PUSH1(0x00u64)
+            TSTORE
+        };
+        if is_reverted {
+            code.append(&bytecode! {
+                PUSH1(0x00u64)
+                PUSH1(0x00u64)
+                REVERT
+            });
+        } else {
+            code.append(&bytecode! { STOP });
+        }
+
+        let mut block: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code.clone()),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        // The geth of geth-utils can't trace TSTORE.
+        block.geth_traces[0] =
+            trace_code(&block.eth_block.transactions[0], &code, &[], Word::one());
+
+        let mut block_data = BlockData::new_from_geth_data(block.clone());
+        block_data.hardfork = Hardfork::Cancun;
+        let mut builder = block_data.new_circuit_input_builder();
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+
+        let step = builder.block.txs()[0]
+            .steps()
+            .iter()
+            .rev() // find last tstore
+            .find(|step| step.exec_state == ExecState::Op(OpcodeId::TSTORE))
+            .unwrap();
+
+        assert_eq!(
+            [0, 1, 2, 3, 4]
+                .map(|idx| &builder.block.container.call_context
+                    [step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op().field.clone())),
+            [
+                (RW::READ, CallContextField::TxId),
+                (RW::READ, CallContextField::IsStatic),
+                (RW::READ, CallContextField::RwCounterEndOfReversion),
+                (RW::READ, CallContextField::IsPersistent),
+                (RW::READ, CallContextField::CalleeAddress),
+            ]
+        );
+        assert_eq!(
+            {
+                let operation =
+                    &builder.block.container.call_context[step.bus_mapping_instance[3].as_usize()];
+                operation.op().clone()
+            },
+            CallContextOp {
+                call_id: 1,
+                field: CallContextField::IsPersistent,
+                value: Word::from(!is_reverted as u8),
+            }
+        );
+
+        assert_eq!(
+            [5, 6]
+                .map(|idx| &builder.block.container.stack[step.bus_mapping_instance[idx].as_usize()])
+                .map(|operation| (operation.rw(), operation.op())),
+            [
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1022), Word::from(0x0u32))
+                ),
+                (
+                    RW::READ,
+                    &StackOp::new(1, StackAddress::from(1023), Word::from(0x70u32))
+                ),
+            ]
+        );
+
+        let transient_storage_op =
+            &builder.block.container.transient_storage[step.bus_mapping_instance[7].as_usize()];
+        assert_eq!(
+            (transient_storage_op.rw(), transient_storage_op.op()),
+            (
+                RW::WRITE,
+                &TransientStorageOp::new(
+                    1,
+                    MOCK_ACCOUNTS[0],
+                    Word::from(0x0u32),
+                    Word::from(0x70u32),
+                    Word::from(0x6fu32),
+                )
+            )
+        );
+        assert!(transient_storage_op.reversible());
+
+        // A reverted call undoes both writes, each one recorded as its reverse.
+        let num_transient_storage_ops = if is_reverted { 4 } else { 2 };
+        assert_eq!(
+            builder.block.container.transient_storage.len(),
+            num_transient_storage_ops
+        );
+    }
+
+    #[test]
+    fn tstore_opcode_impl() {
+        test_ok(false)
+    }
+
+    #[test]
+    fn tstore_opcode_impl_reverted() {
+        test_ok(true)
+    }
+}
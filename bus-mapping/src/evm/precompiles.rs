@@ -0,0 +1,158 @@
+//! Precompiled contracts of the EVM.
+
+use eth_types::{Address, Word};
+use serde::{Deserialize, Serialize};
+
+/// Precompiled contracts, identified by the last byte of their address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PrecompileCalls {
+    /// Elliptic curve public key recovery (0x01)
+    ECRecover = 0x01,
+    /// SHA2-256 hash function (0x02)
+    Sha256 = 0x02,
+    /// RIPEMD-160 hash function (0x03)
+    Ripemd160 = 0x03,
+    /// Identity function (0x04)
+    Identity = 0x04,
+    /// Modular exponentiation (0x05)
+    Modexp = 0x05,
+    /// Point addition on alt_bn128 (0x06)
+    Bn128Add = 0x06,
+    /// Scalar multiplication on alt_bn128 (0x07)
+    Bn128Mul = 0x07,
+    /// Pairing check on alt_bn128 (0x08)
+    Bn128Pairing = 0x08,
+    /// Compression function F of BLAKE2 (0x09)
+    Blake2F = 0x09,
+    /// KZG point evaluation (0x0a), from Cancun on
+    PointEvaluation = 0x0a,
+}
+
+impl PrecompileCalls {
+    /// Return the precompiled contract at `address`, if any, regardless of
+    /// whether it's deployed in the hardfork of the chain.
+    pub fn from_address(address: &Address) -> Option<Self> {
+        if address.0[0..19] != [0u8; 19] {
+            return None;
+        }
+        Some(match address.0[19] {
+            0x01 => Self::ECRecover,
+            0x02 => Self::Sha256,
+            0x03 => Self::Ripemd160,
+            0x04 => Self::Identity,
+            0x05 => Self::Modexp,
+            0x06 => Self::Bn128Add,
+            0x07 => Self::Bn128Mul,
+            0x08 => Self::Bn128Pairing,
+            0x09 => Self::Blake2F,
+            0x0a => Self::PointEvaluation,
+            _ => return None,
+        })
+    }
+
+    /// Length of the output of a successful call with `input`.  The length is
+    /// returned as a [`Word`] since the modulus length of a Modexp input is
+    /// arbitrary, so it must be bounded before allocating anything with it.
+    /// ECRecover returns no output for a signature with invalid scalars; a
+    /// valid signature whose `r` is not the x-coordinate of a curve point is
+    /// not taken into account here.
+    pub fn output_length(&self, input: &[u8]) -> Word {
+        match self {
+            Self::ECRecover => {
+                if ecrecover_input_is_valid(input) {
+                    Word::from(32)
+                } else {
+                    Word::zero()
+                }
+            }
+            Self::Sha256 | Self::Ripemd160 | Self::Bn128Pairing => Word::from(32),
+            Self::Identity => Word::from(input.len()),
+            // The input starts with the lengths of base, exponent and modulus,
+            // as 32 bytes words each.
+            Self::Modexp => input_word(input, 2),
+            // PointEvaluation returns FIELD_ELEMENTS_PER_BLOB and BLS_MODULUS.
+            Self::Bn128Add | Self::Bn128Mul | Self::Blake2F | Self::PointEvaluation => {
+                Word::from(64)
+            }
+        }
+    }
+}
+
+/// Order of the secp256k1 curve.
+const SECP256K1_N: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+/// Return the `index`th 32 bytes word of `input`, which is right padded with
+/// zeros like the input of a precompiled contract.
+fn input_word(input: &[u8], index: usize) -> Word {
+    let mut word = [0u8; 32];
+    for (dst, src) in word.iter_mut().zip(input.iter().skip(32 * index)) {
+        *dst = *src;
+    }
+    Word::from_big_endian(&word)
+}
+
+/// Whether the ECRecover `input` (hash, v, r, s) has a `v` of 27 or 28 and
+/// `r` and `s` in `[1, n)`.
+fn ecrecover_input_is_valid(input: &[u8]) -> bool {
+    let n = Word::from_big_endian(&SECP256K1_N);
+    let (v, r, s) = (
+        input_word(input, 1),
+        input_word(input, 2),
+        input_word(input, 3),
+    );
+    (v == Word::from(27) || v == Word::from(28)) && !r.is_zero() && r < n && !s.is_zero() && s < n
+}
+
+/// Witness of a call to a precompiled contract.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrecompileCall {
+    /// Called precompiled contract
+    pub kind: PrecompileCalls,
+    /// Input bytes (copy of caller's
+    /// memory[call_data_offset..call_data_offset + call_data_length])
+    pub input: Vec<u8>,
+    /// Output bytes, which become the return data of the call
+    pub output: Vec<u8>,
+    /// Gas consumed by the precompiled contract
+    pub gas_used: u64,
+    /// Whether the call ends successfully or not
+    pub is_success: bool,
+}
+
+#[cfg(test)]
+mod precompiles_tests {
+    use super::*;
+
+    #[test]
+    fn output_length_modexp_huge_modulus() {
+        // A modulus length which doesn't fit in 64 bits.
+        let mut input = vec![0u8; 96];
+        input[64 + 23] = 0x01;
+        input[95] = 0x20;
+        assert_eq!(
+            PrecompileCalls::Modexp.output_length(&input),
+            (Word::one() << 64) + Word::from(0x20)
+        );
+    }
+
+    #[test]
+    fn output_length_ecrecover() {
+        let mut input = vec![0u8; 128];
+        input[63] = 27;
+        input[95] = 0x01;
+        input[127] = 0x01;
+        assert_eq!(
+            PrecompileCalls::ECRecover.output_length(&input),
+            Word::from(32)
+        );
+        // `s` must be lower than the order of the curve.
+        input[96..].copy_from_slice(&SECP256K1_N);
+        assert_eq!(
+            PrecompileCalls::ECRecover.output_length(&input),
+            Word::zero()
+        );
+    }
+}
@@ -2,9 +2,27 @@
 
 use crate::{
     circuit_input_builder::{Block, CircuitInputBuilder},
+    evm::{ChainConfig, Hardfork},
+    mpt::{keccak, rlp_account, rlp_word, PartialTrie, EMPTY_TRIE_ROOT},
+    rpc::{serialize, BlockNumber, GethLoggerConfig, RecordedRequest},
     state_db::{self, CodeDB, StateDB},
 };
-use eth_types::{geth_types::GethData, Word};
+use async_trait::async_trait;
+use eth_types::{
+    geth_types::{self, GethData},
+    Address, Bytes, EIP1186ProofResponse, ResultGethExecTrace, ResultGethExecTraces, StorageProof,
+    ToBigEndian, Word, H256, U64,
+};
+use ethers_providers::{JsonRpcClient, ProviderError};
+use keccak256::EMPTY_HASH;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt};
+
+#[cfg(test)]
+mod trace;
+#[cfg(test)]
+pub(crate) use trace::trace_code;
 
 /// BlockData is a type that contains all the information from a block required
 /// to build the circuit inputs.
@@ -16,6 +34,8 @@ pub struct BlockData {
     pub code_db: CodeDB,
     /// chain id
     pub chain_id: Word,
+    /// Hardfork whose rules apply to the block
+    pub hardfork: Hardfork,
     /// history hashes contains most recent 256 block hashes in history, where
     /// the lastest one is at history_hashes[history_hashes.len() - 1].
     pub history_hashes: Vec<Word>,
@@ -32,7 +52,12 @@ impl BlockData {
         CircuitInputBuilder::new(
             self.sdb.clone(),
             self.code_db.clone(),
-            Block::new(self.chain_id, self.history_hashes.clone(), &self.eth_block).unwrap(),
+            Block::new(
+                ChainConfig::new(self.chain_id, self.hardfork),
+                self.history_hashes.clone(),
+                &self.eth_block,
+            )
+            .unwrap(),
         )
     }
 
@@ -66,9 +91,370 @@ impl BlockData {
             sdb,
             code_db,
             chain_id: geth_data.chain_id,
+            hardfork: Hardfork::default(),
             history_hashes: geth_data.history_hashes,
             eth_block: geth_data.eth_block,
             geth_traces: geth_data.geth_traces,
         }
     }
 }
+
+/// Error returned by the [`MockJsonRpcClient`].
+#[derive(Debug)]
+pub enum MockRpcError {
+    /// No response is known for the request with this method and params.
+    UnknownRequest(String, Value),
+    /// Error converting the params or the result of a request.
+    SerdeError(serde_json::Error),
+}
+
+impl fmt::Display for MockRpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MockRpcError::UnknownRequest(method, params) => {
+                write!(f, "unknown request {} with params {}", method, params)
+            }
+            MockRpcError::SerdeError(err) => write!(f, "serde error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MockRpcError {}
+
+impl From<MockRpcError> for ProviderError {
+    fn from(err: MockRpcError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+/// State before the block of a [`MockJsonRpcClient`] fixture, used to answer
+/// `eth_getProof` and `eth_getCode` for any account and keys.
+#[derive(Debug, Clone)]
+struct MockState {
+    block_num: Value,
+    accounts: HashMap<Address, geth_types::Account>,
+    state_trie: PartialTrie,
+    storage_tries: HashMap<Address, PartialTrie>,
+}
+
+impl MockState {
+    fn new(block_num: u64, accounts: &[geth_types::Account]) -> Self {
+        let mut state_trie = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        let mut storage_tries = HashMap::new();
+        for account in accounts {
+            let mut storage_trie = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+            for (key, value) in account.storage.iter().filter(|(_, value)| !value.is_zero()) {
+                storage_trie
+                    .insert(&key.to_be_bytes(), rlp_word(*value))
+                    .expect("trie is fully known");
+            }
+            let account_rlp = rlp_account(
+                account.nonce,
+                account.balance,
+                storage_trie.root(),
+                keccak(account.code.as_ref()),
+            );
+            state_trie
+                .insert(account.address.as_bytes(), account_rlp)
+                .expect("trie is fully known");
+            storage_tries.insert(account.address, storage_trie);
+        }
+        Self {
+            block_num: serialize(&BlockNumber::from(block_num)),
+            accounts: accounts
+                .iter()
+                .map(|account| (account.address, account.clone()))
+                .collect(),
+            state_trie,
+            storage_tries,
+        }
+    }
+
+    fn get_code(&self, address: Address) -> Bytes {
+        self.accounts
+            .get(&address)
+            .map(|account| account.code.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_proof(&self, address: Address, keys: Vec<Word>) -> EIP1186ProofResponse {
+        let account = self.accounts.get(&address);
+        let empty_trie = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        let storage_trie = self.storage_tries.get(&address).unwrap_or(&empty_trie);
+        EIP1186ProofResponse {
+            address,
+            balance: account.map(|account| account.balance).unwrap_or_default(),
+            code_hash: account
+                .map(|account| keccak(account.code.as_ref()))
+                .unwrap_or(H256(*EMPTY_HASH)),
+            nonce: account.map(|account| account.nonce).unwrap_or_default(),
+            storage_hash: storage_trie.root(),
+            account_proof: self
+                .state_trie
+                .proof(address.as_bytes())
+                .expect("trie is fully known"),
+            storage_proof: keys
+                .into_iter()
+                .map(|key| StorageProof {
+                    key,
+                    value: account
+                        .and_then(|account| account.storage.get(&key))
+                        .cloned()
+                        .unwrap_or_default(),
+                    proof: storage_trie
+                        .proof(&key.to_be_bytes())
+                        .expect("trie is fully known"),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// In-process [`JsonRpcClient`] which serves the requests made by the
+/// [`BuilderClient`](crate::circuit_input_builder::BuilderClient) from a
+/// [`GethData`] fixture or from recorded responses, so that the whole input
+/// generation can be tested without a node.
+#[derive(Debug, Clone, Default)]
+pub struct MockJsonRpcClient {
+    /// Results by method and JSON encoded params.
+    responses: HashMap<(String, String), Value>,
+    state: Option<MockState>,
+}
+
+impl MockJsonRpcClient {
+    /// Create a new client without any response.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new client serving the block of `geth_data`, with its traces,
+    /// its parent block, and the proofs and codes of the state before the
+    /// block, made of the `geth_data` accounts.  The parent block only
+    /// contains its number, hash and state root, and its hash must differ from
+    /// the hash of the block.
+    pub fn new_from_geth_data(geth_data: &GethData) -> Self {
+        let eth_block = &geth_data.eth_block;
+        let block_num = eth_block
+            .number
+            .expect("block number should be set")
+            .as_u64();
+        let parent_block_num = block_num.saturating_sub(1);
+        let state = MockState::new(parent_block_num, &geth_data.accounts);
+        let parent_block = eth_types::Block::<eth_types::Transaction> {
+            number: Some(U64::from(parent_block_num)),
+            hash: Some(eth_block.parent_hash),
+            state_root: state.state_trie.root(),
+            ..Default::default()
+        };
+        let traces = ResultGethExecTraces(
+            geth_data
+                .geth_traces
+                .iter()
+                .map(|trace| ResultGethExecTrace {
+                    result: trace.clone(),
+                })
+                .collect(),
+        );
+        let num = serialize(&BlockNumber::from(block_num));
+        let cfg = serialize(&GethLoggerConfig::default());
+
+        let mut client = Self {
+            responses: HashMap::new(),
+            state: Some(state),
+        };
+        client.insert("eth_chainId", (), U64::from(geth_data.chain_id.as_u64()));
+        client.insert("eth_coinbase", (), eth_block.author);
+        client.insert(
+            "eth_getBlockByNumber",
+            [num.clone(), serialize(&true)],
+            eth_block,
+        );
+        // The genesis block has no parent.
+        if block_num > 0 {
+            client.insert(
+                "eth_getBlockByNumber",
+                [
+                    serialize(&BlockNumber::from(parent_block_num)),
+                    serialize(&true),
+                ],
+                &parent_block,
+            );
+            client.insert(
+                "eth_getBlockByHash",
+                [serialize(&eth_block.parent_hash), serialize(&true)],
+                &parent_block,
+            );
+        }
+        client.insert("debug_traceBlockByNumber", [num, cfg.clone()], &traces);
+        if let Some(hash) = eth_block.hash {
+            client.insert(
+                "eth_getBlockByHash",
+                [serialize(&hash), serialize(&true)],
+                eth_block,
+            );
+            client.insert("debug_traceBlockByHash", [serialize(&hash), cfg], &traces);
+        }
+        client
+    }
+
+    /// Create a new client serving the requests recorded in `json`, a list of
+    /// [`RecordedRequest`].
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let requests: Vec<RecordedRequest> = serde_json::from_str(json)?;
+        let mut client = Self::new();
+        for request in requests {
+            client.insert(&request.method, request.params, request.result);
+        }
+        Ok(client)
+    }
+
+    /// Set the result of the request with `method` and `params`.
+    pub fn insert<P: Serialize, R: Serialize>(&mut self, method: &str, params: P, result: R) {
+        self.responses.insert(
+            (method.to_string(), serialize(&params).to_string()),
+            serialize(&result),
+        );
+    }
+
+    fn respond(&self, method: &str, params: Value) -> Result<Value, MockRpcError> {
+        if let Some(result) = self
+            .responses
+            .get(&(method.to_string(), params.to_string()))
+        {
+            return Ok(result.clone());
+        }
+        let unknown_request = || MockRpcError::UnknownRequest(method.to_string(), params.clone());
+        let state = match &self.state {
+            Some(state) => state,
+            None => return Err(unknown_request()),
+        };
+        let from_value =
+            |value: &Value| serde_json::from_value(value.clone()).map_err(MockRpcError::SerdeError);
+        match (method, params.as_array().map(Vec::as_slice)) {
+            ("eth_getCode", Some([address, block_num])) if *block_num == state.block_num => {
+                Ok(serialize(&state.get_code(from_value(address)?)))
+            }
+            ("eth_getProof", Some([address, keys, block_num])) if *block_num == state.block_num => {
+                Ok(serialize(
+                    &state.get_proof(from_value(address)?, from_value(keys)?),
+                ))
+            }
+            _ => Err(unknown_request()),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl JsonRpcClient for MockJsonRpcClient {
+    type Error = MockRpcError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let params = serde_json::to_value(params).map_err(MockRpcError::SerdeError)?;
+        let result = self.respond(method, params)?;
+        serde_json::from_value(result).map_err(MockRpcError::SerdeError)
+    }
+}
+
+#[cfg(test)]
+mod mock_tests {
+    use super::*;
+    use crate::{circuit_input_builder::BuilderClient, rpc::GethClient, Error};
+    use eth_types::bytecode;
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn builder_client_gen_inputs_offline() {
+        let code = bytecode! {
+            PUSH1(0x00)
+            SLOAD
+            PUSH1(0x01)
+            SSTORE
+            PUSH1(0x02)
+            PUSH1(0x00)
+            SSTORE
+            STOP
+        };
+        let geth_data: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(mock::MOCK_ACCOUNTS[0])
+                    .balance(mock::eth(10))
+                    .code(code)
+                    .storage(vec![(Word::zero(), Word::from(7))].into_iter());
+                accs[1]
+                    .address(mock::MOCK_ACCOUNTS[1])
+                    .balance(mock::eth(10));
+            },
+            tx_from_1_to_0,
+            |block, _tx| {
+                block
+                    .number(0xcafeu64)
+                    .parent_hash(eth_types::Hash::repeat_byte(0xca))
+            },
+        )
+        .unwrap()
+        .into();
+
+        let cli = GethClient::new(MockJsonRpcClient::new_from_geth_data(&geth_data));
+        let builder_client = BuilderClient::new(cli, Hardfork::default()).await.unwrap();
+        let builder = builder_client.gen_inputs(0xcafe).await.unwrap();
+
+        let mut expected =
+            BlockData::new_from_geth_data(geth_data.clone()).new_circuit_input_builder();
+        expected
+            .handle_block(&geth_data.eth_block, &geth_data.geth_traces)
+            .unwrap();
+        assert_eq!(builder.block.txs().len(), 1);
+        assert_eq!(builder.block.container, expected.block.container);
+    }
+
+    #[tokio::test]
+    async fn builder_client_gen_inputs_genesis() {
+        let geth_data: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(bytecode! { STOP }),
+            tx_from_1_to_0,
+            |block, _tx| block.number(0u64),
+        )
+        .unwrap()
+        .into();
+
+        let cli = GethClient::new(MockJsonRpcClient::new_from_geth_data(&geth_data));
+        let builder_client = BuilderClient::new(cli, Hardfork::default()).await.unwrap();
+        assert!(matches!(
+            builder_client.gen_inputs(0).await,
+            Err(Error::GenesisBlock)
+        ));
+        assert!(matches!(
+            builder_client.gen_inputs_range(0..=1).await,
+            Err(Error::GenesisBlock)
+        ));
+    }
+
+    #[tokio::test]
+    async fn mock_rpc_recorded_requests() {
+        let json = r#"[
+            {"method": "eth_chainId", "params": null, "result": "0x5"},
+            {"method": "eth_getCode", "params": ["0x0000000000000000000000000000000000000001", "latest"], "result": "0x6001"}
+        ]"#;
+        let cli = GethClient::new(MockJsonRpcClient::from_json(json).unwrap());
+        assert_eq!(cli.get_chain_id().await.unwrap(), 5);
+        assert_eq!(
+            cli.get_code(Address::from_low_u64_be(1), BlockNumber::Latest)
+                .await
+                .unwrap(),
+            vec![0x60, 0x01]
+        );
+        assert!(matches!(
+            cli.get_coinbase().await,
+            Err(Error::JSONRpcError(_))
+        ));
+    }
+}
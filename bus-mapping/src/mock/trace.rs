@@ -0,0 +1,152 @@
+//! Hand-made execution traces for the opcodes which the geth of geth-utils
+//! can't trace.  They are only used by the tests of those opcodes, until
+//! geth-utils is bumped to a geth which implements them.
+
+use eth_types::{
+    evm_types::{
+        gas_utils::{memory_copier_gas_cost, memory_expansion_gas_cost},
+        Gas, GasCost, Memory, OpcodeId, ProgramCounter, Stack, Storage,
+    },
+    Bytecode, GethExecStep, GethExecTrace, ToWord, Word, H256,
+};
+use std::{collections::HashMap, convert::TryFrom};
+
+/// Generate by hand the execution trace of the transaction `tx` calling the
+/// account with `code`, for the opcodes which the geth of geth-utils can't
+/// trace: it implements the London rules, so it traces PUSH0 and the Cancun
+/// opcodes as invalid ones.  Only straight-line code made of pushes, POP,
+/// MSTORE, MCOPY, TLOAD, TSTORE, BLOBHASH, BLOBBASEFEE, STOP and REVERT is
+/// supported, where BLOBHASH and BLOBBASEFEE push the values of `blob_hashes`
+/// and `blob_base_fee`.  The transaction must not have an access list.
+pub(crate) fn trace_code(
+    tx: &eth_types::Transaction,
+    code: &Bytecode,
+    blob_hashes: &[H256],
+    blob_base_fee: Word,
+) -> GethExecTrace {
+    let call_data_gas: u64 = tx
+        .input
+        .iter()
+        .map(|byte| if *byte == 0 { 4 } else { 16 })
+        .sum();
+    let intrinsic_gas = GasCost::TX.as_u64() + call_data_gas;
+    let mut gas = tx.gas.as_u64() - intrinsic_gas;
+    let mut stack = Vec::new();
+    let mut memory = Vec::new();
+    let mut transient_storage = HashMap::new();
+    let mut struct_logs = Vec::new();
+    let mut return_value = Vec::new();
+
+    let code = code.code();
+    let mut pc = 0;
+    loop {
+        let op = OpcodeId::try_from(code.get(pc).copied().unwrap_or_default())
+            .expect("code should be made of valid opcodes");
+        let memory_word_size = (memory.len() / 32) as u64;
+        let step_stack = Stack(stack.clone());
+        let step_memory = Memory(memory.clone());
+        let mut gas_cost = op.constant_gas_cost().as_u64();
+        let mut next_pc = pc + 1;
+        match op {
+            OpcodeId::STOP => (),
+            OpcodeId::PUSH0 => stack.push(Word::zero()),
+            _ if op.is_push() => {
+                let n = (op.as_u8() - OpcodeId::PUSH1.as_u8() + 1) as usize;
+                stack.push(Word::from_big_endian(&code[pc + 1..pc + 1 + n]));
+                next_pc += n;
+            }
+            OpcodeId::POP => {
+                pop(&mut stack);
+            }
+            OpcodeId::MSTORE => {
+                let (offset, value) = (pop(&mut stack).as_usize(), pop(&mut stack));
+                let end = (offset + 32 + 31) / 32 * 32;
+                if end > memory.len() {
+                    memory.resize(end, 0);
+                }
+                gas_cost += memory_expansion_gas_cost(memory_word_size, (memory.len() / 32) as u64);
+                value.to_big_endian(&mut memory[offset..offset + 32]);
+            }
+            OpcodeId::MCOPY => {
+                let (dst_offset, src_offset, length) =
+                    (pop(&mut stack), pop(&mut stack), pop(&mut stack).as_usize());
+                // The offsets of an empty copy aren't used.
+                if length != 0 {
+                    let (dst_offset, src_offset) = (dst_offset.as_usize(), src_offset.as_usize());
+                    let end = (std::cmp::max(dst_offset, src_offset) + length + 31) / 32 * 32;
+                    if end > memory.len() {
+                        memory.resize(end, 0);
+                    }
+                    memory.copy_within(src_offset..src_offset + length, dst_offset);
+                }
+                gas_cost += memory_copier_gas_cost(
+                    memory_word_size,
+                    (memory.len() / 32) as u64,
+                    length as u64,
+                );
+            }
+            OpcodeId::TLOAD => {
+                let key = pop(&mut stack);
+                let value = transient_storage.get(&key).copied().unwrap_or_default();
+                stack.push(value);
+            }
+            OpcodeId::TSTORE => {
+                let (key, value) = (pop(&mut stack), pop(&mut stack));
+                transient_storage.insert(key, value);
+            }
+            OpcodeId::BLOBHASH => {
+                let index = pop(&mut stack);
+                let hash = blob_hashes
+                    .get(index.low_u64() as usize)
+                    .filter(|_| index.bits() <= 64)
+                    .copied()
+                    .unwrap_or_default();
+                stack.push(hash.to_word());
+            }
+            OpcodeId::BLOBBASEFEE => stack.push(blob_base_fee),
+            OpcodeId::REVERT => {
+                let (offset, length) = (pop(&mut stack).as_usize(), pop(&mut stack).as_usize());
+                if length != 0 {
+                    let end = (offset + length + 31) / 32 * 32;
+                    if end > memory.len() {
+                        memory.resize(end, 0);
+                    }
+                    return_value = memory[offset..offset + length].to_vec();
+                }
+                gas_cost += memory_expansion_gas_cost(memory_word_size, (memory.len() / 32) as u64);
+            }
+            _ => panic!("{:?} is not supported by the hand-made traces", op),
+        }
+        struct_logs.push(GethExecStep {
+            pc: ProgramCounter(pc),
+            op,
+            gas: Gas(gas),
+            gas_cost: GasCost(gas_cost),
+            refund: Gas(0),
+            depth: 1,
+            error: None,
+            stack: step_stack,
+            memory: step_memory,
+            storage: Storage::empty(),
+        });
+        gas -= gas_cost;
+        if matches!(op, OpcodeId::STOP | OpcodeId::REVERT) {
+            break;
+        }
+        pc = next_pc;
+    }
+
+    GethExecTrace {
+        gas: Gas(tx.gas.as_u64() - gas),
+        failed: struct_logs.last().map(|step| step.op) == Some(OpcodeId::REVERT),
+        return_value: return_value
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+        struct_logs,
+    }
+}
+
+fn pop(stack: &mut Vec<Word>) -> Word {
+    stack.pop().expect("stack should not underflow")
+}
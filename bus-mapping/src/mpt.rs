@@ -0,0 +1,547 @@
+//! Verification of the Merkle-Patricia trie proofs returned by `eth_getProof`
+//! ([EIP-1186](https://eips.ethereum.org/EIPS/eip-1186)), along with the
+//! minimal RLP encoding and decoding needed to handle the trie nodes, and
+//! computation of the state root after a block from the same proofs.
+
+mod post_state;
+mod trie;
+
+use crate::{error::ProofError, Error};
+use eth_types::{Bytes, EIP1186ProofResponse, ToBigEndian, Word, H256};
+use keccak256::{plain::Keccak, EMPTY_HASH};
+use lazy_static::lazy_static;
+pub use post_state::{post_state_root, TrieAccount, TrieUpdate};
+pub use trie::PartialTrie;
+
+lazy_static! {
+    /// Root of the empty trie: `keccak256(rlp(""))`.
+    pub static ref EMPTY_TRIE_ROOT: H256 = keccak(&rlp_bytes(&[]));
+}
+
+/// Return the keccak256 hash of `data`.
+pub(crate) fn keccak(data: &[u8]) -> H256 {
+    let mut keccak = Keccak::default();
+    keccak.update(data);
+    H256::from_slice(&keccak.digest())
+}
+
+/// Encode the length prefix of an RLP item whose payload is `len` bytes long,
+/// with `offset` being `0x80` for byte strings and `0xc0` for lists.
+fn rlp_length_prefix(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        vec![offset + len as u8]
+    } else {
+        let len_bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|byte| *byte == 0)
+            .collect();
+        let mut prefix = vec![offset + 55 + len_bytes.len() as u8];
+        prefix.extend(len_bytes);
+        prefix
+    }
+}
+
+/// RLP encode a byte string.
+pub(crate) fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut encoded = rlp_length_prefix(bytes.len(), 0x80);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+/// RLP encode a list of already encoded items.
+pub(crate) fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload_len = items.iter().map(Vec::len).sum();
+    let mut encoded = rlp_length_prefix(payload_len, 0xc0);
+    for item in items {
+        encoded.extend_from_slice(item);
+    }
+    encoded
+}
+
+/// RLP encode a [`Word`] as an integer, without leading zeros.
+pub(crate) fn rlp_word(word: Word) -> Vec<u8> {
+    let bytes = word.to_be_bytes();
+    let start = bytes.iter().position(|byte| *byte != 0).unwrap_or(32);
+    rlp_bytes(&bytes[start..])
+}
+
+/// RLP encode an account as stored in the leaves of the state trie.
+pub(crate) fn rlp_account(
+    nonce: Word,
+    balance: Word,
+    storage_root: H256,
+    code_hash: H256,
+) -> Vec<u8> {
+    rlp_list(&[
+        rlp_word(nonce),
+        rlp_word(balance),
+        rlp_bytes(storage_root.as_bytes()),
+        rlp_bytes(code_hash.as_bytes()),
+    ])
+}
+
+/// Decoded RLP item, borrowing from the encoded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RlpItem<'a> {
+    /// Byte string, with its payload.
+    Bytes(&'a [u8]),
+    /// List, with its whole encoding.
+    List(&'a [u8]),
+}
+
+impl<'a> RlpItem<'a> {
+    /// Return the payload of a byte string.
+    pub(crate) fn bytes(self) -> Result<&'a [u8], ProofError> {
+        match self {
+            RlpItem::Bytes(bytes) => Ok(bytes),
+            RlpItem::List(_) => Err(ProofError::InvalidNode),
+        }
+    }
+}
+
+/// Decode a big endian length of an RLP item.
+fn rlp_decode_len(bytes: Option<&[u8]>) -> Result<usize, ProofError> {
+    match bytes {
+        Some(bytes) if bytes.len() <= 8 && bytes.first() != Some(&0) => Ok(bytes
+            .iter()
+            .fold(0, |len, byte| (len << 8) | *byte as usize)),
+        _ => Err(ProofError::InvalidRlp),
+    }
+}
+
+/// Split the first RLP item from `data`, returning it along with the rest of
+/// the data.
+fn rlp_split(data: &[u8]) -> Result<(RlpItem, &[u8]), ProofError> {
+    let prefix = *data.first().ok_or(ProofError::InvalidRlp)?;
+    let (is_list, header_len, payload_len) = match prefix {
+        0x00..=0x7f => return Ok((RlpItem::Bytes(&data[..1]), &data[1..])),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xb8..=0xbf => {
+            let len_len = (prefix - 0xb7) as usize;
+            (
+                false,
+                1 + len_len,
+                rlp_decode_len(data.get(1..1 + len_len))?,
+            )
+        }
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        0xf8..=0xff => {
+            let len_len = (prefix - 0xf7) as usize;
+            (true, 1 + len_len, rlp_decode_len(data.get(1..1 + len_len))?)
+        }
+    };
+    let end = header_len
+        .checked_add(payload_len)
+        .filter(|end| *end <= data.len())
+        .ok_or(ProofError::InvalidRlp)?;
+    let item = if is_list {
+        RlpItem::List(&data[..end])
+    } else {
+        RlpItem::Bytes(&data[header_len..end])
+    };
+    Ok((item, &data[end..]))
+}
+
+/// Decode the items of the RLP list encoded in `data`, which must not contain
+/// anything else.
+pub(crate) fn rlp_list_items(data: &[u8]) -> Result<Vec<RlpItem>, ProofError> {
+    let list = match rlp_split(data)? {
+        (RlpItem::List(list), []) => list,
+        _ => return Err(ProofError::InvalidRlp),
+    };
+    let (_, mut payload) = list.split_at(list.len() - rlp_payload_len(list)?);
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (item, rest) = rlp_split(payload)?;
+        items.push(item);
+        payload = rest;
+    }
+    Ok(items)
+}
+
+/// Decode a [`Word`] from the payload of an RLP encoded integer.
+pub(crate) fn rlp_word_payload(bytes: &[u8]) -> Result<Word, ProofError> {
+    if bytes.len() > 32 || bytes.first() == Some(&0) {
+        return Err(ProofError::InvalidRlp);
+    }
+    Ok(Word::from_big_endian(bytes))
+}
+
+/// Decode a [`Word`] RLP encoded as an integer, as done by [`rlp_word`].
+pub(crate) fn rlp_decode_word(data: &[u8]) -> Result<Word, ProofError> {
+    match rlp_split(data)? {
+        (RlpItem::Bytes(bytes), []) => rlp_word_payload(bytes),
+        _ => Err(ProofError::InvalidRlp),
+    }
+}
+
+/// Return the length of the payload of an encoded RLP list.
+fn rlp_payload_len(list: &[u8]) -> Result<usize, ProofError> {
+    match list[0] {
+        0xc0..=0xf7 => Ok((list[0] - 0xc0) as usize),
+        _ => rlp_decode_len(list.get(1..1 + (list[0] - 0xf7) as usize)),
+    }
+}
+
+/// Split `bytes` into nibbles, most significant first.
+pub(crate) fn to_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+/// Encode the path of a leaf or extension node with the hex-prefix encoding.
+pub(crate) fn hex_prefix_encode(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 };
+    let mut encoded = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        encoded.push(((flag + 1) << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        encoded.push(flag << 4);
+        nibbles
+    };
+    encoded.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    encoded
+}
+
+/// Decode the hex-prefix encoded path of a leaf or extension node, returning
+/// whether the node is a leaf along with the nibbles of the path.
+pub(crate) fn hex_prefix_decode(encoded: &[u8]) -> Result<(bool, Vec<u8>), ProofError> {
+    let first = *encoded.first().ok_or(ProofError::InvalidNode)?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(ProofError::InvalidNode);
+    }
+    let mut nibbles = Vec::with_capacity(encoded.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    } else if first & 0x0f != 0 {
+        return Err(ProofError::InvalidNode);
+    }
+    nibbles.extend(to_nibbles(&encoded[1..]));
+    Ok((flag & 2 == 2, nibbles))
+}
+
+/// Reference from a branch or extension node to a child node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NodeRef<'a> {
+    /// Hash of the child node.
+    Hash(H256),
+    /// Encoding of the child node, which is embedded in its parent because it
+    /// is shorter than 32 bytes.
+    Inline(&'a [u8]),
+}
+
+impl<'a> NodeRef<'a> {
+    /// Decode a child reference, which is `None` for an empty slot.
+    pub(crate) fn decode(item: RlpItem<'a>) -> Result<Option<Self>, ProofError> {
+        match item {
+            RlpItem::Bytes([]) => Ok(None),
+            RlpItem::Bytes(hash) if hash.len() == 32 => {
+                Ok(Some(NodeRef::Hash(H256::from_slice(hash))))
+            }
+            RlpItem::List(node) if node.len() < 32 => Ok(Some(NodeRef::Inline(node))),
+            _ => Err(ProofError::InvalidNode),
+        }
+    }
+}
+
+/// Verify a proof of the value at `key` in the secure trie with root `root`,
+/// returning the value, or `None` if the proof shows that `key` is not in the
+/// trie.  `proof` are the encoded nodes from the root to the value.
+pub fn verify_proof(
+    root: H256,
+    key: &[u8],
+    proof: &[Bytes],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    let nibbles = to_nibbles(keccak(key).as_bytes());
+    let mut path = nibbles.as_slice();
+    let mut proof = proof.iter();
+    let mut node_ref = NodeRef::Hash(root);
+    loop {
+        let node: &[u8] = match node_ref {
+            NodeRef::Hash(hash) => match proof.next() {
+                Some(node) if keccak(node.as_ref()) == hash => node.as_ref(),
+                Some(_) => return Err(ProofError::NodeHashMismatch(hash)),
+                None if hash == *EMPTY_TRIE_ROOT => return Ok(None),
+                None => return Err(ProofError::MissingNode),
+            },
+            NodeRef::Inline(node) => node,
+        };
+        let items = rlp_list_items(node)?;
+        match items.as_slice() {
+            // Branch node
+            [children @ .., value] if children.len() == 16 => match path.split_first() {
+                Some((nibble, rest)) => match NodeRef::decode(children[*nibble as usize])? {
+                    Some(child) => {
+                        path = rest;
+                        node_ref = child;
+                    }
+                    None => return Ok(None),
+                },
+                None => {
+                    let value = value.bytes()?;
+                    return Ok((!value.is_empty()).then(|| value.to_vec()));
+                }
+            },
+            // Leaf or extension node
+            [encoded_path, child] => {
+                let (is_leaf, node_path) = hex_prefix_decode(encoded_path.bytes()?)?;
+                if is_leaf {
+                    if path != node_path.as_slice() {
+                        return Ok(None);
+                    }
+                    return child.bytes().map(|value| Some(value.to_vec()));
+                }
+                match path.strip_prefix(node_path.as_slice()) {
+                    Some(rest) => {
+                        path = rest;
+                        node_ref = NodeRef::decode(*child)?.ok_or(ProofError::InvalidNode)?;
+                    }
+                    None => return Ok(None),
+                }
+            }
+            _ => return Err(ProofError::InvalidNode),
+        }
+    }
+}
+
+/// Verify the account and storage proofs of an `eth_getProof` response
+/// against the state root `state_root`: the account must be proven to be in
+/// the state trie with the claimed nonce, balance, storage root and code hash
+/// (or to be absent, when they are empty), and each storage slot to have the
+/// claimed value in the account storage trie.
+pub fn verify_account_proof(state_root: H256, proof: &EIP1186ProofResponse) -> Result<(), Error> {
+    let address = proof.address;
+    let account = verify_proof(state_root, address.as_bytes(), &proof.account_proof)
+        .map_err(|err| Error::InvalidAccountProof(address, err))?;
+    let exists = match account {
+        Some(account) => {
+            let expected = rlp_account(
+                proof.nonce,
+                proof.balance,
+                proof.storage_hash,
+                proof.code_hash,
+            );
+            if account != expected {
+                return Err(Error::InvalidAccountProof(
+                    address,
+                    ProofError::ValueMismatch,
+                ));
+            }
+            true
+        }
+        None => {
+            // Nodes may return either zero or the empty values for the hashes
+            // of an account which doesn't exist.
+            let is_empty = proof.nonce.is_zero()
+                && proof.balance.is_zero()
+                && (proof.code_hash.is_zero() || proof.code_hash.to_fixed_bytes() == *EMPTY_HASH)
+                && (proof.storage_hash.is_zero() || proof.storage_hash == *EMPTY_TRIE_ROOT);
+            if !is_empty {
+                return Err(Error::InvalidAccountProof(
+                    address,
+                    ProofError::ValueMismatch,
+                ));
+            }
+            false
+        }
+    };
+
+    for storage_proof in &proof.storage_proof {
+        let key = storage_proof.key;
+        let value = if exists {
+            verify_proof(proof.storage_hash, &key.to_be_bytes(), &storage_proof.proof)
+                .map_err(|err| Error::InvalidStorageProof(address, key, err))?
+        } else {
+            None
+        };
+        let is_valid = match value {
+            Some(value) => !storage_proof.value.is_zero() && value == rlp_word(storage_proof.value),
+            None => storage_proof.value.is_zero(),
+        };
+        if !is_valid {
+            return Err(Error::InvalidStorageProof(
+                address,
+                key,
+                ProofError::ValueMismatch,
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod mpt_tests {
+    use super::*;
+    use eth_types::{Address, StorageProof};
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    fn path(key: &[u8]) -> Vec<u8> {
+        to_nibbles(keccak(key).as_bytes())
+    }
+
+    fn leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+        rlp_list(&[rlp_bytes(&hex_prefix_encode(path, true)), rlp_bytes(value)])
+    }
+
+    fn branch(children: &[(u8, H256)]) -> Vec<u8> {
+        let mut items = vec![rlp_bytes(&[]); 17];
+        for (nibble, hash) in children {
+            items[*nibble as usize] = rlp_bytes(hash.as_bytes());
+        }
+        rlp_list(&items)
+    }
+
+    fn account(
+        address: Address,
+        balance: u64,
+        account_proof: Vec<Vec<u8>>,
+    ) -> EIP1186ProofResponse {
+        EIP1186ProofResponse {
+            address,
+            balance: Word::from(balance),
+            code_hash: H256(*EMPTY_HASH),
+            nonce: Word::zero(),
+            storage_hash: *EMPTY_TRIE_ROOT,
+            account_proof: account_proof.into_iter().map(Bytes::from).collect(),
+            storage_proof: Vec::new(),
+        }
+    }
+
+    fn account_leaf(proof: &EIP1186ProofResponse, path: &[u8]) -> Vec<u8> {
+        leaf(
+            path,
+            &rlp_account(
+                proof.nonce,
+                proof.balance,
+                proof.storage_hash,
+                proof.code_hash,
+            ),
+        )
+    }
+
+    #[test]
+    fn empty_trie_root() {
+        assert_eq!(
+            *EMPTY_TRIE_ROOT,
+            H256::from_str("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn verify_account_proof_with_storage() {
+        let address = Address::repeat_byte(0x11);
+        let (key, value) = (Word::from(1), Word::from(0x1234));
+        let storage_leaf = leaf(&path(&key.to_be_bytes()), &rlp_word(value));
+
+        let mut proof = account(address, 100, Vec::new());
+        proof.nonce = Word::one();
+        proof.storage_hash = keccak(&storage_leaf);
+        proof.storage_proof = vec![
+            StorageProof {
+                key,
+                value,
+                proof: vec![storage_leaf.clone().into()],
+            },
+            // Absent key, proven by the same leaf.
+            StorageProof {
+                key: Word::from(2),
+                value: Word::zero(),
+                proof: vec![storage_leaf.into()],
+            },
+        ];
+        let leaf = account_leaf(&proof, &path(address.as_bytes()));
+        proof.account_proof = vec![leaf.clone().into()];
+        let state_root = keccak(&leaf);
+        assert!(verify_account_proof(state_root, &proof).is_ok());
+
+        let mut fake_proof = proof.clone();
+        fake_proof.balance = Word::from(101);
+        assert!(matches!(
+            verify_account_proof(state_root, &fake_proof),
+            Err(Error::InvalidAccountProof(a, ProofError::ValueMismatch)) if a == address
+        ));
+
+        let mut fake_proof = proof;
+        fake_proof.storage_proof[0].value = Word::from(0x4321);
+        assert!(matches!(
+            verify_account_proof(state_root, &fake_proof),
+            Err(Error::InvalidStorageProof(a, k, ProofError::ValueMismatch))
+                if a == address && k == key
+        ));
+    }
+
+    #[test]
+    fn verify_account_proof_branch() {
+        let first_nibble = |address: Address| path(address.as_bytes())[0];
+        let address_a = Address::from_low_u64_be(1);
+        let address_b = (2..)
+            .map(Address::from_low_u64_be)
+            .find(|b| first_nibble(*b) != first_nibble(address_a))
+            .unwrap();
+        let address_c = (2..)
+            .map(Address::from_low_u64_be)
+            .find(|c| {
+                ![first_nibble(address_a), first_nibble(address_b)].contains(&first_nibble(*c))
+            })
+            .unwrap();
+
+        let mut proof_a = account(address_a, 1, Vec::new());
+        let mut proof_b = account(address_b, 2, Vec::new());
+        let leaf_a = account_leaf(&proof_a, &path(address_a.as_bytes())[1..]);
+        let leaf_b = account_leaf(&proof_b, &path(address_b.as_bytes())[1..]);
+        let root = branch(&[
+            (first_nibble(address_a), keccak(&leaf_a)),
+            (first_nibble(address_b), keccak(&leaf_b)),
+        ]);
+        let state_root = keccak(&root);
+
+        proof_a.account_proof = vec![root.clone().into(), leaf_a.into()];
+        proof_b.account_proof = vec![root.clone().into(), leaf_b.clone().into()];
+        assert!(verify_account_proof(state_root, &proof_a).is_ok());
+        assert!(verify_account_proof(state_root, &proof_b).is_ok());
+
+        // Absent account
+        let proof_c = account(address_c, 0, vec![root.clone()]);
+        assert!(verify_account_proof(state_root, &proof_c).is_ok());
+        let fake_proof_c = account(address_c, 1, vec![root.clone()]);
+        assert!(matches!(
+            verify_account_proof(state_root, &fake_proof_c),
+            Err(Error::InvalidAccountProof(_, ProofError::ValueMismatch))
+        ));
+
+        // Leaf of another account
+        proof_a.account_proof[1] = leaf_b.into();
+        let leaf_a_hash = keccak(&account_leaf(&proof_a, &path(address_a.as_bytes())[1..]));
+        assert!(matches!(
+            verify_account_proof(state_root, &proof_a),
+            Err(Error::InvalidAccountProof(a, ProofError::NodeHashMismatch(hash)))
+                if a == address_a && hash == leaf_a_hash
+        ));
+
+        // Missing leaf
+        proof_a.account_proof.pop();
+        assert!(matches!(
+            verify_account_proof(state_root, &proof_a),
+            Err(Error::InvalidAccountProof(_, ProofError::MissingNode))
+        ));
+
+        // Wrong state root
+        assert!(matches!(
+            verify_account_proof(keccak(leaf_a_hash.as_bytes()), &proof_b),
+            Err(Error::InvalidAccountProof(
+                _,
+                ProofError::NodeHashMismatch(_)
+            ))
+        ));
+    }
+}
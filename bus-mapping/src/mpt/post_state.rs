@@ -0,0 +1,606 @@
+//! Computation of the state root after a block, by applying the writes
+//! recorded while building the block to partial tries seeded from the
+//! `eth_getProof` responses of the state before the block.
+
+use super::{
+    rlp_account, rlp_decode_word, rlp_list_items, rlp_word, rlp_word_payload, PartialTrie,
+    EMPTY_TRIE_ROOT,
+};
+use crate::{
+    circuit_input_builder::Block,
+    constants::GWEI,
+    error::ProofError,
+    operation::{AccountDestructedOp, AccountField, AccountOp, StorageOp, RW},
+    Error,
+};
+use eth_types::{Address, Bytes, EIP1186ProofResponse, ToBigEndian, Withdrawal, Word, H256};
+use keccak256::EMPTY_HASH;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Account as stored in the leaves of the state trie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrieAccount {
+    /// Nonce
+    pub nonce: Word,
+    /// Balance
+    pub balance: Word,
+    /// Root of the storage trie
+    pub storage_root: H256,
+    /// Code hash
+    pub code_hash: H256,
+}
+
+impl TrieAccount {
+    fn decode(data: &[u8]) -> Result<Self, ProofError> {
+        let hash = |bytes: &[u8]| {
+            (bytes.len() == 32)
+                .then(|| H256::from_slice(bytes))
+                .ok_or(ProofError::InvalidRlp)
+        };
+        match rlp_list_items(data)?.as_slice() {
+            [nonce, balance, storage_root, code_hash] => Ok(Self {
+                nonce: rlp_word_payload(nonce.bytes()?)?,
+                balance: rlp_word_payload(balance.bytes()?)?,
+                storage_root: hash(storage_root.bytes()?)?,
+                code_hash: hash(code_hash.bytes()?)?,
+            }),
+            _ => Err(ProofError::InvalidRlp),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        rlp_account(self.nonce, self.balance, self.storage_root, self.code_hash)
+    }
+
+    /// Return whether the account is empty as defined in
+    /// [EIP-161](https://eips.ethereum.org/EIPS/eip-161), with no storage.
+    fn is_empty(&self) -> bool {
+        self.nonce.is_zero()
+            && self.balance.is_zero()
+            && self.storage_root == *EMPTY_TRIE_ROOT
+            && self.code_hash.to_fixed_bytes() == *EMPTY_HASH
+    }
+}
+
+/// Change of a leaf of the state trie or of a storage trie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrieUpdate {
+    /// Change of the account at `address`, with `None` for an account which
+    /// is not in the state trie.
+    Account {
+        /// Address of the account
+        address: Address,
+        /// Account before the block
+        old: Option<TrieAccount>,
+        /// Account after the block
+        new: Option<TrieAccount>,
+    },
+    /// Change of the storage slot `key` of the account at `address`, with zero
+    /// for a slot which is not in the storage trie.
+    Storage {
+        /// Address of the account
+        address: Address,
+        /// Storage key
+        key: Word,
+        /// Value before the block
+        old: Word,
+        /// Value after the block
+        new: Word,
+    },
+}
+
+/// Write to the state, in the order in which they happened.
+enum StateWrite<'a> {
+    Account(&'a AccountOp),
+    Storage(&'a StorageOp),
+    Destructed(&'a AccountDestructedOp),
+}
+
+/// Account written by the block.
+struct AccountWrites {
+    old: Option<TrieAccount>,
+    nonce: Word,
+    balance: Word,
+    code_hash: H256,
+    /// Last value written to each storage slot.
+    storage: BTreeMap<Word, Word>,
+    /// Whether the account was self destructed, which clears the storage it
+    /// had before the destruction.
+    storage_cleared: bool,
+}
+
+impl AccountWrites {
+    fn new(old: Option<TrieAccount>) -> Self {
+        Self {
+            old,
+            nonce: old.map(|acc| acc.nonce).unwrap_or_default(),
+            balance: old.map(|acc| acc.balance).unwrap_or_default(),
+            code_hash: old.map(|acc| acc.code_hash).unwrap_or(H256(*EMPTY_HASH)),
+            storage: BTreeMap::new(),
+            storage_cleared: false,
+        }
+    }
+
+    fn destruct(&mut self) {
+        self.nonce = Word::zero();
+        self.balance = Word::zero();
+        self.code_hash = H256(*EMPTY_HASH);
+        self.storage.clear();
+        self.storage_cleared = true;
+    }
+}
+
+/// Compute the state root after `block` from the state root before it,
+/// `prev_state_root`, and the `eth_getProof` responses `proofs` of all the
+/// accounts and storage slots accessed by the block.  Every account and
+/// storage write recorded in the [`OperationContainer`](crate::operation::OperationContainer)
+/// of the block is applied in order, self destructed accounts are removed at
+/// the end of their transaction, the `withdrawals` of the block are credited
+/// after its transactions, and accounts left empty are removed as in
+/// [EIP-161](https://eips.ethereum.org/EIPS/eip-161).
+///
+/// Removing a leaf collapses its branch when a single sibling is left, which
+/// needs the sibling node if it's hashed.  Such a node isn't part of the
+/// proofs of the removed keys, so it must be given in `sibling_nodes` (for
+/// example fetched by hash with `debug_dbGet`), or in additional responses in
+/// `proofs`, which may hold several responses for the same address.  The
+/// nodes of `sibling_nodes` may belong to the state trie or to any storage
+/// trie.
+///
+/// Returns the new state root along with the updates of the trie leaves, with
+/// the storage updates of each account before its own update, ordered by
+/// address and key.  Block rewards aren't part of the recorded writes, so the
+/// result only matches the block header for blocks without them.
+pub fn post_state_root(
+    prev_state_root: H256,
+    proofs: &[EIP1186ProofResponse],
+    sibling_nodes: &[Bytes],
+    withdrawals: &[Withdrawal],
+    block: &Block,
+) -> Result<(H256, Vec<TrieUpdate>), Error> {
+    let mut state_trie = PartialTrie::new(
+        prev_state_root,
+        proofs
+            .iter()
+            .flat_map(|proof| &proof.account_proof)
+            .chain(sibling_nodes),
+    );
+    let mut storage_proofs: HashMap<Address, Vec<&EIP1186ProofResponse>> = HashMap::new();
+    for proof in proofs {
+        storage_proofs.entry(proof.address).or_default().push(proof);
+    }
+
+    let mut writes: Vec<(usize, StateWrite)> = Vec::new();
+    writes.extend(
+        block
+            .container
+            .account
+            .iter()
+            .filter(|op| op.rw() == RW::WRITE)
+            .map(|op| (op.rwc().0, StateWrite::Account(op.op()))),
+    );
+    writes.extend(
+        block
+            .container
+            .storage
+            .iter()
+            .filter(|op| op.rw() == RW::WRITE)
+            .map(|op| (op.rwc().0, StateWrite::Storage(op.op()))),
+    );
+    writes.extend(
+        block
+            .container
+            .account_destructed
+            .iter()
+            .filter(|op| op.rw() == RW::WRITE)
+            .map(|op| (op.rwc().0, StateWrite::Destructed(op.op()))),
+    );
+    writes.sort_by_key(|(rwc, _)| *rwc);
+
+    // Self destructions take effect at the end of the transaction, which is
+    // right before the first step of the next one.
+    let mut tx_starts = block
+        .txs()
+        .iter()
+        .skip(1)
+        .filter_map(|tx| tx.steps().first().map(|step| step.rwc.0))
+        .peekable();
+
+    let mut accounts: BTreeMap<Address, AccountWrites> = BTreeMap::new();
+    let mut destructed: HashSet<Address> = HashSet::new();
+    for (rwc, write) in writes {
+        while tx_starts.next_if(|start| *start <= rwc).is_some() {
+            for address in destructed.drain() {
+                touch(&mut accounts, &state_trie, address)?.destruct();
+            }
+        }
+        match write {
+            StateWrite::Account(op) => {
+                let account = touch(&mut accounts, &state_trie, op.address)?;
+                match op.field {
+                    AccountField::Nonce => account.nonce = op.value,
+                    AccountField::Balance => account.balance = op.value,
+                    AccountField::CodeHash => {
+                        account.code_hash = H256::from(op.value.to_be_bytes())
+                    }
+                }
+            }
+            StateWrite::Storage(op) => {
+                touch(&mut accounts, &state_trie, op.address)?
+                    .storage
+                    .insert(op.key, op.value);
+            }
+            StateWrite::Destructed(op) => {
+                if op.is_destructed {
+                    destructed.insert(op.address);
+                } else {
+                    destructed.remove(&op.address);
+                }
+            }
+        }
+    }
+    for address in destructed {
+        touch(&mut accounts, &state_trie, address)?.destruct();
+    }
+    // Withdrawals of a zero amount don't touch their account.
+    for withdrawal in withdrawals.iter().filter(|w| !w.amount.is_zero()) {
+        let account = touch(&mut accounts, &state_trie, withdrawal.address)?;
+        account.balance += Word::from(withdrawal.amount.as_u64()) * Word::from(GWEI);
+    }
+
+    let mut updates = Vec::new();
+    for (address, account) in accounts {
+        let old_storage_root = account
+            .old
+            .map(|acc| acc.storage_root)
+            .unwrap_or(*EMPTY_TRIE_ROOT);
+        let storage_nodes = storage_proofs
+            .get(&address)
+            .into_iter()
+            .flatten()
+            .flat_map(|proof| &proof.storage_proof)
+            .flat_map(|proof| &proof.proof)
+            .chain(sibling_nodes);
+        let old_storage_trie = PartialTrie::new(old_storage_root, storage_nodes);
+        let mut storage_trie = if account.storage_cleared {
+            PartialTrie::new(*EMPTY_TRIE_ROOT, [])
+        } else {
+            old_storage_trie.clone()
+        };
+        for (key, value) in account.storage {
+            let storage_err = |err| Error::InvalidStorageProof(address, key, err);
+            let old = old_storage_trie
+                .get(&key.to_be_bytes())
+                .and_then(|value| value.map_or(Ok(Word::zero()), |value| rlp_decode_word(&value)))
+                .map_err(storage_err)?;
+            let result = if value.is_zero() {
+                storage_trie.remove(&key.to_be_bytes())
+            } else {
+                storage_trie.insert(&key.to_be_bytes(), rlp_word(value))
+            };
+            result.map_err(storage_err)?;
+            if old != value {
+                updates.push(TrieUpdate::Storage {
+                    address,
+                    key,
+                    old,
+                    new: value,
+                });
+            }
+        }
+
+        let new = TrieAccount {
+            nonce: account.nonce,
+            balance: account.balance,
+            storage_root: storage_trie.root(),
+            code_hash: account.code_hash,
+        };
+        let new = (!new.is_empty()).then(|| new);
+        let result = match new {
+            Some(new) => state_trie.insert(address.as_bytes(), new.encode()),
+            None => state_trie.remove(address.as_bytes()),
+        };
+        result.map_err(|err| Error::InvalidAccountProof(address, err))?;
+        if account.old != new {
+            updates.push(TrieUpdate::Account {
+                address,
+                old: account.old,
+                new,
+            });
+        }
+    }
+
+    Ok((state_trie.root(), updates))
+}
+
+/// Return the writes to the account at `address`, reading the account from
+/// the state trie the first time it's written.
+fn touch<'a>(
+    accounts: &'a mut BTreeMap<Address, AccountWrites>,
+    state_trie: &PartialTrie,
+    address: Address,
+) -> Result<&'a mut AccountWrites, Error> {
+    if !accounts.contains_key(&address) {
+        let old = state_trie
+            .get(address.as_bytes())
+            .and_then(|account| {
+                account
+                    .map(|account| TrieAccount::decode(&account))
+                    .transpose()
+            })
+            .map_err(|err| Error::InvalidAccountProof(address, err))?;
+        accounts.insert(address, AccountWrites::new(old));
+    }
+    Ok(accounts.get_mut(&address).expect("account inserted"))
+}
+
+#[cfg(test)]
+mod post_state_tests {
+    use super::*;
+    use crate::{circuit_input_builder::CircuitInputBuilder, mock::BlockData, state_db::StateDB};
+    use eth_types::{bytecode, geth_types::GethData, Bytecode, StorageProof, ToWord};
+    use mock::{
+        eth,
+        test_ctx::{helpers::*, TestContext},
+        MOCK_ACCOUNTS,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn storage_trie(storage: &HashMap<Word, Word>) -> PartialTrie {
+        let mut trie = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        for (key, value) in storage.iter().filter(|(_, value)| !value.is_zero()) {
+            trie.insert(&key.to_be_bytes(), rlp_word(*value)).unwrap();
+        }
+        trie
+    }
+
+    /// Build the state trie of the `addresses` in `sdb`.
+    fn state_trie(sdb: &StateDB, addresses: &[Address]) -> PartialTrie {
+        let mut trie = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        for address in addresses {
+            let (_, account) = sdb.get_account(address);
+            let account = TrieAccount {
+                nonce: account.nonce,
+                balance: account.balance,
+                storage_root: storage_trie(&account.storage).root(),
+                code_hash: account.code_hash,
+            };
+            if !account.is_empty() {
+                trie.insert(address.as_bytes(), account.encode()).unwrap();
+            }
+        }
+        trie
+    }
+
+    /// Build the block of `code` run by a transaction from the second account
+    /// to the first one, which starts with `storage`, and return it along with
+    /// the state root before the block, the proofs of all the accounts and the
+    /// addresses of the accounts.
+    fn build_block(
+        code: Bytecode,
+        storage: Vec<(Word, Word)>,
+    ) -> (
+        CircuitInputBuilder,
+        H256,
+        Vec<EIP1186ProofResponse>,
+        Vec<Address>,
+    ) {
+        let block: GethData = TestContext::<2, 1>::new(
+            None,
+            |accs| {
+                accs[0]
+                    .address(MOCK_ACCOUNTS[0])
+                    .balance(eth(10))
+                    .code(code)
+                    .storage(storage.into_iter());
+                accs[1].address(MOCK_ACCOUNTS[1]).balance(eth(10));
+            },
+            tx_from_1_to_0,
+            |block, _tx| block.number(0xcafeu64),
+        )
+        .unwrap()
+        .into();
+        let mut builder = BlockData::new_from_geth_data(block.clone()).new_circuit_input_builder();
+        let mut addresses = vec![block.eth_block.author];
+        addresses.extend(block.accounts.iter().map(|account| account.address));
+
+        let prev_state_trie = state_trie(&builder.sdb, &addresses);
+        let proofs = addresses
+            .iter()
+            .map(|address| {
+                let (_, account) = builder.sdb.get_account(address);
+                EIP1186ProofResponse {
+                    address: *address,
+                    balance: account.balance,
+                    code_hash: account.code_hash,
+                    nonce: account.nonce,
+                    storage_hash: storage_trie(&account.storage).root(),
+                    account_proof: prev_state_trie.encoded_nodes(),
+                    storage_proof: vec![StorageProof {
+                        key: Word::zero(),
+                        value: Word::zero(),
+                        proof: storage_trie(&account.storage).encoded_nodes(),
+                    }],
+                }
+            })
+            .collect();
+
+        builder
+            .handle_block(&block.eth_block, &block.geth_traces)
+            .unwrap();
+        (builder, prev_state_trie.root(), proofs, addresses)
+    }
+
+    #[test]
+    fn post_state_root_storage() {
+        let code = bytecode! {
+            PUSH1(0x00)
+            PUSH1(0x01)
+            SSTORE
+            PUSH1(0x05)
+            PUSH1(0x02)
+            SSTORE
+            STOP
+        };
+        let (builder, prev_state_root, proofs, addresses) =
+            build_block(code, vec![(Word::from(1), Word::from(2))]);
+
+        let (root, updates) =
+            post_state_root(prev_state_root, &proofs, &[], &[], &builder.block).unwrap();
+        assert_eq!(root, state_trie(&builder.sdb, &addresses).root());
+        let storage_updates: Vec<_> = updates
+            .into_iter()
+            .filter(|update| matches!(update, TrieUpdate::Storage { .. }))
+            .collect();
+        assert_eq!(
+            storage_updates,
+            vec![
+                TrieUpdate::Storage {
+                    address: MOCK_ACCOUNTS[0],
+                    key: Word::from(1),
+                    old: Word::from(2),
+                    new: Word::zero(),
+                },
+                TrieUpdate::Storage {
+                    address: MOCK_ACCOUNTS[0],
+                    key: Word::from(2),
+                    old: Word::zero(),
+                    new: Word::from(5),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn post_state_root_selfdestruct() {
+        let code = bytecode! {
+            PUSH20(MOCK_ACCOUNTS[2].to_word())
+            SELFDESTRUCT
+        };
+        let (builder, prev_state_root, proofs, mut addresses) =
+            build_block(code, vec![(Word::from(1), Word::from(2))]);
+        addresses.push(MOCK_ACCOUNTS[2]);
+
+        let (root, updates) =
+            post_state_root(prev_state_root, &proofs, &[], &[], &builder.block).unwrap();
+        assert_eq!(root, state_trie(&builder.sdb, &addresses).root());
+        assert!(updates.iter().any(|update| matches!(
+            update,
+            TrieUpdate::Account { address, old: Some(_), new: None } if *address == MOCK_ACCOUNTS[0]
+        )));
+        assert!(updates.iter().any(|update| matches!(
+            update,
+            TrieUpdate::Account { address, old: None, new: Some(new) }
+                if *address == MOCK_ACCOUNTS[2] && !new.balance.is_zero()
+        )));
+    }
+
+    #[test]
+    fn post_state_root_missing_proof() {
+        let (builder, prev_state_root, mut proofs, _) = build_block(bytecode! { STOP }, vec![]);
+        proofs
+            .iter_mut()
+            .for_each(|proof| proof.account_proof.truncate(1));
+        assert!(matches!(
+            post_state_root(prev_state_root, &proofs, &[], &[], &builder.block),
+            Err(Error::InvalidAccountProof(_, ProofError::MissingNode))
+        ));
+    }
+
+    #[test]
+    fn post_state_root_collapse_onto_hashed_sibling() {
+        // The hashed keys of slots 1 and 2 start with different nibbles, so
+        // the storage trie is a branch holding both leaves, which are hashed.
+        // Clearing slot 1 collapses the branch into the leaf of slot 2, which
+        // isn't part of the proof of slot 1.
+        let code = bytecode! {
+            PUSH1(0x00)
+            PUSH1(0x01)
+            SSTORE
+            STOP
+        };
+        let storage = vec![
+            (Word::from(1), Word::from(2)),
+            (Word::from(2), Word::from(3)),
+        ];
+        let (builder, prev_state_root, mut proofs, addresses) = build_block(code, storage.clone());
+        let trie = storage_trie(&storage.into_iter().collect());
+        let proof = proofs
+            .iter_mut()
+            .find(|proof| proof.address == MOCK_ACCOUNTS[0])
+            .unwrap();
+        proof.storage_proof = vec![StorageProof {
+            key: Word::from(1),
+            value: Word::from(2),
+            proof: trie.proof(&Word::from(1).to_be_bytes()).unwrap(),
+        }];
+        assert!(matches!(
+            post_state_root(prev_state_root, &proofs, &[], &[], &builder.block),
+            Err(Error::InvalidStorageProof(_, _, ProofError::MissingNode))
+        ));
+
+        let expected_root = state_trie(&builder.sdb, &addresses).root();
+        let sibling_nodes = trie.proof(&Word::from(2).to_be_bytes()).unwrap();
+        let (root, _) = post_state_root(
+            prev_state_root,
+            &proofs,
+            &sibling_nodes,
+            &[],
+            &builder.block,
+        )
+        .unwrap();
+        assert_eq!(root, expected_root);
+
+        // The sibling can also be given by another proof of the account.
+        let mut sibling_proof = proofs
+            .iter()
+            .find(|proof| proof.address == MOCK_ACCOUNTS[0])
+            .unwrap()
+            .clone();
+        sibling_proof.storage_proof = vec![StorageProof {
+            key: Word::from(2),
+            value: Word::from(3),
+            proof: sibling_nodes,
+        }];
+        proofs.push(sibling_proof);
+        let (root, _) =
+            post_state_root(prev_state_root, &proofs, &[], &[], &builder.block).unwrap();
+        assert_eq!(root, expected_root);
+    }
+
+    #[test]
+    fn post_state_root_withdrawals() {
+        let (mut builder, prev_state_root, proofs, mut addresses) =
+            build_block(bytecode! { STOP }, vec![]);
+        let withdrawals = [
+            Withdrawal {
+                index: 0u64.into(),
+                validator_index: 7u64.into(),
+                address: MOCK_ACCOUNTS[2],
+                amount: 5u64.into(),
+            },
+            Withdrawal {
+                index: 1u64.into(),
+                validator_index: 8u64.into(),
+                address: MOCK_ACCOUNTS[3],
+                amount: 0u64.into(),
+            },
+        ];
+
+        let (root, updates) =
+            post_state_root(prev_state_root, &proofs, &[], &withdrawals, &builder.block).unwrap();
+        builder.sdb.get_account_mut(&MOCK_ACCOUNTS[2]).1.balance = Word::from(5 * GWEI);
+        addresses.extend([MOCK_ACCOUNTS[2], MOCK_ACCOUNTS[3]]);
+        assert_eq!(root, state_trie(&builder.sdb, &addresses).root());
+        assert!(updates.iter().any(|update| matches!(
+            update,
+            TrieUpdate::Account { address, old: None, new: Some(new) }
+                if *address == MOCK_ACCOUNTS[2] && new.balance == Word::from(5 * GWEI)
+        )));
+        // The zero withdrawal doesn't touch its account.
+        assert!(!updates.iter().any(|update| matches!(
+            update,
+            TrieUpdate::Account { address, .. } if *address == MOCK_ACCOUNTS[3]
+        )));
+    }
+}
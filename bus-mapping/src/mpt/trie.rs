@@ -0,0 +1,629 @@
+//! Partial Merkle-Patricia trie, built from the nodes of a set of proofs,
+//! which can be read and updated at the proven keys to compute a new root.
+
+use super::{
+    hex_prefix_decode, hex_prefix_encode, keccak, rlp_bytes, rlp_list, rlp_list_items, to_nibbles,
+    NodeRef, EMPTY_TRIE_ROOT,
+};
+use crate::error::ProofError;
+use eth_types::{Bytes, H256};
+use std::collections::HashMap;
+
+/// Node of a [`PartialTrie`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<Node>,
+    },
+    Branch {
+        children: Box<[Node; 16]>,
+        value: Option<Vec<u8>>,
+    },
+    /// Node which hasn't been decoded yet, known only by its hash.
+    Hash(H256),
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node::Empty
+    }
+}
+
+impl Node {
+    /// Decode an encoded node, leaving its hashed children unresolved.
+    fn decode(data: &[u8]) -> Result<Self, ProofError> {
+        let items = rlp_list_items(data)?;
+        match items.as_slice() {
+            [children @ .., value] if children.len() == 16 => {
+                let mut nodes: Box<[Node; 16]> = Box::default();
+                for (node, child) in nodes.iter_mut().zip(children) {
+                    *node = Node::from_ref(NodeRef::decode(*child)?)?;
+                }
+                let value = value.bytes()?;
+                Ok(Node::Branch {
+                    children: nodes,
+                    value: (!value.is_empty()).then(|| value.to_vec()),
+                })
+            }
+            [encoded_path, child] => {
+                let (is_leaf, path) = hex_prefix_decode(encoded_path.bytes()?)?;
+                if is_leaf {
+                    return Ok(Node::Leaf {
+                        path,
+                        value: child.bytes()?.to_vec(),
+                    });
+                }
+                match NodeRef::decode(*child)? {
+                    Some(child) => Ok(Node::Extension {
+                        path,
+                        child: Box::new(Node::from_ref(Some(child))?),
+                    }),
+                    None => Err(ProofError::InvalidNode),
+                }
+            }
+            _ => Err(ProofError::InvalidNode),
+        }
+    }
+
+    /// Build the node referenced by a branch or extension node.
+    fn from_ref(node_ref: Option<NodeRef>) -> Result<Self, ProofError> {
+        match node_ref {
+            None => Ok(Node::Empty),
+            Some(NodeRef::Hash(hash)) => Ok(Node::Hash(hash)),
+            Some(NodeRef::Inline(node)) => Node::decode(node),
+        }
+    }
+
+    /// Encode the node.  Unresolved nodes can only be referenced by their hash
+    /// and are never encoded.
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Node::Empty => rlp_bytes(&[]),
+            Node::Leaf { path, value } => {
+                rlp_list(&[rlp_bytes(&hex_prefix_encode(path, true)), rlp_bytes(value)])
+            }
+            Node::Extension { path, child } => rlp_list(&[
+                rlp_bytes(&hex_prefix_encode(path, false)),
+                child.reference(),
+            ]),
+            Node::Branch { children, value } => {
+                let mut items: Vec<Vec<u8>> = children.iter().map(Node::reference).collect();
+                items.push(rlp_bytes(value.as_deref().unwrap_or(&[])));
+                rlp_list(&items)
+            }
+            Node::Hash(_) => unreachable!("unresolved nodes are referenced by hash"),
+        }
+    }
+
+    /// Return the reference to the node from its parent: the node itself when
+    /// its encoding is shorter than 32 bytes, or its hash otherwise.
+    fn reference(&self) -> Vec<u8> {
+        if let Node::Hash(hash) = self {
+            return rlp_bytes(hash.as_bytes());
+        }
+        let encoded = self.encode();
+        if encoded.len() < 32 {
+            encoded
+        } else {
+            rlp_bytes(keccak(&encoded).as_bytes())
+        }
+    }
+
+    /// Return the hash of the node, which is always hashed even when short
+    /// as it's the root of a trie.
+    fn hash(&self) -> H256 {
+        match self {
+            Node::Empty => *EMPTY_TRIE_ROOT,
+            Node::Hash(hash) => *hash,
+            node => keccak(&node.encode()),
+        }
+    }
+}
+
+/// Return `child` prefixed by `path`, as an extension node when `path` isn't
+/// empty.
+fn extension(path: Vec<u8>, child: Node) -> Node {
+    if path.is_empty() {
+        child
+    } else {
+        Node::Extension {
+            path,
+            child: Box::new(child),
+        }
+    }
+}
+
+/// Return the length of the common prefix of `a` and `b`.
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Secure Merkle-Patricia trie (with keys hashed by keccak256) of which only
+/// the nodes known from proofs are available.  Reads and updates are supported
+/// at any key whose path is covered by the known nodes, which is the case for
+/// the keys of the proofs.
+///
+/// Removing a key may require the sibling of its leaf, when the branch holding
+/// both is left with only the sibling and has to be collapsed into it.  If the
+/// sibling isn't known, [`ProofError::MissingNode`] is returned.
+/// An update which fails may have been partially applied, so the trie must
+/// not be used after an error.
+///
+/// The tries of transactions and receipts aren't secure: their keys are used
+/// as paths without hashing, see [`PartialTrie::new_unsecured`].
+#[derive(Debug, Clone)]
+pub struct PartialTrie {
+    root: Node,
+    nodes: HashMap<H256, Vec<u8>>,
+    is_secure: bool,
+}
+
+impl PartialTrie {
+    /// Create a trie with root `root` from the encoded `nodes` of a set of
+    /// proofs.
+    pub fn new<'a>(root: H256, nodes: impl IntoIterator<Item = &'a Bytes>) -> Self {
+        Self::with_security(root, nodes, true)
+    }
+
+    /// Create a trie whose keys aren't hashed, with root `root` from the
+    /// encoded `nodes` of a set of proofs.
+    pub fn new_unsecured<'a>(root: H256, nodes: impl IntoIterator<Item = &'a Bytes>) -> Self {
+        Self::with_security(root, nodes, false)
+    }
+
+    fn with_security<'a>(
+        root: H256,
+        nodes: impl IntoIterator<Item = &'a Bytes>,
+        is_secure: bool,
+    ) -> Self {
+        let nodes = nodes
+            .into_iter()
+            .map(|node| (keccak(node.as_ref()), node.to_vec()))
+            .collect();
+        let root = if root == *EMPTY_TRIE_ROOT {
+            Node::Empty
+        } else {
+            Node::Hash(root)
+        };
+        Self {
+            root,
+            nodes,
+            is_secure,
+        }
+    }
+
+    /// Return the root of the trie.
+    pub fn root(&self) -> H256 {
+        self.root.hash()
+    }
+
+    /// Return the path of `key` in the trie.
+    fn path(&self, key: &[u8]) -> Vec<u8> {
+        if self.is_secure {
+            to_nibbles(keccak(key).as_bytes())
+        } else {
+            to_nibbles(key)
+        }
+    }
+
+    /// Return the value at `key`, or `None` if it's not in the trie.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, ProofError> {
+        self.get_node(&self.root, &self.path(key))
+    }
+
+    /// Return the proof of the value at `key`, in the format of
+    /// [`verify_proof`](super::verify_proof): the encoded nodes from the root to
+    /// the value, or to the node showing that `key` is not in the trie.
+    pub fn proof(&self, key: &[u8]) -> Result<Vec<Bytes>, ProofError> {
+        let mut proof = Vec::new();
+        self.proof_node(&self.root, &self.path(key), true, &mut proof)?;
+        Ok(proof)
+    }
+
+    /// Set the value at `key`.  An empty value removes the key.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), ProofError> {
+        if value.is_empty() {
+            return self.remove(key);
+        }
+        let root = std::mem::take(&mut self.root);
+        let path = self.path(key);
+        self.root = self.insert_node(root, &path, value)?;
+        Ok(())
+    }
+
+    /// Remove the value at `key`, if any.
+    pub fn remove(&mut self, key: &[u8]) -> Result<(), ProofError> {
+        let root = std::mem::take(&mut self.root);
+        let path = self.path(key);
+        self.root = self.remove_node(root, &path)?;
+        Ok(())
+    }
+
+    /// Return the encodings of all the hashed nodes of the trie, which must be
+    /// fully resolved.
+    #[cfg(test)]
+    pub(crate) fn encoded_nodes(&self) -> Vec<Bytes> {
+        fn collect(node: &Node, nodes: &mut Vec<Bytes>) {
+            match node {
+                Node::Extension { child, .. } => collect(child, nodes),
+                Node::Branch { children, .. } => {
+                    children.iter().for_each(|child| collect(child, nodes))
+                }
+                _ => {}
+            }
+            let encoded = node.encode();
+            if encoded.len() >= 32 {
+                nodes.push(encoded.into());
+            }
+        }
+        let mut nodes = Vec::new();
+        collect(&self.root, &mut nodes);
+        nodes
+    }
+
+    /// Decode `node` if it's only known by its hash.
+    fn resolve(&self, node: Node) -> Result<Node, ProofError> {
+        match node {
+            Node::Hash(hash) => Node::decode(self.nodes.get(&hash).ok_or(ProofError::MissingNode)?),
+            node => Ok(node),
+        }
+    }
+
+    fn get_node(&self, node: &Node, path: &[u8]) -> Result<Option<Vec<u8>>, ProofError> {
+        match node {
+            Node::Empty => Ok(None),
+            Node::Leaf {
+                path: leaf_path,
+                value,
+            } => Ok((leaf_path.as_slice() == path).then(|| value.clone())),
+            Node::Extension {
+                path: extension_path,
+                child,
+            } => match path.strip_prefix(extension_path.as_slice()) {
+                Some(rest) => self.get_node(child, rest),
+                None => Ok(None),
+            },
+            Node::Branch { children, value } => match path.split_first() {
+                Some((nibble, rest)) => self.get_node(&children[*nibble as usize], rest),
+                None => Ok(value.clone()),
+            },
+            Node::Hash(hash) => self.get_node(&self.resolve(Node::Hash(*hash))?, path),
+        }
+    }
+
+    fn proof_node(
+        &self,
+        node: &Node,
+        path: &[u8],
+        is_root: bool,
+        proof: &mut Vec<Bytes>,
+    ) -> Result<(), ProofError> {
+        if let Node::Hash(hash) = node {
+            return self.proof_node(&self.resolve(Node::Hash(*hash))?, path, true, proof);
+        }
+        // Nodes shorter than 32 bytes are embedded in their parent.
+        let encoded = node.encode();
+        if *node != Node::Empty && (is_root || encoded.len() >= 32) {
+            proof.push(encoded.into());
+        }
+        match node {
+            Node::Extension {
+                path: extension_path,
+                child,
+            } => match path.strip_prefix(extension_path.as_slice()) {
+                Some(rest) => self.proof_node(child, rest, false, proof),
+                None => Ok(()),
+            },
+            Node::Branch { children, .. } => match path.split_first() {
+                Some((nibble, rest)) => {
+                    self.proof_node(&children[*nibble as usize], rest, false, proof)
+                }
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn insert_node(&self, node: Node, path: &[u8], value: Vec<u8>) -> Result<Node, ProofError> {
+        Ok(match self.resolve(node)? {
+            Node::Empty => Node::Leaf {
+                path: path.to_vec(),
+                value,
+            },
+            Node::Leaf {
+                path: leaf_path,
+                value: leaf_value,
+            } => {
+                if leaf_path.as_slice() == path {
+                    Node::Leaf {
+                        path: leaf_path,
+                        value,
+                    }
+                } else {
+                    // Split the leaf with a branch at the end of the common
+                    // prefix, holding both values.
+                    let len = common_prefix_len(&leaf_path, path);
+                    let branch = Node::Branch {
+                        children: Box::default(),
+                        value: None,
+                    };
+                    let branch = self.insert_node(branch, &leaf_path[len..], leaf_value)?;
+                    let branch = self.insert_node(branch, &path[len..], value)?;
+                    extension(path[..len].to_vec(), branch)
+                }
+            }
+            Node::Extension {
+                path: extension_path,
+                child,
+            } => {
+                let len = common_prefix_len(&extension_path, path);
+                if len == extension_path.len() {
+                    Node::Extension {
+                        child: Box::new(self.insert_node(*child, &path[len..], value)?),
+                        path: extension_path,
+                    }
+                } else {
+                    // Split the extension with a branch at the end of the
+                    // common prefix.
+                    let mut children: Box<[Node; 16]> = Box::default();
+                    children[extension_path[len] as usize] =
+                        extension(extension_path[len + 1..].to_vec(), *child);
+                    let branch = Node::Branch {
+                        children,
+                        value: None,
+                    };
+                    let branch = self.insert_node(branch, &path[len..], value)?;
+                    extension(extension_path[..len].to_vec(), branch)
+                }
+            }
+            Node::Branch {
+                mut children,
+                value: branch_value,
+            } => match path.split_first() {
+                Some((nibble, rest)) => {
+                    let index = *nibble as usize;
+                    let child = std::mem::take(&mut children[index]);
+                    children[index] = self.insert_node(child, rest, value)?;
+                    Node::Branch {
+                        children,
+                        value: branch_value,
+                    }
+                }
+                None => Node::Branch {
+                    children,
+                    value: Some(value),
+                },
+            },
+            Node::Hash(_) => unreachable!("node is resolved"),
+        })
+    }
+
+    fn remove_node(&self, node: Node, path: &[u8]) -> Result<Node, ProofError> {
+        match self.resolve(node)? {
+            Node::Empty => Ok(Node::Empty),
+            Node::Leaf {
+                path: leaf_path,
+                value,
+            } => Ok(if leaf_path.as_slice() == path {
+                Node::Empty
+            } else {
+                Node::Leaf {
+                    path: leaf_path,
+                    value,
+                }
+            }),
+            Node::Extension {
+                path: extension_path,
+                child,
+            } => match path.strip_prefix(extension_path.as_slice()) {
+                Some(rest) => {
+                    let child = self.remove_node(*child, rest)?;
+                    self.join(extension_path, child)
+                }
+                None => Ok(Node::Extension {
+                    path: extension_path,
+                    child,
+                }),
+            },
+            Node::Branch {
+                mut children,
+                mut value,
+            } => {
+                match path.split_first() {
+                    Some((nibble, rest)) => {
+                        let index = *nibble as usize;
+                        let child = std::mem::take(&mut children[index]);
+                        children[index] = self.remove_node(child, rest)?;
+                    }
+                    None => value = None,
+                }
+                self.collapse_branch(children, value)
+            }
+            Node::Hash(_) => unreachable!("node is resolved"),
+        }
+    }
+
+    /// Return `child` prefixed by `path`, merging the paths when `child` is a
+    /// leaf or an extension node.
+    fn join(&self, path: Vec<u8>, child: Node) -> Result<Node, ProofError> {
+        Ok(match self.resolve(child)? {
+            Node::Empty => Node::Empty,
+            Node::Leaf {
+                path: child_path,
+                value,
+            } => Node::Leaf {
+                path: [path, child_path].concat(),
+                value,
+            },
+            Node::Extension {
+                path: child_path,
+                child,
+            } => Node::Extension {
+                path: [path, child_path].concat(),
+                child,
+            },
+            node => extension(path, node),
+        })
+    }
+
+    /// Replace a branch node with a single entry by that entry.
+    fn collapse_branch(
+        &self,
+        mut children: Box<[Node; 16]>,
+        value: Option<Vec<u8>>,
+    ) -> Result<Node, ProofError> {
+        let used: Vec<usize> = children
+            .iter()
+            .enumerate()
+            .filter(|(_, child)| **child != Node::Empty)
+            .map(|(index, _)| index)
+            .collect();
+        match (used.as_slice(), value) {
+            ([], None) => Ok(Node::Empty),
+            ([], Some(value)) => Ok(Node::Leaf {
+                path: Vec::new(),
+                value,
+            }),
+            ([index], None) => {
+                let child = std::mem::take(&mut children[*index]);
+                self.join(vec![*index as u8], child)
+            }
+            (_, value) => Ok(Node::Branch { children, value }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod trie_tests {
+    use super::*;
+    use crate::mpt::{rlp_word, verify_proof};
+    use eth_types::Word;
+    use pretty_assertions::assert_eq;
+
+    /// Build the trie with all `entries` from scratch, as a reference.
+    fn full_trie(entries: &[(Vec<u8>, Vec<u8>)]) -> PartialTrie {
+        let mut trie = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        for (key, value) in entries {
+            trie.insert(key, value.clone()).unwrap();
+        }
+        trie
+    }
+
+    fn entries(range: std::ops::Range<u8>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        range
+            .map(|i| (vec![i], vec![i; 1 + i as usize % 40]))
+            .collect()
+    }
+
+    #[test]
+    fn trie_empty_root() {
+        let trie = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        assert_eq!(trie.root(), *EMPTY_TRIE_ROOT);
+        assert_eq!(trie.get(&[1]).unwrap(), None);
+    }
+
+    #[test]
+    fn trie_insert_remove() {
+        let all = entries(0..64);
+        let trie = full_trie(&all);
+        // Insertion order doesn't matter.
+        let mut reversed = all.clone();
+        reversed.reverse();
+        assert_eq!(full_trie(&reversed).root(), trie.root());
+
+        // Rebuild the trie from its root and nodes, and remove half of the
+        // entries.
+        let mut partial = PartialTrie::new(trie.root(), &trie.encoded_nodes());
+        for (key, value) in &all {
+            assert_eq!(partial.get(key).unwrap().as_ref(), Some(value));
+        }
+        for (key, _) in &all[32..] {
+            partial.remove(key).unwrap();
+            assert_eq!(partial.get(key).unwrap(), None);
+        }
+        assert_eq!(partial.root(), full_trie(&all[..32]).root());
+
+        // Update the remaining entries.
+        let updated: Vec<_> = all[..32]
+            .iter()
+            .map(|(key, value)| (key.clone(), [value.as_slice(), &[0xff]].concat()))
+            .collect();
+        for (key, value) in &updated {
+            partial.insert(key, value.clone()).unwrap();
+        }
+        assert_eq!(partial.root(), full_trie(&updated).root());
+
+        for (key, _) in &updated {
+            partial.insert(key, Vec::new()).unwrap();
+        }
+        assert_eq!(partial.root(), *EMPTY_TRIE_ROOT);
+    }
+
+    #[test]
+    fn trie_proof() {
+        let all = entries(0..64);
+        let trie = full_trie(&all);
+        let partial = PartialTrie::new(trie.root(), &trie.encoded_nodes());
+        for (key, value) in &all {
+            assert_eq!(
+                verify_proof(trie.root(), key, &partial.proof(key).unwrap()),
+                Ok(Some(value.clone()))
+            );
+        }
+        let absent = [0xff, 0xff];
+        assert_eq!(
+            verify_proof(trie.root(), &absent, &trie.proof(&absent).unwrap()),
+            Ok(None)
+        );
+        let empty = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        assert_eq!(empty.proof(&absent), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn trie_unsecured() {
+        // A single leaf holds the whole key as its path.
+        let mut trie = PartialTrie::new_unsecured(*EMPTY_TRIE_ROOT, []);
+        let value = vec![0xaa; 40];
+        trie.insert(&[0x80], value.clone()).unwrap();
+        let leaf = rlp_list(&[
+            rlp_bytes(&hex_prefix_encode(&to_nibbles(&[0x80]), true)),
+            rlp_bytes(&value),
+        ]);
+        assert_eq!(trie.root(), keccak(&leaf));
+        assert_eq!(trie.get(&[0x80]).unwrap(), Some(value));
+
+        let mut secure = PartialTrie::new(*EMPTY_TRIE_ROOT, []);
+        secure.insert(&[0x80], vec![0xaa; 40]).unwrap();
+        assert_ne!(secure.root(), trie.root());
+
+        // Keys are the RLP encoded indices, as in the receipts trie.
+        for (key, value) in entries(0..64) {
+            trie.insert(&rlp_word(Word::from(key[0])), value).unwrap();
+        }
+        let partial = PartialTrie::new_unsecured(trie.root(), &trie.encoded_nodes());
+        for (key, value) in entries(0..64) {
+            assert_eq!(
+                partial.get(&rlp_word(Word::from(key[0]))).unwrap(),
+                Some(value)
+            );
+        }
+    }
+
+    #[test]
+    fn trie_missing_node() {
+        let trie = full_trie(&entries(0..16));
+        let partial = PartialTrie::new(trie.root(), []);
+        assert_eq!(partial.get(&[1]), Err(ProofError::MissingNode));
+
+        // Only the root is known
+        let root_node = trie.root.encode();
+        let mut partial = PartialTrie::new(trie.root(), &[Bytes::from(root_node)]);
+        assert_eq!(partial.insert(&[1], vec![1]), Err(ProofError::MissingNode));
+    }
+}
@@ -13,10 +13,11 @@ use core::cmp::Ordering;
 use core::fmt;
 use core::fmt::Debug;
 use eth_types::{Address, Word};
+use serde::{Deserialize, Serialize};
 use std::mem::swap;
 
 /// Marker that defines whether an Operation performs a `READ` or a `WRITE`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum RW {
     /// Marks op as READ.
     READ,
@@ -39,7 +40,7 @@ impl RW {
 /// Wrapper type over `usize` which represents the global counter. The purpose
 /// of the `RWCounter` is to enforce that each Opcode/Instruction and Operation
 /// is unique and just executed once.
-#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct RWCounter(pub usize);
 
 impl fmt::Debug for RWCounter {
@@ -86,7 +87,7 @@ impl RWCounter {
 }
 
 /// Enum used to differenciate between EVM Stack, Memory and Storage operations.
-#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub enum Target {
     /// Means the target of the operation is the Memory.
     Memory,
@@ -110,6 +111,8 @@ pub enum Target {
     TxReceipt,
     /// Means the target of the operation is the TxLog.
     TxLog,
+    /// Means the target of the operation is the TransientStorage.
+    TransientStorage,
 }
 
 /// Trait used for Operation Kinds.
@@ -125,7 +128,7 @@ pub trait Op: Clone + Eq + Ord {
 /// Represents a [`READ`](RW::READ)/[`WRITE`](RW::WRITE) into the memory implied
 /// by an specific [`OpcodeId`](eth_types::evm_types::opcode_ids::OpcodeId) of
 /// the [`ExecStep`](crate::circuit_input_builder::ExecStep).
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MemoryOp {
     /// Call ID
     pub call_id: usize,
@@ -202,7 +205,7 @@ impl Ord for MemoryOp {
 /// Represents a [`READ`](RW::READ)/[`WRITE`](RW::WRITE) into the stack implied
 /// by an specific [`OpcodeId`](eth_types::evm_types::opcode_ids::OpcodeId) of
 /// the [`ExecStep`](crate::circuit_input_builder::ExecStep).
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StackOp {
     /// Call ID
     pub call_id: usize,
@@ -280,7 +283,7 @@ impl Ord for StackOp {
 /// implied by an specific
 /// [`OpcodeId`](eth_types::evm_types::opcode_ids::OpcodeId) of
 /// the [`ExecStep`](crate::circuit_input_builder::ExecStep).
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StorageOp {
     /// Account Address
     pub address: Address,
@@ -377,10 +380,86 @@ impl Ord for StorageOp {
     }
 }
 
+/// Represents a [`READ`](RW::READ)/[`WRITE`](RW::WRITE) into the transient
+/// storage (EIP-1153) implied by a `TLOAD` or `TSTORE` step. The transient
+/// storage is discarded at the end of every transaction.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransientStorageOp {
+    /// Transaction ID: Transaction index in the block starting at 1.
+    pub tx_id: usize,
+    /// Account Address
+    pub address: Address,
+    /// Transient Storage Key
+    pub key: Word,
+    /// Transient Storage Value after the operation
+    pub value: Word,
+    /// Transient Storage Value before the operation
+    pub value_prev: Word,
+}
+
+impl fmt::Debug for TransientStorageOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TransientStorageOp { ")?;
+        f.write_fmt(format_args!(
+            "tx_id: {:?}, addr: {:?}, key: {:?}, val_prev: 0x{:x}, val: 0x{:x}",
+            self.tx_id, self.address, self.key, self.value_prev, self.value
+        ))?;
+        f.write_str(" }")
+    }
+}
+
+impl TransientStorageOp {
+    /// Create a new instance of a `TransientStorageOp` from it's components.
+    pub const fn new(
+        tx_id: usize,
+        address: Address,
+        key: Word,
+        value: Word,
+        value_prev: Word,
+    ) -> TransientStorageOp {
+        TransientStorageOp {
+            tx_id,
+            address,
+            key,
+            value,
+            value_prev,
+        }
+    }
+
+    /// Returns the [`Target`] (operation type) of this operation.
+    pub const fn target(&self) -> Target {
+        Target::TransientStorage
+    }
+}
+
+impl Op for TransientStorageOp {
+    fn into_enum(self) -> OpEnum {
+        OpEnum::TransientStorage(self)
+    }
+
+    fn reverse(&self) -> Self {
+        let mut rev = self.clone();
+        swap(&mut rev.value, &mut rev.value_prev);
+        rev
+    }
+}
+
+impl PartialOrd for TransientStorageOp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TransientStorageOp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.tx_id, &self.address, &self.key).cmp(&(&other.tx_id, &other.address, &other.key))
+    }
+}
+
 /// Represents a change in the Account AccessList implied by a `BeginTx`,
 /// `EXTCODECOPY`, `EXTCODESIZE`, `EXTCODEHASH` `BALANCE`, `SELFDESTRUCT`,
 /// `*CALL`* or `CREATE*` step.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TxAccessListAccountOp {
     /// Transaction ID: Transaction index in the block starting at 1.
     pub tx_id: usize,
@@ -430,7 +509,7 @@ impl Op for TxAccessListAccountOp {
 
 /// Represents a change in the Storage AccessList implied by an `SSTORE` or
 /// `SLOAD` step of the [`ExecStep`](crate::circuit_input_builder::ExecStep).
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TxAccessListAccountStorageOp {
     /// Transaction ID: Transaction index in the block starting at 1.
     pub tx_id: usize,
@@ -482,7 +561,7 @@ impl Op for TxAccessListAccountStorageOp {
 /// Represents a change in the Transaction Refund AccessList implied by an
 /// `SSTORE`, `STOP`, `RETURN` or `REVERT` step of the
 /// [`ExecStep`](crate::circuit_input_builder::ExecStep).
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TxRefundOp {
     /// Transaction ID: Transaction index in the block starting at 1.
     pub tx_id: usize,
@@ -529,7 +608,7 @@ impl Op for TxRefundOp {
 
 /// Represents a field parameter of the Account that can be accessed via EVM
 /// execution.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum AccountField {
     /// Account Nonce
     Nonce,
@@ -542,7 +621,7 @@ pub enum AccountField {
 /// Represents a change in the Account field implied by a `BeginTx`,
 /// `EXTCODECOPY`, `EXTCODESIZE`, `BALANCE`, `SELFDESTRUCT`, `*CALL`*,
 /// `CREATE*`, `STOP`, `RETURN` or `REVERT` step.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AccountOp {
     /// Account Address
     pub address: Address,
@@ -608,7 +687,7 @@ impl Op for AccountOp {
 
 /// Represents an Account destruction implied by a `SELFDESTRUCT` step of the
 /// [`ExecStep`](crate::circuit_input_builder::ExecStep).
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AccountDestructedOp {
     /// Transaction ID: Transaction index in the block starting at 1.
     pub tx_id: usize,
@@ -657,7 +736,7 @@ impl Op for AccountDestructedOp {
 
 /// Represents a field parameter of the CallContext that can be accessed via EVM
 /// execution.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum CallContextField {
     /// RwCounterEndOfReversion
     RwCounterEndOfReversion,
@@ -712,7 +791,7 @@ pub enum CallContextField {
 }
 
 /// Represents an CallContext read/write operation.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CallContextOp {
     /// call_id of CallContext
     pub call_id: usize,
@@ -783,7 +862,7 @@ impl CallContextOp {
 
 /// Represents a field parameter of the TxLog that can be accessed via EVM
 /// execution.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TxLogField {
     /// contract address
     Address,
@@ -796,7 +875,7 @@ pub enum TxLogField {
 }
 
 /// Represents TxLog read/write operation.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TxLogOp {
     /// tx_id of TxLog, starts with 1 in rw table, and it's unique per Tx
     pub tx_id: usize,
@@ -870,7 +949,7 @@ impl Op for TxLogOp {
 
 /// Represents a field parameter of the TxReceipt that can be accessed via EVM
 /// execution.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TxReceiptField {
     /// flag indicates whether a tx succeed or not
     PostStateOrStatus,
@@ -882,7 +961,7 @@ pub enum TxReceiptField {
 }
 
 /// Represents TxReceipt read/write operation.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TxReceiptOp {
     /// tx_id of TxReceipt
     pub tx_id: usize,
@@ -927,7 +1006,7 @@ impl Op for TxReceiptOp {
 
 /// Generic enum that wraps over all the operation types possible.
 /// In particular [`StackOp`], [`MemoryOp`] and [`StorageOp`].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OpEnum {
     /// Stack
     Stack(StackOp),
@@ -951,10 +1030,26 @@ pub enum OpEnum {
     TxReceipt(TxReceiptOp),
     /// TxLog
     TxLog(TxLogOp),
+    /// TransientStorage
+    TransientStorage(TransientStorageOp),
+}
+
+impl OpEnum {
+    /// Return the id of the call this operation belongs to, for the operation
+    /// kinds which are scoped to a call ([`StackOp`], [`MemoryOp`] and
+    /// [`CallContextOp`]).
+    pub fn call_id(&self) -> Option<usize> {
+        match self {
+            OpEnum::Stack(op) => Some(op.call_id),
+            OpEnum::Memory(op) => Some(op.call_id),
+            OpEnum::CallContext(op) => Some(op.call_id),
+            _ => None,
+        }
+    }
 }
 
 /// Operation is a Wrapper over a type that implements Op with a RWCounter.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Operation<T: Op> {
     rwc: RWCounter,
     rw: RW,
@@ -1088,6 +1183,38 @@ impl<T: Op> Operation<T> {
     // }
 }
 
+/// Type-erased view over an [`Operation`] of any [`Target`], which allows
+/// accessing the operations of an [`OperationContainer`] through an
+/// [`OperationRef`](crate::exec_trace::OperationRef).
+pub trait OperationView: Debug {
+    /// Return the `rwc` of the operation.
+    fn rwc(&self) -> RWCounter;
+    /// Return the `rw` of the operation.
+    fn rw(&self) -> RW;
+    /// Return whether the operation is reverted when its call fails.
+    fn reversible(&self) -> bool;
+    /// Return a copy of the inner op as an [`OpEnum`].
+    fn op_enum(&self) -> OpEnum;
+}
+
+impl<T: Op + Debug> OperationView for Operation<T> {
+    fn rwc(&self) -> RWCounter {
+        self.rwc
+    }
+
+    fn rw(&self) -> RW {
+        self.rw
+    }
+
+    fn reversible(&self) -> bool {
+        self.reversible
+    }
+
+    fn op_enum(&self) -> OpEnum {
+        self.op.clone().into_enum()
+    }
+}
+
 #[cfg(test)]
 mod operation_tests {
     use super::*;
@@ -1,10 +1,35 @@
 use super::{
-    AccountDestructedOp, AccountOp, CallContextOp, MemoryOp, Op, OpEnum, Operation, RWCounter,
-    StackOp, StorageOp, Target, TxAccessListAccountOp, TxAccessListAccountStorageOp, TxLogOp,
-    TxReceiptOp, TxRefundOp, RW,
+    AccountDestructedOp, AccountOp, CallContextOp, MemoryOp, Op, OpEnum, Operation, OperationView,
+    RWCounter, StackOp, StorageOp, Target, TransientStorageOp, TxAccessListAccountOp,
+    TxAccessListAccountStorageOp, TxLogOp, TxReceiptOp, TxRefundOp, RW,
 };
-use crate::exec_trace::OperationRef;
+use crate::{circuit_input_builder::ExecStep, exec_trace::OperationRef};
+use core::fmt::Debug;
+use core::ops::{Index, RangeBounds};
+use eth_types::{Address, Word};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Erases the [`Target`] of an [`Operation`].
+fn view<T: Op + Debug + 'static>(op: &Operation<T>) -> &(dyn OperationView + 'static) {
+    op
+}
+
+/// All the [`Target`]s of the operations stored in an [`OperationContainer`].
+const TARGETS: [Target; 12] = [
+    Target::Memory,
+    Target::Stack,
+    Target::Storage,
+    Target::TxAccessListAccount,
+    Target::TxAccessListAccountStorage,
+    Target::TxRefund,
+    Target::Account,
+    Target::AccountDestructed,
+    Target::CallContext,
+    Target::TxReceipt,
+    Target::TxLog,
+    Target::TransientStorage,
+];
 
 /// The `OperationContainer` is meant to store all of the [`Operation`]s that an
 /// [`ExecStep`](crate::circuit_input_builder::ExecStep) performs during its
@@ -20,7 +45,7 @@ use itertools::Itertools;
 /// they have specified.
 /// That serves as a way to get an input with which is easy to work with in
 /// order to construct the State proof.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OperationContainer {
     /// Operations of MemoryOp
     pub memory: Vec<Operation<MemoryOp>>,
@@ -44,6 +69,8 @@ pub struct OperationContainer {
     pub tx_receipt: Vec<Operation<TxReceiptOp>>,
     /// Operations of TxLogOp
     pub tx_log: Vec<Operation<TxLogOp>>,
+    /// Operations of TransientStorageOp
+    pub transient_storage: Vec<Operation<TransientStorageOp>>,
 }
 
 impl Default for OperationContainer {
@@ -52,7 +79,6 @@ impl Default for OperationContainer {
     }
 }
 
-// TODO: impl Index for OperationContainer
 impl OperationContainer {
     /// Generates a new instance of an `OperationContainer`.
     pub fn new() -> Self {
@@ -68,6 +94,7 @@ impl OperationContainer {
             call_context: Vec::new(),
             tx_receipt: Vec::new(),
             tx_log: Vec::new(),
+            transient_storage: Vec::new(),
         }
     }
 
@@ -168,6 +195,14 @@ impl OperationContainer {
                 self.tx_log.push(Operation::new(rwc, rw, op));
                 OperationRef::from((Target::TxLog, self.tx_log.len() - 1))
             }
+            OpEnum::TransientStorage(op) => {
+                self.transient_storage.push(if reversible {
+                    Operation::new_reversible(rwc, rw, op)
+                } else {
+                    Operation::new(rwc, rw, op)
+                });
+                OperationRef::from((Target::TransientStorage, self.transient_storage.len() - 1))
+            }
         }
     }
 
@@ -188,13 +223,181 @@ impl OperationContainer {
     pub fn sorted_storage(&self) -> Vec<Operation<StorageOp>> {
         self.storage.iter().sorted().cloned().collect()
     }
+
+    /// Returns a sorted vector of all of the [`TxAccessListAccountOp`]s
+    /// contained inside of the container.
+    pub fn sorted_tx_access_list_account(&self) -> Vec<Operation<TxAccessListAccountOp>> {
+        self.tx_access_list_account
+            .iter()
+            .sorted()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns a sorted vector of all of the [`TxAccessListAccountStorageOp`]s
+    /// contained inside of the container.
+    pub fn sorted_tx_access_list_account_storage(
+        &self,
+    ) -> Vec<Operation<TxAccessListAccountStorageOp>> {
+        self.tx_access_list_account_storage
+            .iter()
+            .sorted()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns a sorted vector of all of the [`TxRefundOp`]s contained inside
+    /// of the container.
+    pub fn sorted_tx_refund(&self) -> Vec<Operation<TxRefundOp>> {
+        self.tx_refund.iter().sorted().cloned().collect()
+    }
+
+    /// Returns a sorted vector of all of the [`AccountOp`]s contained inside of
+    /// the container.
+    pub fn sorted_account(&self) -> Vec<Operation<AccountOp>> {
+        self.account.iter().sorted().cloned().collect()
+    }
+
+    /// Returns a sorted vector of all of the [`AccountDestructedOp`]s contained
+    /// inside of the container.
+    pub fn sorted_account_destructed(&self) -> Vec<Operation<AccountDestructedOp>> {
+        self.account_destructed.iter().sorted().cloned().collect()
+    }
+
+    /// Returns a sorted vector of all of the [`CallContextOp`]s contained
+    /// inside of the container.
+    pub fn sorted_call_context(&self) -> Vec<Operation<CallContextOp>> {
+        self.call_context.iter().sorted().cloned().collect()
+    }
+
+    /// Returns a sorted vector of all of the [`TxReceiptOp`]s contained inside
+    /// of the container.
+    pub fn sorted_tx_receipt(&self) -> Vec<Operation<TxReceiptOp>> {
+        self.tx_receipt.iter().sorted().cloned().collect()
+    }
+
+    /// Returns a sorted vector of all of the [`TxLogOp`]s contained inside of
+    /// the container.
+    pub fn sorted_tx_log(&self) -> Vec<Operation<TxLogOp>> {
+        self.tx_log.iter().sorted().cloned().collect()
+    }
+
+    /// Returns a sorted vector of all of the [`TransientStorageOp`]s contained
+    /// inside of the container.
+    pub fn sorted_transient_storage(&self) -> Vec<Operation<TransientStorageOp>> {
+        self.transient_storage.iter().sorted().cloned().collect()
+    }
+
+    /// Returns the operation pointed by an [`OperationRef`], or `None` if the
+    /// reference is out of bounds.
+    pub fn get(&self, op_ref: OperationRef) -> Option<&(dyn OperationView + 'static)> {
+        let index = op_ref.as_usize();
+        match op_ref.target() {
+            Target::Memory => self.memory.get(index).map(view),
+            Target::Stack => self.stack.get(index).map(view),
+            Target::Storage => self.storage.get(index).map(view),
+            Target::TxAccessListAccount => self.tx_access_list_account.get(index).map(view),
+            Target::TxAccessListAccountStorage => {
+                self.tx_access_list_account_storage.get(index).map(view)
+            }
+            Target::TxRefund => self.tx_refund.get(index).map(view),
+            Target::Account => self.account.get(index).map(view),
+            Target::AccountDestructed => self.account_destructed.get(index).map(view),
+            Target::CallContext => self.call_context.get(index).map(view),
+            Target::TxReceipt => self.tx_receipt.get(index).map(view),
+            Target::TxLog => self.tx_log.get(index).map(view),
+            Target::TransientStorage => self.transient_storage.get(index).map(view),
+        }
+    }
+
+    /// Returns a copy of the op pointed by an [`OperationRef`] as an
+    /// [`OpEnum`], or `None` if the reference is out of bounds.
+    pub fn get_op_enum(&self, op_ref: OperationRef) -> Option<OpEnum> {
+        self.get(op_ref).map(|op| op.op_enum())
+    }
+
+    /// Returns the number of operations of a [`Target`] in the container.
+    fn len_of(&self, target: Target) -> usize {
+        match target {
+            Target::Memory => self.memory.len(),
+            Target::Stack => self.stack.len(),
+            Target::Storage => self.storage.len(),
+            Target::TxAccessListAccount => self.tx_access_list_account.len(),
+            Target::TxAccessListAccountStorage => self.tx_access_list_account_storage.len(),
+            Target::TxRefund => self.tx_refund.len(),
+            Target::Account => self.account.len(),
+            Target::AccountDestructed => self.account_destructed.len(),
+            Target::CallContext => self.call_context.len(),
+            Target::TxReceipt => self.tx_receipt.len(),
+            Target::TxLog => self.tx_log.len(),
+            Target::TransientStorage => self.transient_storage.len(),
+        }
+    }
+
+    /// Returns an iterator over the references to all the operations in the
+    /// container, grouped by [`Target`].
+    fn refs(&self) -> impl Iterator<Item = OperationRef> + '_ {
+        TARGETS.into_iter().flat_map(move |target| {
+            (0..self.len_of(target)).map(move |index| OperationRef::from((target, index)))
+        })
+    }
+
+    /// Returns the references to the operations matching `filter`, sorted by
+    /// `rwc`.
+    fn refs_by(&self, filter: impl Fn(&dyn OperationView) -> bool) -> Vec<OperationRef> {
+        self.refs()
+            .filter(|op_ref| filter(&self[*op_ref]))
+            .sorted_by_key(|op_ref| self[*op_ref].rwc())
+            .collect()
+    }
+
+    /// Returns the references to all the operations done in the call with id
+    /// `call_id`, sorted by `rwc`.  Only the operations scoped to a call are
+    /// considered, see [`OpEnum::call_id`].
+    pub fn ops_by_call_id(&self, call_id: usize) -> Vec<OperationRef> {
+        self.refs_by(|op| op.op_enum().call_id() == Some(call_id))
+    }
+
+    /// Returns the references to all the operations whose `rwc` is inside of
+    /// `range`, sorted by `rwc`.
+    pub fn ops_by_rwc_range(&self, range: impl RangeBounds<RWCounter>) -> Vec<OperationRef> {
+        self.refs_by(|op| range.contains(&op.rwc()))
+    }
+
+    /// Returns all the [`StorageOp`]s done on the slot `key` of the account at
+    /// `address`, sorted by `rwc`.
+    pub fn storage_ops(&self, address: Address, key: Word) -> Vec<&Operation<StorageOp>> {
+        self.storage
+            .iter()
+            .filter(|op| op.op().address == address && op.op().key == key)
+            .sorted_by_key(|op| op.rwc())
+            .collect()
+    }
+
+    /// Returns the operations referenced by the bus-mapping instance of an
+    /// [`ExecStep`], in the order in which the step did them.
+    pub fn step_ops(&self, step: &ExecStep) -> Vec<&dyn OperationView> {
+        step.bus_mapping_instance
+            .iter()
+            .map(|op_ref| &self[*op_ref])
+            .collect()
+    }
+}
+
+impl Index<OperationRef> for OperationContainer {
+    type Output = dyn OperationView;
+
+    fn index(&self, op_ref: OperationRef) -> &Self::Output {
+        self.get(op_ref)
+            .unwrap_or_else(|| panic!("{:?} out of bounds", op_ref))
+    }
 }
 
 #[cfg(test)]
 mod container_test {
     use super::*;
 
-    use crate::operation::{RWCounter, RW};
+    use crate::operation::{CallContextField, RWCounter, RW};
     use eth_types::evm_types::{MemoryAddress, StackAddress};
     use eth_types::{Address, Word};
 
@@ -235,4 +438,92 @@ mod container_test {
         assert_eq!(memory_ref, OperationRef::from((Target::Memory, 0)));
         assert_eq!(storage_ref, OperationRef::from((Target::Storage, 0)));
     }
+
+    #[test]
+    fn operation_container_query_test() {
+        let mut container = OperationContainer::default();
+        let address = Address::repeat_byte(0xaa);
+        let storage_op = |key: u64, value: u64| {
+            StorageOp::new(
+                address,
+                Word::from(key),
+                Word::from(value),
+                Word::zero(),
+                1,
+                Word::zero(),
+            )
+        };
+        let call_context_op = CallContextOp {
+            call_id: 2,
+            field: CallContextField::IsSuccess,
+            value: Word::one(),
+        };
+
+        let refs = [
+            container.insert(Operation::new(
+                RWCounter(5),
+                RW::WRITE,
+                StackOp::new(1, StackAddress(1023), Word::from(0x100)),
+            )),
+            container.insert(Operation::new_reversible(
+                RWCounter(2),
+                RW::WRITE,
+                storage_op(1, 0x10),
+            )),
+            container.insert(Operation::new(RWCounter(4), RW::READ, storage_op(2, 0))),
+            container.insert(Operation::new(
+                RWCounter(1),
+                RW::READ,
+                call_context_op.clone(),
+            )),
+            container.insert(Operation::new_reversible(
+                RWCounter(3),
+                RW::WRITE,
+                storage_op(1, 0x20),
+            )),
+        ];
+
+        assert_eq!(container[refs[0]].rwc(), RWCounter(5));
+        assert_eq!(container[refs[1]].rw(), RW::WRITE);
+        assert!(container[refs[1]].reversible());
+        assert_eq!(
+            container.get_op_enum(refs[3]),
+            Some(OpEnum::CallContext(call_context_op.clone()))
+        );
+        assert!(container
+            .get(OperationRef::from((Target::TxLog, 0)))
+            .is_none());
+
+        assert_eq!(container.ops_by_call_id(1), vec![refs[0]]);
+        assert_eq!(container.ops_by_call_id(2), vec![refs[3]]);
+        assert_eq!(
+            container.ops_by_rwc_range(RWCounter(2)..RWCounter(5)),
+            vec![refs[1], refs[4], refs[2]]
+        );
+        assert_eq!(
+            container
+                .storage_ops(address, Word::from(1))
+                .into_iter()
+                .map(|op| op.op().value)
+                .collect::<Vec<_>>(),
+            vec![Word::from(0x10), Word::from(0x20)]
+        );
+
+        let step = ExecStep {
+            bus_mapping_instance: vec![refs[3], refs[0]],
+            ..ExecStep::default()
+        };
+        assert_eq!(
+            container
+                .step_ops(&step)
+                .into_iter()
+                .map(|op| op.op_enum())
+                .collect::<Vec<_>>(),
+            vec![
+                OpEnum::CallContext(call_context_op),
+                container.get_op_enum(refs[0]).unwrap()
+            ]
+        );
+        assert_eq!(container.sorted_call_context().len(), 1);
+    }
 }
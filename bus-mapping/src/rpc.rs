@@ -1,14 +1,18 @@
 //! Module which contains all the RPC calls that are needed at any point to
 //! query a Geth node in order to get a Block, Tx or Trace info.
 
+mod cache;
+
 use crate::Error;
+pub use cache::{is_cacheable, CacheError, CachedClient};
 use eth_types::{
     Address, Block, Bytes, EIP1186ProofResponse, GethExecTrace, Hash, ResultGethExecTraces,
     Transaction, Word, U64,
 };
 pub use ethers_core::types::BlockNumber;
 use ethers_providers::JsonRpcClient;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 /// Serialize a type.
 ///
@@ -19,6 +23,19 @@ pub fn serialize<T: serde::Serialize>(t: &T) -> serde_json::Value {
     serde_json::to_value(t).expect("Types never fail to serialize.")
 }
 
+/// Request along with its response, as recorded by the [`CachedClient`] and
+/// in the JSON fixtures of a
+/// [`MockJsonRpcClient`](crate::mock::MockJsonRpcClient).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// JSON-RPC method
+    pub method: String,
+    /// Params of the request
+    pub params: Value,
+    /// Result of the request
+    pub result: Value,
+}
+
 #[derive(Serialize)]
 #[doc(hidden)]
 pub(crate) struct GethLoggerConfig {
@@ -0,0 +1,346 @@
+//! Record-and-replay cache of JSON-RPC responses, so that the inputs of a
+//! block can be regenerated without querying the node again, or without a node
+//! at all from recorded fixtures.
+
+use super::RecordedRequest;
+use crate::mpt::keccak;
+use async_trait::async_trait;
+use ethers_providers::{JsonRpcClient, ProviderError};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Error returned by the [`CachedClient`].
+#[derive(Debug)]
+pub enum CacheError {
+    /// The request with this method and params is not in the cache, and the
+    /// client is replay only.
+    CacheMiss(String, Value),
+    /// Error reading or writing the cache.
+    IoError(PathBuf, io::Error),
+    /// Error converting the params or the result of a request.
+    SerdeError(serde_json::Error),
+    /// Error of the inner client.
+    ClientError(ProviderError),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::CacheMiss(method, params) => {
+                write!(f, "cache miss for {} with params {}", method, params)
+            }
+            CacheError::IoError(path, err) => write!(f, "{}: {}", path.display(), err),
+            CacheError::SerdeError(err) => write!(f, "serde error: {}", err),
+            CacheError::ClientError(err) => write!(f, "client error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<CacheError> for ProviderError {
+    fn from(err: CacheError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+/// [`JsonRpcClient`] which caches the responses of an inner client in a
+/// directory, with one file per request at `<method>/<hash of params>.json`
+/// holding a [`RecordedRequest`].  Requests found in the cache are served from
+/// it, and the others are forwarded to the inner client and recorded, unless
+/// the client is replay only, in which case they fail with
+/// [`CacheError::CacheMiss`].
+///
+/// Only the requests whose result can't change are cached, see
+/// [`is_cacheable`]: the others are always forwarded to the inner client.
+/// Each response is written to a temporary file which is then renamed, so
+/// that an interrupted or concurrent recording never leaves a partial file.
+///
+/// Wrapped in a [`GethClient`](super::GethClient), it records every query of
+/// the [`BuilderClient`](crate::circuit_input_builder::BuilderClient) for a
+/// block, so that the recorded directory can be used as a test fixture.
+#[derive(Debug)]
+pub struct CachedClient<P> {
+    inner: Option<P>,
+    dir: PathBuf,
+}
+
+impl<P: JsonRpcClient> CachedClient<P> {
+    /// Create a client caching the responses of `inner` in `dir`.
+    pub fn new(inner: P, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: Some(inner),
+            dir: dir.into(),
+        }
+    }
+
+    /// Create a client serving only the responses cached in `dir`.
+    pub fn replay(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: None,
+            dir: dir.into(),
+        }
+    }
+
+    /// Return the directory of the cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Return the path of the cached response of the request with `method`
+    /// and `params`.
+    pub fn path(&self, method: &str, params: &Value) -> PathBuf {
+        let hash = keccak(params.to_string().as_bytes());
+        self.dir.join(method).join(format!("{:x}.json", hash))
+    }
+
+    async fn cached_request(&self, method: &str, params: Value) -> Result<Value, CacheError> {
+        if !is_cacheable(method, &params) {
+            return match &self.inner {
+                Some(inner) => inner
+                    .request(method, &params)
+                    .await
+                    .map_err(|err| CacheError::ClientError(err.into())),
+                None => Err(CacheError::CacheMiss(method.to_string(), params)),
+            };
+        }
+
+        let path = self.path(method, &params);
+        match fs::read_to_string(&path) {
+            Ok(json) => {
+                let recorded: RecordedRequest =
+                    serde_json::from_str(&json).map_err(CacheError::SerdeError)?;
+                Ok(recorded.result)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let inner = match &self.inner {
+                    Some(inner) => inner,
+                    None => return Err(CacheError::CacheMiss(method.to_string(), params)),
+                };
+                let result: Value = inner
+                    .request(method, &params)
+                    .await
+                    .map_err(|err| CacheError::ClientError(err.into()))?;
+                let recorded = RecordedRequest {
+                    method: method.to_string(),
+                    params,
+                    result,
+                };
+                let json =
+                    serde_json::to_string_pretty(&recorded).map_err(CacheError::SerdeError)?;
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|err| CacheError::IoError(dir.into(), err))?;
+                }
+                write_atomically(&path, &json).map_err(|err| CacheError::IoError(path, err))?;
+                Ok(recorded.result)
+            }
+            Err(err) => Err(CacheError::IoError(path, err)),
+        }
+    }
+}
+
+/// Return whether the result of the request with `method` and `params` never
+/// changes, so that it can be cached: the chain id, and the requests about a
+/// block pinned by its number or hash.  Requests at a block tag such as
+/// `latest` or `pending`, and requests about the node such as `eth_coinbase`,
+/// aren't.
+pub fn is_cacheable(method: &str, params: &Value) -> bool {
+    let params = params.as_array().map(Vec::as_slice).unwrap_or_default();
+    let block = match method {
+        "eth_chainId" => return true,
+        "eth_getBlockByNumber"
+        | "eth_getBlockByHash"
+        | "debug_traceBlockByNumber"
+        | "debug_traceBlockByHash" => params.first(),
+        "eth_getCode" | "eth_getProof" => params.last(),
+        _ => return false,
+    };
+    // Block numbers and hashes are hex strings, unlike the block tags.
+    block
+        .and_then(Value::as_str)
+        .map_or(false, |block| block.starts_with("0x"))
+}
+
+/// Write `contents` into a temporary file next to `path`, and rename it to
+/// `path`.
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_path = path.with_file_name(tmp_name);
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path).map_err(|err| {
+        let _ = fs::remove_file(&tmp_path);
+        err
+    })
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<P: JsonRpcClient> JsonRpcClient for CachedClient<P> {
+    type Error = CacheError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let params = serde_json::to_value(params).map_err(CacheError::SerdeError)?;
+        let result = self.cached_request(method, params).await?;
+        serde_json::from_value(result).map_err(CacheError::SerdeError)
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+    use crate::{
+        circuit_input_builder::BuilderClient,
+        evm::Hardfork,
+        mock::{BlockData, MockJsonRpcClient},
+        rpc::{BlockNumber, GethClient},
+        Error,
+    };
+    use eth_types::{bytecode, geth_types::GethData, Address, Bytes};
+    use mock::test_ctx::{helpers::*, TestContext};
+    use pretty_assertions::assert_eq;
+
+    /// Return a new empty directory for a cache.
+    fn cache_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "bus-mapping-rpc-cache-{:016x}",
+            rand::random::<u64>()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn cached_client_record_replay() {
+        let code = bytecode! {
+            PUSH1(0x01)
+            PUSH1(0x00)
+            SSTORE
+            STOP
+        };
+        let geth_data: GethData = TestContext::<2, 1>::new(
+            None,
+            account_0_code_account_1_no_code(code),
+            tx_from_1_to_0,
+            |block, _tx| {
+                block
+                    .number(0xcafeu64)
+                    .parent_hash(eth_types::Hash::repeat_byte(0xca))
+            },
+        )
+        .unwrap()
+        .into();
+        let dir = cache_dir();
+
+        // Record
+        let cli = GethClient::new(CachedClient::new(
+            MockJsonRpcClient::new_from_geth_data(&geth_data),
+            &dir,
+        ));
+        let builder_client = BuilderClient::new(cli, Hardfork::default()).await.unwrap();
+        let recorded = builder_client.gen_inputs(0xcafe).await.unwrap();
+        assert!(dir.join("eth_getProof").is_dir());
+
+        // Replay without the inner client
+        let cli = GethClient::new(CachedClient::<MockJsonRpcClient>::replay(&dir));
+        let builder_client = BuilderClient::new(cli, Hardfork::default()).await.unwrap();
+        let replayed = builder_client.gen_inputs(0xcafe).await.unwrap();
+
+        let mut expected =
+            BlockData::new_from_geth_data(geth_data.clone()).new_circuit_input_builder();
+        expected
+            .handle_block(&geth_data.eth_block, &geth_data.geth_traces)
+            .unwrap();
+        assert_eq!(recorded.block.container, expected.block.container);
+        assert_eq!(replayed.block.container, expected.block.container);
+
+        // Requests which weren't recorded fail
+        let cli = GethClient::new(CachedClient::<MockJsonRpcClient>::replay(&dir));
+        assert!(matches!(
+            cli.get_block_by_number(0xcaffu64.into()).await,
+            Err(Error::JSONRpcError(_))
+        ));
+
+        // No temporary file is left behind
+        for entry in fs::read_dir(dir.join("eth_getProof")).unwrap() {
+            let path = entry.unwrap().path();
+            assert_eq!(path.extension().unwrap(), "json");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cached_client_unpinned_requests() {
+        let dir = cache_dir();
+        let mut inner = MockJsonRpcClient::new();
+        inner.insert("eth_coinbase", (), Address::repeat_byte(0x01));
+        inner.insert(
+            "eth_getCode",
+            (Address::zero(), BlockNumber::Latest),
+            Bytes::from(vec![0x01]),
+        );
+        let cli = GethClient::new(CachedClient::new(inner, &dir));
+        assert_eq!(
+            cli.get_coinbase().await.unwrap(),
+            Address::repeat_byte(0x01)
+        );
+        assert_eq!(
+            cli.get_code(Address::zero(), BlockNumber::Latest)
+                .await
+                .unwrap(),
+            vec![0x01]
+        );
+        // Nothing was recorded, so a replay can't serve them.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        let cli = GethClient::new(CachedClient::<MockJsonRpcClient>::replay(&dir));
+        assert!(cli.get_coinbase().await.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cacheable_requests() {
+        let number = serde_json::to_value(BlockNumber::from(0xcafeu64)).unwrap();
+        let hash = serde_json::to_value(eth_types::Hash::repeat_byte(0xca)).unwrap();
+        let latest = serde_json::to_value(BlockNumber::Latest).unwrap();
+        let pending = serde_json::to_value(BlockNumber::Pending).unwrap();
+        let address = serde_json::to_value(Address::zero()).unwrap();
+        let params = |params: &[&Value]| Value::Array(params.iter().cloned().cloned().collect());
+
+        assert!(is_cacheable("eth_chainId", &Value::Null));
+        assert!(!is_cacheable("eth_coinbase", &Value::Null));
+        assert!(is_cacheable(
+            "eth_getBlockByNumber",
+            &params(&[&number, &Value::Bool(true)])
+        ));
+        assert!(is_cacheable(
+            "eth_getBlockByHash",
+            &params(&[&hash, &Value::Bool(true)])
+        ));
+        assert!(!is_cacheable(
+            "eth_getBlockByNumber",
+            &params(&[&latest, &Value::Bool(true)])
+        ));
+        assert!(is_cacheable("eth_getCode", &params(&[&address, &number])));
+        assert!(!is_cacheable("eth_getCode", &params(&[&address, &pending])));
+        assert!(!is_cacheable(
+            "eth_getProof",
+            &params(&[&address, &Value::Array(vec![]), &latest])
+        ));
+    }
+}
@@ -69,6 +69,44 @@ impl Account {
     }
 }
 
+/// Change done to the [`StateDB`], along with the previous state needed to
+/// undo it.
+#[derive(Debug, Clone)]
+enum JournalEntry {
+    /// The account at an address was set, replacing the previous one if any.
+    AccountSet(Address, Option<Account>),
+    /// The account at an address was created as a zero account.
+    AccountCreated(Address),
+    /// The nonce, balance or code hash of the account at an address may have
+    /// changed, from the values stored here.
+    AccountChanged {
+        address: Address,
+        nonce: Word,
+        balance: Word,
+        code_hash: Hash,
+    },
+    /// A storage slot was written, replacing its previous dirty value if any.
+    StorageChanged(Address, Word, Option<Word>),
+    /// A transient storage slot was written, replacing the value stored here.
+    TransientStorageChanged(Address, Word, Word),
+    /// An address was added to the access list.
+    AccessListAccountAdded(Address),
+    /// An address was removed from the access list.
+    AccessListAccountRemoved(Address),
+    /// A storage slot was added to the access list.
+    AccessListStorageAdded(Address, Word),
+    /// A storage slot was removed from the access list.
+    AccessListStorageRemoved(Address, Word),
+    /// The refund changed from the value stored here.
+    RefundChanged(u64),
+    /// An account was set as self destructed.
+    AccountDestructed(Address),
+    /// An account was unset as self destructed.
+    AccountUndestructed(Address),
+    /// A contract was created in the current transaction.
+    ContractCreated(Address),
+}
+
 /// In-memory key-value database that represents the Ethereum State Trie.
 #[derive(Debug, Clone, Default)]
 pub struct StateDB {
@@ -83,10 +121,19 @@ pub struct StateDB {
     // state before current transaction, to calculate gas cost for some opcodes like sstore.
     // So both dirty storage and committed storage are needed.
     dirty_storage: HashMap<(Address, Word), Word>,
+    // Transient storage (EIP-1153), which is discarded at the end of every
+    // transaction.
+    transient_storage: HashMap<(Address, Word), Word>,
     // Accounts that have been through `SELFDESTRUCT` under the situation that `is_persistent` is
     // `true`. These accounts will be reset once `commit_tx` is called.
     destructed_account: HashSet<Address>,
+    // Contracts created in the current transaction, which are the only ones
+    // SELFDESTRUCT removes from Cancun on (EIP-6780).
+    created_contract: HashSet<Address>,
     refund: u64,
+    // Changes done in the current transaction, which allow reverting the
+    // state to any snapshot taken in it.
+    journal: Vec<JournalEntry>,
 }
 
 impl StateDB {
@@ -97,14 +144,18 @@ impl StateDB {
             access_list_account: HashSet::new(),
             access_list_account_storage: HashSet::new(),
             dirty_storage: HashMap::new(),
+            transient_storage: HashMap::new(),
             destructed_account: HashSet::new(),
+            created_contract: HashSet::new(),
             refund: 0,
+            journal: Vec::new(),
         }
     }
 
     /// Set an [`Account`] at `addr` in the StateDB.
     pub fn set_account(&mut self, addr: &Address, acc: Account) {
-        self.state.insert(*addr, acc);
+        let prev = self.state.insert(*addr, acc);
+        self.journal.push(JournalEntry::AccountSet(*addr, prev));
     }
 
     /// Get a reference to the [`Account`] at `addr`.  Returns false and a zero
@@ -119,12 +170,24 @@ impl StateDB {
     /// Get a mutable reference to the [`Account`] at `addr`.  If the
     /// [`Account`] is not found in the state, a zero one will be inserted
     /// and returned along with false.
+    /// Since the returned [`Account`] may be modified, its current nonce,
+    /// balance and code hash are recorded so that they can be reverted.
     pub fn get_account_mut(&mut self, addr: &Address) -> (bool, &mut Account) {
-        let found = if self.state.contains_key(addr) {
-            true
-        } else {
-            self.state.insert(*addr, Account::zero());
-            false
+        let found = match self.state.get(addr) {
+            Some(acc) => {
+                self.journal.push(JournalEntry::AccountChanged {
+                    address: *addr,
+                    nonce: acc.nonce,
+                    balance: acc.balance,
+                    code_hash: acc.code_hash,
+                });
+                true
+            }
+            None => {
+                self.state.insert(*addr, Account::zero());
+                self.journal.push(JournalEntry::AccountCreated(*addr));
+                false
+            }
         };
         (found, self.state.get_mut(addr).expect("addr not inserted"))
     }
@@ -174,7 +237,28 @@ impl StateDB {
     /// After transaction execution, `dirty_storage` is committed into `storage`
     /// in `commit_tx` method.
     pub fn set_storage(&mut self, addr: &Address, key: &Word, value: &Word) {
-        self.dirty_storage.insert((*addr, *key), *value);
+        let prev = self.dirty_storage.insert((*addr, *key), *value);
+        self.journal
+            .push(JournalEntry::StorageChanged(*addr, *key, prev));
+    }
+
+    /// Get a reference to the transient storage value of the account at
+    /// `addr`, at `key`.  Returns a zero [`Word`] when the `key` wasn't
+    /// written in the current transaction.
+    pub fn get_transient_storage(&self, addr: &Address, key: &Word) -> &Word {
+        self.transient_storage
+            .get(&(*addr, *key))
+            .unwrap_or(&VALUE_ZERO)
+    }
+
+    /// Set transient storage value at `addr` and `key`.
+    pub fn set_transient_storage(&mut self, addr: &Address, key: &Word, value: &Word) {
+        let prev = self
+            .transient_storage
+            .insert((*addr, *key), *value)
+            .unwrap_or_default();
+        self.journal
+            .push(JournalEntry::TransientStorageChanged(*addr, *key, prev));
     }
 
     /// Get nonce of account with `addr`.
@@ -199,13 +283,22 @@ impl StateDB {
     /// Add `addr` into account access list. Returns `true` if it's not in the
     /// access list before.
     pub fn add_account_to_access_list(&mut self, addr: Address) -> bool {
-        self.access_list_account.insert(addr)
+        let added = self.access_list_account.insert(addr);
+        if added {
+            self.journal
+                .push(JournalEntry::AccessListAccountAdded(addr));
+        }
+        added
     }
 
     /// Remove `addr` from account access list.
     pub fn remove_account_from_access_list(&mut self, addr: &Address) {
         let exist = self.access_list_account.remove(addr);
         debug_assert!(exist);
+        if exist {
+            self.journal
+                .push(JournalEntry::AccessListAccountRemoved(*addr));
+        }
     }
 
     /// Check whether `(addr, key)` exists in account storage access list.
@@ -216,18 +309,57 @@ impl StateDB {
     /// Add `(addr, key)` into account storage access list. Returns `true` if
     /// it's not in the access list before.
     pub fn add_account_storage_to_access_list(&mut self, (addr, key): (Address, Word)) -> bool {
-        self.access_list_account_storage.insert((addr, key))
+        let added = self.access_list_account_storage.insert((addr, key));
+        if added {
+            self.journal
+                .push(JournalEntry::AccessListStorageAdded(addr, key));
+        }
+        added
     }
 
     /// Remove `(addr, key)` from account storage access list.
     pub fn remove_account_storage_from_access_list(&mut self, pair: &(Address, Word)) {
         let exist = self.access_list_account_storage.remove(pair);
         debug_assert!(exist);
+        if exist {
+            self.journal
+                .push(JournalEntry::AccessListStorageRemoved(pair.0, pair.1));
+        }
+    }
+
+    /// Check whether the account at `addr` has been self destructed in the
+    /// current transaction.
+    pub fn check_account_destructed(&self, addr: &Address) -> bool {
+        self.destructed_account.contains(addr)
     }
 
     /// Set account as self destructed.
     pub fn destruct_account(&mut self, addr: Address) {
-        self.destructed_account.insert(addr);
+        if self.destructed_account.insert(addr) {
+            self.journal.push(JournalEntry::AccountDestructed(addr));
+        }
+    }
+
+    /// Unset account as self destructed.
+    pub fn undestruct_account(&mut self, addr: &Address) {
+        let exist = self.destructed_account.remove(addr);
+        debug_assert!(exist);
+        if exist {
+            self.journal.push(JournalEntry::AccountUndestructed(*addr));
+        }
+    }
+
+    /// Check whether the contract at `addr` has been created in the current
+    /// transaction.
+    pub fn check_contract_created(&self, addr: &Address) -> bool {
+        self.created_contract.contains(addr)
+    }
+
+    /// Set the contract at `addr` as created in the current transaction.
+    pub fn create_contract(&mut self, addr: Address) {
+        if self.created_contract.insert(addr) {
+            self.journal.push(JournalEntry::ContractCreated(addr));
+        }
     }
 
     /// Retrieve refund.
@@ -237,11 +369,82 @@ impl StateDB {
 
     /// Set refund
     pub fn set_refund(&mut self, value: u64) {
+        self.journal.push(JournalEntry::RefundChanged(self.refund));
         self.refund = value;
     }
 
-    /// Clear access list and refund, and commit dirty storage.
-    /// It should be invoked before processing
+    /// Take a snapshot of the current state, returning its id.  The changes
+    /// done after it can be undone with [`StateDB::revert_to`] until the
+    /// current transaction is committed.
+    pub fn snapshot(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// Revert all the changes done after the snapshot with id `snapshot`,
+    /// covering accounts, storage, transient storage, access lists, refund and
+    /// self destructions.
+    pub fn revert_to(&mut self, snapshot: usize) {
+        debug_assert!(snapshot <= self.journal.len(), "invalid snapshot");
+        while self.journal.len() > snapshot {
+            let entry = self.journal.pop().expect("journal is not empty");
+            match entry {
+                JournalEntry::AccountSet(addr, Some(acc)) => {
+                    self.state.insert(addr, acc);
+                }
+                JournalEntry::AccountSet(addr, None) | JournalEntry::AccountCreated(addr) => {
+                    self.state.remove(&addr);
+                }
+                JournalEntry::AccountChanged {
+                    address,
+                    nonce,
+                    balance,
+                    code_hash,
+                } => {
+                    let acc = self
+                        .state
+                        .get_mut(&address)
+                        .expect("changed account not in the state");
+                    acc.nonce = nonce;
+                    acc.balance = balance;
+                    acc.code_hash = code_hash;
+                }
+                JournalEntry::StorageChanged(addr, key, Some(value)) => {
+                    self.dirty_storage.insert((addr, key), value);
+                }
+                JournalEntry::StorageChanged(addr, key, None) => {
+                    self.dirty_storage.remove(&(addr, key));
+                }
+                JournalEntry::TransientStorageChanged(addr, key, value) => {
+                    self.transient_storage.insert((addr, key), value);
+                }
+                JournalEntry::AccessListAccountAdded(addr) => {
+                    self.access_list_account.remove(&addr);
+                }
+                JournalEntry::AccessListAccountRemoved(addr) => {
+                    self.access_list_account.insert(addr);
+                }
+                JournalEntry::AccessListStorageAdded(addr, key) => {
+                    self.access_list_account_storage.remove(&(addr, key));
+                }
+                JournalEntry::AccessListStorageRemoved(addr, key) => {
+                    self.access_list_account_storage.insert((addr, key));
+                }
+                JournalEntry::RefundChanged(value) => self.refund = value,
+                JournalEntry::AccountDestructed(addr) => {
+                    self.destructed_account.remove(&addr);
+                }
+                JournalEntry::AccountUndestructed(addr) => {
+                    self.destructed_account.insert(addr);
+                }
+                JournalEntry::ContractCreated(addr) => {
+                    self.created_contract.remove(&addr);
+                }
+            }
+        }
+    }
+
+    /// Clear access list, transient storage and refund, commit dirty storage,
+    /// apply the self destructions and discard the journal. It should be invoked before processing
     /// with new transaction with the same [`StateDB`].
     pub fn commit_tx(&mut self) {
         self.access_list_account = HashSet::new();
@@ -251,11 +454,14 @@ impl StateDB {
             *ptr = value;
         }
         self.dirty_storage = HashMap::new();
-        for addr in self.destructed_account.clone() {
+        self.transient_storage = HashMap::new();
+        for addr in std::mem::take(&mut self.destructed_account) {
             let (_, account) = self.get_account_mut(&addr);
             *account = ACCOUNT_ZERO.clone();
         }
+        self.created_contract = HashSet::new();
         self.refund = 0;
+        self.journal = Vec::new();
     }
 }
 
@@ -318,4 +524,93 @@ mod statedb_tests {
         assert!(found);
         assert_eq!(value, &Word::from(102));
     }
+
+    #[test]
+    fn statedb_revert() {
+        let addr_a = address!("0x0000000000000000000000000000000000000001");
+        let addr_b = address!("0x0000000000000000000000000000000000000002");
+        let mut statedb = StateDB::new();
+        statedb.get_account_mut(&addr_a).1.balance = Word::from(100);
+        statedb.set_storage(&addr_a, &Word::from(1), &Word::from(10));
+        statedb.add_account_to_access_list(addr_a);
+        statedb.set_refund(5);
+
+        let snapshot_0 = statedb.snapshot();
+        statedb.get_account_mut(&addr_a).1.balance = Word::from(50);
+        statedb.get_account_mut(&addr_b).1.balance = Word::from(50);
+        statedb.set_storage(&addr_a, &Word::from(1), &Word::from(20));
+        statedb.set_storage(&addr_a, &Word::from(2), &Word::from(30));
+        statedb.add_account_storage_to_access_list((addr_a, Word::from(2)));
+        statedb.add_account_to_access_list(addr_b);
+
+        statedb.create_contract(addr_b);
+
+        let snapshot_1 = statedb.snapshot();
+        statedb.increase_nonce(&addr_a);
+        statedb.create_contract(addr_a);
+        statedb.destruct_account(addr_a);
+        statedb.set_refund(10);
+        statedb.remove_account_from_access_list(&addr_a);
+
+        statedb.revert_to(snapshot_1);
+        assert_eq!(statedb.get_nonce(&addr_a), 0);
+        assert!(!statedb.check_account_destructed(&addr_a));
+        assert!(!statedb.check_contract_created(&addr_a));
+        assert!(statedb.check_contract_created(&addr_b));
+        assert_eq!(statedb.refund(), 5);
+        assert!(statedb.check_account_in_access_list(&addr_a));
+        assert_eq!(statedb.get_account(&addr_a).1.balance, Word::from(50));
+
+        statedb.revert_to(snapshot_0);
+        assert!(!statedb.check_contract_created(&addr_b));
+        assert_eq!(statedb.get_account(&addr_a).1.balance, Word::from(100));
+        assert!(!statedb.get_account(&addr_b).0);
+        assert_eq!(
+            statedb.get_storage(&addr_a, &Word::from(1)),
+            (true, &Word::from(10))
+        );
+        assert_eq!(
+            statedb.get_storage(&addr_a, &Word::from(2)),
+            (false, &Word::zero())
+        );
+        assert!(!statedb.check_account_storage_in_access_list(&(addr_a, Word::from(2))));
+        assert!(!statedb.check_account_in_access_list(&addr_b));
+        assert!(statedb.check_account_in_access_list(&addr_a));
+    }
+
+    #[test]
+    fn statedb_transient_storage() {
+        let addr_a = address!("0x0000000000000000000000000000000000000001");
+        let mut statedb = StateDB::new();
+        statedb.set_transient_storage(&addr_a, &Word::from(1), &Word::from(10));
+
+        let snapshot = statedb.snapshot();
+        statedb.set_transient_storage(&addr_a, &Word::from(1), &Word::from(20));
+        statedb.set_transient_storage(&addr_a, &Word::from(2), &Word::from(30));
+        assert_eq!(
+            statedb.get_transient_storage(&addr_a, &Word::from(1)),
+            &Word::from(20)
+        );
+
+        statedb.revert_to(snapshot);
+        assert_eq!(
+            statedb.get_transient_storage(&addr_a, &Word::from(1)),
+            &Word::from(10)
+        );
+        assert_eq!(
+            statedb.get_transient_storage(&addr_a, &Word::from(2)),
+            &Word::zero()
+        );
+        // Transient storage is never persisted.
+        assert_eq!(
+            statedb.get_storage(&addr_a, &Word::from(1)).1,
+            &Word::zero()
+        );
+
+        statedb.commit_tx();
+        assert_eq!(
+            statedb.get_transient_storage(&addr_a, &Word::from(1)),
+            &Word::zero()
+        );
+    }
 }
@@ -197,7 +197,7 @@ macro_rules! bytecode_internal {
         $code.push(n as usize, $v.into());
         $crate::bytecode_internal!($code, $($rest)*);
     }};
-    // Default opcode without any inputs
+    // Default opcode without any inputs, including PUSH0
     ($code:ident, $x:ident $($rest:tt)*) => {{
         debug_assert!(!$crate::evm_types::OpcodeId::$x.is_push(), "invalid push");
         $code.write_op($crate::evm_types::OpcodeId::$x);
@@ -222,6 +222,7 @@ mod tests {
     #[test]
     fn test_bytecode_roundtrip() {
         let code = bytecode! {
+            PUSH0
             PUSH8(0x123)
             POP
             PUSH24(0x321)
@@ -232,6 +233,7 @@ mod tests {
             POP
             POP
             POP
+            POP
             STOP
         };
         assert_eq!(Bytecode::try_from(code.to_vec()).unwrap(), code);
@@ -119,6 +119,10 @@ impl GasCost {
     pub const TX: Self = Self(21000);
     /// Constant cost for a creation transaction
     pub const CREATION_TX: Self = Self(53000);
+    /// Constant cost for every address in the access list of a transaction
+    pub const ACCESS_LIST_ADDRESS: Self = Self(2400);
+    /// Constant cost for every storage key in the access list of a transaction
+    pub const ACCESS_LIST_STORAGE_KEY: Self = Self(1900);
     /// Constant cost for calling with non-zero value
     pub const CALL_WITH_VALUE: Self = Self(9000);
     /// Constant cost for turning empty account into non-empty account
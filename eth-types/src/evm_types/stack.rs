@@ -2,12 +2,12 @@
 use crate::Error;
 use crate::{DebugWord, Word};
 use core::str::FromStr;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// Represents a `StackAddress` of the EVM.
 /// The address range goes `TOP -> DOWN (1024, 0]`.
-#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct StackAddress(pub usize);
 
 impl fmt::Debug for StackAddress {
@@ -111,7 +111,9 @@ impl Stack {
     /// Returns the second last [`Word`] allocated in the `Stack`.
     pub fn nth_last(&self, nth: usize) -> Result<Word, Error> {
         self.0
-            .get(self.0.len() - (nth + 1))
+            .len()
+            .checked_sub(nth + 1)
+            .and_then(|index| self.0.get(index))
             .cloned()
             .ok_or(Error::InvalidStackPointer)
     }
@@ -40,7 +40,7 @@ pub use ethers_core::types::{
     Address, Block, Bytes, H160, H256, U256, U64,
 };
 
-use serde::{de, Deserialize};
+use serde::{de, Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
@@ -194,7 +194,7 @@ impl<F: Field> ToScalar<F> for Address {
 }
 
 /// Struct used to define the storage proof
-#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StorageProof {
     /// Storage key
     pub key: U256,
@@ -205,7 +205,7 @@ pub struct StorageProof {
 }
 
 /// Struct used to define the result of `eth_getProof` call
-#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EIP1186ProofResponse {
     /// Account address
@@ -224,6 +224,21 @@ pub struct EIP1186ProofResponse {
     pub storage_proof: Vec<StorageProof>,
 }
 
+/// Withdrawal of a validator balance from the beacon chain into an account, as
+/// defined in [EIP-4895](https://eips.ethereum.org/EIPS/eip-4895).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Withdrawal {
+    /// Index of the withdrawal
+    pub index: U64,
+    /// Index of the validator
+    pub validator_index: U64,
+    /// Recipient of the withdrawn amount
+    pub address: Address,
+    /// Withdrawn amount, in Gwei
+    pub amount: U64,
+}
+
 #[derive(Deserialize)]
 #[doc(hidden)]
 struct GethExecStepInternal {
@@ -246,6 +261,25 @@ struct GethExecStepInternal {
     storage: HashMap<DebugU256, DebugU256>,
 }
 
+/// Geth representation of a [`GethExecStep`], used to serialize it in the same
+/// format as returned by the debug_trace* methods.
+#[derive(Serialize)]
+#[doc(hidden)]
+struct GethExecStepExternal {
+    pc: ProgramCounter,
+    op: String,
+    gas: Gas,
+    #[serde(rename = "gasCost")]
+    gas_cost: GasCost,
+    refund: Gas,
+    depth: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    stack: Vec<String>,
+    memory: Vec<String>,
+    storage: HashMap<String, String>,
+}
+
 /// The execution step type returned by geth RPC debug_trace* methods.
 /// Corresponds to `StructLogRes` in `go-ethereum/internal/ethapi/api.go`.
 #[derive(Clone, Eq, PartialEq)]
@@ -332,17 +366,53 @@ impl<'de> Deserialize<'de> for GethExecStep {
     }
 }
 
+impl Serialize for GethExecStep {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let op = match self.op {
+            OpcodeId::INVALID(byte) => format!("opcode 0x{:x} not defined", byte),
+            op => op.to_string(),
+        };
+        let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+        GethExecStepExternal {
+            pc: self.pc,
+            op,
+            gas: self.gas,
+            gas_cost: self.gas_cost,
+            refund: self.refund,
+            depth: self.depth,
+            error: self.error.clone(),
+            stack: self
+                .stack
+                .0
+                .iter()
+                .map(|word| format!("0x{:x}", word))
+                .collect(),
+            memory: self.memory.0.chunks(32).map(hex).collect(),
+            storage: self
+                .storage
+                .0
+                .iter()
+                .map(|(key, value)| (hex(&key.to_be_bytes()), hex(&value.to_be_bytes())))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
 /// Helper type built to deal with the weird `result` field added between
 /// `GethExecutionTrace`s in `debug_traceBlockByHash` and
 /// `debug_traceBlockByNumber` Geth JSON-RPC calls.
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[doc(hidden)]
 pub struct ResultGethExecTraces(pub Vec<ResultGethExecTrace>);
 
 /// Helper type built to deal with the weird `result` field added between
 /// `GethExecutionTrace`s in `debug_traceBlockByHash` and
 /// `debug_traceBlockByNumber` Geth JSON-RPC calls.
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[doc(hidden)]
 pub struct ResultGethExecTrace {
     pub result: GethExecTrace,
@@ -353,7 +423,7 @@ pub struct ResultGethExecTrace {
 /// The deserialization truncates the memory of each step in `struct_logs` to
 /// the memory size before the expansion, so that it corresponds to the memory
 /// before the step is executed.
-#[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct GethExecTrace {
     /// Used gas
     pub gas: Gas,
@@ -541,6 +611,38 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn serialize_geth_exec_trace_roundtrip() {
+        let step = |op, error: Option<&str>| GethExecStep {
+            pc: ProgramCounter(7),
+            op,
+            gas: Gas(5217),
+            refund: Gas(4800),
+            gas_cost: GasCost(2100),
+            depth: 2,
+            error: error.map(str::to_string),
+            stack: Stack(vec![word!("0x1003e2d2"), word!("0x0")]),
+            storage: Storage(word_map!("0x0" => "0x6f")),
+            memory: Memory::from(vec![word!("0x80"), word!("0x0")]),
+        };
+        let trace = GethExecTrace {
+            gas: Gas(26809),
+            failed: true,
+            return_value: "".to_string(),
+            struct_logs: vec![
+                step(OpcodeId::SLOAD, None),
+                step(
+                    OpcodeId::INVALID(0x0c),
+                    Some("invalid opcode: opcode 0xc not defined"),
+                ),
+            ],
+        };
+
+        let json = serde_json::to_string(&trace).unwrap();
+        let decoded: GethExecTrace = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, trace);
+    }
 }
 
 #[cfg(test)]